@@ -0,0 +1,250 @@
+/*
+
+    MIT License
+
+    Copyright (c) 2022 Siandfrance
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+
+*/
+
+
+use crate::math::Vec2;
+
+use termios::*;
+
+use std::mem;
+use std::io::{self, stdin, stdout, Write};
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::os::unix::io::{AsRawFd, RawFd};
+
+
+/// Terminal a Renderer draws on: it is set up when the renderer is created, gives the size of the screen
+/// and receives the frames. The rendering server only writes ANSI sequences to it.
+/// 
+/// Implement this trait to draw on something else than a local terminal (see `Renderer::with_backend`).
+/// 
+/// # Usage
+/// 
+/// ```
+/// struct Telnet { stream: TcpStream }
+/// 
+/// impl TerminalBackend for Telnet {
+///     fn size(&self) -> Vec2 {
+///         vec2!(80, 24)
+///     }
+/// 
+///     fn writer(&self) -> io::Result<Box<dyn Write + Send>> {
+///         Ok(Box::new(self.stream.try_clone()?))
+///     }
+/// }
+/// 
+/// let rdr = Renderer::with_backend(Telnet { stream })?;
+/// ```
+pub trait TerminalBackend: Send {
+
+    /// Prepares the terminal for drawing (eg. disables echo), called once when the renderer is created.
+    fn setup(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+
+    /// Restores the settings changed by `setup`, called when the renderer is droped.
+    fn restore(&mut self) {}
+
+
+    /// Returns the size of the terminal in cells (columns, rows).
+    fn size(&self) -> Vec2;
+
+
+    /// Returns a new handle to write to the terminal.
+    fn writer(&self) -> io::Result<Box<dyn Write + Send>>;
+}
+
+
+/// Where a TermiosBackend writes the frames.
+enum Output {
+    Stdout,
+    Tty(File)
+}
+
+
+/// Local terminal, set in raw mode with termios. This is the backend of the Renderer singleton.
+pub struct TermiosBackend {
+    input_fd: RawFd,
+    output: Output,
+    /// Settings of the terminal before `setup`
+    saved: Option<Termios>
+}
+
+
+impl TermiosBackend {
+
+    /// Draws on stdout, the settings of the terminal are changed through stdin.
+    pub fn stdio() -> TermiosBackend {
+        TermiosBackend {
+            input_fd: stdin().as_raw_fd(),
+            output: Output::Stdout,
+            saved: None
+        }
+    }
+
+
+    /// Draws on the terminal at `path` (eg. `/dev/tty` or `/dev/pts/3`).
+    pub fn open<P>(path: P) -> Result<TermiosBackend, String>
+        where P: AsRef<Path>
+    {
+        match OpenOptions::new().read(true).write(true).open(path) {
+            Ok(file) => Ok(TermiosBackend {
+                input_fd: file.as_raw_fd(),
+                output: Output::Tty(file),
+                saved: None
+            }),
+            Err(e) => Err(format!("{}", e))
+        }
+    }
+
+
+    /// Returns the file descriptor the frames are written to.
+    pub(crate) fn output_fd(&self) -> RawFd {
+        match &self.output {
+            Output::Stdout => libc::STDOUT_FILENO,
+            Output::Tty(file) => file.as_raw_fd()
+        }
+    }
+
+
+    /// Returns the file descriptor and the settings to restore on it, once the terminal is set up.
+    pub(crate) fn saved(&self) -> Option<(RawFd, Termios)> {
+        self.saved.map(|t| (self.input_fd, t))
+    }
+}
+
+
+impl TerminalBackend for TermiosBackend {
+
+    /// Puts the terminal in raw mode (no echo, no line buffering, no signals).
+    fn setup(&mut self) -> Result<(), String> {
+        let mut termios = match Termios::from_fd(self.input_fd) {
+            Ok(t)  => t,
+            Err(_) => return Err("Could not read the terminal attributes".to_string())
+        };
+        let saved = termios;
+
+        termios.c_lflag &= !(ECHO | ICANON | ISIG);
+        termios.c_cc[VMIN] = 1;
+        termios.c_cc[VTIME] = 0;
+
+        if tcsetattr(self.input_fd, TCSANOW, &termios).is_err() {
+            return Err("Could not set the terminal attributes".to_string());
+        }
+        self.saved = Some(saved);
+        Ok(())
+    }
+
+
+    fn restore(&mut self) {
+        if let Some(termios) = self.saved.take() {
+            let _ = tcsetattr(self.input_fd, TCSANOW, &termios);
+        }
+    }
+
+
+    fn size(&self) -> Vec2 {
+        term_size(self.output_fd())
+    }
+
+
+    fn writer(&self) -> io::Result<Box<dyn Write + Send>> {
+        match &self.output {
+            Output::Stdout => Ok(Box::new(stdout())),
+            Output::Tty(file) => Ok(Box::new(file.try_clone()?))
+        }
+    }
+}
+
+
+/// Any Write sink (a file, a pipe, a TCP stream...) with a fixed size, the terminal settings are not changed.
+pub struct WriterBackend {
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+    size: Vec2
+}
+
+
+impl WriterBackend {
+
+    /// Writes the frames to `writer`, `size` is the size of the screen in cells (columns, rows).
+    pub fn new<W, A>(writer: W, size: A) -> WriterBackend
+        where W: Write + Send + 'static, A: AsRef<Vec2>
+    {
+        WriterBackend {
+            writer: Arc::new(Mutex::new(Box::new(writer))),
+            size: *size.as_ref()
+        }
+    }
+}
+
+
+impl TerminalBackend for WriterBackend {
+
+    fn size(&self) -> Vec2 {
+        self.size
+    }
+
+
+    fn writer(&self) -> io::Result<Box<dyn Write + Send>> {
+        Ok(Box::new(SharedWriter(Arc::clone(&self.writer))))
+    }
+}
+
+
+/// Handle to a Write sink shared by the Renderer and its rendering thread.
+struct SharedWriter(Arc<Mutex<Box<dyn Write + Send>>>);
+
+
+impl Write for SharedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.lock().unwrap().flush()
+    }
+}
+
+
+/// Returns the size in cells of the terminal `fd`.
+pub(crate) fn term_size(fd: RawFd) -> Vec2 {
+    unsafe {
+        let mut size: TermSize = mem::zeroed();
+        libc::ioctl(fd, libc::TIOCGWINSZ, &mut size as *mut _);
+        vec2!(size.col as i32, size.row as i32)
+    }
+}
+
+
+struct TermSize {
+    row: libc::c_ushort,
+    col: libc::c_ushort,
+    _x : libc::c_ushort,
+    _y : libc::c_ushort
+}
@@ -0,0 +1,102 @@
+/*
+
+    MIT License
+    
+    Copyright (c) 2022 Siandfrance
+    
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+    
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+    
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+
+*/
+
+
+use crate::math::Vec2;
+use crate::img::{Image, Color};
+
+
+/// 4x4 Bayer matrix
+const BAYER: [[u8; 4]; 4] = [
+    [ 0,  8,  2, 10],
+    [12,  4, 14,  6],
+    [ 3, 11,  1,  9],
+    [15,  7, 13,  5]
+];
+
+
+/// Adds `(dr, dg, db)` to `c`, clamping the components.
+fn offset(c: Color, dr: f32, dg: f32, db: f32) -> Color {
+    let add = |v: u8, d: f32| (v as f32 + d).round().clamp(0., 255.) as u8;
+    Color::rgb(add(c.r, dr), add(c.g, dg), add(c.b, db))
+}
+
+
+/// Ordered dithering: each pixel is offset by a threshold of the Bayer matrix (of amplitude `spread`) before
+/// being quantized. The pattern does not depend on the neighbour pixels, so unchanged pixels stay unchanged.
+pub(crate) fn ordered<F>(img: &Image, spread: f32, quantize: F) -> Image
+    where F: Fn(Color) -> Color
+{
+    let size = img.size();
+    let mut result = Image::new(size.x as usize, size.y as usize);
+    for y in 0..size.y {
+        for x in 0..size.x {
+            let t = (BAYER[(y % 4) as usize][(x % 4) as usize] as f32 + 0.5) / 16. - 0.5;
+            let d = t * spread;
+            result.put(vec2!(x, y), quantize(offset(img.at(vec2!(x, y)), d, d, d)));
+        }
+    }
+    result
+}
+
+
+/// Floyd–Steinberg dithering: the quantization error of each pixel is spread on its neighbours.
+pub(crate) fn floyd_steinberg<F>(img: &Image, quantize: F) -> Image
+    where F: Fn(Color) -> Color
+{
+    let size = img.size();
+    let w = size.x as usize;
+    let mut result = Image::new(w, size.y as usize);
+
+    // error carried to the current and next rows
+    let mut errors = vec![[0f32; 3]; w + 2];
+    let mut next = vec![[0f32; 3]; w + 2];
+
+    for y in 0..size.y {
+        for x in 0..size.x {
+            let i = x as usize + 1;
+            let [er, eg, eb] = errors[i];
+            let wanted = offset(img.at(vec2!(x, y)), er, eg, eb);
+            let shown = quantize(wanted);
+            result.put(vec2!(x, y), shown);
+
+            let err = [
+                wanted.r as f32 - shown.r as f32,
+                wanted.g as f32 - shown.g as f32,
+                wanted.b as f32 - shown.b as f32
+            ];
+            for k in 0..3 {
+                errors[i + 1][k] += err[k] * 7. / 16.;
+                next[i - 1][k] += err[k] * 3. / 16.;
+                next[i][k] += err[k] * 5. / 16.;
+                next[i + 1][k] += err[k] / 16.;
+            }
+        }
+        std::mem::swap(&mut errors, &mut next);
+        next.iter_mut().for_each(|e| *e = [0.; 3]);
+    }
+    result
+}
@@ -0,0 +1,273 @@
+/*
+
+    MIT License
+    
+    Copyright (c) 2022 Siandfrance
+    
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+    
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+    
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+
+*/
+
+use crate::math::Vec2;
+use crate::img::{Image, Color};
+
+
+/// Effects that can be applied on a whole image (see `Image::apply_filter`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFilter {
+    /// Each pixel is the average of the square of radius `r` around it.
+    BoxBlur(i32),
+    /// Blur with a gaussian of standard deviation `sigma` (in pixels).
+    GaussianBlur(f32),
+    /// Enhances the edges, `amount` is the strength of the effect (1 is a good start).
+    Sharpen(f32),
+    /// Replaces each color by its opposite.
+    Invert,
+    /// Replaces each color by the gray of same luminance.
+    Grayscale
+}
+
+
+/// How the pixels out of the image are read by a convolution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Edge {
+    /// The closest pixel of the image is used.
+    #[default]
+    Clamp,
+    /// The image is repeated.
+    Wrap,
+    /// The image is mirrored on its edges.
+    Mirror,
+    /// Pixels out of the image are black.
+    Zero
+}
+
+
+impl Edge {
+
+    /// Returns the coordinate to read instead of `x` on an axis of length `len`, `None` if the pixel is black.
+    fn coord(&self, x: i32, len: i32) -> Option<i32> {
+        if (0..len).contains(&x) {
+            return Some(x);
+        }
+        match self {
+            Edge::Clamp => Some(x.clamp(0, len - 1)),
+            Edge::Wrap => Some(x.rem_euclid(len)),
+            Edge::Mirror => {
+                let x = x.rem_euclid(2 * len);
+                Some(if x < len { x } else { 2 * len - 1 - x })
+            }
+            Edge::Zero => None
+        }
+    }
+}
+
+
+/// Square convolution kernel of odd size, used with `Image::convolve`.
+/// 
+/// Each pixel becomes the sum of its neighbours multiplied by the weights, plus a bias.
+/// 
+/// # Usage
+/// 
+/// ```
+/// let kernel = Kernel::new(3, vec![
+///     -1., -1., -1.,
+///     -1.,  8., -1.,
+///     -1., -1., -1.
+/// ])?.with_edge(Edge::Mirror);
+/// img.convolve(&kernel);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Kernel {
+    size: usize,
+    weights: Vec<f32>,
+    bias: f32,
+    edge: Edge
+}
+
+
+impl Kernel {
+
+    /// Creates a kernel of `size` by `size` weights, given row by row. `size` must be odd.
+    pub fn new(size: usize, weights: Vec<f32>) -> Result<Self, String> {
+        if size.is_multiple_of(2) {
+            return Err(format!("the size of a kernel must be odd, got {}", size));
+        }
+        if weights.len() != size * size {
+            return Err(format!("expected {} weights, got {}", size * size, weights.len()));
+        }
+        Ok(Self { size, weights, bias: 0., edge: Edge::Clamp })
+    }
+
+
+    /// Returns the kernel with `bias` added to the result of each pixel (eg. 128 to center the result on gray).
+    pub fn with_bias(mut self, bias: f32) -> Self {
+        self.bias = bias;
+        self
+    }
+
+
+    /// Returns the kernel reading the pixels out of the image according to `edge`.
+    pub fn with_edge(mut self, edge: Edge) -> Self {
+        self.edge = edge;
+        self
+    }
+
+
+    /// Returns the size of the kernel.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+
+    /// Kernel that gives a relief effect, lit from the top left.
+    pub fn emboss() -> Self {
+        Self::new(3, vec![
+            -2., -1., 0.,
+            -1.,  1., 1.,
+             0.,  1., 2.
+        ]).unwrap()
+    }
+
+
+    /// Kernel that only keeps the edges (Laplacian), the rest of the image becomes black.
+    pub fn edge_detect() -> Self {
+        Self::new(3, vec![
+            -1., -1., -1.,
+            -1.,  8., -1.,
+            -1., -1., -1.
+        ]).unwrap()
+    }
+
+
+    /// Kernel that sharpens the image.
+    pub fn sharpen() -> Self {
+        Self::new(3, vec![
+             0., -1.,  0.,
+            -1.,  5., -1.,
+             0., -1.,  0.
+        ]).unwrap()
+    }
+}
+
+
+/// Convolves `img` with `kernel`.
+pub(crate) fn convolve(img: &Image, kernel: &Kernel) -> Image {
+    let size = img.size();
+    let r = (kernel.size / 2) as i32;
+    let mut result = Image::new(size.x as usize, size.y as usize);
+    for y in 0..size.y {
+        for x in 0..size.x {
+            let mut sum = [kernel.bias; 3];
+            for (k, w) in kernel.weights.iter().enumerate() {
+                let dx = (k % kernel.size) as i32 - r;
+                let dy = (k / kernel.size) as i32 - r;
+                let (Some(sx), Some(sy)) = (kernel.edge.coord(x + dx, size.x), kernel.edge.coord(y + dy, size.y)) else {
+                    continue
+                };
+                let c = img.at(vec2!(sx, sy));
+                sum[0] += c.r as f32 * w;
+                sum[1] += c.g as f32 * w;
+                sum[2] += c.b as f32 * w;
+            }
+            result.put(vec2!(x, y), to_color(sum));
+        }
+    }
+    result
+}
+
+
+/// Rounds and clamps the channels of `sum`.
+fn to_color(sum: [f32; 3]) -> Color {
+    let channel = |v: f32| v.round().clamp(0., 255.) as u8;
+    Color::rgb(channel(sum[0]), channel(sum[1]), channel(sum[2]))
+}
+
+
+/// Applies `filter` on `img`.
+pub(crate) fn apply(img: &Image, filter: ImageFilter) -> Image {
+    match filter {
+        ImageFilter::BoxBlur(r) => {
+            let r = r.max(0) as usize;
+            separable(img, &vec![1. / (2 * r + 1) as f32; 2 * r + 1])
+        }
+        ImageFilter::GaussianBlur(sigma) => separable(img, &gaussian(sigma)),
+        ImageFilter::Sharpen(amount) => {
+            // unsharp mask: the details (image - blurred image) are added back to the image
+            let blurred = separable(img, &gaussian(1.));
+            let size = img.size();
+            let mut result = Image::new(size.x as usize, size.y as usize);
+            for y in 0..size.y {
+                for x in 0..size.x {
+                    let (c, b) = (img.at(vec2!(x, y)), blurred.at(vec2!(x, y)));
+                    let sharpen = |c: u8, b: u8| (c as f32 + (c as f32 - b as f32) * amount).round().clamp(0., 255.) as u8;
+                    result.put(vec2!(x, y), Color::rgb(sharpen(c.r, b.r), sharpen(c.g, b.g), sharpen(c.b, b.b)));
+                }
+            }
+            result
+        }
+        ImageFilter::Invert => img.map(|c| Color::rgb(255 - c.r, 255 - c.g, 255 - c.b)),
+        ImageFilter::Grayscale => img.map(|c| {
+            let l = c.luminance();
+            Color::rgb(l, l, l)
+        })
+    }
+}
+
+
+/// Returns the normalized weights of a gaussian of standard deviation `sigma`, up to 3 `sigma` from the center.
+fn gaussian(sigma: f32) -> Vec<f32> {
+    if sigma <= 0. {
+        return vec![1.];
+    }
+    let r = (3. * sigma).ceil() as i32;
+    let weights: Vec<f32> = (-r..=r).map(|x| (-(x * x) as f32 / (2. * sigma * sigma)).exp()).collect();
+    let sum: f32 = weights.iter().sum();
+    weights.iter().map(|w| w / sum).collect()
+}
+
+
+/// Convolves `img` with the centered 1D kernel `weights` horizontally, then vertically.
+/// Pixels out of the image take the value of the closest edge pixel.
+fn separable(img: &Image, weights: &[f32]) -> Image {
+    let horizontal = pass(img, weights, vec2!(1, 0));
+    pass(&horizontal, weights, vec2!(0, 1))
+}
+
+
+/// Convolves `img` with the centered 1D kernel `weights` in the direction `dir`.
+fn pass(img: &Image, weights: &[f32], dir: Vec2) -> Image {
+    let size = img.size();
+    let r = (weights.len() / 2) as i32;
+    let mut result = Image::new(size.x as usize, size.y as usize);
+    for y in 0..size.y {
+        for x in 0..size.x {
+            let mut sum = [0f32; 3];
+            for (k, w) in weights.iter().enumerate() {
+                let d = k as i32 - r;
+                let c = img.at(vec2!((x + d * dir.x).clamp(0, size.x - 1), (y + d * dir.y).clamp(0, size.y - 1)));
+                sum[0] += c.r as f32 * w;
+                sum[1] += c.g as f32 * w;
+                sum[2] += c.b as f32 * w;
+            }
+            result.put(vec2!(x, y), to_color(sum));
+        }
+    }
+    result
+}
@@ -0,0 +1,202 @@
+/*
+
+    MIT License
+    
+    Copyright (c) 2022 Siandfrance
+    
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+    
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+    
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+
+*/
+
+
+use crate::math::Vec2;
+use crate::rds::{Frame, Renderer, RendererGuard};
+use crate::input::{Input, InputEvent};
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+
+/// Settings of the game loop, see `run`.
+/// 
+/// # Usage
+/// 
+/// ```
+/// let config = LoopConfig { fps: 30, mouse: true, ..LoopConfig::default() };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopConfig {
+    /// Number of updates per second, the time step of the game is `1 / fps` seconds
+    pub fps: u32,
+    /// Maximum number of updates done to catch up after a slow frame, the game slows down beyond it
+    pub max_steps: u32,
+    /// Enables the mouse events during the loop
+    pub mouse: bool
+}
+
+
+impl Default for LoopConfig {
+    fn default() -> Self {
+        Self { fps: 60, max_steps: 5, mouse: false }
+    }
+}
+
+
+/// State of the game loop given to the update and draw function, see `run`.
+pub struct Context<'a> {
+    /// Time step of the update, in seconds (always `1 / fps`)
+    pub dt: f32,
+    /// Time simulated since the start of the loop, in seconds
+    pub time: f32,
+    /// Size of the screen
+    pub size: Vec2,
+    /// Input events received since the previous update
+    pub events: &'a [InputEvent],
+    /// Frame to draw on
+    pub frame: &'a mut Frame,
+    /// False when the update only catches up after a slow frame: what is drawn is not shown
+    pub shown: bool,
+    quit: bool
+}
+
+
+impl Context<'_> {
+
+    /// Stops the loop after this update.
+    pub fn quit(&mut self) {
+        self.quit = true;
+    }
+}
+
+
+/// Fixed time step: counts the updates to do for the time that passed.
+pub(crate) struct Timestep {
+    step: Duration,
+    max_steps: u32,
+    lag: Duration
+}
+
+
+impl Timestep {
+
+    /// Creates a time step of `1 / fps` seconds. The first update is due immediately.
+    pub(crate) fn new(fps: u32, max_steps: u32) -> Self {
+        let step = Duration::from_secs(1) / fps.max(1);
+        Self { step, max_steps: max_steps.max(1), lag: step }
+    }
+
+
+    /// Adds `elapsed` to the time to simulate, returns the number of updates to do now (at most `max_steps`,
+    /// the remaining time is dropped) and the time to wait before the next update if there is none.
+    pub(crate) fn advance(&mut self, elapsed: Duration) -> (u32, Duration) {
+        self.lag += elapsed;
+        if self.lag < self.step {
+            return (0, self.step - self.lag);
+        }
+        let steps = (self.lag.as_nanos() / self.step.as_nanos()) as u32;
+        self.lag -= self.step * steps;
+        (steps.min(self.max_steps), Duration::ZERO)
+    }
+}
+
+
+/// Runs a game loop on the Renderer singleton until `f` calls `Context::quit` or a quit event is received
+/// (eg. Ctrl+C). The terminal is restored when the loop ends, even if `f` panics.
+/// 
+/// `f` is called `config.fps` times per second with the input events received since the previous call, it updates
+/// the game by `ctx.dt` seconds and draws on `ctx.frame`. If a frame took too long, `f` is called several times in a
+/// row to catch up and only the last frame is shown.
+/// 
+/// Returns an error if the frames could not be drawn.
+/// 
+/// # Usage
+/// 
+/// ```
+/// game::run(LoopConfig::default(), |ctx| {
+///     for event in ctx.events {
+///         if let InputEvent::Key(KeyEvent { key: Key::Char('q'), .. }) = event {
+///             ctx.quit();
+///         }
+///     }
+///     world.update(ctx.dt);
+///     world.draw(ctx.frame);
+/// })?;
+/// ```
+pub fn run<F>(config: LoopConfig, mut f: F) -> Result<(), String>
+    where F: FnMut(&mut Context)
+{
+    let mut rdr = RendererGuard::new();
+    if config.mouse {
+        Input::enable_mouse();
+    }
+    let result = run_loop(&mut rdr, config, &mut f);
+    if config.mouse {
+        Input::disable_mouse();
+    }
+    result
+}
+
+
+/// Loop of `run`, returns when the game quits.
+fn run_loop<F>(rdr: &mut Renderer, config: LoopConfig, f: &mut F) -> Result<(), String>
+    where F: FnMut(&mut Context)
+{
+    let input = Input::get();
+    let mut timestep = Timestep::new(config.fps, config.max_steps);
+    let dt = 1. / config.fps.max(1) as f32;
+    let mut time = 0.;
+    let mut last = Instant::now();
+
+    loop {
+        let now = Instant::now();
+        let (steps, wait) = timestep.advance(now - last);
+        last = now;
+        if steps == 0 {
+            thread::sleep(wait);
+            continue;
+        }
+
+        let mut events = Vec::new();
+        let mut quit = false;
+        while let Some(event) = input.get_event() {
+            quit |= event == InputEvent::Quit;
+            events.push(event);
+        }
+
+        let size = rdr.size();
+        for k in 0..steps {
+            let shown = k == steps - 1;
+            let events = if k == 0 { &events[..] } else { &[] };
+            let mut update = |frame: &mut Frame| {
+                let mut ctx = Context { dt, time, size, events, frame, shown, quit: false };
+                f(&mut ctx);
+                ctx.quit
+            };
+            quit |= if shown {
+                rdr.with_frame(update).map_err(|e| format!("{}", e))?
+            } else {
+                update(&mut Frame::new())
+            };
+            time += dt;
+        }
+        if quit {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,368 @@
+/*
+
+    MIT License
+    
+    Copyright (c) 2022 Siandfrance
+    
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+    
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+    
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+
+*/
+
+
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::hash::{Hash, Hasher};
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::input::InputEvent;
+use crate::rds::Renderer;
+
+
+/// Interval at which `/dev/input` is scanned for new controllers
+const SCAN_INTERVAL: Duration = Duration::from_secs(1);
+
+
+const EV_KEY: u16 = 0x01;
+const EV_ABS: u16 = 0x03;
+const BTN_GAMEPAD: u16 = 0x130;
+const ABS_CNT: usize = 0x40;
+
+
+/// Button of a game controller, named after its position on the controller.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum GamepadButton {
+    /// A on Xbox controllers, Cross on PlayStation controllers
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    /// Digital trigger, see `GamepadAxis::LeftTrigger` for the analog value
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    Mode,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight
+}
+
+
+impl GamepadButton {
+
+    /// Returns the button of an evdev key code.
+    fn from_code(code: u16) -> Option<GamepadButton> {
+        Some(match code {
+            0x130 => GamepadButton::South,
+            0x131 => GamepadButton::East,
+            0x133 => GamepadButton::North,
+            0x134 => GamepadButton::West,
+            0x136 => GamepadButton::LeftBumper,
+            0x137 => GamepadButton::RightBumper,
+            0x138 => GamepadButton::LeftTrigger,
+            0x139 => GamepadButton::RightTrigger,
+            0x13a => GamepadButton::Select,
+            0x13b => GamepadButton::Start,
+            0x13c => GamepadButton::Mode,
+            0x13d => GamepadButton::LeftStick,
+            0x13e => GamepadButton::RightStick,
+            0x220 => GamepadButton::DPadUp,
+            0x221 => GamepadButton::DPadDown,
+            0x222 => GamepadButton::DPadLeft,
+            0x223 => GamepadButton::DPadRight,
+            _ => return None
+        })
+    }
+}
+
+
+/// Analog axis of a game controller.
+/// Sticks and the d-pad range from -1 (left or up) to 1, triggers from 0 to 1.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum GamepadAxis {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+    LeftTrigger,
+    RightTrigger,
+    DPadX,
+    DPadY
+}
+
+
+impl GamepadAxis {
+
+    /// Returns the axis of an evdev absolute axis code.
+    fn from_code(code: u16) -> Option<GamepadAxis> {
+        Some(match code {
+            0x00 => GamepadAxis::LeftX,
+            0x01 => GamepadAxis::LeftY,
+            0x02 => GamepadAxis::LeftTrigger,
+            0x03 => GamepadAxis::RightX,
+            0x04 => GamepadAxis::RightY,
+            0x05 => GamepadAxis::RightTrigger,
+            0x10 => GamepadAxis::DPadX,
+            0x11 => GamepadAxis::DPadY,
+            _ => return None
+        })
+    }
+
+
+    /// Returns true if the axis ranges from 0 to 1.
+    pub fn is_trigger(&self) -> bool {
+        matches!(self, GamepadAxis::LeftTrigger | GamepadAxis::RightTrigger)
+    }
+}
+
+
+/// Event of a game controller, with the id of the controller.
+/// Ids are given in connection order, and reused once a controller is disconnected (like player numbers).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GamepadEvent {
+    Connected(usize),
+    Disconnected(usize),
+    ButtonPressed(usize, GamepadButton),
+    ButtonReleased(usize, GamepadButton),
+    /// The axis moved to the given value
+    Axis(usize, GamepadAxis, f32)
+}
+
+
+impl GamepadEvent {
+
+    /// Returns the id of the controller.
+    pub fn id(&self) -> usize {
+        match *self {
+            GamepadEvent::Connected(id) | GamepadEvent::Disconnected(id) |
+            GamepadEvent::ButtonPressed(id, _) | GamepadEvent::ButtonReleased(id, _) |
+            GamepadEvent::Axis(id, _, _) => id
+        }
+    }
+}
+
+
+// axis values are never NaN
+impl Eq for GamepadEvent {}
+
+
+impl Hash for GamepadEvent {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match *self {
+            GamepadEvent::Connected(id) | GamepadEvent::Disconnected(id) => id.hash(state),
+            GamepadEvent::ButtonPressed(id, b) | GamepadEvent::ButtonReleased(id, b) => (id, b).hash(state),
+            GamepadEvent::Axis(id, a, v) => (id, a, v.to_bits()).hash(state)
+        }
+    }
+}
+
+
+/// State of a controller, turns evdev events into `GamepadEvent`s
+pub(crate) struct Device {
+    id: usize,
+    /// Range and dead zone of each axis
+    abs: [libc::input_absinfo; ABS_CNT],
+    values: [f32; ABS_CNT]
+}
+
+
+impl Device {
+
+    pub(crate) fn new(id: usize, abs: [libc::input_absinfo; ABS_CNT]) -> Self {
+        Self {
+            id,
+            abs,
+            values: [0.; ABS_CNT]
+        }
+    }
+
+
+    /// Returns the value of `axis` from the raw `value`, with the dead zone removed.
+    fn normalize(&self, axis: GamepadAxis, code: usize, value: i32) -> f32 {
+        let info = &self.abs[code];
+        let range = (info.maximum - info.minimum) as f32;
+        if range <= 0. {
+            return 0.;
+        }
+        let t = (value - info.minimum) as f32 / range;
+        if axis.is_trigger() {
+            return if value - info.minimum <= info.flat { 0. } else { t.clamp(0., 1.) };
+        }
+        let center = info.minimum + (info.maximum - info.minimum) / 2;
+        if (value - center).abs() <= info.flat {
+            0.
+        } else {
+            (t * 2. - 1.).clamp(-1., 1.)
+        }
+    }
+
+
+    /// Returns the events of an evdev event.
+    /// The d-pad of controllers that report it as an axis also sends button events.
+    pub(crate) fn handle(&mut self, kind: u16, code: u16, value: i32) -> Vec<GamepadEvent> {
+        let id = self.id;
+        match kind {
+            EV_KEY => match GamepadButton::from_code(code) {
+                Some(b) if value == 1 => vec![GamepadEvent::ButtonPressed(id, b)],
+                Some(b) if value == 0 => vec![GamepadEvent::ButtonReleased(id, b)],
+                // value 2 is an autorepeat
+                _ => Vec::new()
+            }
+            EV_ABS => {
+                let axis = match GamepadAxis::from_code(code) {
+                    Some(axis) => axis,
+                    None => return Vec::new()
+                };
+                let v = self.normalize(axis, code as usize, value);
+                let prev = std::mem::replace(&mut self.values[code as usize], v);
+                if v == prev {
+                    return Vec::new();
+                }
+
+                let mut events = vec![GamepadEvent::Axis(id, axis, v)];
+                let (neg, pos) = match axis {
+                    GamepadAxis::DPadX => (GamepadButton::DPadLeft, GamepadButton::DPadRight),
+                    GamepadAxis::DPadY => (GamepadButton::DPadUp, GamepadButton::DPadDown),
+                    _ => return events
+                };
+                let dir = |v: f32| if v < 0. { Some(neg) } else if v > 0. { Some(pos) } else { None };
+                if let Some(b) = dir(prev) {
+                    events.push(GamepadEvent::ButtonReleased(id, b));
+                }
+                if let Some(b) = dir(v) {
+                    events.push(GamepadEvent::ButtonPressed(id, b));
+                }
+                events
+            }
+            _ => Vec::new()
+        }
+    }
+}
+
+
+/// Linux `_IOC(_IOC_READ, 'E', nr, size)`
+const fn eviocg(nr: u32, size: usize) -> libc::c_ulong {
+    ((2 << 30) | ((size as u32) << 16) | ((b'E' as u32) << 8) | nr) as libc::c_ulong
+}
+
+
+/// Opens `path` if it is a game controller, returns the ranges of its axes.
+fn open_gamepad(path: &PathBuf) -> Option<(File, [libc::input_absinfo; ABS_CNT])> {
+    let file = File::open(path).ok()?;
+    let fd = file.as_raw_fd();
+
+    let mut keys = [0u8; 0x300 / 8];
+    // EVIOCGBIT(EV_KEY)
+    if unsafe { libc::ioctl(fd, eviocg(0x20 + EV_KEY as u32, keys.len()) as _, keys.as_mut_ptr()) } < 0 {
+        return None;
+    }
+    if keys[BTN_GAMEPAD as usize / 8] & (1 << (BTN_GAMEPAD % 8)) == 0 {
+        return None;
+    }
+
+    let mut abs = [libc::input_absinfo { value: 0, minimum: 0, maximum: 0, fuzz: 0, flat: 0, resolution: 0 }; ABS_CNT];
+    for (code, info) in abs.iter_mut().enumerate() {
+        if GamepadAxis::from_code(code as u16).is_some() {
+            // EVIOCGABS(code), missing axes keep an empty range
+            unsafe { libc::ioctl(fd, eviocg(0x40 + code as u32, std::mem::size_of::<libc::input_absinfo>()) as _, info as *mut _) };
+        }
+    }
+    Some((file, abs))
+}
+
+
+/// Reads the events of a controller until it is disconnected.
+fn read_gamepad(mut file: File, mut device: Device, send: &mpsc::Sender<InputEvent>) {
+    let size = std::mem::size_of::<libc::input_event>();
+    let mut buf = vec![0u8; size * 64];
+    while let Ok(n) = file.read(&mut buf) {
+        if n == 0 {
+            break;
+        }
+        for chunk in buf[..n].chunks_exact(size) {
+            let event = unsafe { std::ptr::read_unaligned(chunk.as_ptr() as *const libc::input_event) };
+            for evt in device.handle(event.type_, event.code, event.value) {
+                if send.send(InputEvent::Gamepad(evt)).is_err() {
+                    return;
+                }
+                Renderer::request_redraw();
+            }
+        }
+    }
+}
+
+
+/// Starts the thread that looks for controllers and sends their events, called when the Input singleton is created.
+pub(crate) fn spawn_server(send: mpsc::Sender<InputEvent>) {
+    // path of the controller of each id
+    let slots: Arc<Mutex<Vec<Option<PathBuf>>>> = Arc::new(Mutex::new(Vec::new()));
+
+    thread::spawn(move || loop {
+        let mut paths: Vec<PathBuf> = fs::read_dir("/dev/input").into_iter().flatten()
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.file_name().is_some_and(|n| n.to_string_lossy().starts_with("event")))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            if slots.lock().unwrap().contains(&Some(path.clone())) {
+                continue;
+            }
+            let (file, abs) = match open_gamepad(&path) {
+                Some(g) => g,
+                None => continue
+            };
+
+            let id = {
+                let mut slots = slots.lock().unwrap();
+                let id = slots.iter().position(|s| s.is_none()).unwrap_or(slots.len());
+                if id == slots.len() {
+                    slots.push(None);
+                }
+                slots[id] = Some(path);
+                id
+            };
+            if send.send(InputEvent::Gamepad(GamepadEvent::Connected(id))).is_err() {
+                return;
+            }
+
+            let send = send.clone();
+            let slots = slots.clone();
+            thread::spawn(move || {
+                read_gamepad(file, Device::new(id, abs), &send);
+                slots.lock().unwrap()[id] = None;
+                let _ = send.send(InputEvent::Gamepad(GamepadEvent::Disconnected(id)));
+                Renderer::request_redraw();
+            });
+        }
+        thread::sleep(SCAN_INTERVAL);
+    });
+}
@@ -0,0 +1,181 @@
+/*
+
+    MIT License
+
+    Copyright (c) 2022 Siandfrance
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+
+*/
+
+// Glyph selection for the render modes that put more than two pixels in a cell.
+
+
+use crate::img::Color;
+
+
+/// Quadrant characters indexed by their mask (pixels of a 2x2 cell, row by row)
+const QUADRANTS: [char; 16] = [
+    ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛',
+    '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█'
+];
+
+
+/// Bit of the braille dot of each pixel of a 2x4 cell (pixels are given row by row)
+const BRAILLE_DOTS: [u8; 8] = [0x01, 0x08, 0x02, 0x10, 0x04, 0x20, 0x40, 0x80];
+
+
+fn distance(c1: Color, c2: Color) -> i32 {
+    let dr = c1.r as i32 - c2.r as i32;
+    let dg = c1.g as i32 - c2.g as i32;
+    let db = c1.b as i32 - c2.b as i32;
+    dr * dr + dg * dg + db * db
+}
+
+
+fn average<I>(colors: I) -> Option<Color>
+    where I: Iterator<Item = Color>
+{
+    let mut sum = [0u32; 3];
+    let mut n = 0;
+    for c in colors {
+        sum[0] += c.r as u32;
+        sum[1] += c.g as u32;
+        sum[2] += c.b as u32;
+        n += 1;
+    }
+    let r = sum[0].checked_div(n)?;
+    Some(Color::rgb(r as u8, (sum[1] / n) as u8, (sum[2] / n) as u8))
+}
+
+
+/// Splits the pixels of a cell (at most 8) in two groups of close colors.
+/// 
+/// Returns the mask of the pixels of the first group (bit `i` is set for the pixel `i`)
+/// and the average color of each group.
+pub(crate) fn split_colors(pixels: &[Color]) -> (u8, Color, Color) {
+    // the two most different pixels are the seeds of the groups
+    let mut seeds = (0, 0);
+    let mut max_dist = 0;
+    for i in 0..pixels.len() {
+        for j in (i + 1)..pixels.len() {
+            let d = distance(pixels[i], pixels[j]);
+            if d > max_dist {
+                max_dist = d;
+                seeds = (i, j);
+            }
+        }
+    }
+
+    if max_dist == 0 {
+        return (0, pixels[0], pixels[0]);
+    }
+
+    let (fg, bg) = (pixels[seeds.0], pixels[seeds.1]);
+    let mut mask = 0u8;
+    for (i, c) in pixels.iter().enumerate() {
+        if distance(*c, fg) < distance(*c, bg) {
+            mask |= 1 << i;
+        }
+    }
+
+    let group = |set: bool| pixels.iter()
+        .enumerate()
+        .filter(move |(i, _)| (mask & (1 << i) != 0) == set)
+        .map(|(_, c)| *c);
+
+    (mask, average(group(true)).unwrap_or(fg), average(group(false)).unwrap_or(bg))
+}
+
+
+/// Returns the braille character with the dots of `mask` (pixels of a 2x4 cell, row by row).
+pub(crate) fn braille(mask: u8) -> char {
+    let mut bits = 0u32;
+    for (i, dot) in BRAILLE_DOTS.iter().enumerate() {
+        if mask & (1 << i) != 0 {
+            bits |= *dot as u32;
+        }
+    }
+    char::from_u32(0x2800 + bits).unwrap()
+}
+
+
+/// Returns the quadrant character with the pixels of `mask` set (pixels of a 2x2 cell, row by row).
+pub(crate) fn quadrant(mask: u8) -> char {
+    QUADRANTS[(mask & 0xf) as usize]
+}
+
+
+/// Returns the sextant character with the pixels of `mask` set (pixels of a 2x3 cell, row by row).
+pub(crate) fn sextant(mask: u8) -> char {
+    // the sextant block skips the characters that already exist as half blocks
+    match mask & 0x3f {
+        0 => ' ',
+        21 => '▌',
+        42 => '▐',
+        63 => '█',
+        m => {
+            let m = m as u32;
+            char::from_u32(0x1fb00 + m - 1 - (m > 21) as u32 - (m > 42) as u32).unwrap()
+        }
+    }
+}
+
+
+/// Returns true if `ch` takes two columns in most terminals (CJK characters, emoji).
+pub(crate) fn is_wide(ch: char) -> bool {
+    matches!(ch as u32,
+        0x1100..=0x115f | 0x2e80..=0x303e | 0x3041..=0xa4cf | 0xac00..=0xd7a3 | 0xf900..=0xfaff |
+        0xfe30..=0xfe4f | 0xff00..=0xff60 | 0xffe0..=0xffe6 | 0x1f300..=0x1f64f | 0x1f900..=0x1f9ff |
+        0x20000..=0x3fffd)
+}
+
+
+/// Shade characters with the part of the cell drawn with the foreground color
+const SHADES: [(char, f32); 3] = [('░', 0.25), ('▒', 0.5), ('▓', 0.75)];
+
+
+/// Finds the shade character (░, ▒, ▓) and the colors displayable with `quantize` that approximate the
+/// flat color `target` better than its closest displayable color.
+/// 
+/// Returns the character, its foreground and background colors, or None if a plain cell is closer.
+pub(crate) fn shade<F>(target: Color, quantize: F) -> Option<(char, Color, Color)>
+    where F: Fn(Color) -> Color
+{
+    let bg = quantize(target);
+    let mut best = distance(target, bg);
+    let mut found = None;
+
+    // the other color is looked for beyond the target, away from its closest color
+    for k in [2, 3, 4, 6] {
+        let beyond = |t: u8, q: u8| (t as i32 + (t as i32 - q as i32) * (k - 1)).clamp(0, 255) as u8;
+        let fg = quantize(Color::rgb(beyond(target.r, bg.r), beyond(target.g, bg.g), beyond(target.b, bg.b)));
+        if fg == bg {
+            continue;
+        }
+        for (ch, part) in SHADES {
+            let d = distance(target, bg.lerp(fg, part));
+            if d < best {
+                best = d;
+                found = Some((ch, fg, bg));
+            }
+        }
+    }
+    found
+}
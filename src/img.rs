@@ -25,15 +25,28 @@
 */
 
 
-use crate::math::Vec2;
+use crate::math::{Vec2, Rect, Transform2};
+use crate::text;
+use crate::filter::{self, ImageFilter, Kernel};
+use crate::sprite::{SpriteSheet, FrameKey};
 
-use std::ops::{Index, IndexMut};
+use crate::rds::{Renderer, Dithering};
+use crate::dither;
+use crate::noise::Noise;
+use crate::raster::{self, DepthBuffer, Vertex, Shading};
+
+use std::ops::{Index, IndexMut, Range};
 use std::fmt;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
 
 
 use image::io::Reader as ImageReader;
-use image::{Pixel, RgbImage};
+use image::{DynamicImage, RgbImage, RgbaImage, ImageEncoder, ColorType};
+use image::codecs::png::PngEncoder;
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize, Serializer, Deserializer};
 
 
 /// RGB Color struct.
@@ -45,6 +58,7 @@ use image::{Pixel, RgbImage};
 /// Color.b
 /// ```
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -188,6 +202,7 @@ impl Color {
     pub const TEAL               : Color = Color::hex(0x008080);
     pub const THISTLE            : Color = Color::hex(0xd8bfd8);
     pub const TOMATO             : Color = Color::hex(0xff6246);
+    #[deprecated(note = "this is white: use an `Image<Rgba>` with `Rgba::TRANSPARENT`, or `Some(Color::WHITE)` as the alpha color of a blit")]
     pub const TRANSPARENT        : Color = Color::hex(0xffffff);
     pub const TURQUOISE          : Color = Color::hex(0x3fdfcf);
     pub const VIOLET             : Color = Color::hex(0xed82ed);
@@ -220,9 +235,194 @@ impl Color {
             b: ((h & 0x000000FF) / 0x00000001) as u8
         }
     }
+
+
+    /// Creates a color from its hue (in degrees), saturation and value (between 0 and 1).
+    pub fn hsv(h: f32, s: f32, v: f32) -> Self {
+        let s = s.clamp(0., 1.);
+        let v = v.clamp(0., 1.);
+        let c = v * s;
+        Color::from_hue(h, c, v - c)
+    }
+
+
+    /// Creates a color from its hue (in degrees), saturation and lightness (between 0 and 1).
+    pub fn hsl(h: f32, s: f32, l: f32) -> Self {
+        let s = s.clamp(0., 1.);
+        let l = l.clamp(0., 1.);
+        let c = (1. - (2. * l - 1.).abs()) * s;
+        Color::from_hue(h, c, l - c / 2.)
+    }
+
+
+    /// Creates the color of hue `h` with chroma `c`, `m` is added to all the components.
+    fn from_hue(h: f32, c: f32, m: f32) -> Self {
+        let h = h.rem_euclid(360.) / 60.;
+        let x = c * (1. - (h % 2. - 1.).abs());
+        let (r, g, b) = match h as u32 {
+            0 => (c, x, 0.),
+            1 => (x, c, 0.),
+            2 => (0., c, x),
+            3 => (0., x, c),
+            4 => (x, 0., c),
+            _ => (c, 0., x)
+        };
+        let to_u8 = |v: f32| ((v + m) * 255.).round() as u8;
+        Color::rgb(to_u8(r), to_u8(g), to_u8(b))
+    }
+
+
+    /// Returns the hue (in degrees, between 0 and 360), the maximum and minimum components
+    /// and the chroma of the color (between 0 and 1).
+    fn hue(&self) -> (f32, f32, f32, f32) {
+        let (r, g, b) = (self.r as f32 / 255., self.g as f32 / 255., self.b as f32 / 255.);
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let c = max - min;
+        let h = if c == 0. {
+            0.
+        } else if max == r {
+            60. * ((g - b) / c).rem_euclid(6.)
+        } else if max == g {
+            60. * ((b - r) / c + 2.)
+        } else {
+            60. * ((r - g) / c + 4.)
+        };
+        (h, max, min, c)
+    }
+
+
+    /// Returns the hue (in degrees), saturation and value (between 0 and 1) of the color.
+    pub fn to_hsv(&self) -> (f32, f32, f32) {
+        let (h, max, _, c) = self.hue();
+        let s = if max == 0. { 0. } else { c / max };
+        (h, s, max)
+    }
+
+
+    /// Returns the hue (in degrees), saturation and lightness (between 0 and 1) of the color.
+    pub fn to_hsl(&self) -> (f32, f32, f32) {
+        let (h, max, min, c) = self.hue();
+        let l = (max + min) / 2.;
+        let s = if l == 0. || l == 1. { 0. } else { c / (1. - (2. * l - 1.).abs()) };
+        (h, s, l)
+    }
+
+
+    /// Linear interpolation between `self` (`t` = 0) and `other` (`t` = 1). `t` is clamped between 0 and 1.
+    pub fn lerp(&self, other: Color, t: f32) -> Self {
+        let t = t.clamp(0., 1.);
+        let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        Color::rgb(lerp(self.r, other.r), lerp(self.g, other.g), lerp(self.b, other.b))
+    }
+
+
+    /// Returns the average of `colors`, or black if there is none.
+    pub fn mix(colors: &[Color]) -> Self {
+        if colors.is_empty() {
+            return Color::BLACK;
+        }
+        let n = colors.len() as u32;
+        let sum = colors.iter().fold([0u32; 3], |s, c| [s[0] + c.r as u32, s[1] + c.g as u32, s[2] + c.b as u32]);
+        Color::rgb(((sum[0] + n / 2) / n) as u8, ((sum[1] + n / 2) / n) as u8, ((sum[2] + n / 2) / n) as u8)
+    }
+
+
+    /// Moves the color towards black by `amount` (0 keeps the color, 1 gives black).
+    pub fn darken(&self, amount: f32) -> Self {
+        self.lerp(Color::BLACK, amount)
+    }
+
+
+    /// Moves the color towards white by `amount` (0 keeps the color, 1 gives white).
+    pub fn lighten(&self, amount: f32) -> Self {
+        self.lerp(Color::WHITE, amount)
+    }
+
+
+    /// Returns the index of the color in `palette` that is the closest to `self`.
+    /// 
+    /// Panics if `palette` is empty.
+    pub fn nearest(&self, palette: &[Color]) -> usize {
+        let dist = |c: &Color| {
+            let dr = self.r as i32 - c.r as i32;
+            let dg = self.g as i32 - c.g as i32;
+            let db = self.b as i32 - c.b as i32;
+            dr * dr + dg * dg + db * db
+        };
+        palette.iter()
+            .enumerate()
+            .min_by_key(|(_, c)| dist(c))
+            .expect("empty palette")
+            .0
+    }
+
+
+    /// Returns the index of the closest color in the xterm 256 colors palette.
+    /// Only the 6x6x6 cube and the gray ramp (16 to 255) are used, as the first 16 colors depend on the terminal theme.
+    pub fn to_ansi256(&self) -> u8 {
+        let level = |v: u8| match v {
+            0..=47 => 0,
+            48..=114 => 1,
+            v => (v - 35) / 40
+        };
+        let (r, g, b) = (level(self.r), level(self.g), level(self.b));
+        let cube = 16 + 36 * r + 6 * g + b;
+
+        let avg = (self.r as i32 + self.g as i32 + self.b as i32) / 3;
+        let gray = 232 + ((avg - 3) / 10).clamp(0, 23) as u8;
+
+        let dist = |i: u8| {
+            let c = Color::from_ansi256(i);
+            let dr = self.r as i32 - c.r as i32;
+            let dg = self.g as i32 - c.g as i32;
+            let db = self.b as i32 - c.b as i32;
+            dr * dr + dg * dg + db * db
+        };
+        if dist(gray) < dist(cube) { gray } else { cube }
+    }
+
+
+    /// Returns the luminance of the color (Rec. 601).
+    pub fn luminance(&self) -> u8 {
+        ((299 * self.r as u32 + 587 * self.g as u32 + 114 * self.b as u32) / 1000) as u8
+    }
+
+
+    /// Returns the index of the closest of the 16 ANSI colors (using the default xterm colors).
+    /// This is the default mapping of `ColorDepth::Ansi16`.
+    pub fn to_ansi16(&self) -> u8 {
+        self.nearest(&ANSI_16) as u8
+    }
+
+
+    /// Returns the color at index `i` in the xterm 256 colors palette (with the default xterm colors for 0 to 15).
+    pub const fn from_ansi256(i: u8) -> Self {
+        const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+        match i {
+            0..=15 => ANSI_16[i as usize],
+            16..=231 => {
+                let i = i - 16;
+                Color::rgb(LEVELS[(i / 36) as usize], LEVELS[(i / 6 % 6) as usize], LEVELS[(i % 6) as usize])
+            }
+            _ => {
+                let v = 8 + 10 * (i - 232);
+                Color::rgb(v, v, v)
+            }
+        }
+    }
 }
 
 
+/// Default xterm colors of the 16 ANSI colors.
+const ANSI_16: [Color; 16] = [
+    Color::hex(0x000000), Color::hex(0xcd0000), Color::hex(0x00cd00), Color::hex(0xcdcd00),
+    Color::hex(0x0000ee), Color::hex(0xcd00cd), Color::hex(0x00cdcd), Color::hex(0xe5e5e5),
+    Color::hex(0x7f7f7f), Color::hex(0xff0000), Color::hex(0x00ff00), Color::hex(0xffff00),
+    Color::hex(0x5c5cff), Color::hex(0xff00ff), Color::hex(0x00ffff), Color::hex(0xffffff)
+];
+
+
 impl fmt::Display for Color {
 
     /// Writes the CSI to set background or color (respectively when using {:-} or {:+}) to `f`.
@@ -236,380 +436,2499 @@ impl fmt::Display for Color {
 }
 
 
-#[derive(Clone)]
-/// Image struct. This is a Color buffer.
-/// Pixels can be accessed by indexing with the pixel coordinates.
-pub struct Image {
-    data: Vec<Color>,
-    size: Vec2
+impl Default for Color {
+
+    /// Black
+    fn default() -> Self {
+        Color::BLACK
+    }
 }
 
 
-impl Image {
+/// RGB Color with an alpha channel (0 is transparent, 255 is opaque).
+/// 
+/// Drawing an `Rgba` over an image of `Color` blends it with the pixels below (see `Blend`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Rgba {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8
+}
 
-    /// Creates an image of size (`w`, `h`). All the pixels are set to black.
-    pub fn new(w: usize, h: usize) -> Self {
-        Self {
-            data: vec![Color::BLACK; w * h],
-            size: vec2!(w as i32, h as i32)
-        }
+
+impl Rgba {
+
+    /// Fully transparent color.
+    pub const TRANSPARENT: Rgba = Rgba::new(0, 0, 0, 0);
+
+
+    /// Creates a color.
+    pub const fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { r, g, b, a }
     }
 
 
-    /// Loads an image from a file.
-    pub fn load<P>(path: P) -> Result<Self, String>
-            where P: AsRef<Path> {
-        let img = match ImageReader::open(path) {
-            Ok(s) => match s.decode() {
-                Ok(img) => img,
-                Err(e) => return Err(format!("{}", e))
-            }
-            Err(e) => return Err(format!("{}", e))
-        }.to_rgb8();
-        let mut result = Image::new(img.width() as usize, img.height() as usize);
-        for i in 0..img.width() {
-            for j in 0..img.height() {
-                let px = img.get_pixel(i, j).channels();
-                result[vec2!(i as i32, j as i32)] = Color::rgb(px[0], px[1], px[2]);
-            }
-        }
-        Ok(result)
+    /// Returns the color without its alpha channel.
+    pub const fn rgb(&self) -> Color {
+        Color::rgb(self.r, self.g, self.b)
     }
 
 
-    /// Saves an image to a file.
-    pub fn save<P>(&self, path: P) -> Result<(), String>
-            where P: AsRef<Path> {
-        let mut img = RgbImage::new(self.size.x as u32, self.size.y as u32);
-        for i in 0..self.size.x {
-            for j in 0..self.size.y {
-                let mut pix = img.get_pixel_mut(i as u32, j as u32);
-                let c = self[vec2!(i, j)];
-                pix.0 = [c.r, c.g, c.b];
-            }
-        }
-        match img.save(path) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(format!("{}", e))
+    /// Returns the color obtained when drawing `src` over `self`, both colors can be transparent.
+    /// Colors are combined using `mode` where `self` is opaque.
+    pub fn over(self, src: Rgba, mode: BlendMode) -> Rgba {
+        let (sa, da) = (src.a as f32 / 255., self.a as f32 / 255.);
+        let a = sa + da * (1. - sa);
+        if a == 0. {
+            return Rgba::TRANSPARENT;
         }
+        let blended = mode.apply(self.rgb(), src.rgb());
+        let mix = |s: u8, d: u8, b: u8| {
+            let s = (1. - da) * s as f32 + da * b as f32;
+            ((sa * s + (1. - sa) * da * d as f32) / a).round() as u8
+        };
+        Rgba::new(mix(src.r, self.r, blended.r), mix(src.g, self.g, blended.g), mix(src.b, self.b, blended.b), (a * 255.).round() as u8)
     }
+}
 
 
+impl Color {
 
-    /// Returns the size of the image.
-    pub fn size(&self) -> Vec2 {
-        self.size
+    /// Returns the color with the alpha channel `a`.
+    pub const fn with_alpha(&self, a: u8) -> Rgba {
+        Rgba::new(self.r, self.g, self.b, a)
     }
+}
 
 
-    /// Resizes the image. New pixels are set to black.
-    /// It corrupts the image (pixel position is misinterpreted, but it is faster than Image::resize).
-    pub fn raw_resize<A>(&mut self, new_size: A) 
-        where A: AsRef<Vec2>
-    {
-        self.size = *new_size.as_ref();
-        self.data.resize((self.size.x * self.size.y) as usize, Color::BLACK);
-        self.data.shrink_to_fit();
+impl From<Color> for Rgba {
+
+    /// Opaque color.
+    fn from(c: Color) -> Self {
+        c.with_alpha(255)
     }
+}
 
 
-    /// Resizes the image keeping the top left part of the image
-    pub fn resize<A>(&mut self, new_size: A) 
-        where A: AsRef<Vec2>
-    {
-        // TOFIX: buffer overflow
-        let new_size = new_size.as_ref();
-        if new_size.x != self.size.x {
-            let mut cnt: usize = new_size.x as usize;
-            for j in 1..new_size.y {
-                for i in 0..new_size.x {
-                    self.data[cnt] = self[(i, j)];
-                    cnt += 1;
-                }
+/// How the colors of an image are combined with the colors below when it is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlendMode {
+    /// The image replaces the pixels below.
+    #[default]
+    Normal,
+    /// Colors are added (lights, glows).
+    Add,
+    /// Colors are multiplied (shadows, tinting).
+    Multiply,
+    /// Inverse of multiplying the inverse colors, lightens the pixels below.
+    Screen,
+    /// The image colors are subtracted from the pixels below.
+    Subtract
+}
+
+
+impl BlendMode {
+
+    /// Returns the color obtained when drawing `src` over `dst`.
+    pub fn apply(&self, dst: Color, src: Color) -> Color {
+        let f = |d: u8, s: u8| {
+            let (d, s) = (d as u32, s as u32);
+            match self {
+                BlendMode::Normal => s,
+                BlendMode::Add => (d + s).min(255),
+                BlendMode::Multiply => (d * s + 127) / 255,
+                BlendMode::Screen => 255 - ((255 - d) * (255 - s) + 127) / 255,
+                BlendMode::Subtract => d.saturating_sub(s)
             }
-        }
-        self.raw_resize(new_size);
+        };
+        Color::rgb(f(dst.r, src.r) as u8, f(dst.g, src.g) as u8, f(dst.b, src.b) as u8)
     }
+}
 
 
-    fn is_out_of_range<A>(&self, p: A) -> bool
-        where A: AsRef<Vec2> 
+/// Pixels of type `Self` on which pixels of type `S` can be drawn.
+/// 
+/// Drawing a `Color` over a `Color` combines them with the `BlendMode`, drawing an `Rgba` over a `Color`
+/// also blends them according to the alpha channel (source over destination).
+/// Other pixels (eg. indexed colors) are replaced and ignore the `BlendMode`.
+pub trait Blend<S> {
+
+    /// Returns the pixel resulting from drawing `src` over `self`.
+    fn blend(self, src: S) -> Self;
+
+
+    /// Returns the pixel resulting from drawing `src` over `self` with the blend mode `mode`.
+    fn blend_mode(self, src: S, mode: BlendMode) -> Self
+        where Self: Sized
     {
-        let p = p.as_ref();
-        p.x < 0 || p.y < 0 || p.x >= self.size.x || p.y >= self.size.y
+        let _ = mode;
+        self.blend(src)
     }
+}
 
 
-    /// Sets the pixel color at `p` to `c`.
-    pub fn point<A>(&mut self, p: A, c: Color)
-        where A: AsRef<Vec2>
-    {
-        self[*p.as_ref()] = c;
+impl Blend<Color> for Color {
+    fn blend(self, src: Color) -> Color {
+        src
     }
 
+    fn blend_mode(self, src: Color, mode: BlendMode) -> Color {
+        mode.apply(self, src)
+    }
+}
 
-    /// Draws a line of color `c` between `p1` and `p2`.
-    pub fn line<A, B>(&mut self, p1: A, p2: B, c: Color)
-        where A: AsRef<Vec2>, B: AsRef<Vec2> 
-    {
-        let mut p1 = *p1.as_ref();
-        let p2 = p2.as_ref();
 
-        let dx = (p2.x - p1.x).abs();
-        let sx = if p1.x < p2.x {1} else {-1};
-        let dy = -(p2.y - p1.y).abs();
-        let sy = if p1.y < p2.y {1} else {-1};
+impl Blend<Rgba> for Color {
+    fn blend(self, src: Rgba) -> Color {
+        let a = src.a as u32;
+        let mix = |s: u8, d: u8| ((s as u32 * a + d as u32 * (255 - a) + 127) / 255) as u8;
+        Color::rgb(mix(src.r, self.r), mix(src.g, self.g), mix(src.b, self.b))
+    }
 
-        let mut err = dx + dy;
+    fn blend_mode(self, src: Rgba, mode: BlendMode) -> Color {
+        self.blend(mode.apply(self, src.rgb()).with_alpha(src.a))
+    }
+}
 
-        self[p1] = c;
 
-        while (p1.x != p2.x || p1.y != p2.y)
-             && ((p1.x < self.size.x && sx > 0) || (p1.x >= 0 && sx < 0))
-             && ((p1.y < self.size.y && sy > 0) || (p1.y >= 0 && sy < 0))
-        {
-            let e2 = 2 * err;
-            if e2 >= dy {
-                err += dy;
-                p1.x += sx;
+/// Pixels that are replaced when drawn over.
+macro_rules! replace_blend {
+    ($( $t:ty ),*) => {$(
+        impl Blend<$t> for $t {
+            fn blend(self, src: $t) -> $t {
+                src
             }
-            if e2 <= dx {
-                err += dx;
-                p1.y += sy;
-            }
-
-            self[p1] = c;
         }
-    }
+    )*};
+}
 
 
-    /// Same as `rect` but draws only the four sides of the rectangle.
-    pub fn rect_boudary<A, B>(&mut self, p: A, s: B, c: Color)
-        where A: AsRef<Vec2>, B: AsRef<Vec2>
-    {
-        let p = p.as_ref();
-        let s = s.as_ref();
-        self.line((p.x      , p.y      ), (p.x + s.x, p.y      ), c);
-        self.line((p.x + s.x, p.y      ), (p.x + s.x, p.y + s.y), c);
-        self.line((p.x + s.x, p.y + s.y), (p.x      , p.y + s.y), c);
-        self.line((p.x      , p.y + s.y), (p.x      , p.y      ), c);
-    }
+replace_blend!(u8, Rgba);
 
 
-    /// Draws a rectangle of color `c` and of size `s`. 
-    /// `p` is the coordinate of the top left corner of the rectangle.
-    pub fn rect<A, B>(&mut self, p: A, s: B, c: Color) 
-        where A: AsRef<Vec2>, B: AsRef<Vec2>
-    {
-        let mut p = *p.as_ref();
-        let mut s = *s.as_ref();
+/// Part of an image that stays in place when it is resized (see `Image::resize_anchored`).
+/// Also aligns the text of a `widgets::Label`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Anchor {
+    #[default]
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight
+}
 
-        if p.x < 0 {
-            s.x += p.x - 1;
-            p.x = 0;
-        }
-        if p.y < 0 {
-            s.y += p.y - 1;
-            p.y = 0;
-        }
 
-        let dx = if s.x > 0 {1} else {-1};
-        let dy = if s.y > 0 {1} else {-1};
+impl Anchor {
+
+    /// Returns the position of the content of an image of size `old` in the image resized to `new`.
+    pub(crate) fn offset(&self, old: Vec2, new: Vec2) -> Vec2 {
+        let (h, v) = match self {
+            Anchor::TopLeft => (0, 0),
+            Anchor::Top => (1, 0),
+            Anchor::TopRight => (2, 0),
+            Anchor::Left => (0, 1),
+            Anchor::Center => (1, 1),
+            Anchor::Right => (2, 1),
+            Anchor::BottomLeft => (0, 2),
+            Anchor::Bottom => (1, 2),
+            Anchor::BottomRight => (2, 2)
+        };
+        vec2!((new.x - old.x) * h / 2, (new.y - old.y) * v / 2)
+    }
+}
 
-        for j in 0..(s.y.abs()) {
-            let y = p.y + j * dy;
-            if y >= self.size.y {break}
-            for i in 0..(s.x.abs()) {
-                let x = p.x + i * dx;
-                if x >= self.size.x {break}
 
-                self[(x, y)] = c;
-            }
+/// Mirroring of an image when it is drawn (see `Image::image_flipped`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Flip {
+    #[default]
+    None,
+    /// Left and right are swapped.
+    Horizontal,
+    /// Top and bottom are swapped.
+    Vertical,
+    /// Both axes are swapped (same as a half turn).
+    Both
+}
+
+
+impl Flip {
+
+    /// Returns the flip that mirrors the horizontal axis if `h` and the vertical axis if `v`.
+    pub const fn from_axes(h: bool, v: bool) -> Self {
+        match (h, v) {
+            (false, false) => Flip::None,
+            (true, false) => Flip::Horizontal,
+            (false, true) => Flip::Vertical,
+            (true, true) => Flip::Both
         }
     }
 
 
-    /// Sets all the pixels' color in the screen to `c`.
-    pub fn clear(&mut self, c: Color) {
-        for i in 0..self.data.len() {
-            self.data[i] = c;
-        }
+    /// Returns the flip that mirrors the axes mirrored by only one of `self` and `other`.
+    pub(crate) const fn then(&self, other: Flip) -> Self {
+        let ((h1, v1), (h2, v2)) = (self.axes(), other.axes());
+        Flip::from_axes(h1 != h2, v1 != v2)
     }
 
 
-    fn plot_ellipse_points<A, B>(&mut self, center: A, pos: B, c: Color) 
-        where A: AsRef<Vec2>, B: AsRef<Vec2>
-    {
-        let center = center.as_ref();
-        let pos    = pos.as_ref();
-        self[(center.x + pos.x, center.y + pos.y)] = c;
-        self[(center.x + pos.x, center.y - pos.y)] = c;
-        self[(center.x - pos.x, center.y + pos.y)] = c;
-        self[(center.x - pos.x, center.y - pos.y)] = c;
+    /// Returns whether the horizontal and the vertical axes are mirrored.
+    pub const fn axes(&self) -> (bool, bool) {
+        match self {
+            Flip::None => (false, false),
+            Flip::Horizontal => (true, false),
+            Flip::Vertical => (false, true),
+            Flip::Both => (true, true)
+        }
     }
+}
 
 
-    /// Draws an ellipse of color `col`. `c` is the center of the ellipse and `s` is the size of the rectangle
-    /// in which the ellipse is inscribed.
-    pub fn ellipse_boundary<A, B>(&mut self, center: A, size: B, c: Color) 
-        where A: AsRef<Vec2>, B: AsRef<Vec2>
-    {
-        let center = center.as_ref();
-        let size   = size.as_ref();
+/// Repeating fill used instead of a flat color by `Image::rect_pattern` and `Image::polygon_pattern`.
+/// 
+/// Patterns are aligned on the image they are drawn on (and not on the shape), so that neighbouring shapes tile seamlessly.
+/// 
+/// # Usage
+/// 
+/// ```
+/// // diagonal hatching, the background is left untouched
+/// let hatch = Pattern::Bits { rows: [0x80, 0x40, 0x20, 0x10, 0x08, 0x04, 0x02, 0x01], fg: Color::WHITE, bg: None };
+/// img.rect_pattern(((2, 2), (16, 8)), &hatch);
+/// 
+/// // textured floor
+/// img.polygon_pattern(&floor, &Pattern::Image(Image::load("tiles.png").unwrap()));
+/// ```
+#[derive(Clone)]
+pub enum Pattern<S = Color> {
+    /// 8x8 bit pattern, one byte per row with the most significant bit on the left. Set bits are drawn with `fg`,
+    /// the others with `bg` (or are left untouched if `bg` is None).
+    Bits { rows: [u8; 8], fg: S, bg: Option<S> },
+    /// Image repeated over the shape.
+    Image(Image<S>)
+}
 
-        let a = size.x / 2;
-        let b = size.y / 2;
-
-        //prepare to plot in the first region
-        let mut x = 0;
-        let mut y = b;
-        let mut p    = b * b + (a * a * (1 - 4*b) - 2) / 4;
-        let mut dpe  = 3 * b * b;
-        let mut dpse  = dpe - 2 * a * a * (b - 1);
-        let d2pe  = 2 * b * b;
-        let d2pse = d2pe + 2 * a * a;
-
-        //plot in the first region
-        self.plot_ellipse_points(center, (x, y), c);
-        while dpse < 2 * a * a + 3 * b * b {
-            if p < 0 { //east
-                p    += dpe;
-                dpe  += d2pe;
-                dpse += d2pe;
-            } else {     //south-east
-                p    += dpse;
-                dpe  += d2pe;
-                dpse += d2pse;
-                y -= 1;
-            }
-            x += 1;
-            self.plot_ellipse_points(center, (x, y), c);
-        }
-
-        //prepare to plot in the second region
-        let mut p    = p - (a * a * (4 * y - 3) + b * b * (4 * x + 3) + 2) / 4;
-        let mut dpse = 2 * b * b + 3 * a * a;
-        let dps  = a * a * (3 - 2 * y);
-        let d2ps = 2 * a * a;
-
-        //plot in the second region
-        while y > 0 {
-            if p > 0 { //south
-                p    += dps;
-                dpe  += d2ps;
-                dpse += d2ps;
-            } else {     //south-east
-                p    += dpse;
-                dpe  += d2ps;
-                dpse += d2pse;
-                x += 1;
+
+impl<S: Copy + PartialEq + Default> Pattern<S> {
+
+    /// Returns a checkerboard of 1x1 squares alternating between `fg` and `bg`.
+    pub fn checker(fg: S, bg: S) -> Self {
+        Pattern::Bits { rows: [0xaa, 0x55, 0xaa, 0x55, 0xaa, 0x55, 0xaa, 0x55], fg, bg: Some(bg) }
+    }
+
+
+    /// Returns the value of the pattern at the image pixel `p`, or None if the pixel must not be drawn.
+    fn sample(&self, p: Vec2) -> Option<S> {
+        match self {
+            Pattern::Bits { rows, fg, bg } => {
+                let row = rows[p.y.rem_euclid(8) as usize];
+                if row & (0x80 >> p.x.rem_euclid(8)) != 0 { Some(*fg) } else { *bg }
+            },
+            Pattern::Image(img) => {
+                let size = img.size();
+                if size.x <= 0 || size.y <= 0 {
+                    None
+                } else {
+                    Some(img.at((p.x.rem_euclid(size.x), p.y.rem_euclid(size.y))))
+                }
             }
-            y -= 1;
-            self.plot_ellipse_points(center, (x, y), c);
         }
     }
+}
 
 
-    /// Draws an image at position `pos`. 
-    /// 
-    /// Negative size results in flipped image. Alpha is used to ignore a given color while drawing.
-    pub fn image<A, B, C>(&mut self, img: &Image, pos: A, size: B, offset: C, alpha: Option<Color>) 
-        where A: AsRef<Vec2>, B: AsRef<Vec2>, C: AsRef<Vec2>
-    {
-        let offset = offset.as_ref();
-        let mut p = *pos.as_ref();
-        let mut s = *size.as_ref();
+/// How the pixels are sampled when an image is scaled (see `Image::scaled`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Filter {
+    /// Each pixel takes the value of the closest source pixel (sharp, good for pixel art).
+    #[default]
+    Nearest,
+    /// Each pixel is interpolated between the four closest source pixels (smooth).
+    Bilinear
+}
 
-        if p.x < 0 {
-            s.x += p.x - 1;
-            p.x = 0;
-        }
-        if p.y < 0 {
-            s.y += p.y - 1;
-            p.y = 0;
-        }
 
+/// Pixels that can be interpolated, used by `Filter::Bilinear`.
+pub trait Lerp: Copy {
 
-        let dx = if s.x > 0 {1} else {-1};
-        let dy = if s.y > 0 {1} else {-1};
+    /// Interpolation between `self` (`t` = 0) and `other` (`t` = 1).
+    fn lerp(self, other: Self, t: f32) -> Self;
+}
 
 
-        for j in 0..(s.y.abs()) {
-            let y = p.y + j * dy;
-            let src_y = offset.y + j;
-            if y >= self.size.y {break}
-            if src_y >= img.size.y {break}
-            for i in 0..(s.x.abs()) {
-                let x = p.x + i * dx;
-                let src_x = offset.x + i;
-                if x >= self.size.x {break}
-                if src_x >= img.size.x {break}
+impl Lerp for Color {
+    fn lerp(self, other: Color, t: f32) -> Color {
+        Color::lerp(&self, other, t)
+    }
+}
 
-                let pos = vec2!(x, y);
-                let src_pos = vec2!(src_x, src_y);
 
-                if let Some(acolor) = alpha {
-                    if acolor == img[src_pos] {
-                        continue;
-                    }
-                }
-                self[pos] = img[src_pos];
-            }
-        }
+impl Lerp for Rgba {
+    fn lerp(self, other: Rgba, t: f32) -> Rgba {
+        let a = (self.a as f32 + (other.a as f32 - self.a as f32) * t.clamp(0., 1.)).round() as u8;
+        self.rgb().lerp(other.rgb(), t).with_alpha(a)
     }
+}
 
 
-    /// Draws the whole image at `pos`, ignoring the color `alpha`.
-    /// 
-    /// Literally:
-    /// ```
-    /// <image>.image(img, pos, img.size(), Vec2::ZERO, Some(alpha));
-    /// ```
-    pub fn whole_image_alpha<A>(&mut self, img: &Image, pos: A, alpha: Color) 
-        where A: AsRef<Vec2>
-    {
-        self.image(img, pos, img.size(), Vec2::ZERO, Some(alpha));
+/// Palette indices can not be mixed, the closest one is kept.
+impl Lerp for u8 {
+    fn lerp(self, other: u8, t: f32) -> u8 {
+        if t < 0.5 { self } else { other }
     }
+}
 
 
-    /// Draws the whole image at `pos`.
-    /// 
-    /// Literally:
-    /// ```
-    /// <image>.image(img, pos, img.size(), Vec2::ZERO, None);
-    /// ```
-    pub fn whole_image<A>(&mut self, img: &Image, pos: A) 
-        where A: AsRef<Vec2>
-    {
-        self.image(img, pos, img.size(), Vec2::ZERO, None);
-    }
+#[derive(Clone)]
+/// Image struct. This is a pixel buffer, by default of Color (see `Image<u8>` for indexed images).
+/// Pixels can be accessed by indexing with the pixel coordinates (which panics out of the image), or with `get` and `set`.
+/// 
+/// Pixels are stored contiguously in row-major order: the pixel (x, y) is at the index `x + y * width`
+/// of `Image::as_slice`, without any padding between the rows.
+pub struct Image<P = Color> {
+    data: Vec<P>,
+    size: Vec2,
+    clip: Option<Rect>
 }
 
 
-impl<A: AsRef<Vec2>> Index<A> for Image {
-    type Output = Color;
+impl Image {
 
-    fn index(&self, p: A) -> &Self::Output {
-        let p = p.as_ref();
-        if !self.is_out_of_range(p) {
-            &self.data[(p.x + p.y * self.size.x) as usize]
-        } else {
-            &Color::BLACK
+    /// Creates an image of size (`w`, `h`). All the pixels are set to black.
+    pub fn new(w: usize, h: usize) -> Self {
+        Self {
+            data: vec![Color::BLACK; w * h],
+            size: vec2!(w as i32, h as i32),
+            clip: None
         }
     }
-}
 
 
-impl<A: AsRef<Vec2>> IndexMut<A> for Image {
+    /// Loads an image from a file.
+    pub fn load<P>(path: P) -> Result<Self, String>
+            where P: AsRef<Path> {
+        let img = match ImageReader::open(path) {
+            Ok(s) => match s.decode() {
+                Ok(img) => img,
+                Err(e) => return Err(format!("{}", e))
+            }
+            Err(e) => return Err(format!("{}", e))
+        };
+        Ok(img.into())
+    }
 
-    fn index_mut(&mut self, p: A) -> &mut Self::Output {
-        static mut TEMP: Color = Color::BLACK;
-        let p = p.as_ref();
 
-        if !self.is_out_of_range(p) {
-            &mut self.data[(p.x + p.y * self.size.x) as usize]
-        } else {
-            unsafe { &mut TEMP } // NOT GOOD, ignore index out of range
-        }
+    /// Creates an image of size (`w`, `h`) from raw 8 bit pixels in row-major order. `data` holds either 3 (RGB)
+    /// or 4 (RGBA, the alpha channel is ignored) bytes per pixel.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// // frame received from a decoder
+    /// let frame = Image::from_raw(640, 480, &rgb_bytes)?;
+    /// ```
+    pub fn from_raw(w: usize, h: usize, data: &[u8]) -> Result<Self, String> {
+        let channels = raw_channels(w, h, data)?;
+        Ok(Self {
+            data: data.chunks_exact(channels).map(|px| Color::rgb(px[0], px[1], px[2])).collect(),
+            size: vec2!(w as i32, h as i32),
+            clip: None
+        })
     }
-}
\ No newline at end of file
+
+
+    /// Saves an image to a file.
+    pub fn save<P>(&self, path: P) -> Result<(), String>
+            where P: AsRef<Path> {
+        match self.to_rgb_image().save(path) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("{}", e))
+        }
+    }
+
+
+    /// Converts the image to an `image` crate buffer, to use its processing functions or encoders.
+    pub fn to_rgb_image(&self) -> RgbImage {
+        let data = self.data.iter().flat_map(|c| [c.r, c.g, c.b]).collect();
+        RgbImage::from_raw(self.size.x as u32, self.size.y as u32, data).unwrap()
+    }
+
+
+    /// Encodes the image as PNG.
+    pub(crate) fn encode_png(&self) -> Result<Vec<u8>, String> {
+        let mut data = Vec::with_capacity(3 * self.data.len());
+        for c in self.data.iter() {
+            data.extend_from_slice(&[c.r, c.g, c.b]);
+        }
+        let mut png = Vec::new();
+        match PngEncoder::new(&mut png).write_image(&data, self.size.x as u32, self.size.y as u32, ColorType::Rgb8) {
+            Ok(_) => Ok(png),
+            Err(e) => Err(format!("{}", e))
+        }
+    }
+
+
+    /// Draws the rows of `img` that were already received at `pos`.
+    pub fn streamed_image<A>(&mut self, img: &StreamedImage, pos: A) 
+        where A: AsRef<Vec2>
+    {
+        self.streamed_image_map(img, pos, |c| c);
+    }
+
+
+    /// Draws the frame `frame` of `sheet` at `pos` (see `sprite::SpriteSheet`).
+    /// 
+    /// Panics if the frame does not exist.
+    pub fn sprite<K, A>(&mut self, sheet: &SpriteSheet, frame: K, pos: A) 
+        where K: FrameKey, A: AsRef<Vec2>
+    {
+        let rect = sheet.frame(frame).expect("no such frame in the sprite sheet");
+        self.image(&sheet.image(), pos, rect.size, rect.pos, None, BlendMode::Normal);
+    }
+
+
+    /// Draws the part of `img` at `offset` of size `size` at `pos`, the indices are converted with the palette of `img`.
+    /// Negative size results in flipped image (see `image_flipped`). The pixels of index `transparent` are not drawn.
+    pub fn indexed_image<A, B, C>(&mut self, img: &IndexedImage, pos: A, size: B, offset: C, transparent: Option<u8>) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>, C: AsRef<Vec2>
+    {
+        self.indexed_image_palette(img, img.palette(), pos, size, offset, transparent);
+    }
+
+
+    /// Same as `indexed_image` but the indices are converted with `palette` (eg. to draw an enemy with alternative colors).
+    pub fn indexed_image_palette<A, B, C>(&mut self, img: &IndexedImage, palette: &Palette, pos: A, size: B, offset: C, transparent: Option<u8>) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>, C: AsRef<Vec2>
+    {
+        self.image_blend(img.indices(), pos, size, offset, transparent, |_, i| palette.color(i));
+    }
+
+
+    /// Applies `filter` on the whole image (blur, sharpen, invert or grayscale).
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// // blurred background behind a menu
+    /// background.apply_filter(ImageFilter::GaussianBlur(2.));
+    /// ```
+    pub fn apply_filter(&mut self, filter: ImageFilter) {
+        *self = filter::apply(self, filter);
+    }
+
+
+    /// Convolves the image with `kernel` (see `filter::Kernel`), for custom effects like emboss or edge detection.
+    pub fn convolve(&mut self, kernel: &Kernel) {
+        *self = filter::convolve(self, kernel);
+    }
+
+
+    /// Returns the image with each pixel replaced by a color of `palette`, dithered according to `dithering`
+    /// (see `Image::quantize_indexed` to get the palette indices).
+    /// 
+    /// Panics if `palette` is empty.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// let gameboy = Palette::new(&[Color::hex(0x0f380f), Color::hex(0x306230), Color::hex(0x8bac0f), Color::hex(0x9bbc0f)]);
+    /// let retro = photo.quantize(&gameboy, Dithering::Ordered);
+    /// ```
+    pub fn quantize(&self, palette: &Palette, dithering: Dithering) -> Image {
+        let nearest = |c| palette.color(palette.nearest(c));
+        match dithering {
+            Dithering::None => self.map(nearest),
+            Dithering::Ordered => dither::ordered(self, palette.step(), nearest),
+            Dithering::FloydSteinberg => dither::floyd_steinberg(self, nearest)
+        }
+    }
+
+
+    /// Returns the image with an alpha channel, where the pixels of color `key` are fully transparent.
+    /// Useful to turn color keyed sprites into images that can be scaled or blended.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// // white was the transparent color of this sprite
+    /// let sprite = Image::load("player.png")?.with_color_key(Color::WHITE);
+    /// ```
+    pub fn with_color_key(&self, key: Color) -> Image<Rgba> {
+        self.map(|c| if c == key { Rgba::TRANSPARENT } else { c.into() })
+    }
+
+
+    /// Same as `Image::quantize` but returns the palette indices of the pixels.
+    pub fn quantize_indexed(&self, palette: &Palette, dithering: Dithering) -> IndexedImage {
+        IndexedImage::from_image(&self.quantize(palette, dithering), palette.clone())
+    }
+
+
+    /// Draws a rectangle of size `s` filled with a linear gradient from `c1` to `c2`.
+    /// `p` is the coordinate of the top left corner of the rectangle, `angle` is the direction of the gradient
+    /// in radians (0 goes from left to right, PI/2 from top to bottom).
+    pub fn rect_gradient<A, B>(&mut self, p: A, s: B, c1: Color, c2: Color, angle: f32) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>
+    {
+        self.rect_gradient_map(p, s, c1, c2, angle, |c| c);
+    }
+
+
+    /// Draws an ellipse filled with a radial gradient, from `c1` at the center to `c2` on the boundary.
+    /// `center` is the center of the ellipse and `size` is the size of the rectangle in which the ellipse is inscribed.
+    pub fn ellipse_gradient<A, B>(&mut self, center: A, size: B, c1: Color, c2: Color) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>
+    {
+        self.ellipse_gradient_map(center, size, c1, c2, |c| c);
+    }
+}
+
+
+impl Image<Rgba> {
+
+    /// Loads an image with its alpha channel from a file.
+    pub fn load_rgba<P>(path: P) -> Result<Self, String>
+            where P: AsRef<Path> {
+        let img = match ImageReader::open(path) {
+            Ok(s) => match s.decode() {
+                Ok(img) => img,
+                Err(e) => return Err(format!("{}", e))
+            }
+            Err(e) => return Err(format!("{}", e))
+        };
+        Ok(img.into())
+    }
+
+
+    /// Same as `Image::to_rgb_image` but the alpha channel is kept.
+    pub fn to_rgba_image(&self) -> RgbaImage {
+        let data = self.data.iter().flat_map(|c| [c.r, c.g, c.b, c.a]).collect();
+        RgbaImage::from_raw(self.size.x as u32, self.size.y as u32, data).unwrap()
+    }
+
+
+    /// Same as `Image::from_raw` but the alpha channel is kept (RGB pixels are opaque).
+    pub fn from_raw_rgba(w: usize, h: usize, data: &[u8]) -> Result<Self, String> {
+        let channels = raw_channels(w, h, data)?;
+        Ok(Self {
+            data: data.chunks_exact(channels).map(|px| Rgba::new(px[0], px[1], px[2], if channels == 4 { px[3] } else { 255 })).collect(),
+            size: vec2!(w as i32, h as i32),
+            clip: None
+        })
+    }
+}
+
+
+/// Returns the number of bytes per pixel of a raw RGB or RGBA buffer of an image of size (`w`, `h`).
+fn raw_channels(w: usize, h: usize, data: &[u8]) -> Result<usize, String> {
+    match data.len() {
+        n if n == 3 * w * h => Ok(3),
+        n if n == 4 * w * h => Ok(4),
+        n => Err(format!("{} bytes is neither an RGB nor an RGBA buffer of size {}x{}", n, w, h))
+    }
+}
+
+
+impl<P: Copy + PartialEq + Default> Image<P> {
+
+    /// Creates an image of size (`w`, `h`) with all the pixels set to `p`.
+    pub fn filled(w: usize, h: usize, p: P) -> Self {
+        Self {
+            data: vec![p; w * h],
+            size: vec2!(w as i32, h as i32),
+            clip: None
+        }
+    }
+
+
+    /// Creates an image of size `size` from the values of `noise` at each pixel, converted to pixels with `f`.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// let clouds = Noise::new(NoiseKind::Perlin, 7).with_frequency(0.1).with_octaves(3, 0.5);
+    /// let sky = Image::from_noise((64, 32), &clouds, |v| Color::DEEP_SKY_BLUE.lerp(Color::WHITE, v));
+    /// ```
+    pub fn from_noise<A, F>(size: A, noise: &Noise, f: F) -> Self 
+        where A: AsRef<Vec2>, F: Fn(f32) -> P
+    {
+        let size = size.as_ref();
+        let size = vec2!(size.x.max(0), size.y.max(0));
+        Self {
+            data: noise.field(size).into_iter().map(f).collect(),
+            size,
+            clip: None
+        }
+    }
+
+
+    /// Returns the size of the image.
+    pub fn size(&self) -> Vec2 {
+        self.size
+    }
+
+
+    /// Resizes the image. New pixels are set to black.
+    /// It corrupts the image (pixel position is misinterpreted, but it is faster than Image::resize).
+    pub fn raw_resize<A>(&mut self, new_size: A) 
+        where A: AsRef<Vec2>
+    {
+        self.size = *new_size.as_ref();
+        self.data.resize((self.size.x * self.size.y) as usize, P::default());
+        self.data.shrink_to_fit();
+    }
+
+
+    /// Resizes the image keeping the top left part of the image. New pixels are set to the default pixel (black).
+    pub fn resize<A>(&mut self, new_size: A) 
+        where A: AsRef<Vec2>
+    {
+        self.resize_anchored(new_size, Anchor::TopLeft);
+    }
+
+
+    /// Resizes the image keeping the part of the image given by `anchor` in place (the image is cropped when it shrinks).
+    /// New pixels are set to the default pixel (black).
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// // adds a 2 pixels border around the image
+    /// img.resize_anchored(img.size() + vec2!(4, 4), Anchor::Center);
+    /// ```
+    pub fn resize_anchored<A>(&mut self, new_size: A, anchor: Anchor) 
+        where A: AsRef<Vec2>
+    {
+        let new_size = new_size.as_ref();
+        let new_size = vec2!(new_size.x.max(0), new_size.y.max(0));
+        let offset = anchor.offset(self.size, new_size);
+
+        let mut data = vec![P::default(); (new_size.x * new_size.y) as usize];
+        for y in 0..new_size.y {
+            let src_y = y - offset.y;
+            if src_y < 0 || src_y >= self.size.y {continue}
+            for x in 0..new_size.x {
+                let src_x = x - offset.x;
+                if src_x < 0 || src_x >= self.size.x {continue}
+                data[(x + y * new_size.x) as usize] = self.data[(src_x + src_y * self.size.x) as usize];
+            }
+        }
+        self.data = data;
+        self.size = new_size;
+    }
+
+
+    /// Resizes the image scaling its content to the new size, pixels are sampled using `filter`.
+    pub fn resize_scaled<A>(&mut self, new_size: A, filter: Filter) 
+        where A: AsRef<Vec2>, P: Lerp
+    {
+        let clip = self.clip;
+        *self = self.scaled(new_size, filter);
+        self.clip = clip;
+    }
+
+
+    fn is_out_of_range<A>(&self, p: A) -> bool
+        where A: AsRef<Vec2> 
+    {
+        let p = p.as_ref();
+        p.x < 0 || p.y < 0 || p.x >= self.size.x || p.y >= self.size.y
+    }
+
+
+    /// Returns the index of the pixel `p` in the buffer, or None if it is out of the image.
+    fn index_of(&self, p: &Vec2) -> Option<usize> {
+        if self.is_out_of_range(p) { None } else { Some((p.x + p.y * self.size.x) as usize) }
+    }
+
+
+    /// Returns the pixel at `p`, or None if `p` is out of the image.
+    pub fn get<A>(&self, p: A) -> Option<P>
+        where A: AsRef<Vec2>
+    {
+        self.index_of(p.as_ref()).map(|i| self.data[i])
+    }
+
+
+    /// Sets the pixel at `p` to `c`, ignoring the clipping region.
+    /// Returns false (and does nothing) if `p` is out of the image.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// if !img.set(cursor, Color::RED) {
+    ///     println!("the cursor is out of the canvas");
+    /// }
+    /// ```
+    pub fn set<A>(&mut self, p: A, c: P) -> bool
+        where A: AsRef<Vec2>
+    {
+        match self.index_of(p.as_ref()) {
+            Some(i) => {
+                self.data[i] = c;
+                true
+            }
+            None => false
+        }
+    }
+
+
+    /// Clips all the subsequent drawing to `r`: pixels outside of it are left untouched.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// // the content of a panel can not overdraw its border
+    /// img.set_clip(((1, 1), (20, 8)));
+    /// img.text((1, 1 - scroll), &content, Color::WHITE);
+    /// img.clear_clip();
+    /// ```
+    pub fn set_clip<R>(&mut self, r: R) 
+        where R: Into<Rect>
+    {
+        self.clip = Some(r.into().normalized());
+    }
+
+
+    /// Removes the clipping region, drawing can cover the whole image again.
+    pub fn clear_clip(&mut self) {
+        self.clip = None;
+    }
+
+
+    /// Returns the current clipping region (see `Image::set_clip`).
+    pub fn clip(&self) -> Option<Rect> {
+        self.clip
+    }
+
+
+    /// Returns the part of the image that can be drawn on: the whole image intersected with the clipping region.
+    fn drawable_area(&self) -> Option<Rect> {
+        let bounds = Rect::new(Vec2::ZERO, self.size);
+        match self.clip {
+            Some(clip) => bounds.intersection(&clip),
+            None if bounds.is_empty() => None,
+            None => Some(bounds)
+        }
+    }
+
+
+    fn is_clipped<A>(&self, p: A) -> bool
+        where A: AsRef<Vec2> 
+    {
+        let p = p.as_ref();
+        self.is_out_of_range(p) || self.clip.is_some_and(|r| !r.contains(p))
+    }
+
+
+    /// Sets the pixel color at `p` to `c`.
+    pub fn point<A, S>(&mut self, p: A, c: S)
+        where A: AsRef<Vec2>, P: Blend<S>
+    {
+        self.blend_at(p, c);
+    }
+
+
+    /// Sets the pixels of `points` to their color.
+    pub fn points<S>(&mut self, points: &[(Vec2, S)])
+        where S: Copy, P: Blend<S>
+    {
+        for (p, c) in points {
+            self.blend_at(p, *c);
+        }
+    }
+
+
+    /// Draws a line of color `c` between `p1` and `p2`.
+    pub fn line<A, B, S>(&mut self, p1: A, p2: B, c: S)
+        where A: AsRef<Vec2>, B: AsRef<Vec2>, S: Copy, P: Blend<S>
+    {
+        let size = self.size;
+        bresenham(*p1.as_ref(), *p2.as_ref(), size, |p| self.blend_at(p, c));
+    }
+
+
+    /// Draws lines of color `c` between the consecutive points of `points`.
+    pub fn polyline<S>(&mut self, points: &[Vec2], c: S)
+        where S: Copy, P: Blend<S>
+    {
+        self.path(points, false, c);
+    }
+
+
+    /// Draws the sides of the polygon with the vertices `points` (`polyline` that goes back to the first point).
+    pub fn polygon_boundary<S>(&mut self, points: &[Vec2], c: S)
+        where S: Copy, P: Blend<S>
+    {
+        self.path(points, true, c);
+    }
+
+
+    /// Draws the lines between the consecutive points of `points`, and between the last and first points if `closed`.
+    pub(crate) fn path<S>(&mut self, points: &[Vec2], closed: bool, c: S)
+        where S: Copy, P: Blend<S>
+    {
+        let size = self.size;
+        let segments = if closed && points.len() > 2 { points.len() } else { points.len().saturating_sub(1) };
+
+        // segments share their ends, pixels must be blended only once
+        let mut pixels = Vec::new();
+        for i in 0..segments {
+            bresenham(points[i], points[(i + 1) % points.len()], size, |p| pixels.push(p));
+        }
+        if points.len() == 1 {
+            pixels.push(points[0]);
+        }
+        pixels.sort_by_key(|p| (p.y, p.x));
+        pixels.dedup();
+
+        for p in pixels {
+            self.blend_at(p, c);
+        }
+    }
+
+
+    /// Same as `rect` but draws only the four sides of the rectangle.
+    pub fn rect_boudary<R, S>(&mut self, r: R, c: S)
+        where R: Into<Rect>, S: Copy, P: Blend<S>
+    {
+        let Rect { pos: p, size: s } = r.into();
+        self.line((p.x      , p.y      ), (p.x + s.x, p.y      ), c);
+        self.line((p.x + s.x, p.y      ), (p.x + s.x, p.y + s.y), c);
+        self.line((p.x + s.x, p.y + s.y), (p.x      , p.y + s.y), c);
+        self.line((p.x      , p.y + s.y), (p.x      , p.y      ), c);
+    }
+
+
+    /// Draws the rectangle `r` filled with the color `c`.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// img.rect(Rect::new((2, 2), (10, 4)), Color::RED);
+    /// img.rect(((2, 2), (10, 4)), Color::RED); // same as above
+    /// ```
+    pub fn rect<R, S>(&mut self, r: R, c: S) 
+        where R: Into<Rect>, S: Copy, P: Blend<S>
+    {
+        let r = match self.drawable_area().and_then(|area| r.into().intersection(&area)) {
+            Some(r) => r,
+            None => return
+        };
+
+        for y in r.pos.y..r.end().y {
+            for x in r.pos.x..r.end().x {
+                self.blend_at(vec2!(x, y), c);
+            }
+        }
+    }
+
+
+    /// Same as `rect` but the rectangle is filled with `pattern` (see `Pattern`).
+    pub fn rect_pattern<R, S>(&mut self, r: R, pattern: &Pattern<S>) 
+        where R: Into<Rect>, S: Copy + PartialEq + Default, P: Blend<S>
+    {
+        let r = match self.drawable_area().and_then(|area| r.into().intersection(&area)) {
+            Some(r) => r,
+            None => return
+        };
+
+        for y in r.pos.y..r.end().y {
+            self.pattern_hline(r.pos.x, r.end().x - 1, y, pattern);
+        }
+    }
+
+
+    /// Same as `rect_gradient` but the colors are converted with `f` before being drawn.
+    pub(crate) fn rect_gradient_map<A, B, F>(&mut self, p: A, s: B, c1: Color, c2: Color, angle: f32, f: F) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>, F: Fn(Color) -> P
+    {
+        let p = p.as_ref();
+        let s = s.as_ref();
+        let (x0, x1) = (p.x.min(p.x + s.x), p.x.max(p.x + s.x));
+        let (y0, y1) = (p.y.min(p.y + s.y), p.y.max(p.y + s.y));
+
+        // projection of the rectangle on the gradient direction
+        let (dx, dy) = (angle.cos(), angle.sin());
+        let half = ((x1 - x0 - 1) as f32 * dx.abs() + (y1 - y0 - 1) as f32 * dy.abs()) / 2.;
+        let (cx, cy) = ((x0 + x1 - 1) as f32 / 2., (y0 + y1 - 1) as f32 / 2.);
+
+        for y in y0.max(0)..y1.min(self.size.y) {
+            for x in x0.max(0)..x1.min(self.size.x) {
+                let proj = (x as f32 - cx) * dx + (y as f32 - cy) * dy;
+                let t = if half > 0. { (proj / half + 1.) / 2. } else { 0. };
+                self.put(vec2!(x, y), f(c1.lerp(c2, t)));
+            }
+        }
+    }
+
+
+    /// Same as `ellipse_gradient` but the colors are converted with `f` before being drawn.
+    pub(crate) fn ellipse_gradient_map<A, B, F>(&mut self, center: A, size: B, c1: Color, c2: Color, f: F) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>, F: Fn(Color) -> P
+    {
+        let center = center.as_ref();
+        let size   = size.as_ref();
+        let a = (size.x / 2).max(1) as f32;
+        let b = (size.y / 2).max(1) as f32;
+
+        for y in (center.y - size.y / 2).max(0)..=(center.y + size.y / 2).min(self.size.y - 1) {
+            for x in (center.x - size.x / 2).max(0)..=(center.x + size.x / 2).min(self.size.x - 1) {
+                let (u, v) = ((x - center.x) as f32 / a, (y - center.y) as f32 / b);
+                let d = (u * u + v * v).sqrt();
+                if d <= 1. {
+                    self.put(vec2!(x, y), f(c1.lerp(c2, d)));
+                }
+            }
+        }
+    }
+
+
+    /// Sets all the pixels' color in the screen to `c` (only the ones in the clipping region if there is one).
+    pub fn clear(&mut self, c: P) {
+        if self.clip.is_some() {
+            if let Some(r) = self.drawable_area() {
+                for y in r.pos.y..r.end().y {
+                    let start = (r.pos.x + y * self.size.x) as usize;
+                    self.data[start..start + r.size.x as usize].fill(c);
+                }
+            }
+            return;
+        }
+        self.data.fill(c);
+    }
+
+
+    fn plot_ellipse_points<A, B, S>(&mut self, center: A, pos: B, c: S) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>, S: Copy, P: Blend<S>
+    {
+        let center = center.as_ref();
+        let pos    = pos.as_ref();
+        // points on the axes must be blended only once
+        self.blend_at(vec2!(center.x + pos.x, center.y + pos.y), c);
+        if pos.y != 0 {
+            self.blend_at(vec2!(center.x + pos.x, center.y - pos.y), c);
+        }
+        if pos.x != 0 {
+            self.blend_at(vec2!(center.x - pos.x, center.y + pos.y), c);
+            if pos.y != 0 {
+                self.blend_at(vec2!(center.x - pos.x, center.y - pos.y), c);
+            }
+        }
+    }
+
+
+    /// Draws an ellipse of color `col`. `c` is the center of the ellipse and `s` is the size of the rectangle
+    /// in which the ellipse is inscribed.
+    pub fn ellipse_boundary<A, B, S>(&mut self, center: A, size: B, c: S) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>, S: Copy, P: Blend<S>
+    {
+        let center = center.as_ref();
+        midpoint_ellipse(*size.as_ref(), |p| self.plot_ellipse_points(center, p, c));
+    }
+
+
+    /// Draws a filled ellipse of color `c`. `center` is the center of the ellipse and `size` is the size of the rectangle
+    /// in which the ellipse is inscribed.
+    pub fn ellipse<A, B, S>(&mut self, center: A, size: B, c: S) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>, S: Copy, P: Blend<S>
+    {
+        let center = center.as_ref();
+        let size = size.as_ref();
+
+        // half width of the ellipse for each row of the first quadrant
+        let mut spans = vec![0; (size.y / 2).max(0) as usize + 1];
+        midpoint_ellipse(*size, |p| {
+            if let Some(w) = spans.get_mut(p.y as usize) {
+                *w = p.x.max(*w);
+            }
+        });
+
+        self.symmetric_spans(*center, &spans, c);
+    }
+
+
+    /// Draws a circle of color `c`, of center `center` and of radius `radius`.
+    pub fn circle_boundary<A, S>(&mut self, center: A, radius: i32, c: S) 
+        where A: AsRef<Vec2>, S: Copy, P: Blend<S>
+    {
+        let center = center.as_ref();
+        for p in circle_points(radius) {
+            self.blend_at(*center + p, c);
+        }
+    }
+
+
+    /// Draws the part of the circle of center `center` and of radius `radius` that goes from `start_angle`
+    /// to `end_angle` (in radians). Angles start on the right of the circle and go clockwise (as y goes down).
+    pub fn arc<A, S>(&mut self, center: A, radius: i32, start_angle: f32, end_angle: f32, c: S) 
+        where A: AsRef<Vec2>, S: Copy, P: Blend<S>
+    {
+        use std::f32::consts::TAU;
+
+        let center = center.as_ref();
+        let sweep = end_angle - start_angle;
+        let sweep = if sweep >= TAU { TAU } else { sweep.rem_euclid(TAU) };
+
+        for p in circle_points(radius) {
+            let angle = (p.y as f32).atan2(p.x as f32);
+            if (angle - start_angle).rem_euclid(TAU) <= sweep {
+                self.blend_at(*center + p, c);
+            }
+        }
+    }
+
+
+    /// Draws a filled circle of color `c`, of center `center` and of radius `radius`.
+    pub fn circle<A, S>(&mut self, center: A, radius: i32, c: S) 
+        where A: AsRef<Vec2>, S: Copy, P: Blend<S>
+    {
+        let mut spans = vec![0; radius.max(0) as usize + 1];
+        midpoint_circle(radius, |p| {
+            spans[p.y as usize] = p.x.max(spans[p.y as usize]);
+            spans[p.x as usize] = p.y.max(spans[p.x as usize]);
+        });
+        self.symmetric_spans(*center.as_ref(), &spans, c);
+    }
+
+
+    /// Draws a filled triangle of color `c` with the vertices `p0`, `p1` and `p2`.
+    pub fn triangle<A, B, C, S>(&mut self, p0: A, p1: B, p2: C, c: S) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>, C: AsRef<Vec2>, S: Copy, P: Blend<S>
+    {
+        let pts = [*p0.as_ref(), *p1.as_ref(), *p2.as_ref()];
+        let y0 = pts.iter().map(|p| p.y).min().unwrap().max(0);
+        let y1 = pts.iter().map(|p| p.y).max().unwrap().min(self.size.y - 1);
+
+        for y in y0..=y1 {
+            // the row is filled between the leftmost and rightmost crossing of the edges
+            let mut span: Option<(i32, i32)> = None;
+            for i in 0..3 {
+                let (a, b) = (pts[i], pts[(i + 1) % 3]);
+                if y < a.y.min(b.y) || y > a.y.max(b.y) {
+                    continue;
+                }
+                let (xa, xb) = if a.y == b.y {
+                    (a.x.min(b.x), a.x.max(b.x))
+                } else {
+                    let x = a.x as f32 + (y - a.y) as f32 * (b.x - a.x) as f32 / (b.y - a.y) as f32;
+                    (x.round() as i32, x.round() as i32)
+                };
+                span = Some(match span {
+                    None => (xa, xb),
+                    Some((x0, x1)) => (x0.min(xa), x1.max(xb))
+                });
+            }
+            if let Some((x0, x1)) = span {
+                self.hline(x0, x1, y, c);
+            }
+        }
+    }
+
+
+    /// Draws a filled triangle with the vertices `vertices`, only on the pixels where it is closer than what was
+    /// already drawn according to `depth` (which is updated). `depth` should have the size of the image.
+    /// 
+    /// Depths and colors are interpolated linearly on the image.
+    pub fn triangle_depth(&mut self, depth: &mut DepthBuffer, vertices: [Vertex; 3], shading: Shading) 
+        where P: Blend<Color>
+    {
+        let Some(bounds) = self.drawable_area() else { return };
+        let pts = vertices.map(|v| v.pos);
+        raster::rasterize(pts, bounds, |p, w| {
+            let z = w[0] * vertices[0].z + w[1] * vertices[1].z + w[2] * vertices[2].z;
+            if !depth.test(p, z) {
+                return;
+            }
+            let c = match shading {
+                Shading::Flat => vertices[0].color,
+                Shading::Gouraud => {
+                    let channel = |f: fn(&Color) -> u8| {
+                        (w[0] * f(&vertices[0].color) as f32 + w[1] * f(&vertices[1].color) as f32
+                            + w[2] * f(&vertices[2].color) as f32).round().clamp(0., 255.) as u8
+                    };
+                    Color::rgb(channel(|c| c.r), channel(|c| c.g), channel(|c| c.b))
+                }
+            };
+            self.blend_at(p, c);
+        });
+    }
+
+
+    /// Draws a triangle with the vertices `vertices` filled with `texture`, sampled at the texture coordinates of the
+    /// vertices (the texture repeats outside of 0 to 1). If `depth` is given, the pixels behind what was already
+    /// drawn are not drawn.
+    /// 
+    /// Texture coordinates are interpolated linearly on the image (affine mapping), so large triangles seen
+    /// in perspective look distorted: split them into smaller ones.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// let a = Vertex::new((0., 0.), 1., Color::WHITE).with_uv((0., 0.));
+    /// let b = Vertex::new((32., 0.), 1., Color::WHITE).with_uv((1., 0.));
+    /// let c = Vertex::new((0., 32.), 1., Color::WHITE).with_uv((0., 1.));
+    /// img.triangle_textured(None, [a, b, c], &bricks);
+    /// ```
+    pub fn triangle_textured<S>(&mut self, depth: Option<&mut DepthBuffer>, vertices: [Vertex; 3], texture: &Image<S>) 
+        where S: Copy + PartialEq + Default, P: Blend<S>
+    {
+        let Some(bounds) = self.drawable_area() else { return };
+        let size = texture.size();
+        if size.x <= 0 || size.y <= 0 {
+            return;
+        }
+        let mut depth = depth;
+        let pts = vertices.map(|v| v.pos);
+        raster::rasterize(pts, bounds, |p, w| {
+            if let Some(depth) = depth.as_deref_mut() {
+                let z = w[0] * vertices[0].z + w[1] * vertices[1].z + w[2] * vertices[2].z;
+                if !depth.test(p, z) {
+                    return;
+                }
+            }
+            let u = w[0] * vertices[0].uv.0 + w[1] * vertices[1].uv.0 + w[2] * vertices[2].uv.0;
+            let v = w[0] * vertices[0].uv.1 + w[1] * vertices[1].uv.1 + w[2] * vertices[2].uv.1;
+            let tx = ((u * size.x as f32).floor() as i32).rem_euclid(size.x);
+            let ty = ((v * size.y as f32).floor() as i32).rem_euclid(size.y);
+            self.blend_at(p, texture.at((tx, ty)));
+        });
+    }
+
+
+    /// Draws a filled polygon of color `c` with the vertices `points`. The polygon can be concave or self-intersecting,
+    /// pixels are filled using the even-odd rule.
+    pub fn polygon<S>(&mut self, points: &[Vec2], c: S) 
+        where S: Copy, P: Blend<S>
+    {
+        let height = self.size.y;
+        polygon_spans(points, height, |x0, x1, y| self.hline(x0, x1, y, c));
+    }
+
+
+    /// Same as `polygon` but the polygon is filled with `pattern` (see `Pattern`).
+    pub fn polygon_pattern<S>(&mut self, points: &[Vec2], pattern: &Pattern<S>) 
+        where S: Copy + PartialEq + Default, P: Blend<S>
+    {
+        let height = self.size.y;
+        polygon_spans(points, height, |x0, x1, y| self.pattern_hline(x0, x1, y, pattern));
+    }
+
+
+    /// Draws `text` with the embedded bitmap font (see the `text` module), `pos` is the top left corner of the text.
+    /// Lines are separated by `\n`.
+    pub fn text<A, S>(&mut self, pos: A, text: &str, c: S) 
+        where A: AsRef<Vec2>, S: Copy, P: Blend<S>
+    {
+        let pos = pos.as_ref();
+        for (j, line) in text.split('\n').enumerate() {
+            let y = pos.y + j as i32 * text::LINE_HEIGHT;
+            for (i, ch) in line.chars().enumerate() {
+                let x = pos.x + i as i32 * text::ADVANCE;
+                for (dx, column) in text::glyph(ch).iter().enumerate() {
+                    for dy in 0..text::GLYPH_HEIGHT {
+                        if column & (1 << dy) != 0 {
+                            self.blend_at(vec2!(x + dx as i32, y + dy), c);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+
+    /// Draws `text` with `font` at a size of `px` pixels, `pos` is the top left corner of the text.
+    /// Lines are separated by `\n` and the edges of the glyphs are antialiased.
+    /// 
+    /// Only available with the `ttf` feature.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// let font = text::Font::load("title.ttf")?;
+    /// img.ttf_text((4, 4), "Title", &font, 24., Color::WHITE);
+    /// ```
+    #[cfg(feature = "ttf")]
+    pub fn ttf_text<A>(&mut self, pos: A, text: &str, font: &text::Font, px: f32, c: Color) 
+        where A: AsRef<Vec2>, P: Blend<Rgba>
+    {
+        self.ttf_text_blend(pos, text, font, px, c, |dst, src| dst.blend(src));
+    }
+
+
+    /// Draws `text` with `font` like `ttf_text`, the partially covered pixels are combined using `blend`.
+    #[cfg(feature = "ttf")]
+    pub(crate) fn ttf_text_blend<A, F>(&mut self, pos: A, text: &str, font: &text::Font, px: f32, c: Color, blend: F) 
+        where A: AsRef<Vec2>, F: Fn(P, Rgba) -> P
+    {
+        font.rasterize(*pos.as_ref(), text, px, |p, coverage| {
+            if !self.is_out_of_range(p) {
+                let dst = self.at(p);
+                self.put(p, blend(dst, c.with_alpha(coverage)));
+            }
+        });
+    }
+
+
+    /// Draws the rows `center.y + y` and `center.y - y` from `center.x - spans[y]` to `center.x + spans[y]`.
+    fn symmetric_spans<S>(&mut self, center: Vec2, spans: &[i32], c: S)
+        where S: Copy, P: Blend<S>
+    {
+        for (y, w) in spans.iter().enumerate() {
+            let y = y as i32;
+            self.hline(center.x - w, center.x + w, center.y + y, c);
+            if y != 0 {
+                self.hline(center.x - w, center.x + w, center.y - y, c);
+            }
+        }
+    }
+
+
+    /// Draws the pixels from `x0` to `x1` (included) of the row `y`.
+    fn hline<S>(&mut self, x0: i32, x1: i32, y: i32, c: S)
+        where S: Copy, P: Blend<S>
+    {
+        if y < 0 || y >= self.size.y {
+            return;
+        }
+        for x in x0.max(0)..=x1.min(self.size.x - 1) {
+            self.blend_at(vec2!(x, y), c);
+        }
+    }
+
+
+    /// Same as `hline` but the pixels are taken from `pattern`.
+    fn pattern_hline<S>(&mut self, x0: i32, x1: i32, y: i32, pattern: &Pattern<S>)
+        where S: Copy + PartialEq + Default, P: Blend<S>
+    {
+        if y < 0 || y >= self.size.y {
+            return;
+        }
+        for x in x0.max(0)..=x1.min(self.size.x - 1) {
+            if let Some(c) = pattern.sample(vec2!(x, y)) {
+                self.blend_at(vec2!(x, y), c);
+            }
+        }
+    }
+
+
+    /// Draws an image at position `pos`. 
+    /// 
+    /// Negative size results in flipped image (see `image_flipped`). Alpha is used to ignore a given color while drawing.
+    /// Pixels are combined with the pixels below using `mode` (an `Image<Rgba>` drawn over an `Image<Color>`
+    /// also uses its alpha channel).
+    pub fn image<A, B, C, S>(&mut self, img: &Image<S>, pos: A, size: B, offset: C, alpha: Option<S>, mode: BlendMode) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>, C: AsRef<Vec2>, S: Copy + PartialEq + Default, P: Blend<S>
+    {
+        self.image_blend(img, pos, size, offset, alpha, |dst, src| dst.blend_mode(src, mode));
+    }
+
+
+    /// Draws the part of `img` at `offset` of size `size` at position `pos`, mirrored according to `flip`.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// // the character faces left
+    /// img.image_flipped(&sprite, pos, sprite.size(), Vec2::ZERO, Flip::Horizontal, None, BlendMode::Normal);
+    /// ```
+    #[allow(clippy::too_many_arguments)]
+    pub fn image_flipped<A, B, C, S>(&mut self, img: &Image<S>, pos: A, size: B, offset: C, flip: Flip, alpha: Option<S>, mode: BlendMode) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>, C: AsRef<Vec2>, S: Copy + PartialEq + Default, P: Blend<S>
+    {
+        self.image_blend_flip(img, pos, size, offset, flip, alpha, |dst, src| dst.blend_mode(src, mode));
+    }
+
+
+    /// Same as `image` but the pixels of `img` are converted with `f` before being drawn.
+    /// `alpha` is compared to the pixels before the conversion.
+    pub(crate) fn image_map<Q, A, B, C, F>(&mut self, img: &Image<Q>, pos: A, size: B, offset: C, alpha: Option<Q>, f: F) 
+        where Q: Copy + PartialEq + Default, A: AsRef<Vec2>, B: AsRef<Vec2>, C: AsRef<Vec2>, F: Fn(Q) -> P
+    {
+        self.image_blend(img, pos, size, offset, alpha, |_, src| f(src));
+    }
+
+
+    /// Same as `image` but the pixels are set to `f(dst, src)`, where `dst` is the pixel of `self`
+    /// and `src` the pixel of `img`.
+    pub(crate) fn image_blend<Q, A, B, C, F>(&mut self, img: &Image<Q>, pos: A, size: B, offset: C, alpha: Option<Q>, f: F) 
+        where Q: Copy + PartialEq + Default, A: AsRef<Vec2>, B: AsRef<Vec2>, C: AsRef<Vec2>, F: Fn(P, Q) -> P
+    {
+        let (p, s, flip) = unflip(*pos.as_ref(), *size.as_ref());
+        self.image_blend_flip(img, p, s, offset, flip, alpha, f);
+    }
+
+
+    /// Same as `image_flipped` but the pixels are set to `f(dst, src)`, where `dst` is the pixel of `self`
+    /// and `src` the pixel of `img`.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn image_blend_flip<Q, A, B, C, F>(&mut self, img: &Image<Q>, pos: A, size: B, offset: C, flip: Flip, alpha: Option<Q>, f: F) 
+        where Q: Copy + PartialEq + Default, A: AsRef<Vec2>, B: AsRef<Vec2>, C: AsRef<Vec2>, F: Fn(P, Q) -> P
+    {
+        let blit = |d: &mut P, src: Q| if alpha != Some(src) { *d = f(*d, src) };
+        self.image_rows(img, *pos.as_ref(), *size.as_ref(), *offset.as_ref(), flip, |dst, src, h| if h {
+            dst.iter_mut().zip(src.iter().rev()).for_each(|(d, src)| blit(d, *src));
+        } else {
+            dst.iter_mut().zip(src).for_each(|(d, src)| blit(d, *src));
+        });
+    }
+
+
+    /// Same as `image` with no alpha and `BlendMode::Normal`, the rows of `img` are copied as is,
+    /// which is faster than blending each pixel.
+    pub(crate) fn image_copy<A, B, C>(&mut self, img: &Image<P>, pos: A, size: B, offset: C) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>, C: AsRef<Vec2>
+    {
+        let (p, s, flip) = unflip(*pos.as_ref(), *size.as_ref());
+        self.image_rows(img, p, s, *offset.as_ref(), flip, |dst, src, h| if h {
+            dst.iter_mut().zip(src.iter().rev()).for_each(|(d, src)| *d = *src);
+        } else {
+            dst.copy_from_slice(src);
+        });
+    }
+
+
+    /// Calls `blit(dst, src, h)` for each row of the clipped destination with the matching source pixels,
+    /// `h` tells if `src` should be read backwards.
+    fn image_rows<Q, F>(&mut self, img: &Image<Q>, p: Vec2, s: Vec2, offset: Vec2, flip: Flip, mut blit: F) 
+        where Q: Copy + PartialEq + Default, F: FnMut(&mut [P], &[Q], bool)
+    {
+        let (h, v) = flip.axes();
+
+        // the destination is clipped first, so that the source pixels stay in place
+        if s.x <= 0 || s.y <= 0 {
+            return;
+        }
+        let Some(area) = self.drawable_area().and_then(|a| a.intersection(&Rect::new(p, s))) else { return };
+        // columns (relative to `p`) that have a source pixel
+        let (i0, i1) = if h {
+            (offset.x + s.x - img.size.x, offset.x + s.x)
+        } else {
+            (-offset.x, img.size.x - offset.x)
+        };
+        let x0 = area.pos.x.max(p.x + i0);
+        let x1 = area.end().x.min(p.x + i1);
+        if x0 >= x1 {
+            return;
+        }
+
+        // whole rows are blitted, without checking the bounds of each pixel
+        let src_x = |x: i32| offset.x + if h { s.x - 1 - (x - p.x) } else { x - p.x };
+        let (sx0, sx1) = if h { (src_x(x1 - 1), src_x(x0) + 1) } else { (src_x(x0), src_x(x1 - 1) + 1) };
+        for y in area.pos.y..area.end().y {
+            let j = y - p.y;
+            let src_y = offset.y + if v { s.y - 1 - j } else { j };
+            if src_y < 0 || src_y >= img.size.y {continue}
+
+            let row = (y * self.size.x) as usize;
+            let src_row = (src_y * img.size.x) as usize;
+            let dst = &mut self.data[row + x0 as usize..row + x1 as usize];
+            let src = &img.data[src_row + sx0 as usize..src_row + sx1 as usize];
+            blit(dst, src, h);
+        }
+    }
+
+
+    /// Replaces each pixel by `f(pos, pixel)`, where `pos` is the position of the pixel.
+    /// Only the pixels of the clipping region are changed.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// // plasma
+    /// img.shade(|p, _| Color::hsv((p.x as f32 * 0.1).sin() * 180. + t * 60., 1., 1.));
+    /// ```
+    pub fn shade<F>(&mut self, f: F) 
+        where F: FnMut(Vec2, P) -> P
+    {
+        self.shade_rect(Rect::new(Vec2::ZERO, self.size), f);
+    }
+
+
+    /// Same as `shade` but only the pixels of the rectangle `r` are changed.
+    pub fn shade_rect<R, F>(&mut self, r: R, mut f: F) 
+        where R: Into<Rect>, F: FnMut(Vec2, P) -> P
+    {
+        let Some(area) = self.drawable_area().and_then(|a| a.intersection(&r.into().normalized())) else { return };
+        for y in area.pos.y..area.end().y {
+            for x in area.pos.x..area.end().x {
+                let i = (x + y * self.size.x) as usize;
+                self.data[i] = f(vec2!(x, y), self.data[i]);
+            }
+        }
+    }
+
+
+    /// Mirrors the image horizontally (the left column becomes the right one).
+    pub fn flip_h(&mut self) {
+        let w = self.size.x as usize;
+        if w > 0 {
+            for row in self.data.chunks_exact_mut(w) {
+                row.reverse();
+            }
+        }
+    }
+
+
+    /// Mirrors the image vertically (the top row becomes the bottom one).
+    pub fn flip_v(&mut self) {
+        let (w, h) = (self.size.x as usize, self.size.y as usize);
+        for j in 0..(h / 2) {
+            let (top, bottom) = self.data.split_at_mut((h - 1 - j) * w);
+            top[j * w..(j + 1) * w].swap_with_slice(&mut bottom[..w]);
+        }
+    }
+
+
+    /// Draws the whole image at `pos`, ignoring the color `alpha`.
+    /// 
+    /// Literally:
+    /// ```
+    /// <image>.image(img, pos, img.size(), Vec2::ZERO, Some(alpha), BlendMode::Normal);
+    /// ```
+    pub fn whole_image_alpha<A, S>(&mut self, img: &Image<S>, pos: A, alpha: S) 
+        where A: AsRef<Vec2>, S: Copy + PartialEq + Default, P: Blend<S>
+    {
+        self.image(img, pos, img.size(), Vec2::ZERO, Some(alpha), BlendMode::Normal);
+    }
+
+
+    /// Draws the whole image at `pos`.
+    /// 
+    /// Literally:
+    /// ```
+    /// <image>.image(img, pos, img.size(), Vec2::ZERO, None, BlendMode::Normal);
+    /// ```
+    pub fn whole_image<A, S>(&mut self, img: &Image<S>, pos: A) 
+        where A: AsRef<Vec2>, S: Copy + PartialEq + Default, P: Blend<S>
+    {
+        self.image(img, pos, img.size(), Vec2::ZERO, None, BlendMode::Normal);
+    }
+
+
+
+    /// Draws the rows of `img` that were already received at `pos`, converting the pixels with `f`.
+    pub(crate) fn streamed_image_map<A, F>(&mut self, img: &StreamedImage, pos: A, f: F) 
+        where A: AsRef<Vec2>, F: Fn(Color) -> P
+    {
+        let pos = pos.as_ref();
+        let size = img.size();
+        for y in 0..size.y {
+            if img.is_row_loaded(y as usize) {
+                self.image_map(&img.image, (pos.x, pos.y + y), (size.x, 1), (0, y), None, &f);
+            }
+        }
+    }
+
+
+    /// Returns a view of the part of the image at `pos` of size `size`, without copying the pixels.
+    /// The part is clipped to the image.
+    pub fn view<A, B>(&self, pos: A, size: B) -> ImageView<'_, P> 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>
+    {
+        let (pos, size) = (pos.as_ref(), size.as_ref());
+        let min = vec2!(pos.x.clamp(0, self.size.x), pos.y.clamp(0, self.size.y));
+        let max = vec2!((pos.x + size.x).clamp(min.x, self.size.x), (pos.y + size.y).clamp(min.y, self.size.y));
+        ImageView { img: self, rect: Rect::new(min, max - min) }
+    }
+
+
+    /// Returns a copy of the part of the image at `pos` of size `size` (clipped to the image).
+    pub fn sub_image<A, B>(&self, pos: A, size: B) -> Self 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>
+    {
+        self.view(pos, size).to_image()
+    }
+
+
+    /// Draws the part of an image seen by `view` at `pos`, combined with the pixels below using `mode`.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// let tile = tileset.view((16, 0), (16, 16));
+    /// img.image_view(&tile, (x, y), BlendMode::Normal);
+    /// ```
+    pub fn image_view<A, S>(&mut self, view: &ImageView<S>, pos: A, mode: BlendMode) 
+        where A: AsRef<Vec2>, S: Copy + PartialEq + Default, P: Blend<S>
+    {
+        self.image(view.img, pos, view.rect.size, view.rect.pos, None, mode);
+    }
+
+
+    /// Returns a copy of the image scaled to `new_size`, pixels are sampled using `filter`.
+    pub fn scaled<A>(&self, new_size: A, filter: Filter) -> Self 
+        where A: AsRef<Vec2>, P: Lerp
+    {
+        let new_size = new_size.as_ref();
+        let mut result = Image::filled(new_size.x.max(0) as usize, new_size.y.max(0) as usize, P::default());
+        result.scaled_image_blend(self, Rect::new(Vec2::ZERO, self.size), Rect::new(Vec2::ZERO, new_size), filter, Flip::None, |_, src| src);
+        result
+    }
+
+
+    /// Draws the part `src` of `img` stretched over the rectangle `dst`, pixels are sampled using `filter`
+    /// and combined with the pixels below using `mode`.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// // draws the 16x16 sprite twice as big
+    /// img.scaled_image(&sprite, Rect::new((0, 0), (16, 16)), Rect::new(pos, (32, 32)), Filter::Nearest, BlendMode::Normal);
+    /// ```
+    pub fn scaled_image<S>(&mut self, img: &Image<S>, src: Rect, dst: Rect, filter: Filter, mode: BlendMode) 
+        where S: Lerp + PartialEq + Default, P: Blend<S>
+    {
+        self.scaled_image_blend(img, src, dst, filter, Flip::None, |dst, src| dst.blend_mode(src, mode));
+    }
+
+
+    /// Same as `scaled_image` but the pixels are set to `f(dst, src)`, where `dst` is the pixel of `self`
+    /// and `src` the sampled pixel of `img`. The image is also mirrored according to `flip`.
+    pub(crate) fn scaled_image_blend<Q, F>(&mut self, img: &Image<Q>, src: Rect, dst: Rect, filter: Filter, flip: Flip, f: F) 
+        where Q: Lerp + PartialEq + Default, F: Fn(P, Q) -> P
+    {
+        if src.size.x <= 0 || src.size.y <= 0 || dst.size.x <= 0 || dst.size.y <= 0 {
+            return;
+        }
+        let scale_x = src.size.x as f32 / dst.size.x as f32;
+        let scale_y = src.size.y as f32 / dst.size.y as f32;
+        let max = src.pos + src.size - vec2!(1, 1);
+        let (h, v) = flip.axes();
+
+        // only the visible part of `dst` is sampled
+        for y in dst.pos.y.max(0)..(dst.pos.y + dst.size.y).min(self.size.y) {
+            let j = if v { dst.pos.y + dst.size.y - 1 - y } else { y - dst.pos.y };
+            let v = j as f32 + 0.5;
+            for x in dst.pos.x.max(0)..(dst.pos.x + dst.size.x).min(self.size.x) {
+                let i = if h { dst.pos.x + dst.size.x - 1 - x } else { x - dst.pos.x };
+                let u = i as f32 + 0.5;
+                let c = match filter {
+                    Filter::Nearest => img.at(src.pos + vec2!((u * scale_x) as i32, (v * scale_y) as i32)),
+                    Filter::Bilinear => {
+                        let sx = (u * scale_x - 0.5).max(0.);
+                        let sy = (v * scale_y - 0.5).max(0.);
+                        let (tx, ty) = (sx.fract(), sy.fract());
+                        let x0 = (src.pos.x + sx as i32).min(max.x);
+                        let y0 = (src.pos.y + sy as i32).min(max.y);
+                        let (x1, y1) = ((x0 + 1).min(max.x), (y0 + 1).min(max.y));
+                        let top = img.at((x0, y0)).lerp(img.at((x1, y0)), tx);
+                        let bottom = img.at((x0, y1)).lerp(img.at((x1, y1)), tx);
+                        top.lerp(bottom, ty)
+                    }
+                };
+                let pos = vec2!(x, y);
+                self.put(pos, f(self.at(pos), c));
+            }
+        }
+    }
+
+
+    /// Draws `img` transformed by `t`: the pixel `p` of `img` is drawn at `t.apply(p)`.
+    /// Pixels are sampled with the nearest filter.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// // the sprite rotates around its center, placed at `pos`
+    /// let center = sprite.size() / 2;
+    /// let t = Transform2::translation(vec2!(-center.x, -center.y))
+    ///     .then(&Transform2::rotation(angle))
+    ///     .then(&Transform2::translation(pos));
+    /// img.draw_transformed(&sprite, &t);
+    /// ```
+    pub fn draw_transformed<S>(&mut self, img: &Image<S>, t: &Transform2) 
+        where S: Copy + PartialEq + Default, P: Blend<S>
+    {
+        let inv = match t.inverse() {
+            Some(inv) => inv,
+            None => return
+        };
+
+        // bounding box of the transformed image, clipped to self
+        let (w, h) = (img.size.x as f32, img.size.y as f32);
+        let corners = [t.apply_f32(0., 0.), t.apply_f32(w, 0.), t.apply_f32(0., h), t.apply_f32(w, h)];
+        let min_x = corners.iter().map(|c| c.0).fold(f32::INFINITY, f32::min).floor().max(0.) as i32;
+        let min_y = corners.iter().map(|c| c.1).fold(f32::INFINITY, f32::min).floor().max(0.) as i32;
+        let max_x = (corners.iter().map(|c| c.0).fold(f32::NEG_INFINITY, f32::max).ceil() as i32).min(self.size.x);
+        let max_y = (corners.iter().map(|c| c.1).fold(f32::NEG_INFINITY, f32::max).ceil() as i32).min(self.size.y);
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let (u, v) = inv.apply_f32(x as f32 + 0.5, y as f32 + 0.5);
+                if u < 0. || v < 0. || u >= w || v >= h {
+                    continue;
+                }
+                self.blend_at(vec2!(x, y), img.at((u as i32, v as i32)));
+            }
+        }
+    }
+
+
+    /// Returns a copy of the image where every pixel was converted with `f`.
+    pub fn map<Q, F>(&self, f: F) -> Image<Q>
+        where F: Fn(P) -> Q
+    {
+        Image {
+            data: self.data.iter().map(|p| f(*p)).collect(),
+            size: self.size,
+            clip: self.clip
+        }
+    }
+
+
+    /// Returns the pixel buffer, in row-major order (see `Image`).
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// // the third row of the image
+    /// let w = img.size().x as usize;
+    /// let row = &img.as_slice()[2 * w..3 * w];
+    /// ```
+    pub fn as_slice(&self) -> &[P] {
+        &self.data
+    }
+
+
+    /// Returns the mutable pixel buffer, in row-major order (see `Image`). The clipping region is ignored.
+    pub fn as_mut_slice(&mut self) -> &mut [P] {
+        &mut self.data
+    }
+
+
+    /// Returns an iterator over the pixels, row by row from the top left corner.
+    pub fn pixels(&self) -> impl Iterator<Item = &P> + '_ {
+        self.data.iter()
+    }
+
+
+    /// Returns an iterator over mutable references to the pixels, row by row from the top left corner.
+    /// The clipping region is ignored.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// // threshold
+    /// for c in img.pixels_mut() {
+    ///     *c = if c.luminance() > 127 { Color::WHITE } else { Color::BLACK };
+    /// }
+    /// ```
+    pub fn pixels_mut(&mut self) -> impl Iterator<Item = &mut P> + '_ {
+        self.data.iter_mut()
+    }
+
+
+    /// Same as `pixels` but each pixel comes with its coordinates.
+    pub fn enumerate_pixels(&self) -> impl Iterator<Item = (Vec2, &P)> + '_ {
+        let w = self.size.x.max(1);
+        self.data.iter().enumerate().map(move |(i, c)| (vec2!(i as i32 % w, i as i32 / w), c))
+    }
+
+
+    /// Returns the pixel at `p`, or the default pixel if `p` is out of the image.
+    pub(crate) fn at<A>(&self, p: A) -> P
+        where A: AsRef<Vec2>
+    {
+        let p = p.as_ref();
+        if self.is_out_of_range(p) {
+            P::default()
+        } else {
+            self.data[(p.x + p.y * self.size.x) as usize]
+        }
+    }
+
+
+    /// Draws `c` over the pixel at `p`, does nothing if `p` is out of the image or of the clipping region.
+    fn blend_at<A, S>(&mut self, p: A, c: S)
+        where A: AsRef<Vec2>, P: Blend<S>
+    {
+        let p = p.as_ref();
+        if !self.is_clipped(p) {
+            let i = (p.x + p.y * self.size.x) as usize;
+            self.data[i] = self.data[i].blend(c);
+        }
+    }
+
+
+    /// Sets the pixel at `p` to `c`, does nothing if `p` is out of the image or of the clipping region.
+    pub(crate) fn put<A>(&mut self, p: A, c: P)
+        where A: AsRef<Vec2>
+    {
+        let p = p.as_ref();
+        if !self.is_clipped(p) {
+            self.data[(p.x + p.y * self.size.x) as usize] = c;
+        }
+    }
+}
+
+
+/// Scanline fill of the polygon with the vertices `points` using the even-odd rule: calls `span` with
+/// the first and last pixel (included) of each horizontal span and its row, for the rows in `0..height`.
+fn polygon_spans<F>(points: &[Vec2], height: i32, mut span: F)
+    where F: FnMut(i32, i32, i32)
+{
+    if points.len() < 3 {
+        return;
+    }
+    let y0 = points.iter().map(|p| p.y).min().unwrap().max(0);
+    let y1 = points.iter().map(|p| p.y).max().unwrap().min(height - 1);
+
+    let mut crossings = Vec::new();
+    for y in y0..=y1 {
+        // the scanline goes through the center of the pixels
+        let sy = y as f32 + 0.5;
+        crossings.clear();
+        for (i, a) in points.iter().enumerate() {
+            let b = points[(i + 1) % points.len()];
+            let (ay, by) = (a.y as f32 + 0.5, b.y as f32 + 0.5);
+            if (ay <= sy) != (by <= sy) {
+                crossings.push(a.x as f32 + (sy - ay) * (b.x - a.x) as f32 / (by - ay));
+            }
+        }
+        crossings.sort_by(|a, b| a.total_cmp(b));
+
+        for pair in crossings.chunks_exact(2) {
+            span(pair[0].round() as i32, pair[1].round() as i32, y);
+        }
+    }
+}
+
+
+/// Converts a blit with a negative size (the image is drawn towards the left or the top, mirrored)
+/// to the rectangle with a positive size covered by the image and the corresponding flip.
+pub(crate) fn unflip(mut pos: Vec2, mut size: Vec2) -> (Vec2, Vec2, Flip) {
+    let (h, v) = (size.x < 0, size.y < 0);
+    if h {
+        size.x = -size.x;
+        pos.x -= size.x - 1;
+    }
+    if v {
+        size.y = -size.y;
+        pos.y -= size.y - 1;
+    }
+    (pos, size, Flip::from_axes(h, v))
+}
+
+
+/// Bresenham's line algorithm: calls `plot` with the points of the line between `p1` and `p2`,
+/// stops when the line leaves an image of size `size`.
+fn bresenham<F>(p1: Vec2, p2: Vec2, size: Vec2, mut plot: F)
+    where F: FnMut(Vec2)
+{
+    let mut p1 = p1;
+
+    let dx = (p2.x - p1.x).abs();
+    let sx = if p1.x < p2.x {1} else {-1};
+    let dy = -(p2.y - p1.y).abs();
+    let sy = if p1.y < p2.y {1} else {-1};
+
+    let mut err = dx + dy;
+
+    plot(p1);
+
+    while (p1.x != p2.x || p1.y != p2.y)
+         && ((p1.x < size.x && sx > 0) || (p1.x >= 0 && sx < 0))
+         && ((p1.y < size.y && sy > 0) || (p1.y >= 0 && sy < 0))
+    {
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            p1.x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            p1.y += sy;
+        }
+
+        plot(p1);
+    }
+}
+
+
+/// Midpoint ellipse algorithm: calls `plot` with the points of the first quadrant of the ellipse
+/// inscribed in a rectangle of size `size` (relative to its center).
+fn midpoint_ellipse<F>(size: Vec2, mut plot: F)
+    where F: FnMut(Vec2)
+{
+    let a = size.x / 2;
+    let b = size.y / 2;
+
+    //prepare to plot in the first region
+    let mut x = 0;
+    let mut y = b;
+    let mut p    = b * b + (a * a * (1 - 4*b) - 2) / 4;
+    let mut dpe  = 3 * b * b;
+    let mut dpse  = dpe - 2 * a * a * (b - 1);
+    let d2pe  = 2 * b * b;
+    let d2pse = d2pe + 2 * a * a;
+
+    //plot in the first region
+    plot(vec2!(x, y));
+    while dpse < 2 * a * a + 3 * b * b {
+        if p < 0 { //east
+            p    += dpe;
+            dpe  += d2pe;
+            dpse += d2pe;
+        } else {     //south-east
+            p    += dpse;
+            dpe  += d2pe;
+            dpse += d2pse;
+            y -= 1;
+        }
+        x += 1;
+        plot(vec2!(x, y));
+    }
+
+    //prepare to plot in the second region
+    let mut p    = p - (a * a * (4 * y - 3) + b * b * (4 * x + 3) + 2) / 4;
+    let mut dpse = 2 * b * b + 3 * a * a;
+    let dps  = a * a * (3 - 2 * y);
+    let d2ps = 2 * a * a;
+
+    //plot in the second region
+    while y > 0 {
+        if p > 0 { //south
+            p    += dps;
+            dpe  += d2ps;
+            dpse += d2ps;
+        } else {     //south-east
+            p    += dpse;
+            dpe  += d2ps;
+            dpse += d2pse;
+            x += 1;
+        }
+        y -= 1;
+        plot(vec2!(x, y));
+    }
+}
+
+
+/// Returns the points of the circle of radius `r` (relative to its center), each point is returned once.
+fn circle_points(r: i32) -> Vec<Vec2> {
+    // octants overlap on the diagonals and the axes
+    let mut points = Vec::new();
+    midpoint_circle(r, |p| {
+        for (x, y) in [(p.x, p.y), (p.y, p.x)] {
+            for (sx, sy) in [(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+                points.push(vec2!(sx * x, sy * y));
+            }
+        }
+    });
+    points.sort_by_key(|p| (p.y, p.x));
+    points.dedup();
+    points
+}
+
+
+/// Midpoint circle algorithm: calls `plot` with the points of the circle of radius `r` (relative to its center)
+/// in the octant where 0 <= x <= y.
+fn midpoint_circle<F>(r: i32, mut plot: F)
+    where F: FnMut(Vec2)
+{
+    if r < 0 {
+        return;
+    }
+    let mut x = 0;
+    let mut y = r;
+    let mut d = 1 - r;
+    while x <= y {
+        plot(vec2!(x, y));
+        if d < 0 {
+            d += 2 * x + 3;
+        } else {
+            d += 2 * (x - y) + 5;
+            y -= 1;
+        }
+        x += 1;
+    }
+}
+
+
+/// List of the colors of an `IndexedImage`: the pixels of value `i` are displayed with the color at index `i`.
+/// Only the first 256 colors can be indexed.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct Palette {
+    colors: Vec<Color>
+}
+
+
+impl Palette {
+
+    /// Creates a palette with the colors `colors`.
+    pub fn new(colors: &[Color]) -> Self {
+        Self { colors: colors.to_vec() }
+    }
+
+
+    /// Returns the colors of the palette.
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+
+
+    /// Returns the number of colors in the palette.
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+
+    /// Returns true if the palette has no colors.
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+
+    /// Returns the color at index `i`, or black if there is none.
+    pub fn color(&self, i: u8) -> Color {
+        self.colors.get(i as usize).copied().unwrap_or_default()
+    }
+
+
+    /// Sets the color at index `i` to `c`, does nothing if there is no such index.
+    pub fn set_color(&mut self, i: u8, c: Color) {
+        if let Some(color) = self.colors.get_mut(i as usize) {
+            *color = c;
+        }
+    }
+
+
+    /// Returns the index of the closest color to `c` (see `Color::nearest`).
+    /// 
+    /// Panics if the palette is empty.
+    pub fn nearest(&self, c: Color) -> u8 {
+        c.nearest(&self.colors[..self.colors.len().min(256)]) as u8
+    }
+
+
+    /// Rotates the colors of `range` by `n` steps towards the higher indices (lower if `n` is negative),
+    /// for palette cycling animations (water, fire, ...). Indices past the end of the palette are ignored.
+    pub fn rotate(&mut self, range: Range<usize>, n: i32) {
+        let end = range.end.min(self.colors.len());
+        let colors = &mut self.colors[range.start.min(end)..end];
+        if !colors.is_empty() {
+            let n = n.rem_euclid(colors.len() as i32) as usize;
+            colors.rotate_right(n);
+        }
+    }
+
+
+    /// Returns the average distance between a color of the palette and the closest other one,
+    /// used as the amplitude of ordered dithering.
+    fn step(&self) -> f32 {
+        if self.colors.len() < 2 {
+            return 0.;
+        }
+        let dist = |a: &Color, b: &Color| {
+            let (dr, dg, db) = (a.r as f32 - b.r as f32, a.g as f32 - b.g as f32, a.b as f32 - b.b as f32);
+            (dr * dr + dg * dg + db * db).sqrt()
+        };
+        let total: f32 = self.colors.iter().enumerate()
+            .map(|(i, a)| self.colors.iter().enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, b)| dist(a, b))
+                .fold(f32::INFINITY, f32::min))
+            .sum();
+        total / self.colors.len() as f32
+    }
+}
+
+
+impl From<Vec<Color>> for Palette {
+
+    fn from(colors: Vec<Color>) -> Self {
+        Self { colors }
+    }
+}
+
+
+/// Image that stores a palette index per pixel instead of a color. The colors are looked up when the image
+/// is drawn (see `Image::indexed_image`), so changing the palette recolors the image without touching its pixels,
+/// and each pixel only takes one byte.
+/// 
+/// # Usage
+/// 
+/// ```
+/// let mut tiles = IndexedImage::new(64, 64, Palette::new(&[Color::BLACK, Color::BLUE, Color::AQUA]));
+/// tiles.indices_mut().rect(((0, 0), (16, 16)), 1);
+/// 
+/// // animated water
+/// tiles.palette_mut().rotate(1..3, 1);
+/// screen.indexed_image(&tiles, (10, 10), tiles.size(), Vec2::ZERO, Some(0));
+/// ```
+#[derive(Clone)]
+pub struct IndexedImage {
+    indices: Image<u8>,
+    palette: Palette
+}
+
+
+impl IndexedImage {
+
+    /// Creates an image of size (`w`, `h`) with all the pixels set to the index 0.
+    pub fn new(w: usize, h: usize, palette: Palette) -> Self {
+        Self {
+            indices: Image::filled(w, h, 0),
+            palette
+        }
+    }
+
+
+    /// Converts `img` by taking the closest color of `palette` for each pixel.
+    /// 
+    /// Panics if `palette` is empty.
+    pub fn from_image(img: &Image, palette: Palette) -> Self {
+        Self {
+            indices: img.map(|c| palette.nearest(c)),
+            palette
+        }
+    }
+
+
+    /// Returns the size of the image.
+    pub fn size(&self) -> Vec2 {
+        self.indices.size()
+    }
+
+
+    /// Returns the palette indices of the pixels.
+    pub fn indices(&self) -> &Image<u8> {
+        &self.indices
+    }
+
+
+    /// Returns the palette indices of the pixels, to draw on them.
+    pub fn indices_mut(&mut self) -> &mut Image<u8> {
+        &mut self.indices
+    }
+
+
+    /// Returns the palette of the image.
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+
+    /// Returns the palette of the image, to change its colors.
+    pub fn palette_mut(&mut self) -> &mut Palette {
+        &mut self.palette
+    }
+
+
+    /// Replaces the palette of the image.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+
+    /// Returns the image with the palette colors.
+    pub fn to_image(&self) -> Image {
+        self.indices.map(|i| self.palette.color(i))
+    }
+}
+
+
+/// Borrowed rectangular part of an `Image`, created with `Image::view`.
+/// Pixels are indexed relatively to the top left corner of the view.
+#[derive(Clone, Copy)]
+pub struct ImageView<'a, P = Color> {
+    img: &'a Image<P>,
+    rect: Rect
+}
+
+
+impl<'a, P: Copy + PartialEq + Default> ImageView<'a, P> {
+
+    /// Returns the size of the view.
+    pub fn size(&self) -> Vec2 {
+        self.rect.size
+    }
+
+
+    /// Returns the position of the view in the viewed image.
+    pub fn pos(&self) -> Vec2 {
+        self.rect.pos
+    }
+
+
+    /// Returns the viewed image.
+    pub fn image(&self) -> &'a Image<P> {
+        self.img
+    }
+
+
+    /// Returns a view of the part of this view at `pos` of size `size` (clipped to this view).
+    pub fn view<A, B>(&self, pos: A, size: B) -> ImageView<'a, P> 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>
+    {
+        let (pos, size) = (pos.as_ref(), size.as_ref());
+        let min = vec2!(pos.x.clamp(0, self.rect.size.x), pos.y.clamp(0, self.rect.size.y));
+        let max = vec2!((pos.x + size.x).clamp(min.x, self.rect.size.x), (pos.y + size.y).clamp(min.y, self.rect.size.y));
+        ImageView { img: self.img, rect: Rect::new(self.rect.pos + min, max - min) }
+    }
+
+
+    /// Returns the pixel at `p` (relatively to the view), or None if `p` is out of the view.
+    pub fn get<A>(&self, p: A) -> Option<P>
+        where A: AsRef<Vec2>
+    {
+        let p = p.as_ref();
+        if p.x < 0 || p.y < 0 || p.x >= self.rect.size.x || p.y >= self.rect.size.y {
+            None
+        } else {
+            self.img.get(self.rect.pos + *p)
+        }
+    }
+
+
+    /// Copies the pixels of the view in a new image.
+    pub fn to_image(&self) -> Image<P> {
+        let mut result = Image::filled(self.rect.size.x as usize, self.rect.size.y as usize, P::default());
+        result.image_map(self.img, Vec2::ZERO, self.rect.size, self.rect.pos, None, |c| c);
+        result
+    }
+}
+
+
+/// Image that is received row by row (eg. while it is being decoded or downloaded).
+/// Only the rows that were received are drawn.
+/// 
+/// Created with `image_stream`, rows are sent through the returned `ImageStreamWriter`.
+pub struct StreamedImage {
+    image: Image,
+    loaded: Vec<bool>,
+    loaded_count: usize
+}
+
+
+impl StreamedImage {
+
+    /// Returns the size of the complete image.
+    pub fn size(&self) -> Vec2 {
+        self.image.size()
+    }
+
+
+    /// Returns the underlying image, rows that were not received yet are black.
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+
+    /// Returns true if the row `y` was received.
+    pub fn is_row_loaded(&self, y: usize) -> bool {
+        y < self.loaded.len() && self.loaded[y]
+    }
+
+
+    /// Returns the number of rows that were received.
+    pub fn loaded_rows(&self) -> usize {
+        self.loaded_count
+    }
+
+
+    /// Returns true if all the rows were received.
+    pub fn is_complete(&self) -> bool {
+        self.loaded_count == self.loaded.len()
+    }
+}
+
+
+/// Sending half of an image stream, see `image_stream`.
+#[derive(Clone)]
+pub struct ImageStreamWriter {
+    target: Arc<Mutex<StreamedImage>>
+}
+
+
+impl ImageStreamWriter {
+
+    /// Writes the row `y` of the image and asks the renderer for a redraw.
+    /// Extra pixels in `row` are ignored, missing ones are left black.
+    pub fn write_row(&self, y: usize, row: &[Color]) {
+        {
+            let mut target = self.target.lock().unwrap();
+            let size = target.size();
+            if y >= size.y as usize {
+                return;
+            }
+
+            for (x, c) in row.iter().take(size.x as usize).enumerate() {
+                target.image[vec2!(x as i32, y as i32)] = *c;
+            }
+
+            if !target.loaded[y] {
+                target.loaded[y] = true;
+                target.loaded_count += 1;
+            }
+        }
+        Renderer::request_redraw();
+    }
+}
+
+
+/// Creates an image of size (`w`, `h`) that is filled progressively.
+/// The returned `StreamedImage` can be drawn while rows are written with the `ImageStreamWriter`,
+/// possibly from another thread.
+/// 
+/// # Usage
+/// 
+/// ```
+/// let (writer, img) = image_stream(w, h);
+/// 
+/// thread::spawn(move || {
+///     for y in 0..h {
+///         let row = ... // decode a row
+///         writer.write_row(y, &row);
+///     }
+/// });
+/// 
+/// ...
+/// rdr.draw_streamed_image(img.clone(), pos);
+/// ```
+pub fn image_stream(w: usize, h: usize) -> (ImageStreamWriter, Arc<Mutex<StreamedImage>>) {
+    let img = Arc::new(Mutex::new(StreamedImage {
+        image: Image::new(w, h),
+        loaded: vec![false; h],
+        loaded_count: 0
+    }));
+    (ImageStreamWriter { target: img.clone() }, img)
+}
+
+
+/// Run-length encoded image, every row is stored as a list of (color, length) runs.
+/// Used to store frames that are mostly made of long runs of the same color.
+#[derive(Clone)]
+pub(crate) struct RleImage {
+    rows: Vec<Vec<(Color, u32)>>,
+    size: Vec2
+}
+
+
+impl RleImage {
+
+    /// Encodes an image of size `size` where the color of the pixel at `p` is `f(p)`.
+    pub(crate) fn from_fn<F>(size: Vec2, f: F) -> Self
+        where F: Fn(Vec2) -> Color
+    {
+        Self {
+            rows: (0..size.y).map(|y| encode_row((0..size.x).map(|x| f(vec2!(x, y))))).collect(),
+            size
+        }
+    }
+
+
+    pub(crate) fn size(&self) -> Vec2 {
+        self.size
+    }
+
+
+    /// Returns an iterator over the colors of row `y`, starting at `x` (`x` should be positive).
+    /// The iterator never ends, pixels out of the image are black.
+    pub(crate) fn row(&self, x: i32, y: i32) -> RleRow<'_> {
+        let runs: &[(Color, u32)] = if y >= 0 && y < self.size.y { &self.rows[y as usize] } else { &[] };
+        let mut row = RleRow { runs, run: 0, left: runs.first().map_or(0, |r| r.1) };
+
+        let mut skip = x.max(0) as u32;
+        while skip > 0 && row.run < runs.len() {
+            let n = skip.min(row.left);
+            skip -= n;
+            row.left -= n;
+            if row.left == 0 {
+                row.next_run();
+            }
+        }
+        row
+    }
+
+
+    /// Replaces the pixels of row `y` between `x0` (included) and `x1` (excluded) with `f(p)`.
+    pub(crate) fn set_row_span<F>(&mut self, y: i32, x0: i32, x1: i32, f: F)
+        where F: Fn(Vec2) -> Color
+    {
+        if y < 0 || y >= self.size.y {
+            return;
+        }
+        let old = self.row(0, y);
+        let new = old.take(self.size.x as usize)
+            .enumerate()
+            .map(|(x, c)| {
+                let x = x as i32;
+                if x >= x0 && x < x1 { f(vec2!(x, y)) } else { c }
+            });
+        self.rows[y as usize] = encode_row(new);
+    }
+}
+
+
+fn encode_row<I>(colors: I) -> Vec<(Color, u32)>
+    where I: Iterator<Item = Color>
+{
+    let mut runs: Vec<(Color, u32)> = Vec::new();
+    for c in colors {
+        match runs.last_mut() {
+            Some((color, n)) if *color == c => *n += 1,
+            _ => runs.push((c, 1))
+        }
+    }
+    runs.shrink_to_fit();
+    runs
+}
+
+
+/// Iterator over a row of an RleImage.
+pub(crate) struct RleRow<'a> {
+    runs: &'a [(Color, u32)],
+    run: usize,
+    left: u32
+}
+
+
+impl RleRow<'_> {
+
+    fn next_run(&mut self) {
+        self.run += 1;
+        self.left = self.runs.get(self.run).map_or(0, |r| r.1);
+    }
+}
+
+
+impl Iterator for RleRow<'_> {
+    type Item = Color;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.run >= self.runs.len() {
+            return Some(Color::BLACK);
+        }
+        let c = self.runs[self.run].0;
+        self.left -= 1;
+        if self.left == 0 {
+            self.next_run();
+        }
+        Some(c)
+    }
+}
+
+
+/// Converts any image decoded by the `image` crate (alpha is dropped).
+impl From<DynamicImage> for Image {
+
+    fn from(img: DynamicImage) -> Self {
+        let img = img.to_rgb8();
+        Image::from_raw(img.width() as usize, img.height() as usize, img.as_raw()).unwrap()
+    }
+}
+
+
+/// Converts any image decoded by the `image` crate, keeping its alpha channel.
+impl From<DynamicImage> for Image<Rgba> {
+
+    fn from(img: DynamicImage) -> Self {
+        let img = img.to_rgba8();
+        Image::from_raw_rgba(img.width() as usize, img.height() as usize, img.as_raw()).unwrap()
+    }
+}
+
+
+/// Serialized form of an image: its size and the run-length encoded pixels, in row-major order.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Image")]
+struct SerdeImage<P> {
+    width: i32,
+    height: i32,
+    runs: Vec<(P, u32)>
+}
+
+
+#[cfg(feature = "serde")]
+impl<P: Copy + PartialEq + Serialize> Serialize for Image<P> {
+
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut runs: Vec<(P, u32)> = Vec::new();
+        for p in self.data.iter() {
+            match runs.last_mut() {
+                Some((q, n)) if q == p => *n += 1,
+                _ => runs.push((*p, 1))
+            }
+        }
+        SerdeImage { width: self.size.x, height: self.size.y, runs }.serialize(serializer)
+    }
+}
+
+
+#[cfg(feature = "serde")]
+impl<'de, P: Copy + Deserialize<'de>> Deserialize<'de> for Image<P> {
+
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let img = SerdeImage::<P>::deserialize(deserializer)?;
+        if img.width < 0 || img.height < 0 {
+            return Err(D::Error::custom(format!("invalid image size {}x{}", img.width, img.height)));
+        }
+        // the size comes from the file, the pixels are only allocated as the runs are read
+        let len = match img.width.checked_mul(img.height) {
+            Some(len) => len as usize,
+            None => return Err(D::Error::custom(format!("invalid image size {}x{}", img.width, img.height)))
+        };
+        let mut data = Vec::new();
+        for (p, n) in img.runs {
+            if n as usize > len - data.len() {
+                return Err(D::Error::custom("more pixels than the size of the image"));
+            }
+            data.extend(std::iter::repeat_n(p, n as usize));
+        }
+        if data.len() != len {
+            return Err(D::Error::custom("less pixels than the size of the image"));
+        }
+        Ok(Image { data, size: vec2!(img.width, img.height), clip: None })
+    }
+}
+
+
+impl<P: Copy + PartialEq + Default, A: AsRef<Vec2>> Index<A> for Image<P> {
+    type Output = P;
+
+    /// Panics if `p` is out of the image, see `Image::get` for a checked access.
+    fn index(&self, p: A) -> &Self::Output {
+        let p = p.as_ref();
+        match self.index_of(p) {
+            Some(i) => &self.data[i],
+            None => panic!("pixel ({}, {}) is out of the image of size ({}, {})", p.x, p.y, self.size.x, self.size.y)
+        }
+    }
+}
+
+
+impl<P: Copy + PartialEq + Default, A: AsRef<Vec2>> IndexMut<A> for Image<P> {
+
+    /// Panics if `p` is out of the image, see `Image::set` for a checked access.
+    fn index_mut(&mut self, p: A) -> &mut Self::Output {
+        let p = p.as_ref();
+        match self.index_of(p) {
+            Some(i) => &mut self.data[i],
+            None => panic!("pixel ({}, {}) is out of the image of size ({}, {})", p.x, p.y, self.size.x, self.size.y)
+        }
+    }
+}
+
+
+impl<P: Copy + PartialEq + Default, A: AsRef<Vec2>> Index<A> for ImageView<'_, P> {
+    type Output = P;
+
+    /// Panics if `p` is out of the view, see `ImageView::get` for a checked access.
+    fn index(&self, p: A) -> &Self::Output {
+        let p = p.as_ref();
+        if p.x < 0 || p.y < 0 || p.x >= self.rect.size.x || p.y >= self.rect.size.y {
+            panic!("pixel ({}, {}) is out of the view of size ({}, {})", p.x, p.y, self.rect.size.x, self.rect.size.y);
+        }
+        &self.img[self.rect.pos + *p]
+    }
+}
@@ -27,29 +27,102 @@
 // HEAVELY inspired by the termion library
 
 
-use std::io::{Error, ErrorKind};
+use std::io::{self, Error, ErrorKind};
 use std::str;
 
 // use std::{fs, io};
-use std::io::{Read, Write, stdin, stdout};
+use std::io::{Read, stdin};
+use std::fs::File;
 
 use std::thread;
-use std::sync::mpsc;
+use std::sync::{mpsc, Mutex};
+use std::collections::{VecDeque, HashSet, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
 use crate::math::Vec2;
+use crate::rds::{self, Renderer};
+#[cfg(all(feature = "gamepad", target_os = "linux"))]
+use crate::gamepad::{self, GamepadEvent, GamepadButton, GamepadAxis};
 
 
+/// Event read from the terminal (or from a game controller). New kinds of events can be added by
+/// the features of the crate, so matches should have a wildcard arm.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum InputEvent {
     Key(KeyEvent),
     Mouse(MouseEvent),
-    Unsupported(Vec<u8>)
+    Unsupported(Vec<u8>),
+    /// SIGINT (Ctrl+C) or SIGTERM was received, see `Renderer::exit_requested`
+    Quit,
+    /// The terminal was resized, with the new size of the screen in pixels (see `Renderer::get_size`)
+    Resize(Vec2),
+    /// Game controller event, read through evdev (see `Input::gamepad_axis`)
+    #[cfg(all(feature = "gamepad", target_os = "linux"))]
+    Gamepad(GamepadEvent)
+}
+
+
+/// Key that was pressed, with the modifiers that were held.
+/// 
+/// Releases and repeats are only reported with the kitty keyboard protocol
+/// (see `Input::enable_kitty_keyboard`), otherwise all the events are presses.
+/// 
+/// # Usage
+/// 
+/// ```
+/// match event {
+///     InputEvent::Key(KeyEvent { key: Key::Char('q'), .. }) => quit(),
+///     InputEvent::Key(KeyEvent { key: Key::Char('s'), mods: Modifiers::CTRL, .. }) => save(),
+///     InputEvent::Key(KeyEvent { key: Key::Right, mods }) if mods.shift => select_right(),
+///     _ => ()
+/// }
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct KeyEvent {
+    pub key: Key,
+    pub mods: Modifiers,
+    pub kind: KeyKind
+}
+
+
+impl KeyEvent {
+
+    pub const fn new(key: Key, mods: Modifiers) -> Self {
+        Self { key, mods, kind: KeyKind::Press }
+    }
+
+
+    /// Key pressed without modifiers.
+    pub const fn plain(key: Key) -> Self {
+        Self { key, mods: Modifiers::NONE, kind: KeyKind::Press }
+    }
+
+
+    /// Returns the same key event with the kind `kind`.
+    pub const fn with_kind(self, kind: KeyKind) -> Self {
+        Self { kind, ..self }
+    }
+}
+
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum KeyKind {
+    #[default]
+    Press,
+    /// The key is held, sent by the terminal or by the key auto-repeat (see `Input::set_key_repeat`)
+    Repeat,
+    Release
 }
 
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
-pub enum KeyEvent {
+pub enum Key {
     Backspace,
+    Enter,
+    Tab,
+    Esc,
     Left,
     Right,
     Up,
@@ -58,113 +131,336 @@ pub enum KeyEvent {
     End,
     PageUp,
     PageDown,
-    BackTab, // Shift + Tab
     Delete,
     Insert,
-    F(u8),   // Only some function keys are reachable
+    F(u8),
+    /// Printable character, shift is already applied (eg. 'A' and not Shift + 'a'), except with the
+    /// kitty keyboard protocol where it is the unshifted key so that presses and releases match
     Char(char),
-    Alt(char),
-    Ctrl(char),
-    Null,
-    Esc
+    Null
+}
+
+
+/// Modifier keys held while a key is pressed. Terminals do not report all the combinations
+/// (eg. Ctrl + Shift + a is usually sent as Ctrl + a).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub alt: bool,
+    pub ctrl: bool
+}
+
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers { shift: false, alt: false, ctrl: false };
+    pub const SHIFT: Modifiers = Modifiers { shift: true, alt: false, ctrl: false };
+    pub const ALT: Modifiers = Modifiers { shift: false, alt: true, ctrl: false };
+    pub const CTRL: Modifiers = Modifiers { shift: false, alt: false, ctrl: true };
+
+
+    /// Decodes the modifier parameter of xterm sequences (eg. 5 in `ESC [ 1 ; 5 A` for Ctrl + Up).
+    fn from_param(param: u16) -> Modifiers {
+        let bits = param.saturating_sub(1);
+        Modifiers {
+            shift: bits & 1 != 0,
+            alt: bits & 2 != 0,
+            ctrl: bits & 4 != 0
+        }
+    }
 }
 
 
 // TODO: add modifiers (shift, ctrl, meta) to MouseEvent
+/// Mouse event, the position is in pixels of the main renderer, at the top left pixel of the
+/// cell under the pointer (eg. in `RenderMode::HalfBlock`, the cell (1, 1) is the pixel (0, 0)
+/// and the cell (3, 5) is the pixel (2, 8)).
+/// 
+/// # Usage
+/// 
+/// ```
+/// Input::enable_mouse();
+/// match inp.get_event() {
+///     Some(InputEvent::Mouse(MouseEvent::ButtonPressed(MouseButton::Left, pos))) => select(pos),
+///     Some(InputEvent::Mouse(MouseEvent::Hold(MouseButton::Left, pos))) => drag_to(pos),
+///     Some(InputEvent::Mouse(MouseEvent::ButtonPressed(MouseButton::WheelUp, _))) => zoom_in(),
+///     _ => ()
+/// }
+/// ```
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum MouseEvent {
     ButtonPressed(MouseButton, Vec2),
     ButtonReleased(MouseButton, Vec2),
+    /// The pointer moved while the button is held (drag)
     Hold(MouseButton, Vec2)
 }
 
 
+impl MouseEvent {
+
+    /// Returns the button of the event.
+    pub fn button(&self) -> MouseButton {
+        match self {
+            MouseEvent::ButtonPressed(b, _) | MouseEvent::ButtonReleased(b, _) | MouseEvent::Hold(b, _) => *b
+        }
+    }
+
+
+    /// Returns the position of the pointer in pixels.
+    pub fn pos(&self) -> Vec2 {
+        match self {
+            MouseEvent::ButtonPressed(_, p) | MouseEvent::ButtonReleased(_, p) | MouseEvent::Hold(_, p) => *p
+        }
+    }
+}
+
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum MouseButton {
     Left,
     Right,
     Middle,
     WheelUp,
-    WheelDown
+    WheelDown,
+    WheelLeft,
+    WheelRight
+}
+
+
+impl MouseButton {
+
+    /// Returns true for the wheel "buttons", that are pressed but never released.
+    pub fn is_wheel(&self) -> bool {
+        matches!(self, MouseButton::WheelUp | MouseButton::WheelDown | MouseButton::WheelLeft | MouseButton::WheelRight)
+    }
 }
 
 
 
+/// Converts 1-based cell coordinates to pixel coordinates of the main renderer.
 fn get_real_mouse_pos(cx: u16, cy: u16) -> Vec2 {
-    vec2!(cx as i32 - 1, 2 * (cy as i32) - 2)
+    let cell = rds::main_cell_size();
+    vec2!(cell.x * (cx as i32 - 1), cell.y * (cy as i32 - 1))
+}
+
+
+/// Decodes a SGR mouse report `ESC [ < Cb ; Cx ; Cy (M or m)`, `pressed` is true for `M`.
+///
+/// The low bits of `Cb` are the button, 32 is added for motion and 64 for the wheel,
+/// 4, 8 and 16 are the shift, meta and ctrl modifiers.
+fn parse_sgr_mouse(cb: u16, cx: u16, cy: u16, pressed: bool) -> Option<MouseEvent> {
+    let button = match (cb & 0b11, cb & 64 != 0) {
+        (0, false) => MouseButton::Left,
+        (1, false) => MouseButton::Middle,
+        (2, false) => MouseButton::Right,
+        (0, true) => MouseButton::WheelUp,
+        (1, true) => MouseButton::WheelDown,
+        (2, true) => MouseButton::WheelLeft,
+        (3, true) => MouseButton::WheelRight,
+        // motion without a button held, or X10 style release
+        _ => return None
+    };
+    let pos = get_real_mouse_pos(cx, cy);
+    Some(if cb & 32 != 0 && !button.is_wheel() {
+        MouseEvent::Hold(button, pos)
+    } else if pressed {
+        MouseEvent::ButtonPressed(button, pos)
+    } else {
+        MouseEvent::ButtonReleased(button, pos)
+    })
+}
+
+
+/// Remembers the button pressed in a legacy mouse report in `mb`, and uses it for releases and holds.
+fn legacy_mouse(mb: &mut MouseButton, event: MouseEvent) -> MouseEvent {
+    match event {
+        // the wheel is not held
+        MouseEvent::ButtonPressed(button, _) => {
+            if !button.is_wheel() {
+                *mb = button;
+            }
+            event
+        }
+        MouseEvent::ButtonReleased(_, pos) => MouseEvent::ButtonReleased(*mb, pos),
+        MouseEvent::Hold(_, pos) => MouseEvent::Hold(*mb, pos)
+    }
+}
+
+
+/// Parses all the events in `bytes`, that were read at once from the terminal.
+/// An escape that ends the bytes is the Esc key, since sequences are sent at once.
+///
+/// `mb` is the last pressed mouse button, the legacy (X10 and rxvt) reports do not tell which
+/// button is released or held.
+pub(crate) fn parse_events(bytes: &[u8], mb: &mut MouseButton) -> Vec<InputEvent> {
+    let mut iter = bytes.iter().map(|b| Ok(*b));
+    let mut events = Vec::new();
+    while let Some(Ok(item)) = iter.next() {
+        if let Ok(event) = parse_event(item, &mut iter, mb) {
+            events.push(event);
+        }
+    }
+    events
 }
 
 
 /// Parse an Event from `item` and possibly subsequent bytes through `iter`.
-fn parse_event<I>(item: u8, iter: &mut I) -> Result<InputEvent, Error>
+fn parse_event<I>(item: u8, iter: &mut I, mb: &mut MouseButton) -> Result<InputEvent, Error>
     where I: Iterator<Item = Result<u8, Error>>
 {
     let error = Error::new(ErrorKind::Other, "Could not parse an event");
+    let key = |key, mods| Ok(InputEvent::Key(KeyEvent::new(key, mods)));
     match item {
         b'\x1B' => {
             // This is an escape character, leading a control sequence.
-            Ok(match iter.next() {
+            match iter.next() {
+                None => key(Key::Esc, Modifiers::NONE),
                 Some(Ok(b'O')) => {
                     match iter.next() {
                         // F1-F4
-                        Some(Ok(val @ b'P'..=b'S')) => InputEvent::Key(KeyEvent::F(1 + val - b'P')),
-                        _ => return Err(error),
+                        Some(Ok(val @ b'P'..=b'S')) => key(Key::F(1 + val - b'P'), Modifiers::NONE),
+                        _ => Err(error),
                     }
                 }
                 Some(Ok(b'[')) => {
                     // This is a CSI sequence.
-                    parse_csi(iter).ok_or(error)?
+                    parse_csi(iter, mb).ok_or(error)
                 }
+                Some(Ok(b'\x1B')) => key(Key::Esc, Modifiers::ALT),
                 Some(Ok(c)) => {
-                    let ch = parse_utf8_char(c, iter)?;
-                    InputEvent::Key(KeyEvent::Alt(ch))
+                    // Alt + key
+                    match parse_event(c, iter, mb)? {
+                        InputEvent::Key(KeyEvent { key: k, mods, .. }) => key(k, Modifiers { alt: true, ..mods }),
+                        _ => Err(error)
+                    }
                 }
-                Some(Err(_)) | None => return Err(error),
-            })
-        }
-        b'\n' | b'\r' => Ok(InputEvent::Key(KeyEvent::Char('\n'))),
-        b'\t' => Ok(InputEvent::Key(KeyEvent::Char('\t'))),
-        b'\x7F' => Ok(InputEvent::Key(KeyEvent::Backspace)),
-        c @ b'\x01'..=b'\x1A' => Ok(InputEvent::Key(KeyEvent::Ctrl((c as u8 - 0x1 + b'a') as char))),
-        c @ b'\x1C'..=b'\x1F' => Ok(InputEvent::Key(KeyEvent::Ctrl((c as u8 - 0x1C + b'4') as char))),
-        b'\0' => Ok(InputEvent::Key(KeyEvent::Null)),
-        c => {
-            Ok({
-                let ch = parse_utf8_char(c, iter)?;
-                InputEvent::Key(KeyEvent::Char(ch))
-            })
+                Some(Err(_)) => Err(error),
+            }
         }
+        b'\n' | b'\r' => key(Key::Enter, Modifiers::NONE),
+        b'\t' => key(Key::Tab, Modifiers::NONE),
+        b'\x7F' => key(Key::Backspace, Modifiers::NONE),
+        c @ b'\x01'..=b'\x1A' => key(Key::Char((c - 0x1 + b'a') as char), Modifiers::CTRL),
+        c @ b'\x1C'..=b'\x1F' => key(Key::Char((c - 0x1C + b'4') as char), Modifiers::CTRL),
+        b'\0' => key(Key::Null, Modifiers::NONE),
+        c => key(Key::Char(parse_utf8_char(c, iter)?), Modifiers::NONE)
     }
 }
 
 
+/// Returns the key of the final byte `c` of a CSI sequence (eg. `A` for Up).
+fn csi_key(c: u8) -> Option<Key> {
+    Some(match c {
+        b'D' => Key::Left,
+        b'C' => Key::Right,
+        b'A' => Key::Up,
+        b'B' => Key::Down,
+        b'H' => Key::Home,
+        b'F' => Key::End,
+        b'P'..=b'S' => Key::F(1 + c - b'P'),
+        _ => return None
+    })
+}
+
+
+/// Returns the key of the number `n` of a `ESC [ n ~` sequence.
+fn tilde_key(n: u16) -> Option<Key> {
+    Some(match n {
+        1 | 7 => Key::Home,
+        2 => Key::Insert,
+        3 => Key::Delete,
+        4 | 8 => Key::End,
+        5 => Key::PageUp,
+        6 => Key::PageDown,
+        v @ 11..=15 => Key::F(v as u8 - 10),
+        v @ 17..=21 => Key::F(v as u8 - 11),
+        v @ 23..=26 => Key::F(v as u8 - 12),
+        v @ 28..=29 => Key::F(v as u8 - 13),
+        v @ 31..=34 => Key::F(v as u8 - 14),
+        _ => return None
+    })
+}
+
+
+/// Returns the key of the unicode key code `code` of a `ESC [ code u` sequence of the kitty keyboard protocol.
+/// Keys without a code point are in the private use area (eg. 57376 for F13).
+fn kitty_key(code: u32) -> Option<Key> {
+    Some(match code {
+        13 | 57414 => Key::Enter,
+        9 => Key::Tab,
+        27 => Key::Esc,
+        8 | 127 => Key::Backspace,
+        v @ 57376..=57398 => Key::F((v - 57376 + 13) as u8),
+        // keypad
+        v @ 57399..=57408 => Key::Char((b'0' + (v - 57399) as u8) as char),
+        57409 => Key::Char('.'),
+        57410 => Key::Char('/'),
+        57411 => Key::Char('*'),
+        57412 => Key::Char('-'),
+        57413 => Key::Char('+'),
+        57415 => Key::Char('='),
+        57417 => Key::Left,
+        57418 => Key::Right,
+        57419 => Key::Up,
+        57420 => Key::Down,
+        57421 => Key::PageUp,
+        57422 => Key::PageDown,
+        57423 => Key::Home,
+        57424 => Key::End,
+        57425 => Key::Insert,
+        57426 => Key::Delete,
+        // other functional keys, like the modifier keys themselves
+        57344..=63743 => return None,
+        c => Key::Char(char::from_u32(c)?)
+    })
+}
+
+
+/// Splits the parameters of a CSI sequence, with the sub-parameters separated by ':'
+/// (eg. `1;5:3` is `[[1], [5, 3]]`). Empty parameters are 0.
+fn csi_params(s: &str) -> Option<Vec<Vec<u32>>> {
+    s.split(';')
+        .map(|p| p.split(':').map(|n| if n.is_empty() {Some(0)} else {n.parse().ok()}).collect())
+        .collect()
+}
+
+
+/// Decodes the modifiers parameter of a key sequence, with the event kind as sub-parameter
+/// with the kitty keyboard protocol (eg. `5:3` for the release of a key with Ctrl).
+fn key_modifiers(param: Option<&Vec<u32>>) -> (Modifiers, KeyKind) {
+    let param = match param {
+        Some(p) => p,
+        None => return (Modifiers::NONE, KeyKind::Press)
+    };
+    let mods = Modifiers::from_param(param.first().map_or(1, |m| *m as u16));
+    let kind = match param.get(1) {
+        Some(2) => KeyKind::Repeat,
+        Some(3) => KeyKind::Release,
+        _ => KeyKind::Press
+    };
+    (mods, kind)
+}
+
+
 /// Parses a CSI sequence, just after reading ^[
 ///
 /// Returns None if an unrecognized sequence is found.
-fn parse_csi<I>(iter: &mut I) -> Option<InputEvent>
+fn parse_csi<I>(iter: &mut I, mb: &mut MouseButton) -> Option<InputEvent>
     where I: Iterator<Item = Result<u8, Error>>
 {
-    Some(match iter.next() {
-        Some(Ok(b'[')) => match iter.next() {
-            Some(Ok(val @ b'A'..=b'E')) => InputEvent::Key(KeyEvent::F(1 + val - b'A')),
+    let mut next = || iter.next().and_then(|r| r.ok());
+    Some(match next()? {
+        b'[' => match next()? {
+            val @ b'A'..=b'E' => InputEvent::Key(KeyEvent::plain(Key::F(1 + val - b'A'))),
             _ => return None,
         },
-        Some(Ok(b'D')) => InputEvent::Key(KeyEvent::Left),
-        Some(Ok(b'C')) => InputEvent::Key(KeyEvent::Right),
-        Some(Ok(b'A')) => InputEvent::Key(KeyEvent::Up),
-        Some(Ok(b'B')) => InputEvent::Key(KeyEvent::Down),
-        Some(Ok(b'H')) => InputEvent::Key(KeyEvent::Home),
-        Some(Ok(b'F')) => InputEvent::Key(KeyEvent::End),
-        Some(Ok(b'Z')) => InputEvent::Key(KeyEvent::BackTab),
-        Some(Ok(b'M')) => {
+        b'Z' => InputEvent::Key(KeyEvent::new(Key::Tab, Modifiers::SHIFT)),
+        b'M' => {
             // X10 emulation mouse encoding: ESC [ CB Cx Cy (6 characters only).
-            let mut next = || iter.next().unwrap().unwrap();
-
-            let cb = next() as i8 - 32;
+            let cb = next()? as i8 - 32;
             // (0, 0) are the coords for upper left.
-            let cx = next().saturating_sub(32) as u16;
-            let cy = next().saturating_sub(32) as u16;
-            InputEvent::Mouse(match cb & 0b11 {
+            let cx = next()?.saturating_sub(32) as u16;
+            let cy = next()?.saturating_sub(32) as u16;
+            InputEvent::Mouse(legacy_mouse(mb, match cb & 0b11 {
                 0 => {
                     if cb & 0x40 != 0 {
                         MouseEvent::ButtonPressed(MouseButton::WheelUp, get_real_mouse_pos(cx, cy))
@@ -180,138 +476,70 @@ fn parse_csi<I>(iter: &mut I) -> Option<InputEvent>
                     }
                 }
                 2 => MouseEvent::ButtonPressed(MouseButton::Right, get_real_mouse_pos(cx, cy)),
-                // default to Left button, will be modified down the line
+                // the released button is not reported
                 3 => MouseEvent::ButtonReleased(MouseButton::Left, get_real_mouse_pos(cx, cy)),
                 _ => return None,
-            })
+            }))
         }
-        Some(Ok(b'<')) => {
+        b'<' => {
             // xterm mouse encoding:
             // ESC [ < Cb ; Cx ; Cy (;) (M or m)
             let mut buf = Vec::new();
-            let mut c = iter.next().unwrap().unwrap();
-            while match c {
-                      b'm' | b'M' => false,
-                      _ => true,
-                  } {
+            let mut c = next()?;
+            while c != b'm' && c != b'M' {
                 buf.push(c);
-                c = iter.next().unwrap().unwrap();
+                c = next()?;
             }
-            let str_buf = String::from_utf8(buf).unwrap();
-            let nums = &mut str_buf.split(';');
-
-            let cb = nums.next()
-                .unwrap()
-                .parse::<u16>()
-                .unwrap();
-            let cx = nums.next()
-                .unwrap()
-                .parse::<u16>()
-                .unwrap();
-            let cy = nums.next()
-                .unwrap()
-                .parse::<u16>()
-                .unwrap();
-
-            let event = match cb {
-                0..=2 | 64..=65 => {
-                    let button = match cb {
-                        0 => MouseButton::Left,
-                        1 => MouseButton::Middle,
-                        2 => MouseButton::Right,
-                        64 => MouseButton::WheelUp,
-                        65 => MouseButton::WheelDown,
-                        _ => unreachable!(),
-                    };
-                    match c {
-                        b'M' => MouseEvent::ButtonPressed(button, get_real_mouse_pos(cx, cy)),
-                        // default to Left button, will be modified down the line
-                        b'm' => MouseEvent::ButtonReleased(MouseButton::Left, get_real_mouse_pos(cx, cy)),
-                        _ => return None,
-                    }
-                }
-                // default to Left button, will be modified down the line
-                32 => MouseEvent::Hold(MouseButton::Left, get_real_mouse_pos(cx, cy)),
-                // default to Left button, will be modified down the line
-                3 => MouseEvent::ButtonReleased(MouseButton::Left, get_real_mouse_pos(cx, cy)),
-                _ => return None,
-            };
+            let str_buf = String::from_utf8(buf).ok()?;
+            let nums: Vec<u16> = str_buf.split(';').map(|n| n.parse().ok()).collect::<Option<_>>()?;
+            let (cb, cx, cy) = (*nums.first()?, *nums.get(1)?, *nums.get(2)?);
 
-            InputEvent::Mouse(event)
+            InputEvent::Mouse(parse_sgr_mouse(cb, cx, cy, c == b'M')?)
         }
-        Some(Ok(c @ b'0'..=b'9')) => {
+        c @ b'0'..=b'9' => {
             // Numbered escape code.
-            let mut buf = Vec::new();
-            buf.push(c);
-            let mut c = iter.next().unwrap().unwrap();
+            let mut buf = vec![c];
+            let mut c = next()?;
             // The final byte of a CSI sequence can be in the range 64-126, so
             // let's keep reading anything else.
-            while c < 64 || c > 126 {
+            while !(64..=126).contains(&c) {
                 buf.push(c);
-                c = iter.next().unwrap().unwrap();
+                c = next()?;
             }
 
+            let str_buf = String::from_utf8(buf).ok()?;
+            let params = csi_params(&str_buf)?;
+            let nums: Vec<u16> = params.iter().map(|p| p[0] as u16).collect();
+            let (mods, kind) = key_modifiers(params.get(1));
+
             match c {
                 // rxvt mouse encoding:
                 // ESC [ Cb ; Cx ; Cy ; M
                 b'M' => {
-                    let str_buf = String::from_utf8(buf).unwrap();
-
-                    let nums: Vec<u16> = str_buf.split(';').map(|n| n.parse().unwrap()).collect();
-
-                    let cb = nums[0];
-                    let cx = nums[1];
-                    let cy = nums[2];
+                    let (cb, cx, cy) = (*nums.first()?, *nums.get(1)?, *nums.get(2)?);
 
                     let event = match cb {
                         32 => MouseEvent::ButtonPressed(MouseButton::Left, get_real_mouse_pos(cx, cy)),
                         33 => MouseEvent::ButtonPressed(MouseButton::Middle, get_real_mouse_pos(cx, cy)),
                         34 => MouseEvent::ButtonPressed(MouseButton::Right, get_real_mouse_pos(cx, cy)),
-                        // default to Left button, will be modified down the line
+                        // the released and held buttons are not reported
                         35 => MouseEvent::ButtonReleased(MouseButton::Left, get_real_mouse_pos(cx, cy)),
-                        // default to Left button, will be modified down the line
                         64 => MouseEvent::Hold(MouseButton::Left, get_real_mouse_pos(cx, cy)),
                         96 | 97 => MouseEvent::ButtonPressed(MouseButton::WheelUp, get_real_mouse_pos(cx, cy)),
                         _ => return None,
                     };
 
-                    InputEvent::Mouse(event)
-                }
-                // Special key code.
-                b'~' => {
-                    let str_buf = String::from_utf8(buf).unwrap();
-
-                    // This CSI sequence can be a list of semicolon-separated
-                    // numbers.
-                    let nums: Vec<u8> = str_buf.split(';').map(|n| n.parse().unwrap()).collect();
-
-                    if nums.is_empty() {
-                        return None;
-                    }
-
-                    // TODO: handle multiple values for key modififiers (ex: values
-                    // [3, 2] means Shift+Delete)
-                    if nums.len() > 1 {
-                        return None;
-                    }
-
-                    match nums[0] {
-                        1 | 7 => InputEvent::Key(KeyEvent::Home),
-                        2 => InputEvent::Key(KeyEvent::Insert),
-                        3 => InputEvent::Key(KeyEvent::Delete),
-                        4 | 8 => InputEvent::Key(KeyEvent::End),
-                        5 => InputEvent::Key(KeyEvent::PageUp),
-                        6 => InputEvent::Key(KeyEvent::PageDown),
-                        v @ 11..=15 => InputEvent::Key(KeyEvent::F(v - 10)),
-                        v @ 17..=21 => InputEvent::Key(KeyEvent::F(v - 11)),
-                        v @ 23..=24 => InputEvent::Key(KeyEvent::F(v - 12)),
-                        _ => return None,
-                    }
+                    InputEvent::Mouse(legacy_mouse(mb, event))
                 }
-                _ => return None,
+                // Special key code, with the modifiers as second number (eg. ESC [ 3 ; 2 ~ for Shift + Delete)
+                b'~' => InputEvent::Key(KeyEvent { key: tilde_key(*nums.first()?)?, mods, kind }),
+                // Kitty keyboard protocol: ESC [ code ; modifiers : kind u
+                b'u' => InputEvent::Key(KeyEvent { key: kitty_key(*params.first()?.first()?)?, mods, kind }),
+                // Modified arrows and function keys (eg. ESC [ 1 ; 5 A for Ctrl + Up)
+                c => InputEvent::Key(KeyEvent { key: csi_key(c)?, mods, kind })
             }
         }
-        _ => return None,
+        c => InputEvent::Key(KeyEvent::plain(csi_key(c)?)),
     })
 }
 
@@ -345,27 +573,383 @@ fn parse_utf8_char<I>(c: u8, iter: &mut I) -> Result<char, Error>
 }
 
 
+/// Key or mouse button that triggers an action of an `ActionMap`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Binding {
+    Key(Key),
+    Mouse(MouseButton),
+    /// Button of any game controller, the action is held while one of the controllers holds it
+    #[cfg(all(feature = "gamepad", target_os = "linux"))]
+    Gamepad(GamepadButton),
+    /// Button of the game controller with the given id (eg. one `ActionMap` per player)
+    #[cfg(all(feature = "gamepad", target_os = "linux"))]
+    GamepadOf(usize, GamepadButton)
+}
+
+
+impl From<Key> for Binding {
+    fn from(key: Key) -> Self {
+        Binding::Key(key)
+    }
+}
+
+
+impl From<MouseButton> for Binding {
+    fn from(button: MouseButton) -> Self {
+        Binding::Mouse(button)
+    }
+}
+
+
+#[cfg(all(feature = "gamepad", target_os = "linux"))]
+impl From<GamepadButton> for Binding {
+    fn from(button: GamepadButton) -> Self {
+        Binding::Gamepad(button)
+    }
+}
+
+
+#[cfg(all(feature = "gamepad", target_os = "linux"))]
+impl From<(usize, GamepadButton)> for Binding {
+    fn from((id, button): (usize, GamepadButton)) -> Self {
+        Binding::GamepadOf(id, button)
+    }
+}
+
+
+/// Time a binding was last seen held, to release the keys of terminals that do not report releases
+#[derive(Debug, Clone, Copy)]
+struct BindingState {
+    last_seen: Instant,
+    repeating: bool,
+    /// Released at the next update (mouse wheel)
+    momentary: bool
+}
+
+
+/// Named actions ("jump", "left", ...) bound to keys and mouse buttons, that can be remapped at runtime.
+/// The input events of each frame are given to `update`, then the state of the actions can be queried.
+/// 
+/// Modifiers are ignored. Without the kitty keyboard protocol (see `Input::enable_kitty_keyboard`), terminals
+/// do not report key releases: a key is considered held while the terminal repeats it.
+/// 
+/// # Usage
+/// 
+/// ```
+/// let mut actions = ActionMap::new();
+/// actions.bind("jump", Key::Char(' '));
+/// actions.bind("jump", MouseButton::Left);
+/// actions.bind("left", Key::Left);
+/// 
+/// loop {
+///     let events: Vec<InputEvent> = std::iter::from_fn(|| inp.get_event()).collect();
+///     actions.update(&events);
+///     if actions.pressed("jump") {
+///         player.jump();
+///     }
+///     if actions.held("left") {
+///         player.x -= 1;
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ActionMap {
+    bindings: HashMap<String, Vec<Binding>>,
+    /// Held bindings, with the id of the controller that holds them (0 for the keyboard and the mouse)
+    states: HashMap<(Binding, usize), BindingState>,
+    held: HashSet<String>,
+    pressed: HashSet<String>,
+    released: HashSet<String>
+}
+
+
+impl ActionMap {
+
+    /// Creates an action map without bindings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+
+    /// Binds `binding` to `action`, in addition to its other bindings.
+    pub fn bind<B>(&mut self, action: &str, binding: B) 
+        where B: Into<Binding>
+    {
+        let binding = binding.into();
+        let bindings = self.bindings.entry(action.to_string()).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+
+    /// Removes `binding` from the bindings of `action`.
+    pub fn unbind<B>(&mut self, action: &str, binding: B) 
+        where B: Into<Binding>
+    {
+        let binding = binding.into();
+        if let Some(bindings) = self.bindings.get_mut(action) {
+            bindings.retain(|b| *b != binding);
+        }
+    }
+
+
+    /// Replaces all the bindings of `action` by `binding` (eg. from the controls menu of a game).
+    pub fn rebind<B>(&mut self, action: &str, binding: B) 
+        where B: Into<Binding>
+    {
+        self.bindings.insert(action.to_string(), vec![binding.into()]);
+    }
+
+
+    /// Returns the bindings of `action`.
+    pub fn bindings(&self, action: &str) -> &[Binding] {
+        self.bindings.get(action).map_or(&[], |b| b)
+    }
+
+
+    /// Updates the state of the actions with the events received since the previous update.
+    /// Should be called once per frame, even if there was no event.
+    pub fn update<'a, I>(&mut self, events: I) 
+        where I: IntoIterator<Item = &'a InputEvent>
+    {
+        let now = Instant::now();
+        self.states.retain(|_, s| !s.momentary);
+
+        let mut pressed_bindings = HashSet::new();
+        let held = |states: &mut HashMap<(Binding, usize), BindingState>, b: Binding, id: usize, repeat: bool, momentary: bool| {
+            let repeating = repeat || states.contains_key(&(b, id));
+            states.insert((b, id), BindingState { last_seen: now, repeating, momentary });
+        };
+        for event in events {
+            match event {
+                InputEvent::Key(KeyEvent { key, kind: KeyKind::Release, .. }) => { self.states.remove(&(Binding::Key(*key), 0)); }
+                InputEvent::Key(KeyEvent { key, kind, .. }) => {
+                    held(&mut self.states, Binding::Key(*key), 0, *kind == KeyKind::Repeat, false);
+                    if *kind == KeyKind::Press {
+                        pressed_bindings.insert(Binding::Key(*key));
+                    }
+                }
+                InputEvent::Mouse(MouseEvent::ButtonPressed(button, _)) => {
+                    held(&mut self.states, Binding::Mouse(*button), 0, false, button.is_wheel());
+                    pressed_bindings.insert(Binding::Mouse(*button));
+                }
+                InputEvent::Mouse(MouseEvent::ButtonReleased(button, _)) => { self.states.remove(&(Binding::Mouse(*button), 0)); }
+                #[cfg(all(feature = "gamepad", target_os = "linux"))]
+                InputEvent::Gamepad(GamepadEvent::ButtonPressed(id, button)) => {
+                    for b in [Binding::Gamepad(*button), Binding::GamepadOf(*id, *button)] {
+                        held(&mut self.states, b, *id, false, false);
+                        pressed_bindings.insert(b);
+                    }
+                }
+                #[cfg(all(feature = "gamepad", target_os = "linux"))]
+                InputEvent::Gamepad(GamepadEvent::ButtonReleased(id, button)) => {
+                    self.states.remove(&(Binding::Gamepad(*button), *id));
+                    self.states.remove(&(Binding::GamepadOf(*id, *button), *id));
+                }
+                #[cfg(all(feature = "gamepad", target_os = "linux"))]
+                InputEvent::Gamepad(GamepadEvent::Disconnected(id)) => {
+                    self.states.retain(|(b, i), _| i != id || !matches!(b, Binding::Gamepad(_) | Binding::GamepadOf(..)));
+                }
+                _ => ()
+            }
+        }
+
+        // keys without release events are released when the terminal stops repeating them
+        if !KITTY_KEYBOARD.load(Ordering::Relaxed) {
+            self.states.retain(|(b, _), s| {
+                !matches!(b, Binding::Key(_)) || now < s.last_seen + if s.repeating {TERM_REPEAT_INTERVAL} else {TERM_REPEAT_DELAY}
+            });
+        }
+
+        let prev = std::mem::take(&mut self.held);
+        self.pressed.clear();
+        self.released.clear();
+        for (action, bindings) in &self.bindings {
+            let held = bindings.iter().any(|b| self.states.keys().any(|(s, _)| s == b));
+            let pressed = bindings.iter().any(|b| pressed_bindings.contains(b));
+            let was_held = prev.contains(action);
+            if held {
+                self.held.insert(action.clone());
+            }
+            if pressed && !was_held {
+                self.pressed.insert(action.clone());
+            }
+            if (was_held || pressed) && !held {
+                self.released.insert(action.clone());
+            }
+        }
+    }
+
+
+    /// Returns true if `action` started being held during the last update.
+    pub fn pressed(&self, action: &str) -> bool {
+        self.pressed.contains(action)
+    }
+
+
+    /// Returns true if `action` is held.
+    pub fn held(&self, action: &str) -> bool {
+        self.held.contains(action)
+    }
+
+
+    /// Returns true if `action` stopped being held during the last update.
+    pub fn released(&self, action: &str) -> bool {
+        self.released.contains(action)
+    }
+}
+
+
+/// Key auto-repeat settings, see `Input::set_key_repeat`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct KeyRepeat {
+    /// Time between the key press and the first repeat
+    pub delay: Duration,
+    /// Time between two repeats
+    pub interval: Duration
+}
+
+
+impl KeyRepeat {
+
+    pub const fn new(delay: Duration, interval: Duration) -> Self {
+        Self { delay, interval }
+    }
+}
+
+
+/// Maximum time the terminal takes to start repeating a held key
+const TERM_REPEAT_DELAY: Duration = Duration::from_millis(700);
+
+/// Maximum time between two repeats of a held key sent by the terminal
+const TERM_REPEAT_INTERVAL: Duration = Duration::from_millis(150);
+
+
+/// Escape codes that stop the mouse reports, also written when the Renderer singleton exits
+pub(crate) const DISABLE_MOUSE: &str = "\x1b[?1006l\x1b[?1015l\x1b[?1002l\x1b[?1000l";
+
+
+/// Key that is considered held down by the key repeat synthesis
+struct HeldKey {
+    key: KeyEvent,
+    /// True once the terminal started to repeat the key
+    repeating: bool,
+    last_seen: Instant,
+    next_repeat: Instant
+}
+
+
+impl HeldKey {
+
+    /// Returns true if the terminal stopped repeating the key
+    fn is_released(&self, now: Instant) -> bool {
+        now >= self.release_time()
+    }
+
+
+    /// Returns when the key will be considered released if the terminal does not repeat it
+    fn release_time(&self) -> Instant {
+        self.last_seen + if self.repeating {TERM_REPEAT_INTERVAL} else {TERM_REPEAT_DELAY}
+    }
+
+
+    /// Returns when the held key should be checked again
+    fn next_update(&self) -> Instant {
+        if self.repeating {
+            self.next_repeat.min(self.release_time())
+        } else {
+            self.release_time()
+        }
+    }
+}
+
+
 /// Input Server Singleton instance
 static mut INPUT_SERVER: Option<Input> = None;
 
 
+/// True while the kitty keyboard protocol is enabled
+static KITTY_KEYBOARD: AtomicBool = AtomicBool::new(false);
+
+
+/// Key that toggles the debug console, see `Input::set_console_key`
+static CONSOLE_KEY: Mutex<Option<Key>> = Mutex::new(None);
+
+
+/// Interval at which exit signals are checked while waiting for an event
+const SIGNAL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+
 /// The Input is a singleton that handles async io operations
 /// 
 /// # Usage
 /// 
-/// To get events from keyboard and mouse, there are two functions:
+/// To get events from keyboard and mouse, there are three functions:
 /// 
 /// get_event - returns Some(InputEvent) or None depending on weather there was an input
 /// 
 /// get_event_blocking - waits for an event and returns it
 /// 
+/// wait_event - waits for a key, mouse, resize or quit event and returns it
+/// 
 /// # Mouse
 /// 
 /// To have mouse input or not use enable_mouse or disable_mouse
 /// by default, there is no mouse input
+/// 
+/// # Frame synchronization
+/// 
+/// When frame sync is enabled (see enable_frame_sync), the events are sampled when
+/// `Renderer::begin_draw` is called: get_event only returns the events that occured
+/// before the start of the current frame. Events that happen while the frame is built
+/// are kept for the next one.
+/// 
+/// # Key repeat
+/// 
+/// By default, held keys are repeated by the terminal with the settings of the system.
+/// Use set_key_repeat to replace those repeats with repeats at a fixed rate.
+/// 
+/// # Key releases
+/// 
+/// Legacy terminal input only reports key presses. Use enable_kitty_keyboard on terminals that
+/// support the kitty keyboard protocol to also get the releases and know which keys are held (is_held).
+/// 
+/// # Exit signals
+/// 
+/// Once the Renderer singleton is created, SIGINT (or Ctrl+C) and SIGTERM are reported once as
+/// `InputEvent::Quit`, before the other pending events. Terminal resizes (SIGWINCH) are reported
+/// as `InputEvent::Resize`.
+/// 
+/// # Debug console
+/// 
+/// The key set with set_console_key shows and hides the debug console of the renderer (see `Renderer::log`).
+/// 
+/// # Game controllers
+/// 
+/// With the `gamepad` feature, game controllers are read through evdev (`/dev/input/event*`) and reported
+/// as `InputEvent::Gamepad`; use gamepad_axis and is_gamepad_held to get their state. The user needs read
+/// access to the devices (usually by being in the `input` group), otherwise no controller is found.
 pub struct Input {
     _server_handle: Option<thread::JoinHandle<()>>,
-    input_recv: mpsc::Receiver<InputEvent>
+    input_recv: mpsc::Receiver<InputEvent>,
+
+    frame_sync: bool,
+    frame_events: VecDeque<InputEvent>,
+
+    key_repeat: Option<KeyRepeat>,
+    held_key: Option<HeldKey>,
+
+    /// Keys that are pressed, with the kitty keyboard protocol
+    held_keys: HashSet<Key>,
+
+    #[cfg(all(feature = "gamepad", target_os = "linux"))]
+    gamepads: HashSet<usize>,
+    #[cfg(all(feature = "gamepad", target_os = "linux"))]
+    gamepad_buttons: HashSet<(usize, GamepadButton)>,
+    #[cfg(all(feature = "gamepad", target_os = "linux"))]
+    gamepad_axes: HashMap<(usize, GamepadAxis), f32>
 }
 
 
@@ -375,36 +959,67 @@ impl Input {
     fn init() -> Self {
         let (input_send, input_recv) = mpsc::channel();
 
+        let reader: Box<dyn Read + Send> = if rds::uses_controlling_terminal() {
+            Box::new(File::open("/dev/tty").expect("Could not open /dev/tty"))
+        } else {
+            Box::new(stdin())
+        };
+
+        #[cfg(all(feature = "gamepad", target_os = "linux"))]
+        gamepad::spawn_server(input_send.clone());
+
         let handle = thread::spawn(move || {
+            let mut reader = reader;
             let mut mb = MouseButton::Left;
+            let mut buf = [0; 1024];
             loop {
-                let mut stdin = stdin().bytes();
-
-                if let Some(Ok(item)) = stdin.next() {
-                    match parse_event(item, &mut stdin) {
-                        Ok(evt) => {
-                            let event = match evt {
-                                InputEvent::Mouse(MouseEvent::ButtonPressed(button, _)) => {
-                                    mb = button;
-                                    evt
-                                }
-                                InputEvent::Mouse(MouseEvent::ButtonReleased(_, pos)) =>
-                                    InputEvent::Mouse(MouseEvent::ButtonReleased(mb, pos)),
-                                InputEvent::Mouse(MouseEvent::Hold(_, pos)) =>
-                                    InputEvent::Mouse(MouseEvent::Hold(mb, pos)),
-                                _ => evt
-                            };
-                            input_send.send(event).expect("input recv dropped")
-                        }
-                        Err(_) => {}
+                let n = match reader.read(&mut buf) {
+                    Ok(n) if n > 0 => n,
+                    // nothing to read anymore, avoid a busy loop
+                    _ => {
+                        thread::sleep(SIGNAL_POLL_INTERVAL);
+                        continue;
                     }
                 };
+                for evt in parse_events(&buf[..n], &mut mb) {
+                    let event = match evt {
+                        InputEvent::Key(KeyEvent { key: Key::Char('c'), mods: Modifiers::CTRL, kind: KeyKind::Press }) => {
+                            rds::interrupt();
+                            evt
+                        }
+                        // the console key is not reported
+                        InputEvent::Key(KeyEvent { key, kind, .. }) if Some(key) == *CONSOLE_KEY.lock().unwrap() => {
+                            if kind == KeyKind::Press {
+                                rds::toggle_console();
+                            }
+                            continue;
+                        }
+                        _ => evt
+                    };
+                    input_send.send(event).expect("input recv dropped");
+                    Renderer::request_redraw();
+                }
             }
         });
 
         Self {
             _server_handle: Some(handle),
-            input_recv: input_recv
+            input_recv: input_recv,
+
+            frame_sync: false,
+            frame_events: VecDeque::new(),
+
+            key_repeat: None,
+            held_key: None,
+
+            held_keys: HashSet::new(),
+
+            #[cfg(all(feature = "gamepad", target_os = "linux"))]
+            gamepads: HashSet::new(),
+            #[cfg(all(feature = "gamepad", target_os = "linux"))]
+            gamepad_buttons: HashSet::new(),
+            #[cfg(all(feature = "gamepad", target_os = "linux"))]
+            gamepad_axes: HashMap::new()
         }
     }
 
@@ -425,29 +1040,325 @@ impl Input {
     }
 
 
+    /// Called by the renderer at the start of each frame.
+    /// Does nothing if the Input singleton was never created.
+    pub(crate) fn sample_frame() {
+        unsafe {
+            if let Some(inp) = (*std::ptr::addr_of_mut!(INPUT_SERVER)).as_mut() {
+                if inp.frame_sync {
+                    inp.frame_events.extend(inp.input_recv.try_iter());
+                }
+            }
+        }
+    }
+
+
     /// If there was an event, return it.
     /// Never blocks the current thread.
+    /// 
+    /// With frame sync enabled, only the events that occured before the last
+    /// `begin_draw` are returned.
     pub fn get_event(&mut self) -> Option<InputEvent> {
-        self.input_recv.try_recv().ok()
+        if rds::take_quit_event() {
+            return Some(InputEvent::Quit);
+        }
+        if rds::take_resize_event() {
+            return Some(InputEvent::Resize(Renderer::get_size()));
+        }
+
+        loop {
+            let event = match self.frame_events.pop_front() {
+                Some(event) => Some(event),
+                None if self.frame_sync => None,
+                None => self.input_recv.try_recv().ok()
+            };
+
+            match event {
+                Some(event) => if let Some(event) = self.filter_repeat(event) {
+                    return Some(event);
+                }
+                None => return self.synthesize_repeat()
+            }
+        }
     }
 
 
     /// Wait for an InputEvent to occur and return it.
+    /// 
+    /// With frame sync enabled, the sampled events are returned first; if there are none left,
+    /// this waits for the next event regardless of the frame it belongs to.
     pub fn get_event_blocking(&mut self) -> InputEvent {
-        self.input_recv.recv().ok().expect("Input thread was killed")
+        loop {
+            if let Some(event) = self.get_event() {
+                return event;
+            }
+
+            let event = match self.held_key.as_ref().map(|k| k.next_update()) {
+                Some(next_update) => {
+                    let timeout = next_update.saturating_duration_since(Instant::now());
+                    match self.input_recv.recv_timeout(timeout) {
+                        Ok(event) => event,
+                        Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                        Err(mpsc::RecvTimeoutError::Disconnected) => panic!("Input thread was killed")
+                    }
+                }
+                None => match self.input_recv.recv_timeout(SIGNAL_POLL_INTERVAL) {
+                    Ok(event) => event,
+                    Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => panic!("Input thread was killed")
+                }
+            };
+
+            if let Some(event) = self.filter_repeat(event) {
+                return event;
+            }
+        }
+    }
+
+
+    /// Blocks until a key, mouse, resize or quit event arrives and returns it,
+    /// for applications that only redraw after an event (eg. turn-based games).
+    /// Unlike `get_event_blocking`, unsupported sequences are skipped.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// loop {
+    ///     match inp.wait_event() {
+    ///         InputEvent::Key(KeyEvent { key: Key::Char('q'), .. }) | InputEvent::Quit => break,
+    ///         InputEvent::Key(event) => game.play(event),
+    ///         _ => ()
+    ///     }
+    ///     draw(&mut renderer, &game);
+    /// }
+    /// ```
+    pub fn wait_event(&mut self) -> InputEvent {
+        loop {
+            match self.get_event_blocking() {
+                InputEvent::Unsupported(_) => continue,
+                event => return event
+            }
+        }
+    }
+
+
+    /// Sets the key auto-repeat: when `Some`, the repeats sent by the terminal are replaced by repeats
+    /// with the given delay and interval, so that held keys behave the same on all terminals.
+    /// 
+    /// Since most terminals do not report key releases, a key is considered held while the terminal repeats it.
+    /// The first synthesized repeat is sent once the terminal started repeating the key, so the actual delay
+    /// can not be shorter than the delay of the system.
+    pub fn set_key_repeat(&mut self, repeat: Option<KeyRepeat>) {
+        self.key_repeat = repeat;
+        self.held_key = None;
+    }
+
+
+    /// Tracks held keys, returns None if `event` is a repeat from the terminal that should be ignored.
+    fn filter_repeat(&mut self, event: InputEvent) -> Option<InputEvent> {
+        self.track_held(&event);
+        let repeat = match self.key_repeat {
+            Some(r) => r,
+            None => return Some(event)
+        };
+        let key = match event {
+            InputEvent::Key(key) => key,
+            _ => return Some(event)
+        };
+
+        let now = Instant::now();
+        if key.kind == KeyKind::Release {
+            if self.held_key.as_ref().is_some_and(|held| held.key.key == key.key) {
+                self.held_key = None;
+            }
+            return Some(event);
+        }
+        if let Some(held) = &mut self.held_key {
+            if held.key.key == key.key && held.key.mods == key.mods && !held.is_released(now) {
+                held.repeating = true;
+                held.last_seen = now;
+                return None;
+            }
+        }
+
+        self.held_key = Some(HeldKey {
+            key: key.with_kind(KeyKind::Repeat),
+            repeating: false,
+            last_seen: now,
+            next_repeat: now + repeat.delay
+        });
+        Some(event)
+    }
+
+
+    /// Updates the keys held with the kitty keyboard protocol, that reports the releases.
+    /// Also updates the state of the game controllers.
+    fn track_held(&mut self, event: &InputEvent) {
+        if let InputEvent::Key(key) = event {
+            match key.kind {
+                KeyKind::Release => {
+                    self.held_keys.remove(&key.key);
+                }
+                _ if KITTY_KEYBOARD.load(Ordering::Relaxed) => {
+                    self.held_keys.insert(key.key);
+                }
+                _ => ()
+            }
+        }
+        #[cfg(all(feature = "gamepad", target_os = "linux"))]
+        if let InputEvent::Gamepad(event) = event {
+            match *event {
+                GamepadEvent::Connected(id) => {
+                    self.gamepads.insert(id);
+                }
+                GamepadEvent::Disconnected(id) => {
+                    self.gamepads.remove(&id);
+                    self.gamepad_buttons.retain(|(i, _)| *i != id);
+                    self.gamepad_axes.retain(|(i, _), _| *i != id);
+                }
+                GamepadEvent::ButtonPressed(id, b) => {
+                    self.gamepad_buttons.insert((id, b));
+                }
+                GamepadEvent::ButtonReleased(id, b) => {
+                    self.gamepad_buttons.remove(&(id, b));
+                }
+                GamepadEvent::Axis(id, a, v) => {
+                    self.gamepad_axes.insert((id, a), v);
+                }
+            }
+        }
+    }
+
+
+    /// Returns true if `key` is currently held down.
+    /// 
+    /// Only available with the kitty keyboard protocol (see `enable_kitty_keyboard`), since legacy
+    /// terminal input does not report key releases: always returns false otherwise.
+    /// The keys are updated when the events are returned by get_event, get_event_blocking or wait_event.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// Input::enable_kitty_keyboard();
+    /// loop {
+    ///     while let Some(_) = inp.get_event() {}
+    ///     if inp.is_held(Key::Right) {
+    ///         player.x += 1;
+    ///     }
+    /// }
+    /// ```
+    pub fn is_held(&self, key: Key) -> bool {
+        self.held_keys.contains(&key)
+    }
+
+
+    /// Returns the ids of the connected game controllers, in increasing order.
+    #[cfg(all(feature = "gamepad", target_os = "linux"))]
+    pub fn gamepads(&self) -> Vec<usize> {
+        let mut ids: Vec<usize> = self.gamepads.iter().copied().collect();
+        ids.sort();
+        ids
+    }
+
+
+    /// Returns true if `button` of the controller `id` is held down.
+    /// Like is_held, the buttons are updated when the events are returned.
+    #[cfg(all(feature = "gamepad", target_os = "linux"))]
+    pub fn is_gamepad_held(&self, id: usize, button: GamepadButton) -> bool {
+        self.gamepad_buttons.contains(&(id, button))
+    }
+
+
+    /// Returns the value of `axis` of the controller `id` (0 if it is not connected), see `GamepadAxis`.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// while let Some(_) = inp.get_event() {}
+    /// for id in inp.gamepads() {
+    ///     players[id].x += inp.gamepad_axis(id, GamepadAxis::LeftX) * speed * dt;
+    /// }
+    /// ```
+    #[cfg(all(feature = "gamepad", target_os = "linux"))]
+    pub fn gamepad_axis(&self, id: usize, axis: GamepadAxis) -> f32 {
+        self.gamepad_axes.get(&(id, axis)).copied().unwrap_or(0.)
+    }
+
+
+    /// Returns a repeat of the held key if it is time for one.
+    fn synthesize_repeat(&mut self) -> Option<InputEvent> {
+        let interval = self.key_repeat?.interval;
+        let held = self.held_key.as_mut()?;
+        let now = Instant::now();
+
+        if held.is_released(now) {
+            self.held_key = None;
+            return None;
+        }
+        if !held.repeating || now < held.next_repeat {
+            return None;
+        }
+
+        held.next_repeat = (held.next_repeat + interval).max(now);
+        Some(InputEvent::Key(held.key))
+    }
+
+
+    /// Enable frame synchronized input: events are sampled at each `Renderer::begin_draw`.
+    pub fn enable_frame_sync(&mut self) {
+        self.frame_sync = true;
+    }
+
+
+    /// Disable frame synchronized input. Events that were already sampled are still returned first.
+    pub fn disable_frame_sync(&mut self) {
+        self.frame_sync = false;
+    }
+
+
+    /// Returns true if frame synchronized input is enabled.
+    pub fn is_frame_synced(&self) -> bool {
+        self.frame_sync
     }
 
 
     /// Enable MouseEvent.
     pub fn enable_mouse() {
-        print!("\x1b[?1000h\x1b[?1002h\x1b[?1015h\x1b[?1006h");
-        stdout().flush().expect("Could not write to stdout");
+        rds::write_to_terminal("\x1b[?1000h\x1b[?1002h\x1b[?1015h\x1b[?1006h").expect("Could not write to the terminal");
     }
 
 
     /// Disable MouseEvent.
     pub fn disable_mouse() {
-        print!("\x1b[?1006l\x1b[?1015l\x1b[?1002l\x1b[?1000l");
-        stdout().flush().expect("Could not write to stdout");
+        rds::write_to_terminal(DISABLE_MOUSE).expect("Could not write to the terminal");
+    }
+
+
+    /// Enable the kitty keyboard protocol, so that key releases and repeats are reported
+    /// (see `KeyKind` and `is_held`). Terminals that do not support it ignore the request
+    /// and keep sending legacy key presses.
+    /// 
+    /// All the keys are reported as escape codes: Char keys are not shifted and the modifier
+    /// keys alone are not reported.
+    pub fn enable_kitty_keyboard() {
+        // disambiguate escape codes (1), report event types (2), report all keys as escape codes (8)
+        rds::write_to_terminal("\x1b[>11u").expect("Could not write to the terminal");
+        KITTY_KEYBOARD.store(true, Ordering::Relaxed);
+    }
+
+
+    /// Sets the key that shows and hides the debug console of the renderer (see `Renderer::log`),
+    /// or None to disable it (the default). The events of this key are not reported.
+    pub fn set_console_key(key: Option<Key>) {
+        *CONSOLE_KEY.lock().unwrap() = key;
+    }
+
+
+    /// Disable the kitty keyboard protocol, called when the Renderer singleton exits.
+    pub fn disable_kitty_keyboard() -> io::Result<()> {
+        if KITTY_KEYBOARD.swap(false, Ordering::Relaxed) {
+            rds::write_to_terminal("\x1b[<u")?;
+        }
+        Ok(())
     }
 }
@@ -38,9 +38,26 @@ extern crate image;
 #[macro_use]
 pub mod math;
 pub mod img;
+pub mod text;
+pub mod filter;
+pub mod sprite;
+pub mod particles;
+pub mod noise;
+pub mod raster;
+pub mod game;
+pub mod ui;
+pub mod widgets;
+#[cfg(all(feature = "gamepad", target_os = "linux"))]
+pub mod gamepad;
 
 pub mod rds;
 pub mod input;
+pub mod backend;
+
+mod glyph;
+mod dither;
+mod terminfo;
+mod record;
 
 
 
@@ -48,39 +65,50 @@ pub mod input;
 #[cfg(test)]
 mod tests {
 
-    use crate::rds::Renderer;
+    use crate::rds::{Renderer, RendererGuard, RenderMode, ColorDepth};
+    use crate::glyph;
+    use crate::text;
+    use crate::dither;
+    use crate::filter;
+    use crate::sprite;
+    use crate::particles;
+    use crate::game;
+    use crate::noise::{Noise, NoiseKind};
+    use crate::raster::{DepthBuffer, Vertex, Shading};
+    use crate::rds;
+    use crate::backend;
 
-    use crate::math::Vec2;
+    use crate::math::{Vec2, Rect, Transform2};
     use crate::img::*;
-    use crate::input::{Input, InputEvent, KeyEvent, MouseEvent};
+    use crate::input::{self, Input, InputEvent, KeyEvent, Key, KeyKind, Modifiers, MouseEvent, MouseButton, ActionMap};
 
-    use std::sync::{Arc, Mutex};
+    use std::sync::Arc;
 
 
     #[test]
     fn renderer() {
         // load an image and draw it on screen
-        let img = Arc::new(Mutex::new(Image::load("icon.png").unwrap()));
+        let img = Arc::new(Image::load("icon.png").unwrap());
 
         // get the renderer
         let rdr = Renderer::get();
 
 
         // draw a frame on screen
-        rdr.begin_draw();
+        rdr.begin_draw().unwrap();
         rdr.draw_line((2, 7), (28, 6), Color::WHITE);
-        rdr.draw_rect((40, 15), (15, -10), Color::RED);
-        rdr.draw_rect_boundary((40, 15), (15, -10), Color::CHOCOLATE);
+        rdr.draw_rect(Rect::new((40, 15), (15, -10)), Color::RED);
+        rdr.draw_rect_boundary(Rect::new((40, 15), (15, -10)), Color::CHOCOLATE);
         rdr.draw_ellipse_boundary((45, 25), (25, 8), Color::AQUAMARINE);
 
         rdr.draw_ellipse_boundary((60, 30), (4, 4), Color::DEEP_PINK);
 
-        rdr.draw_rect((80, 5), (16, 8), Color::CORAL);
+        rdr.draw_rect(Rect::new((80, 5), (16, 8)), Color::CORAL);
         rdr.draw_whole_image_alpha(img.clone(), (80, 5), Color::BLACK);
 
         rdr.ring_bell();
 
-        rdr.end_draw();
+        rdr.end_draw().unwrap();
         
         
         // wait for input and exit
@@ -93,7 +121,7 @@ mod tests {
 
     #[test]
     fn input() {
-        let rdr = Renderer::get();
+        let mut rdr = RendererGuard::new();
         let inp = Input::get();
         Input::enable_mouse();
 
@@ -106,12 +134,12 @@ mod tests {
             match inp.get_event() {
                 Some(event) => {
                     match event {
-                    InputEvent::Key(event) => match event {
-                        KeyEvent::Ctrl('c') => Renderer::exit(),
-                        KeyEvent::Up        => if pos.y >  1            {pos.y -= 1},
-                        KeyEvent::Down      => if pos.y <= size.y - 2   {pos.y += 1},
-                        KeyEvent::Left      => if pos.x >  1            {pos.x -= 1},
-                        KeyEvent::Right     => if pos.x <= size.x - 2   {pos.x += 1},
+                    InputEvent::Key(event) => match event.key {
+                        Key::Char('c') if event.mods.ctrl => break,
+                        Key::Up        => if pos.y >  1            {pos.y -= 1},
+                        Key::Down      => if pos.y <= size.y - 2   {pos.y += 1},
+                        Key::Left      => if pos.x >  1            {pos.x -= 1},
+                        Key::Right     => if pos.x <= size.x - 2   {pos.x += 1},
                         _ => ()
                     }
                     InputEvent::Mouse(event) => match event {
@@ -119,6 +147,7 @@ mod tests {
                             => pos = mpos,
                         _ => ()
                     }
+                    InputEvent::Quit => break,
                     _ => ()
                 }
                 }
@@ -126,11 +155,2137 @@ mod tests {
             };
 
             // draw on screen
-            rdr.begin_draw();
+            rdr.begin_draw().unwrap();
             rdr.clear_screen(Color::BLACK);
-            rdr.draw_rect_boundary(Vec2::ZERO, size - vec2!(1, 1), Color::BROWN);
+            rdr.draw_rect_boundary(Rect::new(Vec2::ZERO, size - vec2!(1, 1)), Color::BROWN);
             rdr.draw_point(pos, Color::WHITE);
-            rdr.end_draw();
+            rdr.end_draw().unwrap();
+        }
+    }
+
+
+    #[test]
+    fn streamed_image() {
+        let (writer, img) = image_stream(4, 3);
+        writer.write_row(1, &[Color::RED; 4]);
+
+        let mut target = Image::new(4, 3);
+        target.clear(Color::BLUE);
+        target.streamed_image(&img.lock().unwrap(), Vec2::ZERO);
+
+        assert_eq!(target[(0, 0)], Color::BLUE);
+        assert_eq!(target[(3, 1)], Color::RED);
+        assert_eq!(target[(2, 2)], Color::BLUE);
+        assert!(!img.lock().unwrap().is_complete());
+
+        writer.write_row(0, &[Color::RED; 4]);
+        writer.write_row(2, &[Color::RED; 4]);
+        assert!(img.lock().unwrap().is_complete());
+    }
+
+
+    #[test]
+    fn indexed_image() {
+        let palette = [Color::BLACK, Color::RED, Color::BLUE];
+        assert_eq!(Color::rgb(200, 10, 20).nearest(&palette), 1);
+
+        let mut img: Image<u8> = Image::filled(4, 4, 0);
+        img.rect(Rect::new((1, 1), (2, 2)), 2);
+
+        let rgb = img.map(|i| palette[i as usize]);
+        assert_eq!(rgb[(0, 0)], Color::BLACK);
+        assert_eq!(rgb[(2, 2)], Color::BLUE);
+    }
+
+
+    #[test]
+    fn rle_image() {
+        let mut img = RleImage::from_fn(vec2!(6, 2), |p| if p.x < 4 {Color::RED} else {Color::BLUE});
+
+        let row: Vec<Color> = img.row(3, 0).take(4).collect();
+        assert_eq!(row, vec![Color::RED, Color::BLUE, Color::BLUE, Color::BLACK]);
+
+        img.set_row_span(1, 1, 3, |_| Color::WHITE);
+        let row: Vec<Color> = img.row(0, 1).take(6).collect();
+        assert_eq!(row, vec![Color::RED, Color::WHITE, Color::WHITE, Color::RED, Color::BLUE, Color::BLUE]);
+    }
+
+
+    #[test]
+    fn fixed_size() {
+        let (mut rdr, _) = Renderer::headless((4, 2)).unwrap();
+        let (other, _) = Renderer::headless((4, 2)).unwrap();
+        rdr.set_fixed_size((80, 48));
+        assert_eq!(rdr.size(), vec2!(80, 48));
+        // only this renderer is affected
+        assert_eq!(other.size(), vec2!(4, 4));
+        rdr.clear_fixed_size();
+        assert_eq!(rdr.size(), vec2!(4, 4));
+    }
+
+
+    #[test]
+    fn render_to_image() {
+        let img = Renderer::render_to_image((8, 8), |frame| {
+            frame.clear_screen(Color::BLUE);
+            frame.draw_rect(Rect::new((2, 2), (2, 2)), Color::RED);
+        });
+
+        assert_eq!(img.size(), vec2!(8, 8));
+        assert_eq!(img[(0, 0)], Color::BLUE);
+        assert_eq!(img[(3, 3)], Color::RED);
+    }
+
+
+    #[test]
+    fn braille_glyph() {
+        let mut pixels = [Color::BLACK; 8];
+        pixels[0] = Color::WHITE;
+        pixels[7] = Color::WHITE;
+
+        let (mask, fg, bg) = glyph::split_colors(&pixels);
+        let (mask, fg, bg) = if fg == Color::WHITE { (mask, fg, bg) } else { (!mask, bg, fg) };
+        assert_eq!((fg, bg), (Color::WHITE, Color::BLACK));
+        assert_eq!(glyph::braille(mask), '⢁');
+        assert_eq!(RenderMode::Braille.cell_size(), vec2!(2, 4));
+    }
+
+
+    #[test]
+    fn block_glyphs() {
+        assert_eq!(glyph::quadrant(0b1001), '▚');
+        assert_eq!(glyph::quadrant(0b1111), '█');
+        assert_eq!(glyph::sextant(0b000001), '🬀');
+        assert_eq!(glyph::sextant(0b010101), '▌');
+        assert_eq!(glyph::sextant(0b111110), '🬻');
+        assert_eq!(RenderMode::Sextant.cell_size(), vec2!(2, 3));
+    }
+
+
+    #[test]
+    fn inline_image_encoding() {
+        assert_eq!(rds::base64(b"Man"), "TWFu");
+        assert_eq!(rds::base64(b"Ma"), "TWE=");
+        assert_eq!(rds::base64(b"M"), "TQ==");
+
+        let png = Image::new(4, 2).encode_png().unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+    }
+
+
+    #[test]
+    fn ansi256() {
+        assert_eq!(Color::RED.to_ansi256(), 196);
+        assert_eq!(Color::WHITE.to_ansi256(), 231);
+        assert_eq!(Color::rgb(128, 128, 128).to_ansi256(), 244);
+        assert_eq!(Color::from_ansi256(244), Color::rgb(128, 128, 128));
+        for i in 16..=255 {
+            assert_eq!(Color::from_ansi256(i).to_ansi256(), i);
+        }
+    }
+
+
+    #[test]
+    fn ansi16() {
+        assert_eq!(Color::BLACK.to_ansi16(), 0);
+        assert_eq!(Color::RED.to_ansi16(), 9);
+        assert_eq!(Color::rgb(200, 0, 0).to_ansi16(), 1);
+
+        let dark = ColorDepth::Ansi16(|c| c.to_ansi16() % 8);
+        assert_eq!(dark.quantize(Color::RED), Color::from_ansi256(1));
+        assert_eq!(dark, dark);
+        assert_ne!(dark, ColorDepth::Ansi256);
+    }
+
+
+    #[test]
+    fn ansi16_output() {
+        // a mapping that is not idempotent, red (9) is displayed as bright green (10)
+        let (mut rdr, output) = Renderer::headless((1, 1)).unwrap();
+        rdr.set_config(rds::RendererConfig {
+            protocol: rds::OutputProtocol::Text,
+            colors: ColorDepth::Ansi16(|c| (c.to_ansi16() + 1) % 16),
+            dithering: rds::Dithering::None,
+            shades: false
+        });
+        rdr.begin_draw().unwrap();
+        rdr.clear_screen(Color::RED);
+        rdr.end_draw().unwrap();
+        rdr.screenshot().unwrap();
+
+        let out = String::from_utf8(output.take_bytes()).unwrap();
+        assert!(out.contains("\x1b[102m"));
+        assert!(!out.contains("\x1b[103m"));
+    }
+
+
+    #[test]
+    fn grayscale() {
+        assert_eq!(Color::WHITE.luminance(), 255);
+        assert_eq!(Color::BLACK.luminance(), 0);
+
+        let gray = ColorDepth::Grayscale;
+        assert_eq!(gray.quantize(Color::WHITE), Color::rgb(238, 238, 238));
+        assert_eq!(gray.quantize(Color::rgb(128, 128, 128)), Color::rgb(128, 128, 128));
+        assert_eq!(gray.quantize(Color::RED).to_ansi256(), 239);
+    }
+
+
+    #[test]
+    fn alpha_blending() {
+        let mut img = Image::new(4, 4);
+        img.rect(Rect::new((0, 0), (4, 4)), Color::WHITE);
+        img.rect(Rect::new((0, 0), (2, 2)), Color::BLACK.with_alpha(128));
+        assert_eq!(img[(0, 0)], Color::rgb(127, 127, 127));
+        assert_eq!(img[(3, 3)], Color::WHITE);
+
+        let mut sprite = Image::filled(2, 1, Rgba::TRANSPARENT);
+        sprite.point((1, 0), Rgba::from(Color::RED));
+        img.whole_image(&sprite, (2, 3));
+        assert_eq!(img[(2, 3)], Color::WHITE);
+        assert_eq!(img[(3, 3)], Color::RED);
+
+        let sprite = Arc::new(sprite);
+        let frame = Renderer::render_to_image((4, 4), |frame| {
+            frame.clear_screen(Color::BLUE);
+            frame.draw_rgba_image(sprite, (0, 0), (2, 1), (0, 0));
+        });
+        assert_eq!(frame[(0, 0)], Color::BLUE);
+        assert_eq!(frame[(1, 0)], Color::RED);
+    }
+
+
+    #[test]
+    fn hsv_hsl() {
+        assert_eq!(Color::hsv(0., 1., 1.), Color::RED);
+        assert_eq!(Color::hsv(120., 1., 1.), Color::rgb(0, 255, 0));
+        assert_eq!(Color::hsv(-120., 1., 1.), Color::BLUE);
+        assert_eq!(Color::hsl(60., 1., 0.5), Color::YELLOW);
+        assert_eq!(Color::hsl(0., 0., 1.), Color::WHITE);
+
+        let (h, s, v) = Color::rgb(255, 128, 0).to_hsv();
+        assert!((h - 30.1).abs() < 0.5 && s == 1. && v == 1.);
+        for c in [Color::CORAL, Color::CADET_BLUE, Color::BLACK, Color::WHITE] {
+            let (h, s, v) = c.to_hsv();
+            assert_eq!(Color::hsv(h, s, v), c);
+            let (h, s, l) = c.to_hsl();
+            assert_eq!(Color::hsl(h, s, l), c);
+        }
+    }
+
+
+    #[test]
+    fn color_mixing() {
+        assert_eq!(Color::BLACK.lerp(Color::WHITE, 0.5), Color::rgb(128, 128, 128));
+        assert_eq!(Color::RED.lerp(Color::BLUE, 2.), Color::BLUE);
+        assert_eq!(Color::mix(&[Color::RED, Color::BLUE]), Color::rgb(128, 0, 128));
+        assert_eq!(Color::mix(&[]), Color::BLACK);
+        assert_eq!(Color::WHITE.darken(1.), Color::BLACK);
+        assert_eq!(Color::rgb(100, 100, 100).lighten(0.5), Color::rgb(178, 178, 178));
+    }
+
+
+    #[test]
+    fn blend_modes() {
+        let (dst, src) = (Color::rgb(200, 100, 0), Color::rgb(100, 100, 100));
+        assert_eq!(BlendMode::Add.apply(dst, src), Color::rgb(255, 200, 100));
+        assert_eq!(BlendMode::Multiply.apply(dst, src), Color::rgb(78, 39, 0));
+        assert_eq!(BlendMode::Screen.apply(dst, src), Color::rgb(222, 161, 100));
+        assert_eq!(BlendMode::Subtract.apply(dst, src), Color::rgb(100, 0, 0));
+
+        let light = Arc::new(Image::filled(2, 2, Color::rgb(0, 0, 100)));
+        let frame = Renderer::render_to_image((4, 4), |frame| {
+            frame.clear_screen(Color::rgb(50, 0, 0));
+            frame.draw_image(light, (0, 0), (2, 2), (0, 0), None, BlendMode::Add);
+        });
+        assert_eq!(frame[(1, 1)], Color::rgb(50, 0, 100));
+        assert_eq!(frame[(2, 2)], Color::rgb(50, 0, 0));
+    }
+
+
+    #[test]
+    fn gradients() {
+        let mut img = Image::new(5, 3);
+        img.rect_gradient((0, 0), (5, 3), Color::BLACK, Color::WHITE, 0.);
+        assert_eq!(img[(0, 1)], Color::BLACK);
+        assert_eq!(img[(2, 0)], Color::rgb(128, 128, 128));
+        assert_eq!(img[(4, 2)], Color::WHITE);
+
+        img.ellipse_gradient((2, 1), (4, 2), Color::RED, Color::BLUE);
+        assert_eq!(img[(2, 1)], Color::RED);
+        assert_eq!(img[(0, 1)], Color::BLUE);
+        assert_eq!(img[(0, 0)], Color::BLACK);
+
+        let frame = Renderer::render_to_image((2, 4), |frame| {
+            frame.draw_rect_gradient((0, 0), (2, 4), Color::BLACK, Color::WHITE, std::f32::consts::FRAC_PI_2);
+        });
+        assert_eq!(frame[(1, 0)], Color::BLACK);
+        assert_eq!(frame[(1, 3)], Color::WHITE);
+    }
+
+
+    #[test]
+    fn filled_ellipse() {
+        let mut img = Image::new(11, 7);
+        img.ellipse((5, 3), (10, 6), Color::WHITE);
+        img.ellipse_boundary((5, 3), (10, 6), Color::RED);
+        for y in 0..7 {
+            // the inside of each row is filled between the two boundary points
+            let row: Vec<Color> = (0..11).map(|x| img[vec2!(x, y)]).collect();
+            let first = row.iter().position(|c| *c == Color::RED).unwrap();
+            let last = row.iter().rposition(|c| *c == Color::RED).unwrap();
+            assert!(row[first..=last].iter().all(|c| *c != Color::BLACK));
+        }
+
+        // each pixel is drawn only once
+        let mut img = Image::new(5, 5);
+        img.clear(Color::WHITE);
+        img.ellipse((2, 2), (4, 4), Color::BLACK.with_alpha(128));
+        assert_eq!(img[(2, 2)], Color::rgb(127, 127, 127));
+        assert_eq!(img[(2, 0)], Color::rgb(127, 127, 127));
+    }
+
+
+    #[test]
+    fn circles() {
+        let count = |img: &Image| (0..img.size().y)
+            .flat_map(|y| (0..img.size().x).map(move |x| vec2!(x, y)))
+            .filter(|p| img[*p] == Color::WHITE)
+            .count();
+
+        let mut img = Image::new(5, 5);
+        img.circle_boundary((2, 2), 0, Color::WHITE);
+        assert_eq!(count(&img), 1);
+
+        let mut img = Image::new(5, 5);
+        img.circle_boundary((2, 2), 1, Color::WHITE);
+        assert_eq!(count(&img), 4);
+        assert_eq!(img[(2, 2)], Color::BLACK);
+
+        let mut img = Image::new(5, 5);
+        img.circle((2, 2), 1, Color::WHITE);
+        assert_eq!(count(&img), 5);
+
+        let mut img = Image::new(9, 9);
+        img.clear(Color::WHITE);
+        img.circle((4, 4), 4, Color::BLACK.with_alpha(128));
+        img.circle_boundary((4, 4), 4, Color::BLACK.with_alpha(128));
+        assert_eq!(img[(4, 4)], Color::rgb(127, 127, 127));
+        assert_eq!(img[(4, 0)], Color::rgb(63, 63, 63));
+    }
+
+
+    #[test]
+    fn triangle() {
+        let mut img = Image::new(6, 6);
+        img.triangle((0, 0), (4, 0), (0, 4), Color::WHITE);
+        for y in 0..5 {
+            for x in 0..5 {
+                let inside = x + y <= 4;
+                assert_eq!(img[vec2!(x, y)] == Color::WHITE, inside, "({}, {})", x, y);
+            }
+        }
+
+        // degenerated triangles are lines
+        let mut img = Image::new(6, 6);
+        img.triangle((1, 1), (4, 1), (2, 1), Color::WHITE);
+        assert_eq!(img[(1, 1)], Color::WHITE);
+        assert_eq!(img[(4, 1)], Color::WHITE);
+        assert_eq!(img[(1, 2)], Color::BLACK);
+    }
+
+
+    #[test]
+    fn polygon() {
+        // U shape
+        let mut img = Image::new(8, 8);
+        img.polygon(&[vec2!(0, 0), vec2!(2, 0), vec2!(2, 4), vec2!(4, 4), vec2!(4, 0), vec2!(6, 0), vec2!(6, 6), vec2!(0, 6)], Color::WHITE);
+        assert_eq!(img[(1, 1)], Color::WHITE);
+        assert_eq!(img[(3, 1)], Color::BLACK);
+        assert_eq!(img[(5, 1)], Color::WHITE);
+        assert_eq!(img[(3, 5)], Color::WHITE);
+        assert_eq!(img[(7, 7)], Color::BLACK);
+
+        // clipped
+        let mut img = Image::new(4, 4);
+        img.polygon(&[vec2!(-10, -10), vec2!(10, -10), vec2!(10, 10), vec2!(-10, 10)], Color::WHITE);
+        assert_eq!(img[(0, 0)], Color::WHITE);
+        assert_eq!(img[(3, 3)], Color::WHITE);
+    }
+
+
+    #[test]
+    fn polyline() {
+        let points = [vec2!(0, 0), vec2!(3, 0), vec2!(3, 3)];
+
+        let mut img = Image::new(5, 5);
+        img.clear(Color::WHITE);
+        img.polyline(&points, Color::BLACK.with_alpha(128));
+        assert_eq!(img[(3, 0)], Color::rgb(127, 127, 127));
+        assert_eq!(img[(1, 1)], Color::WHITE);
+
+        let mut img = Image::new(5, 5);
+        img.polygon_boundary(&points, Color::RED);
+        assert_eq!(img[(1, 1)], Color::RED);
+        assert_eq!(img[(2, 1)], Color::BLACK);
+
+        let frame = Renderer::render_to_image((5, 5), |frame| frame.draw_polyline(&points, Color::RED));
+        assert_eq!(frame[(3, 2)], Color::RED);
+        assert_eq!(frame[(1, 1)], Color::BLACK);
+    }
+
+
+    #[test]
+    fn arc() {
+        use std::f32::consts::{PI, FRAC_PI_2};
+
+        // bottom right quarter
+        let mut img = Image::new(9, 9);
+        img.arc((4, 4), 3, 0., FRAC_PI_2, Color::WHITE);
+        assert_eq!(img[(7, 4)], Color::WHITE);
+        assert_eq!(img[(4, 7)], Color::WHITE);
+        assert_eq!(img[(1, 4)], Color::BLACK);
+        assert_eq!(img[(4, 1)], Color::BLACK);
+
+        // the arc can go through the angle 0
+        let mut img = Image::new(9, 9);
+        img.arc((4, 4), 3, 3. * FRAC_PI_2, 2. * PI + 0.1, Color::WHITE);
+        assert_eq!(img[(4, 1)], Color::WHITE);
+        assert_eq!(img[(7, 4)], Color::WHITE);
+        assert_eq!(img[(4, 7)], Color::BLACK);
+    }
+
+
+    #[test]
+    fn dithering() {
+        // gray that is between two colors of the 16 colors palette
+        let mut img = Image::new(8, 8);
+        img.clear(Color::rgb(160, 160, 160));
+        let depth = ColorDepth::Ansi16(|c| c.to_ansi16());
+        let mean = |img: &Image| (0..64).map(|i| img[vec2!(i % 8, i / 8)].r as u32).sum::<u32>() / 64;
+
+        let plain = img.map(|c| depth.quantize(c));
+        assert_eq!(plain[(0, 0)], plain[(1, 0)]);
+
+        let ordered = dither::ordered(&img, 128., |c| depth.quantize(c));
+        assert_ne!(ordered[(0, 0)], ordered[(3, 0)]);
+        assert!(mean(&ordered).abs_diff(160) < mean(&plain).abs_diff(160));
+
+        let fs = dither::floyd_steinberg(&img, |c| depth.quantize(c));
+        assert!(mean(&fs).abs_diff(160) < mean(&plain).abs_diff(160));
+    }
+
+
+    #[test]
+    fn bitmap_text() {
+        assert_eq!(text::text_size("Hi"), vec2!(11, 7));
+        assert_eq!(text::text_size("abc\nd"), vec2!(17, 15));
+
+        let mut img = Image::new(12, 16);
+        img.text((0, 0), "Hi\n.", Color::WHITE);
+        // 'H'
+        assert_eq!(img[(0, 0)], Color::WHITE);
+        assert_eq!(img[(2, 3)], Color::WHITE);
+        assert_eq!(img[(2, 0)], Color::BLACK);
+        // 'i'
+        assert_eq!(img[(8, 2)], Color::WHITE);
+        // '.' on the second line
+        assert_eq!(img[(1, 13)], Color::WHITE);
+
+        let frame = Renderer::render_to_image((12, 8), |frame| frame.draw_text((6, 0), "I", Color::RED));
+        assert_eq!(frame[(8, 3)], Color::RED);
+    }
+
+
+    #[test]
+    fn text_style() {
+        let style = rds::TextStyle::new(Color::WHITE, Color::BLUE);
+        assert_eq!(style.cell('a'), rds::Cell::new('a', Color::WHITE, Color::BLUE));
+
+        let bold = style.bold().reverse();
+        assert!(bold.attrs.bold && bold.attrs.reverse && !bold.attrs.italic);
+        assert_eq!(bold.cell('a').attrs, bold.attrs);
+        assert_eq!(style.attrs, rds::Attributes::NONE);
+    }
+
+
+    #[cfg(feature = "ttf")]
+    #[test]
+    fn ttf_text() {
+        assert!(text::Font::from_bytes(&[0; 16]).is_err());
+        let font = match text::Font::load("/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf") {
+            Ok(font) => font,
+            Err(_) => return
+        };
+
+        let size = font.text_size("I\nI", 16.);
+        assert!(size.x > 0 && size.y >= 32);
+
+        let mut img = Image::new(size.x as usize, size.y as usize);
+        img.ttf_text((0, 0), "I\nI", &font, 16., Color::WHITE);
+        assert!((0..size.y).any(|y| (0..size.x).any(|x| img[(x, y)] == Color::WHITE)));
+        assert_eq!(img[(size.x - 1, 0)], Color::BLACK);
+    }
+
+
+    #[test]
+    fn scaling() {
+        let mut img = Image::new(2, 2);
+        img[(1, 0)] = Color::WHITE;
+        img[(1, 1)] = Color::WHITE;
+
+        let big = img.scaled((4, 4), Filter::Nearest);
+        assert_eq!(big.size(), vec2!(4, 4));
+        assert_eq!(big[(1, 3)], Color::BLACK);
+        assert_eq!(big[(2, 0)], Color::WHITE);
+
+        let smooth = img.scaled((4, 1), Filter::Bilinear);
+        assert_eq!(smooth[(0, 0)], Color::BLACK);
+        assert_eq!(smooth[(3, 0)], Color::WHITE);
+        assert!(smooth[(1, 0)].r > 0 && smooth[(1, 0)].r < 255);
+
+        let mut dst = Image::new(8, 8);
+        dst.scaled_image(&img, Rect::new((1, 0), (1, 1)), Rect::new((6, 6), (4, 4)), Filter::Nearest, BlendMode::Normal);
+        assert_eq!(dst[(7, 7)], Color::WHITE);
+        assert_eq!(dst[(5, 5)], Color::BLACK);
+    }
+
+
+    #[test]
+    fn sub_images() {
+        let mut img = Image::new(4, 4);
+        img[(2, 1)] = Color::RED;
+
+        let view = img.view((1, 1), (10, 2));
+        assert_eq!(view.size(), vec2!(3, 2));
+        assert_eq!(view[(1, 0)], Color::RED);
+        assert_eq!(view.get((3, 0)), None);
+
+        let inner = view.view((1, 0), (1, 1));
+        assert_eq!(inner.pos(), vec2!(2, 1));
+        assert_eq!(inner.to_image()[(0, 0)], Color::RED);
+
+        let sub = img.sub_image((2, 1), (2, 2));
+        assert_eq!(sub.size(), vec2!(2, 2));
+        assert_eq!(sub[(0, 0)], Color::RED);
+
+        let mut dst = Image::new(3, 3);
+        dst.image_view(&img.view((2, 1), (1, 1)), (2, 2), BlendMode::Normal);
+        assert_eq!(dst[(2, 2)], Color::RED);
+    }
+
+
+    #[test]
+    fn flips() {
+        let mut img = Image::new(3, 2);
+        img[(0, 0)] = Color::RED;
+
+        let mut h = img.clone();
+        h.flip_h();
+        assert_eq!(h[(2, 0)], Color::RED);
+        assert_eq!(h[(0, 0)], Color::BLACK);
+
+        let mut v = img.clone();
+        v.flip_v();
+        assert_eq!(v[(0, 1)], Color::RED);
+        assert_eq!(v[(0, 0)], Color::BLACK);
+
+        // flipped blits are clipped on the destination
+        let mut dst = Image::new(3, 2);
+        dst.image_flipped(&img, (-1, 0), (3, 2), (0, 0), Flip::Both, None, BlendMode::Normal);
+        assert_eq!(dst[(1, 1)], Color::RED);
+
+        let mut dst = Image::new(3, 2);
+        dst.image(&img, (-1, 0), (3, 2), (0, 0), None, BlendMode::Normal);
+        assert_eq!(dst[(0, 0)], Color::BLACK);
+        dst.image(&img, (2, 0), (-3, 2), (0, 0), None, BlendMode::Normal);
+        assert_eq!(dst[(2, 0)], Color::RED);
+    }
+
+
+    #[test]
+    fn image_filters() {
+        let gray = |l| Color::rgb(l, l, l);
+        let mut img = Image::new(5, 5);
+        img[(2, 2)] = Color::WHITE;
+
+        let mut blurred = img.clone();
+        blurred.apply_filter(filter::ImageFilter::BoxBlur(1));
+        assert_eq!(blurred[(1, 1)], gray(28));
+        assert_eq!(blurred[(0, 0)], Color::BLACK);
+
+        let mut gaussian = img.clone();
+        gaussian.apply_filter(filter::ImageFilter::GaussianBlur(1.));
+        assert!(gaussian[(2, 2)].r > gaussian[(2, 1)].r && gaussian[(2, 1)].r > gaussian[(2, 0)].r);
+
+        let mut sharp = Image::filled(3, 1, gray(100));
+        sharp[(1, 0)] = gray(150);
+        sharp.apply_filter(filter::ImageFilter::Sharpen(1.));
+        assert!(sharp[(1, 0)].r > 150 && sharp[(0, 0)].r < 100);
+
+        let mut inverted = img.clone();
+        inverted.apply_filter(filter::ImageFilter::Invert);
+        assert_eq!(inverted[(0, 0)], Color::WHITE);
+
+        let mut grayscale = Image::filled(1, 1, Color::RED);
+        grayscale.apply_filter(filter::ImageFilter::Grayscale);
+        assert_eq!(grayscale[(0, 0)], gray(76));
+    }
+
+
+    #[test]
+    fn convolution() {
+        assert!(filter::Kernel::new(2, vec![0.; 4]).is_err());
+        assert!(filter::Kernel::new(3, vec![0.; 4]).is_err());
+
+        let mut img = Image::filled(3, 3, Color::rgb(50, 50, 50));
+        img[(0, 1)] = Color::WHITE;
+
+        // identity
+        let mut same = img.clone();
+        same.convolve(&filter::Kernel::new(1, vec![1.]).unwrap());
+        assert_eq!(same[(0, 1)], Color::WHITE);
+
+        let mut edges = img.clone();
+        edges.convolve(&filter::Kernel::edge_detect());
+        assert_eq!(edges[(2, 2)], Color::BLACK);
+        assert_eq!(edges[(0, 1)], Color::WHITE);
+
+        // the left neighbour of (0, 1) is (2, 1) with wrapping, (1, 1) with mirroring
+        let shift = vec![0., 0., 0., 1., 0., 0., 0., 0., 0.];
+        let mut wrapped = img.clone();
+        wrapped.convolve(&filter::Kernel::new(3, shift.clone()).unwrap().with_edge(filter::Edge::Wrap));
+        assert_eq!(wrapped[(0, 1)], Color::rgb(50, 50, 50));
+        assert_eq!(wrapped[(1, 1)], Color::WHITE);
+
+        let mut zero = img.clone();
+        zero.convolve(&filter::Kernel::new(3, shift).unwrap().with_edge(filter::Edge::Zero).with_bias(10.));
+        assert_eq!(zero[(0, 0)], Color::rgb(10, 10, 10));
+    }
+
+
+    #[test]
+    fn sprite_sheets() {
+        let mut img = Image::new(8, 6);
+        img[(4, 0)] = Color::RED;
+        img[(1, 4)] = Color::BLUE;
+
+        let mut sheet = sprite::SpriteSheet::grid(img, (3, 3));
+        assert_eq!(sheet.len(), 4);
+        assert_eq!(sheet.frame(1), Some(Rect::new((3, 0), (3, 3))));
+        assert_eq!(sheet.frame(4), None);
+
+        sheet.name_frame("blue", 2);
+        let coin = sheet.add_named_frame("coin", Rect::new((6, 0), (2, 2)));
+        assert_eq!(coin, 4);
+        assert_eq!(sheet.frame("coin"), sheet.frame(4));
+        assert_eq!(sheet.frame("missing"), None);
+
+        let mut dst = Image::new(3, 3);
+        dst.sprite(&sheet, 1, (0, 0));
+        assert_eq!(dst[(1, 0)], Color::RED);
+        dst.sprite(&sheet, "blue", (0, 0));
+        assert_eq!(dst[(1, 1)], Color::BLUE);
+
+        let frame = Renderer::render_to_image((3, 3), |frame| frame.draw_sprite(&sheet, "blue", (1, 0)));
+        assert_eq!(frame[(2, 1)], Color::BLUE);
+    }
+
+
+    #[test]
+    fn animations() {
+        use std::time::Duration;
+        use sprite::{Animation, LoopMode};
+
+        let sheet = sprite::SpriteSheet::grid(Image::new(4, 1), (1, 1));
+        let ms = Duration::from_millis;
+
+        let mut once = Animation::new(sheet.clone(), LoopMode::Once);
+        once.add_frame(0, ms(100));
+        once.add_frame(2, ms(50));
+        assert_eq!(once.duration(), ms(150));
+        assert_eq!(once.frame_at(ms(99)), 0);
+        assert_eq!(once.frame_at(ms(100)), 2);
+        assert_eq!(once.frame_at(ms(1000)), 2);
+        assert!(once.is_finished(ms(150)) && !once.is_finished(ms(149)));
+
+        let looped = Animation::uniform(sheet.clone(), &[1, 2, 3], ms(10), LoopMode::Loop);
+        assert_eq!(looped.frame_at(ms(35)), 1);
+        assert!(!looped.is_finished(ms(1000)));
+
+        let ping_pong = Animation::uniform(sheet, &[1, 2, 3], ms(10), LoopMode::PingPong);
+        assert_eq!(ping_pong.frame_at(ms(25)), 3);
+        assert_eq!(ping_pong.frame_at(ms(35)), 3);
+        assert_eq!(ping_pong.frame_at(ms(45)), 2);
+        assert_eq!(ping_pong.frame_at(ms(65)), 1);
+    }
+
+
+    #[test]
+    fn camera() {
+        let camera = rds::Camera::new((10, 5), 2.);
+        assert_eq!(camera.to_screen((12, 5)), vec2!(4, 0));
+        assert_eq!(camera.to_world((5, 3)), vec2!(12, 6));
+
+        let frame = Renderer::render_to_image((8, 8), |frame| {
+            frame.set_camera(rds::Camera::new((10, 10), 1.));
+            frame.draw_point((11, 12), Color::RED);
+            frame.set_camera(rds::Camera::new((0, 0), 2.));
+            frame.draw_rect(Rect::new((2, 2), (1, 1)), Color::BLUE);
+        });
+        assert_eq!(frame[(1, 2)], Color::RED);
+        assert_eq!(frame[(4, 4)], Color::BLUE);
+        assert_eq!(frame[(5, 5)], Color::BLUE);
+        assert_eq!(frame[(6, 6)], Color::BLACK);
+
+        let mut img = Image::new(2, 1);
+        img[(1, 0)] = Color::GREEN;
+        let img = Arc::new(img);
+        let frame = Renderer::render_to_image((8, 8), |frame| {
+            frame.set_camera(rds::Camera::new((0, 0), 3.));
+            frame.draw_whole_image(img.clone(), (0, 1));
+            frame.draw_image(img.clone(), (1, 2), (-2, 1), (0, 0), None, BlendMode::Normal);
+        });
+        assert_eq!(frame[(5, 3)], Color::GREEN);
+        assert_eq!(frame[(2, 3)], Color::BLACK);
+        // flipped by the negative size: the green pixel is on the left
+        assert_eq!(frame[(0, 6)], Color::GREEN);
+        assert_eq!(frame[(5, 6)], Color::BLACK);
+    }
+
+
+    #[test]
+    fn layers() {
+        let half = Rgba::new(255, 255, 255, 128);
+        assert_eq!(Rgba::TRANSPARENT.over(half, BlendMode::Normal), half);
+        assert_eq!(Color::BLACK.with_alpha(255).over(half, BlendMode::Normal), Color::rgb(128, 128, 128).with_alpha(255));
+
+        let frame = Renderer::render_to_image((4, 1), |frame| {
+            frame.create_layer("ui", 10);
+            frame.create_layer("world", 5);
+
+            frame.set_layer("ui");
+            frame.draw_point((0, 0), Color::RED);
+            frame.set_layer("world");
+            frame.draw_rect(Rect::new((0, 0), (2, 1)), Color::BLUE);
+            frame.set_layer(rds::MAIN_LAYER);
+            frame.clear_screen(Color::WHITE);
+        });
+        // the ui is over the world, itself over the main layer
+        assert_eq!(frame[(0, 0)], Color::RED);
+        assert_eq!(frame[(1, 0)], Color::BLUE);
+        assert_eq!(frame[(2, 0)], Color::WHITE);
+
+        let frame = Renderer::render_to_image((2, 1), |frame| {
+            frame.create_layer("a", 1);
+            frame.create_layer("b", 2);
+            frame.set_layer("a");
+            frame.draw_point((0, 0), Color::RED);
+            frame.set_layer("b");
+            frame.draw_point((0, 0), Color::BLUE);
+            frame.draw_point((1, 0), Color::BLUE);
+            frame.clear_layer();
+            frame.draw_point((1, 0), Color::GREEN);
+            frame.set_layer_z("a", 3);
+        });
+        assert_eq!(frame[(0, 0)], Color::RED);
+        assert_eq!(frame[(1, 0)], Color::GREEN);
+    }
+
+
+    #[test]
+    fn layer_compositing() {
+        let frame = Renderer::render_to_image((3, 1), |frame| {
+            frame.clear_screen(Color::WHITE);
+            frame.create_layer("menu", 1);
+            frame.set_layer("menu");
+            frame.draw_point((1, 0), Color::RED);
+            frame.create_layer("shadow", 2);
+            frame.set_layer("shadow");
+            frame.draw_point((2, 0), Color::rgb(128, 128, 128));
+
+            frame.set_layer_opacity(rds::MAIN_LAYER, 0.5);
+            frame.set_layer_opacity("menu", 0.);
+            frame.set_layer_blend_mode("shadow", BlendMode::Multiply);
+        });
+        // white dimmed by half
+        assert_eq!(frame[(0, 0)], Color::rgb(127, 127, 127));
+        assert_eq!(frame[(1, 0)], Color::rgb(127, 127, 127));
+        assert!(frame[(2, 0)].r.abs_diff(64) <= 1);
+    }
+
+
+    #[test]
+    fn particle_emitter() {
+        use std::time::Duration;
+
+        let mut emitter = particles::Emitter::new((2., 2.))
+            .with_rate(8.)
+            .with_lifetime(Duration::from_millis(500))
+            .with_velocity((4., 0.), (0., 0.))
+            .with_colors(&[Color::WHITE, Color::BLACK])
+            .with_seed(42);
+
+        emitter.update(Duration::from_millis(250));
+        assert_eq!(emitter.particles().len(), 2);
+        emitter.update(Duration::from_millis(250));
+        assert_eq!(emitter.particles().len(), 4);
+        assert_eq!(emitter.particles()[0].pos, (3., 2.));
+        // the first particles die
+        emitter.update(Duration::from_millis(250));
+        assert_eq!(emitter.particles().len(), 4);
+
+        assert_eq!(emitter.color_at(0.), Color::WHITE);
+        assert_eq!(emitter.color_at(0.5), Color::BLACK);
+        assert_eq!(emitter.color_at(0.25), Color::rgb(128, 128, 128));
+
+        let mut spread = particles::Emitter::new((0., 0.)).with_velocity((0., 0.), (1., 1.));
+        spread.burst(50);
+        assert!(spread.particles().iter().all(|p| p.vel.0.abs() <= 1. && p.vel.1.abs() <= 1.));
+        assert!(spread.particles().iter().any(|p| p.vel.0 != spread.particles()[0].vel.0));
+
+        emitter.clear();
+        emitter.burst(1);
+        let frame = Renderer::render_to_image((4, 4), |frame| frame.draw_particles(&emitter));
+        assert_eq!(frame[(2, 2)], Color::WHITE);
+    }
+
+
+    #[test]
+    fn render_errors() {
+        let err: Result<(), rds::RenderError> = Err(rds::RenderError::NotDrawing);
+        let msg: Result<(), String> = err.map_err(String::from);
+        assert_eq!(msg, Err("drawing outside of a frame build (call begin_draw)".to_string()));
+        assert_eq!(rds::RenderError::ThreadStopped.to_string(), "rendering thread stopped");
+    }
+
+
+    #[test]
+    fn write_sink() {
+        struct Sink(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+        impl std::io::Write for Sink {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> { self.0.lock().unwrap().write(buf) }
+            fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+        }
+
+        let bytes = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut rdr = Renderer::from_writer(Sink(bytes.clone()), (4, 2)).unwrap();
+
+        rdr.begin_draw().unwrap();
+        rdr.draw_text((0, 0), "hi", Color::WHITE);
+        rdr.end_draw().unwrap();
+        drop(rdr);
+
+        let out = String::from_utf8_lossy(&bytes.lock().unwrap()).to_string();
+        assert!(out.starts_with("\x1b[?25l\x1b[?1049h"));
+        assert!(out.contains("\x1b[1;1H"));
+        assert!(out.ends_with("\x1b[?1049l"));
+    }
+
+
+    #[test]
+    fn write_errors() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        struct Broken(Arc<AtomicBool>);
+        impl std::io::Write for Broken {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                match self.0.load(Ordering::SeqCst) {
+                    true => Err(std::io::ErrorKind::BrokenPipe.into()),
+                    false => Ok(buf.len())
+                }
+            }
+            fn flush(&mut self) -> std::io::Result<()> { self.write(&[]).map(|_| ()) }
         }
+
+        let broken = Arc::new(AtomicBool::new(false));
+        let mut rdr = Renderer::from_writer(Broken(broken.clone()), (4, 2)).unwrap();
+        rdr.with_frame(|frame| frame.clear_screen(Color::RED)).unwrap();
+
+        broken.store(true, Ordering::SeqCst);
+        let error = Err(rds::RenderError::Output(std::io::ErrorKind::BrokenPipe));
+        rdr.set_title("title");
+        assert_eq!(rdr.begin_draw(), error);
+        // the frame is pushed asynchronously, the error can be returned by the next frame
+        rdr.begin_draw().unwrap();
+        rdr.clear_screen(Color::BLUE);
+        match rdr.end_draw() {
+            Ok(()) => assert_eq!(rdr.begin_draw(), error),
+            pushed => assert_eq!(pushed, error)
+        }
+
+        // the rendering thread is still running
+        broken.store(false, Ordering::SeqCst);
+        rdr.with_frame(|frame| frame.clear_screen(Color::GREEN)).unwrap();
+        assert_eq!(rdr.screenshot().unwrap()[(0, 0)], Color::GREEN);
+    }
+
+
+    #[test]
+    fn headless() {
+        let (mut rdr, output) = Renderer::headless((4, 2)).unwrap();
+        let out = output.take_bytes();
+        assert_eq!(out, b"\x1b[?25l\x1b[?1049h");
+
+        assert_eq!(rdr.frame_mut().err(), Some(rds::RenderError::NotDrawing));
+        rdr.draw_point((0, 0), Color::RED);
+        assert_eq!(rdr.begin_draw(), Err(rds::RenderError::NotDrawing));
+
+        rdr.begin_draw().unwrap();
+        assert_eq!(rdr.begin_draw(), Err(rds::RenderError::AlreadyDrawing));
+        rdr.clear_screen(Color::BLUE);
+        rdr.draw_point((0, 0), Color::RED);
+        rdr.frame_mut().unwrap().flip_h();
+        rdr.draw_point((0, 1), Color::GREEN);
+        rdr.end_draw().unwrap();
+
+        let screen = rdr.screenshot().unwrap();
+        assert_eq!(screen[(3, 0)], Color::RED);
+        assert_eq!(screen[(0, 0)], Color::BLUE);
+        assert_eq!(screen[(0, 1)], Color::GREEN);
+        assert!(!output.take_bytes().is_empty());
+    }
+
+
+    #[test]
+    fn recording() {
+        let (mut rdr, _) = Renderer::headless((4, 2)).unwrap();
+        let dir = std::env::temp_dir();
+        assert!(rdr.start_recording(dir.join("str_recording.bmp")).is_err());
+        assert!(rdr.stop_recording().is_err());
+
+        // no frame, no file
+        let empty = dir.join("str_recording_empty.gif");
+        rdr.start_recording(&empty).unwrap();
+        assert!(rdr.stop_recording().is_err());
+        assert!(!empty.exists());
+
+        for path in ["str_recording.gif", "str_recording.png"] {
+            let path = dir.join(path);
+            rdr.start_recording(&path).unwrap();
+            for c in [Color::RED, Color::BLUE, Color::GREEN] {
+                rdr.begin_draw().unwrap();
+                rdr.clear_screen(c);
+                rdr.end_draw().unwrap();
+            }
+            rdr.stop_recording().unwrap();
+
+            let data = std::fs::read(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            if path.extension().unwrap() == "gif" {
+                use image::AnimationDecoder;
+                let decoder = image::codecs::gif::GifDecoder::new(&data[..]).unwrap();
+                let frames = decoder.into_frames().collect_frames().unwrap();
+                assert_eq!(frames.len(), 3);
+                assert_eq!(frames[1].buffer().get_pixel(0, 0).0, [0, 0, 255, 255]);
+            } else {
+                let decoder = png::Decoder::new(&data[..]);
+                let reader = decoder.read_info().unwrap();
+                assert_eq!(reader.info().animation_control().unwrap().num_frames, 3);
+            }
+        }
+    }
+
+
+    #[test]
+    fn asciinema_cast() {
+        let (mut rdr, _) = Renderer::headless((4, 2)).unwrap();
+        let path = std::env::temp_dir().join("str_cast.cast");
+        assert!(rdr.stop_cast().is_err());
+
+        rdr.start_cast(&path).unwrap();
+        for c in [Color::RED, Color::BLUE] {
+            rdr.begin_draw().unwrap();
+            rdr.clear_screen(c);
+            rdr.end_draw().unwrap();
+        }
+        rdr.stop_cast().unwrap();
+
+        let cast = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = cast.lines().collect();
+        assert!(lines[0].starts_with("{\"version\": 2, \"width\": 4, \"height\": 2, "));
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1..].iter().all(|l| l.starts_with('[') && l.contains(", \"o\", \"\\u001b[")));
+    }
+
+
+    #[test]
+    fn span_batching() {
+        let (mut rdr, output) = Renderer::headless((4, 1)).unwrap();
+        rdr.set_config(rds::RendererConfig {
+            protocol: rds::OutputProtocol::Text,
+            colors: ColorDepth::TrueColor,
+            dithering: rds::Dithering::None,
+            shades: false
+        });
+        rdr.begin_draw().unwrap();
+        rdr.clear_screen(Color::BLACK);
+        rdr.draw_rect(Rect::new((0, 0), (2, 2)), Color::RED);
+        rdr.end_draw().unwrap();
+        drop(rdr);
+
+        let out = String::from_utf8(output.bytes()).unwrap();
+        assert!(out.contains(&format!("\x1b[1;1H{:+}{:-}  {:+}{:-}  ", Color::RED, Color::RED, Color::BLACK, Color::BLACK)));
+    }
+
+
+    #[test]
+    fn single_write_per_frame() {
+        struct Counter(std::sync::Arc<std::sync::atomic::AtomicUsize>);
+        impl std::io::Write for Counter {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+        }
+
+        let writes = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let mut rdr = Renderer::from_writer(Counter(writes.clone()), (40, 10)).unwrap();
+        rdr.begin_draw().unwrap();
+        rdr.screenshot().unwrap();
+        let before = writes.load(std::sync::atomic::Ordering::SeqCst);
+        for i in 0..40 {
+            rdr.draw_point((i * 2, i % 20), Color::rgb(i as u8 * 6, 0, 0));
+        }
+        rdr.end_draw().unwrap();
+        rdr.screenshot().unwrap();
+        assert_eq!(writes.load(std::sync::atomic::Ordering::SeqCst), before + 1);
+    }
+
+
+    #[test]
+    fn custom_backend() {
+        struct Log(std::sync::Arc<std::sync::Mutex<Vec<&'static str>>>);
+        impl backend::TerminalBackend for Log {
+            fn setup(&mut self) -> Result<(), String> { self.0.lock().unwrap().push("setup"); Ok(()) }
+            fn restore(&mut self) { self.0.lock().unwrap().push("restore"); }
+            fn size(&self) -> Vec2 { vec2!(3, 2) }
+            fn writer(&self) -> std::io::Result<Box<dyn std::io::Write + Send>> { Ok(Box::new(std::io::sink())) }
+        }
+
+        let log = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut rdr = Renderer::with_backend(Log(log.clone())).unwrap();
+        rdr.begin_draw().unwrap();
+        rdr.end_draw().unwrap();
+        drop(rdr);
+        assert_eq!(*log.lock().unwrap(), vec!["setup", "restore"]);
+    }
+
+
+    #[test]
+    fn key_modifiers() {
+        let keys = |bytes: &[u8]| -> Vec<KeyEvent> {
+            input::parse_events(bytes, &mut MouseButton::Left).into_iter().filter_map(|e| match e {
+                InputEvent::Key(k) => Some(k),
+                _ => None
+            }).collect()
+        };
+
+        assert_eq!(keys(b"a\x03\r\t\x7F"), vec![
+            KeyEvent::plain(Key::Char('a')),
+            KeyEvent::new(Key::Char('c'), Modifiers::CTRL),
+            KeyEvent::plain(Key::Enter),
+            KeyEvent::plain(Key::Tab),
+            KeyEvent::plain(Key::Backspace)
+        ]);
+        assert_eq!(keys(b"\x1B"), vec![KeyEvent::plain(Key::Esc)]);
+        assert_eq!(keys(b"\x1Bx"), vec![KeyEvent::new(Key::Char('x'), Modifiers::ALT)]);
+        assert_eq!(keys(b"\x1B[Z"), vec![KeyEvent::new(Key::Tab, Modifiers::SHIFT)]);
+        assert_eq!(keys(b"\x1B[A\x1B[1;5D"), vec![
+            KeyEvent::plain(Key::Up),
+            KeyEvent::new(Key::Left, Modifiers::CTRL)
+        ]);
+        assert_eq!(keys(b"\x1B[5~\x1B[3;2~\x1BOP\x1B[15;3~"), vec![
+            KeyEvent::plain(Key::PageUp),
+            KeyEvent::new(Key::Delete, Modifiers::SHIFT),
+            KeyEvent::plain(Key::F(1)),
+            KeyEvent::new(Key::F(5), Modifiers::ALT)
+        ]);
+        assert_eq!(keys(b"\x1B[1;6H"), vec![
+            KeyEvent::new(Key::Home, Modifiers { shift: true, ctrl: true, alt: false })
+        ]);
+    }
+
+
+    #[test]
+    fn sgr_mouse() {
+        let cell = rds::main_cell_size();
+        let at = |x: i32, y: i32| vec2!(cell.x * x, cell.y * y);
+
+        let mut mb = MouseButton::Left;
+        let events = input::parse_events(b"\x1B[<0;1;1M\x1B[<34;3;5M\x1B[<2;3;5m\x1B[<65;10;2M\x1B[<35;4;4M", &mut mb);
+        assert_eq!(events, vec![
+            InputEvent::Mouse(MouseEvent::ButtonPressed(MouseButton::Left, at(0, 0))),
+            InputEvent::Mouse(MouseEvent::Hold(MouseButton::Right, at(2, 4))),
+            InputEvent::Mouse(MouseEvent::ButtonReleased(MouseButton::Right, at(2, 4))),
+            InputEvent::Mouse(MouseEvent::ButtonPressed(MouseButton::WheelDown, at(9, 1)))
+        ]);
+
+        // the released button is the one reported, not the last pressed
+        let events = input::parse_events(b"\x1B[<0;1;1M\x1B[<2;1;1M\x1B[<0;1;1m\x1B[<2;1;1m", &mut mb);
+        assert_eq!(events, vec![
+            InputEvent::Mouse(MouseEvent::ButtonPressed(MouseButton::Left, at(0, 0))),
+            InputEvent::Mouse(MouseEvent::ButtonPressed(MouseButton::Right, at(0, 0))),
+            InputEvent::Mouse(MouseEvent::ButtonReleased(MouseButton::Left, at(0, 0))),
+            InputEvent::Mouse(MouseEvent::ButtonReleased(MouseButton::Right, at(0, 0)))
+        ]);
+
+        // legacy reports release the last pressed button
+        let events = input::parse_events(b"\x1B[M\"!!\x1B[M#!!\x1B[34;1;1M\x1B[35;1;1M", &mut mb);
+        assert_eq!(events, vec![
+            InputEvent::Mouse(MouseEvent::ButtonPressed(MouseButton::Right, at(0, 0))),
+            InputEvent::Mouse(MouseEvent::ButtonReleased(MouseButton::Right, at(0, 0))),
+            InputEvent::Mouse(MouseEvent::ButtonPressed(MouseButton::Right, at(0, 0))),
+            InputEvent::Mouse(MouseEvent::ButtonReleased(MouseButton::Right, at(0, 0)))
+        ]);
+
+        let wheel = MouseEvent::ButtonPressed(MouseButton::WheelUp, at(1, 1));
+        assert!(wheel.button().is_wheel());
+        assert_eq!(wheel.pos(), at(1, 1));
+    }
+
+
+    #[test]
+    fn kitty_keyboard() {
+        let events = input::parse_events(b"\x1B[97u\x1B[97;1:2u\x1B[97;1:3u\x1B[99;5u\x1B[13u\x1B[1;2:3A\x1B[3;1:3~\x1B[57441;2u", &mut MouseButton::Left);
+        assert_eq!(events, vec![
+            InputEvent::Key(KeyEvent::plain(Key::Char('a'))),
+            InputEvent::Key(KeyEvent::plain(Key::Char('a')).with_kind(KeyKind::Repeat)),
+            InputEvent::Key(KeyEvent::plain(Key::Char('a')).with_kind(KeyKind::Release)),
+            InputEvent::Key(KeyEvent::new(Key::Char('c'), Modifiers::CTRL)),
+            InputEvent::Key(KeyEvent::plain(Key::Enter)),
+            InputEvent::Key(KeyEvent::new(Key::Up, Modifiers::SHIFT).with_kind(KeyKind::Release)),
+            InputEvent::Key(KeyEvent::plain(Key::Delete).with_kind(KeyKind::Release))
+        ]);
+    }
+
+
+    #[test]
+    fn transform() {
+        use std::f32::consts::FRAC_PI_2;
+
+        let t = Transform2::new((10, 5), FRAC_PI_2, (2., 2.));
+        assert_eq!(t.apply((1, 0)), vec2!(10, 7));
+        assert_eq!(t.apply((0, 1)), vec2!(8, 5));
+        assert_eq!(t.inverse().unwrap().apply((10, 7)), vec2!(1, 0));
+        assert_eq!(Transform2::translation((3, 4)) * Transform2::scale(2., 2.), Transform2::scale(2., 2.).then(&Transform2::translation((3, 4))));
+        assert!(Transform2::scale(0., 1.).inverse().is_none());
+
+        let mut sprite = Image::new(2, 1);
+        sprite.point((0, 0), Color::rgb(255, 0, 0));
+        sprite.point((1, 0), Color::rgb(0, 255, 0));
+
+        let mut img = Image::new(8, 8);
+        img.draw_transformed(&sprite, &Transform2::translation((3, 2)));
+        assert_eq!(img.at((3, 2)), Color::rgb(255, 0, 0));
+        assert_eq!(img.at((4, 2)), Color::rgb(0, 255, 0));
+
+        // a quarter turn clockwise puts the second pixel below the first one
+        let mut img = Image::new(8, 8);
+        img.draw_transformed(&sprite, &Transform2::new((4, 2), FRAC_PI_2, (1., 1.)));
+        assert_eq!(img.at((3, 2)), Color::rgb(255, 0, 0));
+        assert_eq!(img.at((3, 3)), Color::rgb(0, 255, 0));
+        assert_eq!(img.at((4, 2)), Color::BLACK);
+    }
+
+
+    #[test]
+    fn rect_operations() {
+        let a = Rect::new((0, 0), (4, 4));
+        let b = Rect::new((2, 3), (4, 4));
+        assert!(a.contains((3, 3)) && !a.contains((4, 0)));
+        assert!(a.intersects(&b));
+        assert_eq!(a.intersection(&b), Some(Rect::new((2, 3), (2, 1))));
+        assert_eq!(a.intersection(&Rect::new((4, 0), (2, 2))), None);
+        assert_eq!(a.union(&b), Rect::new((0, 0), (6, 7)));
+        assert_eq!(a.union(&Rect::new((9, 9), (0, 0))), a);
+        assert_eq!(Rect::new((5, 5), (-2, 1)).normalized(), Rect::new((4, 5), (2, 1)));
+        assert_eq!(Rect::from_corners((3, 3), (1, 0)), Rect::new((1, 0), (2, 3)));
+
+        // clipped to the image, a negative size goes to the top left
+        let mut img = Image::new(4, 4);
+        img.rect(Rect::new((-1, -1), (2, 2)), Color::RED);
+        img.rect(((3, 3), (-2, -1)), Color::BLUE);
+        assert_eq!(img.at((0, 0)), Color::RED);
+        assert_eq!(img.at((1, 1)), Color::BLACK);
+        assert_eq!(img.at((2, 3)), Color::BLUE);
+        assert_eq!(img.at((3, 3)), Color::BLUE);
+        assert_eq!(img.at((1, 3)), Color::BLACK);
+    }
+
+
+    #[test]
+    fn clipping() {
+        let mut img = Image::new(6, 6);
+        img.set_clip(((1, 1), (3, 3)));
+        assert_eq!(img.clip(), Some(Rect::new((1, 1), (3, 3))));
+        img.rect(((0, 0), (6, 6)), Color::RED);
+        img.line((0, 5), (5, 0), Color::BLUE);
+        assert_eq!(img.at((0, 0)), Color::BLACK);
+        assert_eq!(img.at((1, 1)), Color::RED);
+        assert_eq!(img.at((3, 3)), Color::RED);
+        assert_eq!(img.at((4, 4)), Color::BLACK);
+        assert_eq!(img.at((2, 3)), Color::BLUE);
+        assert_eq!(img.at((0, 5)), Color::BLACK);
+
+        img.clear(Color::WHITE);
+        assert_eq!(img.at((2, 2)), Color::WHITE);
+        assert_eq!(img.at((5, 5)), Color::BLACK);
+
+        img.clear_clip();
+        img.point((5, 5), Color::GREEN);
+        assert_eq!(img.at((5, 5)), Color::GREEN);
+    }
+
+
+    #[test]
+    fn pattern_fills() {
+        let mut img = Image::new(10, 10);
+        img.rect_pattern(((1, 1), (4, 4)), &Pattern::checker(Color::WHITE, Color::RED));
+        assert_eq!(img.at((0, 0)), Color::BLACK);
+        assert_eq!(img.at((2, 2)), Color::WHITE);
+        assert_eq!(img.at((3, 2)), Color::RED);
+        assert_eq!(img.at((4, 4)), Color::WHITE);
+
+        // unset bits without background are left untouched
+        let stripes = Pattern::Bits { rows: [0xff, 0, 0xff, 0, 0xff, 0, 0xff, 0], fg: Color::BLUE, bg: None };
+        let mut img = Image::new(10, 10);
+        img.polygon_pattern(&[vec2!(0, 0), vec2!(9, 0), vec2!(9, 9), vec2!(0, 9)], &stripes);
+        assert_eq!(img.at((5, 4)), Color::BLUE);
+        assert_eq!(img.at((5, 5)), Color::BLACK);
+
+        // image patterns repeat over the shape
+        let mut tile = Image::new(2, 1);
+        tile.point((1, 0), Color::GREEN);
+        let mut img = Image::filled(6, 2, Color::WHITE);
+        img.rect_pattern(((0, 0), (6, 2)), &Pattern::Image(tile));
+        assert_eq!(img.at((0, 1)), Color::BLACK);
+        assert_eq!(img.at((3, 0)), Color::GREEN);
+        assert_eq!(img.at((5, 1)), Color::GREEN);
+    }
+
+
+    #[test]
+    fn pixel_iterators() {
+        let mut img = Image::new(3, 2);
+        img.point((2, 1), Color::WHITE);
+        assert_eq!(img.pixels().filter(|c| **c == Color::WHITE).count(), 1);
+        assert_eq!(img.enumerate_pixels().find(|(_, c)| **c == Color::WHITE).map(|(p, _)| p), Some(vec2!(2, 1)));
+
+        for c in img.pixels_mut() {
+            *c = c.lerp(Color::RED, 0.5);
+        }
+        assert_eq!(img.at((0, 0)), Color::BLACK.lerp(Color::RED, 0.5));
+        assert_eq!(img.pixels().count(), 6);
+    }
+
+
+    #[test]
+    fn raw_buffer() {
+        let mut img = Image::new(4, 3);
+        img.point((1, 2), Color::WHITE);
+        assert_eq!(img.as_slice().len(), 12);
+        assert_eq!(img.as_slice()[1 + 2 * 4], Color::WHITE);
+
+        // copy the last row to the first one
+        img.as_mut_slice().copy_within(8..12, 0);
+        assert_eq!(img.at((1, 0)), Color::WHITE);
+    }
+
+
+    #[test]
+    fn raw_pixels() {
+        let rgb = [255, 0, 0, 0, 255, 0, 0, 0, 255, 9, 9, 9];
+        let img = Image::from_raw(2, 2, &rgb).unwrap();
+        assert_eq!(img.at((1, 0)), Color::rgb(0, 255, 0));
+        assert_eq!(img.at((0, 1)), Color::rgb(0, 0, 255));
+
+        let rgba = [1, 2, 3, 4, 5, 6, 7, 8];
+        assert_eq!(Image::from_raw(2, 1, &rgba).unwrap().at((1, 0)), Color::rgb(5, 6, 7));
+        assert_eq!(Image::from_raw_rgba(2, 1, &rgba).unwrap().at((1, 0)), Rgba::new(5, 6, 7, 8));
+        assert_eq!(Image::from_raw_rgba(4, 1, &rgb).unwrap().at((0, 0)), Rgba::new(255, 0, 0, 255));
+        assert!(Image::from_raw(3, 3, &rgb).is_err());
+    }
+
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let mut img = Image::new(5, 3);
+        img.rect(((1, 1), (3, 1)), Color::rgb(255, 127, 80));
+        let json = serde_json::to_string(&img).unwrap();
+        assert_eq!(json, r#"{"width":5,"height":3,"runs":[[{"r":0,"g":0,"b":0},6],[{"r":255,"g":127,"b":80},3],[{"r":0,"g":0,"b":0},6]]}"#);
+
+        let back: Image = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.size(), img.size());
+        assert_eq!(back.as_slice(), img.as_slice());
+        assert!(serde_json::from_str::<Image>(r#"{"width":2,"height":2,"runs":[[{"r":0,"g":0,"b":0},5]]}"#).is_err());
+        assert!(serde_json::from_str::<Image>(r#"{"width":2147483647,"height":2147483647,"runs":[]}"#).is_err());
+        assert!(serde_json::from_str::<Image>(r#"{"width":46340,"height":46340,"runs":[[{"r":0,"g":0,"b":0},1]]}"#).is_err());
+
+        assert_eq!(serde_json::from_str::<Vec2>("{\"x\":1,\"y\":-2}").unwrap(), vec2!(1, -2));
+    }
+
+
+    #[test]
+    fn image_crate_interop() {
+        let mut img = Image::new(3, 2);
+        img.point((2, 1), Color::rgb(10, 20, 30));
+        let buf = img.to_rgb_image();
+        assert_eq!(buf.get_pixel(2, 1).0, [10, 20, 30]);
+
+        let back: Image = image::DynamicImage::ImageRgb8(buf).into();
+        assert_eq!(back.size(), vec2!(3, 2));
+        assert_eq!(back.at((2, 1)), Color::rgb(10, 20, 30));
+
+        let rgba = Image::filled(1, 1, Rgba::new(1, 2, 3, 4)).to_rgba_image();
+        let back: Image<Rgba> = image::DynamicImage::ImageRgba8(rgba).into();
+        assert_eq!(back.at((0, 0)), Rgba::new(1, 2, 3, 4));
+    }
+
+
+    #[test]
+    fn indexed_images() {
+        let mut palette = Palette::new(&[Color::BLACK, Color::RED, Color::GREEN, Color::BLUE]);
+        assert_eq!(palette.nearest(Color::rgb(0, 200, 10)), 2);
+        palette.rotate(1..4, 1);
+        assert_eq!(palette.colors(), &[Color::BLACK, Color::BLUE, Color::RED, Color::GREEN]);
+        palette.rotate(1..4, -1);
+        assert_eq!(palette.color(1), Color::RED);
+        assert_eq!(palette.color(9), Color::BLACK);
+        palette.rotate(2..10, 1);
+        assert_eq!(palette.colors(), &[Color::BLACK, Color::RED, Color::BLUE, Color::GREEN]);
+        palette.rotate(8..10, 1);
+        palette.rotate(2..10, -1);
+
+        let mut sprite = IndexedImage::new(2, 2, palette);
+        sprite.indices_mut().point((1, 0), 1);
+        sprite.indices_mut().point((1, 1), 3);
+
+        let mut img = Image::filled(4, 4, Color::WHITE);
+        img.indexed_image(&sprite, (2, 2), sprite.size(), Vec2::ZERO, Some(0));
+        assert_eq!(img.at((2, 2)), Color::WHITE);
+        assert_eq!(img.at((3, 2)), Color::RED);
+        assert_eq!(img.at((3, 3)), Color::BLUE);
+
+        // palette swap at draw time
+        let swap = Palette::new(&[Color::BLACK, Color::YELLOW]);
+        img.indexed_image_palette(&sprite, &swap, (0, 0), sprite.size(), Vec2::ZERO, None);
+        assert_eq!(img.at((0, 0)), Color::BLACK);
+        assert_eq!(img.at((1, 0)), Color::YELLOW);
+        assert_eq!(img.at((1, 1)), Color::BLACK);
+
+        sprite.palette_mut().set_color(1, Color::AQUA);
+        assert_eq!(sprite.to_image().at((1, 0)), Color::AQUA);
+        assert_eq!(IndexedImage::from_image(&img, Palette::new(&[Color::WHITE, Color::RED])).indices().at((3, 2)), 1);
+    }
+
+
+    #[test]
+    fn palette_quantization() {
+        let palette = Palette::new(&[Color::BLACK, Color::WHITE]);
+        let mut img = Image::new(8, 8);
+        img.clear(Color::rgb(128, 128, 128));
+        img.point((0, 0), Color::rgb(250, 240, 250));
+
+        let flat = img.quantize(&palette, rds::Dithering::None);
+        assert_eq!(flat.at((0, 0)), Color::WHITE);
+        assert_eq!(flat.at((5, 5)), Color::WHITE);
+
+        // dithered mid gray is a mix of black and white pixels
+        for dithering in [rds::Dithering::Ordered, rds::Dithering::FloydSteinberg] {
+            let indexed = img.quantize_indexed(&palette, dithering);
+            let whites = indexed.indices().pixels().filter(|i| **i == 1).count();
+            assert!(whites > 16 && whites < 48);
+            assert!(indexed.to_image().pixels().all(|c| *c == Color::BLACK || *c == Color::WHITE));
+        }
+    }
+
+
+    #[test]
+    fn textures() {
+        let (mut rdr, _output) = Renderer::headless((4, 4)).unwrap();
+        let mut sprite = Image::new(2, 2);
+        sprite.point((1, 1), Color::RED);
+        let tex = rdr.upload_texture(sprite);
+        assert_eq!(rdr.texture_size(tex), Some(vec2!(2, 2)));
+
+        rdr.begin_draw().unwrap();
+        rdr.clear_screen(Color::WHITE);
+        rdr.draw_whole_texture(tex, (1, 1));
+        rdr.draw_texture(tex, (0, 3), (1, 1), (1, 1), None, BlendMode::Normal);
+        rdr.end_draw().unwrap();
+
+        let screen = rdr.screenshot().unwrap();
+        assert_eq!(screen[(1, 1)], Color::BLACK);
+        assert_eq!(screen[(2, 2)], Color::RED);
+        assert_eq!(screen[(0, 3)], Color::RED);
+        assert_eq!(screen[(0, 0)], Color::WHITE);
+
+        // freed textures are not drawn
+        rdr.free_texture(tex);
+        assert_eq!(rdr.texture_size(tex), None);
+        rdr.begin_draw().unwrap();
+        rdr.clear_screen(Color::WHITE);
+        rdr.draw_texture(tex, (0, 0), (2, 2), Vec2::ZERO, None, BlendMode::Normal);
+        rdr.end_draw().unwrap();
+        assert_eq!(rdr.screenshot().unwrap()[(1, 1)], Color::WHITE);
+    }
+
+
+    #[test]
+    fn batched_commands() {
+        let (mut rdr, _output) = Renderer::headless((4, 4)).unwrap();
+        let tex = rdr.upload_texture(Image::filled(1, 1, Color::GREEN));
+
+        rdr.begin_draw().unwrap();
+        rdr.submit(&[
+            rds::DrawCommand::Clear(Color::BLUE),
+            rds::DrawCommand::Rect(Rect::new((0, 0), (2, 2)), Color::RED),
+            rds::DrawCommand::Point(vec2!(1, 1), Color::WHITE),
+            rds::DrawCommand::Texture(tex, vec2!(3, 3), vec2!(1, 1), Vec2::ZERO, None, BlendMode::Normal)
+        ]);
+        rdr.end_draw().unwrap();
+
+        let screen = rdr.screenshot().unwrap();
+        assert_eq!(screen[(0, 0)], Color::RED);
+        assert_eq!(screen[(1, 1)], Color::WHITE);
+        assert_eq!(screen[(2, 2)], Color::BLUE);
+        assert_eq!(screen[(3, 3)], Color::GREEN);
+    }
+
+
+    #[test]
+    fn with_frame() {
+        let (mut rdr, _output) = Renderer::headless((4, 2)).unwrap();
+        let drawn = rdr.with_frame(|frame| {
+            frame.clear_screen(Color::BLUE);
+            frame.draw_line((0, 1), (3, 1), Color::RED);
+            42
+        });
+        assert_eq!(drawn, Ok(42));
+
+        let screen = rdr.screenshot().unwrap();
+        assert_eq!(screen[(0, 0)], Color::BLUE);
+        assert_eq!(screen[(2, 1)], Color::RED);
+
+        rdr.begin_draw().unwrap();
+        assert_eq!(rdr.with_frame(|_| ()), Err(rds::RenderError::AlreadyDrawing));
+        rdr.end_draw().unwrap();
+    }
+
+
+    #[test]
+    fn frame_size() {
+        let (mut rdr, _output) = Renderer::headless((4, 2)).unwrap();
+        assert_eq!(rdr.frame_size(), Vec2::ZERO);
+
+        rdr.begin_draw().unwrap();
+        let size = rdr.frame_size();
+        assert_eq!(size, rdr.size());
+        // resized during the frame
+        rdr.set_fixed_size((8, 6));
+        assert_eq!(rdr.size(), vec2!(8, 6));
+        assert_eq!(rdr.frame_size(), size);
+        rdr.end_draw().unwrap();
+        assert_eq!(rdr.frame_size(), size);
+
+        rdr.with_frame(|_| ()).unwrap();
+        assert_eq!(rdr.frame_size(), vec2!(8, 6));
+    }
+
+
+    #[test]
+    fn cursor_control() {
+        let (mut rdr, output) = Renderer::headless((4, 2)).unwrap();
+        output.take_bytes();
+
+        rdr.set_cursor_cell(2, 1);
+        rdr.show_cursor();
+        rdr.begin_draw().unwrap();
+        rdr.draw_point((0, 0), Color::RED);
+        rdr.end_draw().unwrap();
+        rdr.screenshot().unwrap();
+        let out = String::from_utf8_lossy(&output.take_bytes()).to_string();
+        assert!(out.ends_with("\x1b[2;3H\x1b[?25h"));
+
+        rdr.hide_cursor();
+        rdr.screenshot().unwrap();
+        assert_eq!(output.take_bytes(), b"\x1b[?25l");
+    }
+
+
+    #[test]
+    fn terminal_title() {
+        let (mut rdr, output) = Renderer::headless((4, 2)).unwrap();
+        output.take_bytes();
+
+        rdr.set_title("Snake\x07 - 12");
+        rdr.set_icon_name("snake");
+        rdr.screenshot().unwrap();
+        assert!(output.take_bytes().ends_with(b"\x1b]2;Snake - 12\x07\x1b]1;snake\x07"));
+    }
+
+
+    #[test]
+    fn logical_size() {
+        let (mut rdr, output) = Renderer::headless((8, 4)).unwrap();
+        let term = rdr.size();
+        rdr.set_logical_size((4, 4), rds::Scaling::Integer);
+        assert_eq!(rdr.size(), vec2!(4, 4));
+
+        rdr.with_frame(|frame| frame.clear_screen(Color::RED)).unwrap();
+        assert_eq!(rdr.frame_size(), vec2!(4, 4));
+        assert_eq!(rdr.screenshot().unwrap().size(), vec2!(4, 4));
+        assert!(!output.take_bytes().is_empty());
+
+        rdr.clear_logical_size();
+        assert_eq!(rdr.size(), term);
+
+        // a terminal smaller than the frame downscales it
+        assert_eq!(rds::Scaling::Integer.scaled_size(vec2!(4, 4), vec2!(9, 8)), vec2!(8, 8));
+        assert_eq!(rds::Scaling::Integer.scaled_size(vec2!(160, 100), vec2!(80, 48)), vec2!(76, 48));
+    }
+
+
+    #[test]
+    fn draw_cell() {
+        let (mut rdr, output) = Renderer::headless((4, 2)).unwrap();
+        rdr.begin_draw().unwrap();
+        rdr.clear_screen(Color::BLUE);
+        rdr.draw_cell((0, 0), '🍎', Color::RED, Color::BLACK);
+        rdr.draw_cell((2, 0), '┐', Color::WHITE, Color::BLACK);
+        rdr.end_draw().unwrap();
+        rdr.screenshot().unwrap();
+
+        let out = String::from_utf8(output.take_bytes()).unwrap();
+        let apple = out.find('🍎').unwrap();
+        // the cell covered by the apple is skipped
+        assert!(out[apple..].contains("\x1b[1;3H"));
+        assert!(out[apple..].contains('┐'));
+        assert!(!out.contains('\0'));
+    }
+
+
+    #[test]
+    fn shade_mixing() {
+        let (mut rdr, output) = Renderer::headless((1, 1)).unwrap();
+        let mut config = rds::RendererConfig {
+            protocol: rds::OutputProtocol::Text,
+            colors: ColorDepth::Ansi16(|c| c.to_ansi16()),
+            dithering: rds::Dithering::None,
+            shades: false
+        };
+        let draw = |rdr: &mut Renderer, color| {
+            rdr.begin_draw().unwrap();
+            rdr.clear_screen(color);
+            rdr.end_draw().unwrap();
+            rdr.screenshot().unwrap();
+        };
+
+        rdr.set_config(config);
+        draw(&mut rdr, Color::rgb(64, 0, 0));
+        let out = String::from_utf8(output.take_bytes()).unwrap();
+        assert!(!out.contains(['░', '▒', '▓']));
+
+        config.shades = true;
+        rdr.set_config(config);
+        draw(&mut rdr, Color::rgb(0, 64, 0));
+        let out = String::from_utf8(output.take_bytes()).unwrap();
+        assert!(out.contains(['░', '▒', '▓']));
+    }
+
+
+    #[test]
+    fn color_key() {
+        let mut sprite = Image::filled(2, 1, Color::WHITE);
+        sprite.put((1, 0), Color::RED);
+        let sprite = sprite.with_color_key(Color::WHITE);
+        assert_eq!(sprite.at((0, 0)), Rgba::TRANSPARENT);
+
+        let mut img = Image::filled(2, 1, Color::BLUE);
+        img.image(&sprite, (0, 0), (2, 1), (0, 0), None, BlendMode::Normal);
+        assert_eq!(img[(0, 0)], Color::BLUE);
+        assert_eq!(img[(1, 0)], Color::RED);
+    }
+
+
+    #[test]
+    fn checked_pixel_access() {
+        let mut img = Image::new(2, 2);
+        assert!(img.set((1, 1), Color::RED));
+        assert!(!img.set((2, 0), Color::RED));
+        assert!(!img.set((-1, 0), Color::RED));
+        assert_eq!(img.get((1, 1)), Some(Color::RED));
+        assert_eq!(img.get((0, 2)), None);
+
+        // the clipping region only applies to drawing
+        img.set_clip(Rect::new((0, 0), (1, 1)));
+        assert!(img.set((1, 0), Color::BLUE));
+        assert_eq!(img[(1, 0)], Color::BLUE);
+
+        let result = std::panic::catch_unwind(move || img[(2, 2)]);
+        assert!(result.is_err());
+    }
+
+
+    #[test]
+    fn resize() {
+        let mut img = Image::new(3, 2);
+        img[(0, 0)] = Color::RED;
+        img[(2, 1)] = Color::BLUE;
+
+        let mut grown = img.clone();
+        grown.resize((4, 4));
+        assert_eq!(grown.size(), vec2!(4, 4));
+        assert_eq!(grown[(0, 0)], Color::RED);
+        assert_eq!(grown[(2, 1)], Color::BLUE);
+        assert_eq!(grown[(3, 3)], Color::BLACK);
+
+        let mut shrunk = img.clone();
+        shrunk.resize((2, 1));
+        assert_eq!(shrunk.as_slice(), &[Color::RED, Color::BLACK]);
+
+        let mut centered = img.clone();
+        centered.resize_anchored((5, 4), Anchor::Center);
+        assert_eq!(centered[(1, 1)], Color::RED);
+        assert_eq!(centered[(3, 2)], Color::BLUE);
+
+        let mut corner = img.clone();
+        corner.resize_anchored((1, 1), Anchor::BottomRight);
+        assert_eq!(corner[(0, 0)], Color::BLUE);
+
+        img.resize_scaled((6, 4), Filter::Nearest);
+        assert_eq!(img[(1, 1)], Color::RED);
+        assert_eq!(img[(5, 3)], Color::BLUE);
+    }
+
+
+    #[test]
+    fn noise() {
+        for kind in [NoiseKind::Value, NoiseKind::Perlin, NoiseKind::Simplex] {
+            let noise = Noise::new(kind, 3).with_frequency(0.1).with_octaves(3, 0.5);
+            let field = noise.field((32, 32));
+            assert_eq!(field.len(), 32 * 32);
+            assert!(field.iter().all(|v| (0. ..=1.).contains(v)));
+            // the noise is not flat, and is the same for the same seed
+            assert!(field.iter().any(|v| (v - field[0]).abs() > 0.05));
+            assert_eq!(noise.get(5.5, 7.25), Noise::new(kind, 3).with_frequency(0.1).with_octaves(3, 0.5).get(5.5, 7.25));
+            // and it is continuous
+            assert!((noise.get(10., 10.) - noise.get(10.01, 10.)).abs() < 0.02);
+        }
+        let a = Noise::new(NoiseKind::Perlin, 1).with_frequency(0.3).field((8, 8));
+        let b = Noise::new(NoiseKind::Perlin, 2).with_frequency(0.3).field((8, 8));
+        assert_ne!(a, b);
+
+        let img = Image::from_noise((4, 3), &Noise::new(NoiseKind::Value, 0), |v| Color::BLACK.lerp(Color::WHITE, v));
+        assert_eq!(img.size(), vec2!(4, 3));
+    }
+
+
+    #[test]
+    fn depth_triangles() {
+        let mut img = Image::new(8, 8);
+        let mut depth = DepthBuffer::new(img.size());
+        let quad = |z, c| [
+            [Vertex::new((0., 0.), z, c), Vertex::new((8., 0.), z, c), Vertex::new((0., 8.), z, c)],
+            [Vertex::new((8., 0.), z, c), Vertex::new((8., 8.), z, c), Vertex::new((0., 8.), z, c)]
+        ];
+
+        // the far triangles are drawn last but stay hidden
+        for t in quad(1., Color::BLUE).into_iter().chain(quad(2., Color::RED)) {
+            img.triangle_depth(&mut depth, t, Shading::Flat);
+        }
+        assert!(img.pixels().all(|c| *c == Color::BLUE));
+        assert_eq!(depth.get((3, 3)), Some(1.));
+
+        depth.clear();
+        img.clear(Color::BLACK);
+        let t = [Vertex::new((0., 0.), 1., Color::RED), Vertex::new((8., 0.), 1., Color::GREEN), Vertex::new((0., 8.), 1., Color::BLUE)];
+        img.triangle_depth(&mut depth, t, Shading::Gouraud);
+        assert!(img[(0, 0)].r > 200);
+        assert!(img[(6, 0)].g > img[(6, 0)].r);
+        assert_eq!(img[(7, 7)], Color::BLACK);
+
+        let v = Vertex::project((1., 1., 2.), Color::RED, 4., (8, 8)).unwrap();
+        assert_eq!(v.pos, (6., 2.));
+        assert!(Vertex::project((0., 0., -1.), Color::RED, 4., (8, 8)).is_none());
+    }
+
+
+    #[test]
+    fn textured_triangles() {
+        let mut texture = Image::filled(2, 2, Color::WHITE);
+        texture.put((1, 0), Color::RED);
+        texture.put((0, 1), Color::RED);
+
+        // the texture is repeated twice on each axis
+        let v = |x: f32, y: f32| Vertex::new((x * 8., y * 8.), 1., Color::WHITE).with_uv((x * 2., y * 2.));
+        let mut img = Image::new(8, 8);
+        img.triangle_textured(None, [v(0., 0.), v(1., 0.), v(0., 1.)], &texture);
+        img.triangle_textured(None, [v(1., 0.), v(1., 1.), v(0., 1.)], &texture);
+        assert_eq!(img[(0, 0)], Color::WHITE);
+        assert_eq!(img[(2, 0)], Color::RED);
+        assert_eq!(img[(4, 0)], Color::WHITE);
+        assert_eq!(img[(6, 7)], Color::WHITE);
+        assert_eq!(img[(7, 5)], Color::RED);
+
+        let mut depth = DepthBuffer::new(img.size());
+        let front = [v(0., 0.), v(1., 0.), v(0., 1.)].map(|v| Vertex { z: 0.5, ..v });
+        img.triangle_depth(&mut depth, front.map(|v| Vertex { color: Color::BLUE, ..v }), Shading::Flat);
+        img.triangle_textured(Some(&mut depth), [v(0., 0.), v(1., 0.), v(0., 1.)], &texture);
+        assert_eq!(img[(2, 0)], Color::BLUE);
+    }
+
+
+    #[test]
+    fn shaders() {
+        let mut img = Image::filled(4, 2, Color::RED);
+        img.shade(|p, c| if p.x % 2 == 0 { c } else { Color::BLUE });
+        assert_eq!(img.as_slice()[..4], [Color::RED, Color::BLUE, Color::RED, Color::BLUE]);
+
+        img.set_clip(Rect::new((0, 0), (4, 1)));
+        img.shade_rect(Rect::new((0, 0), (2, 2)), |_, _| Color::WHITE);
+        assert_eq!(img[(1, 0)], Color::WHITE);
+        assert_eq!(img[(2, 0)], Color::RED);
+        assert_eq!(img[(0, 1)], Color::RED);
+
+        let (mut rdr, _output) = Renderer::headless((4, 2)).unwrap();
+        rdr.with_frame(|frame| {
+            frame.clear_screen(Color::WHITE);
+            frame.shade(Rect::new((0, 0), (2, 4)), |_, c| c.darken(1.));
+        }).unwrap();
+        let screen = rdr.screenshot().unwrap();
+        assert_eq!(screen[(1, 3)], Color::BLACK);
+        assert_eq!(screen[(2, 0)], Color::WHITE);
+    }
+
+
+    #[test]
+    fn palette_cycling() {
+        let (mut rdr, output) = Renderer::headless((4, 2)).unwrap();
+        rdr.enable_indexed_mode(vec![Color::BLACK, Color::RED, Color::BLUE]);
+        rdr.with_frame(|frame| {
+            frame.clear_screen(Color::BLACK);
+            frame.draw_point((1, 1), Color::RED);
+        }).unwrap();
+        rdr.screenshot().unwrap();
+        output.take_bytes();
+
+        rdr.rotate_palette(1..3, 1);
+        rdr.with_frame(|_| ()).unwrap();
+        let screen = rdr.screenshot().unwrap();
+        assert_eq!(screen[(1, 1)], Color::BLUE);
+        assert_eq!(screen[(0, 0)], Color::BLACK);
+        // only the changed cell is sent
+        let out = String::from_utf8(output.take_bytes()).unwrap();
+        assert_eq!(out.matches('H').count(), 1);
+
+        // out of the palette
+        rdr.rotate_palette(2..10, 1);
+        rdr.with_frame(|_| ()).unwrap();
+    }
+
+
+    #[test]
+    fn indexed_diff() {
+        let (mut rdr, output) = Renderer::headless((4, 4)).unwrap();
+        rdr.enable_indexed_mode(vec![Color::BLACK, Color::RED, Color::BLUE]);
+        rdr.with_frame(|frame| frame.clear_screen(Color::BLACK)).unwrap();
+        rdr.screenshot().unwrap();
+        output.take_bytes();
+
+        rdr.with_frame(|frame| {
+            frame.clear_screen(Color::BLACK);
+            frame.draw_point((3, 2), Color::BLUE);
+        }).unwrap();
+        let screen = rdr.screenshot().unwrap();
+        assert_eq!(screen[(3, 2)], Color::BLUE);
+        let out = String::from_utf8(output.take_bytes()).unwrap();
+        assert_eq!(out.matches('H').count(), 1);
+    }
+
+
+    #[test]
+    fn fixed_timestep() {
+        use std::time::Duration;
+        let ms = Duration::from_millis;
+        let mut timestep = game::Timestep::new(10, 3);
+        // the first update is immediate
+        assert_eq!(timestep.advance(ms(0)), (1, ms(0)));
+        assert_eq!(timestep.advance(ms(40)), (0, ms(60)));
+        assert_eq!(timestep.advance(ms(70)), (1, ms(0)));
+        // the late updates are caught up, up to the limit
+        assert_eq!(timestep.advance(ms(190)), (2, ms(0)));
+        assert_eq!(timestep.advance(ms(1000)), (3, ms(0)));
+        assert_eq!(timestep.advance(ms(0)), (0, ms(100)));
+    }
+
+
+    #[test]
+    fn frame_clock() {
+        use std::time::Duration;
+        let (mut rdr, _output) = Renderer::headless((4, 2)).unwrap();
+        assert_eq!(rdr.delta_time(), Duration::ZERO);
+
+        rdr.with_frame(|_| ()).unwrap();
+        assert_eq!(rdr.delta_time(), Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(20));
+        rdr.with_frame(|_| ()).unwrap();
+        assert!(rdr.delta_time() >= Duration::from_millis(20));
+        assert!(rdr.elapsed() >= rdr.delta_time());
+    }
+
+
+    #[test]
+    fn action_map() {
+        let mut actions = ActionMap::new();
+        actions.bind("jump", Key::Char(' '));
+        actions.bind("jump", MouseButton::Left);
+        actions.bind("zoom", MouseButton::WheelUp);
+
+        let key = |kind| InputEvent::Key(KeyEvent::plain(Key::Char(' ')).with_kind(kind));
+        actions.update(&[key(KeyKind::Press)]);
+        assert!(actions.pressed("jump") && actions.held("jump"));
+
+        actions.update(&[key(KeyKind::Repeat)]);
+        assert!(!actions.pressed("jump") && actions.held("jump"));
+
+        actions.update(&[key(KeyKind::Release)]);
+        assert!(actions.released("jump") && !actions.held("jump"));
+
+        let mouse = |e: fn(MouseButton, Vec2) -> MouseEvent, b| InputEvent::Mouse(e(b, Vec2::ZERO));
+        actions.update(&[mouse(MouseEvent::ButtonPressed, MouseButton::Left), mouse(MouseEvent::ButtonPressed, MouseButton::WheelUp)]);
+        assert!(actions.pressed("jump") && actions.pressed("zoom"));
+        actions.update(&[]);
+        assert!(actions.held("jump") && actions.released("zoom"));
+
+        // remapping
+        actions.rebind("jump", Key::Up);
+        assert_eq!(actions.bindings("jump"), &[input::Binding::Key(Key::Up)]);
+        actions.update(&[mouse(MouseEvent::ButtonReleased, MouseButton::Left)]);
+        assert!(!actions.held("jump") && actions.released("jump"));
+        assert!(!actions.held("unknown"));
+    }
+
+
+    #[test]
+    #[cfg(all(feature = "gamepad", target_os = "linux"))]
+    fn gamepad_events() {
+        use crate::gamepad::{Device, GamepadEvent, GamepadButton, GamepadAxis};
+
+        let info = |minimum, maximum, flat| libc::input_absinfo { value: 0, minimum, maximum, fuzz: 0, flat, resolution: 0 };
+        let mut abs = [info(0, 0, 0); 0x40];
+        abs[0x00] = info(-32768, 32767, 128);
+        abs[0x02] = info(0, 255, 0);
+        abs[0x10] = info(-1, 1, 0);
+        let mut pad = Device::new(1, abs);
+
+        assert_eq!(pad.handle(0x01, 0x130, 1), vec![GamepadEvent::ButtonPressed(1, GamepadButton::South)]);
+        assert_eq!(pad.handle(0x01, 0x130, 2), vec![]);
+        assert_eq!(pad.handle(0x01, 0x130, 0), vec![GamepadEvent::ButtonReleased(1, GamepadButton::South)]);
+
+        // dead zone
+        assert_eq!(pad.handle(0x03, 0x00, 100), vec![]);
+        assert_eq!(pad.handle(0x03, 0x00, 32767), vec![GamepadEvent::Axis(1, GamepadAxis::LeftX, 1.)]);
+        assert_eq!(pad.handle(0x03, 0x00, -32768), vec![GamepadEvent::Axis(1, GamepadAxis::LeftX, -1.)]);
+        assert_eq!(pad.handle(0x03, 0x02, 255), vec![GamepadEvent::Axis(1, GamepadAxis::LeftTrigger, 1.)]);
+
+        // d-pad reported as a hat
+        assert_eq!(pad.handle(0x03, 0x10, -1), vec![
+            GamepadEvent::Axis(1, GamepadAxis::DPadX, -1.),
+            GamepadEvent::ButtonPressed(1, GamepadButton::DPadLeft)
+        ]);
+        assert_eq!(pad.handle(0x03, 0x10, 1), vec![
+            GamepadEvent::Axis(1, GamepadAxis::DPadX, 1.),
+            GamepadEvent::ButtonReleased(1, GamepadButton::DPadLeft),
+            GamepadEvent::ButtonPressed(1, GamepadButton::DPadRight)
+        ]);
+
+        let mut actions = ActionMap::new();
+        actions.bind("jump", GamepadButton::South);
+        actions.bind("fire", (1, GamepadButton::East));
+        actions.update(&[
+            InputEvent::Gamepad(GamepadEvent::ButtonPressed(0, GamepadButton::South)),
+            InputEvent::Gamepad(GamepadEvent::ButtonPressed(0, GamepadButton::East))
+        ]);
+        assert!(actions.pressed("jump"));
+        assert!(!actions.held("fire"));
+
+        // the second controller releasing the button does not release the first one
+        actions.update(&[
+            InputEvent::Gamepad(GamepadEvent::ButtonPressed(1, GamepadButton::South)),
+            InputEvent::Gamepad(GamepadEvent::ButtonReleased(1, GamepadButton::South)),
+            InputEvent::Gamepad(GamepadEvent::ButtonPressed(1, GamepadButton::East))
+        ]);
+        assert!(actions.held("jump"));
+        assert!(actions.pressed("fire"));
+        actions.update(&[InputEvent::Gamepad(GamepadEvent::Disconnected(0))]);
+        assert!(actions.released("jump"));
+        assert!(actions.held("fire"));
+    }
+
+
+    #[test]
+    fn immediate_ui() {
+        use crate::ui::{self, UiState};
+        use crate::rds::Frame;
+
+        let mut state = UiState::new();
+        let mut frame = Frame::new();
+        let button = Rect::new(vec2!(0, 0), vec2!(20, 10));
+        let slider = Rect::new(vec2!(0, 20), vec2!(11, 5));
+        let mut volume = 0.5;
+        let mouse = |e: fn(MouseButton, Vec2) -> MouseEvent, p| InputEvent::Mouse(e(MouseButton::Left, p));
+        let key = |k| InputEvent::Key(KeyEvent::plain(k));
+
+        // press and release in the same frame
+        let mut ui = ui::begin(&mut state, &mut frame, &[mouse(MouseEvent::ButtonPressed, vec2!(5, 5)), mouse(MouseEvent::ButtonReleased, vec2!(6, 5))]);
+        assert!(ui.button("Start", button));
+        assert!(!ui.slider(&mut volume, 0.0..1.0, slider));
+        drop(ui);
+
+        // released outside of the button
+        let mut ui = ui::begin(&mut state, &mut frame, &[mouse(MouseEvent::ButtonPressed, vec2!(5, 5))]);
+        assert!(!ui.button("Start", button));
+        drop(ui);
+        let mut ui = ui::begin(&mut state, &mut frame, &[mouse(MouseEvent::ButtonReleased, vec2!(30, 5))]);
+        assert!(!ui.button("Start", button));
+        drop(ui);
+
+        // dragging the slider
+        let mut ui = ui::begin(&mut state, &mut frame, &[mouse(MouseEvent::ButtonPressed, vec2!(2, 22)), mouse(MouseEvent::Hold, vec2!(10, 22))]);
+        ui.button("Start", button);
+        assert!(ui.slider(&mut volume, 0.0..1.0, slider));
+        assert_eq!(volume, 1.);
+        drop(ui);
+
+        // keyboard focus
+        let mut ui = ui::begin(&mut state, &mut frame, &[mouse(MouseEvent::ButtonReleased, vec2!(10, 22)), key(Key::Left), key(Key::Left)]);
+        ui.button("Start", button);
+        assert!(ui.slider(&mut volume, 0.0..1.0, slider));
+        assert!((volume - 0.9).abs() < 1e-5);
+        drop(ui);
+        let mut ui = ui::begin(&mut state, &mut frame, &[key(Key::Tab), key(Key::Enter)]);
+        assert!(ui.button("Start", button));
+        ui.slider(&mut volume, 0.0..1.0, slider);
+        drop(ui);
+
+        let mut checked = false;
+        let mut ui = ui::begin(&mut state, &mut frame, &[key(Key::Char(' '))]);
+        assert!(ui.button("Start", button));
+        assert!(!ui.checkbox("Sound", &mut checked, slider));
+    }
+
+
+    #[test]
+    fn widgets() {
+        use crate::widgets::{Widget, Label, ProgressBar, Slider, ListBox};
+        use crate::ui::Style;
+        use crate::img::Anchor;
+
+        let style = Style { padding: 1, ..Style::default() };
+        let mut img = Image::new(40, 30);
+
+        let bar = Rect::new(vec2!(0, 0), vec2!(12, 6));
+        ProgressBar::new(0.5).draw(&mut img, bar, &style);
+        assert_eq!(img[(0, 0)], style.border);
+        assert_eq!(img[(11, 5)], style.border);
+        assert_eq!(img[(2, 2)], style.accent);
+        assert_eq!(img[(5, 2)], style.accent);
+        assert_eq!(img[(6, 2)], style.background);
+        assert_eq!(img[(12, 2)], Color::BLACK);
+
+        let mut slider = Slider::new(0., 0.0..10.0);
+        let r = Rect::new(vec2!(0, 10), vec2!(14, 5));
+        slider.set_from_pos(r, &style, vec2!(100, 0));
+        assert_eq!(slider.value, 10.);
+        slider.set_from_pos(r, &style, vec2!(2, 0));
+        assert_eq!(slider.value, 0.);
+        slider.draw(&mut img, r, &style);
+        assert_eq!(img[(2, 12)], style.accent);
+
+        let mut list = ListBox::new((0..10).map(|i| format!("item {}", i)).collect());
+        let r = Rect::new(vec2!(0, 0), vec2!(40, 22));
+        assert_eq!(list.visible_items(r, &style), 2);
+        list.select(5, r, &style);
+        assert_eq!(list.scroll, 4);
+        list.select_previous(r, &style);
+        list.select_previous(r, &style);
+        assert_eq!((list.selected, list.scroll), (Some(3), 3));
+        assert_eq!(list.item_at(r, &style, vec2!(5, 3 + 8)), Some(4));
+        assert_eq!(list.item_at(r, &style, vec2!(0, 3)), None);
+
+        // the items are clipped to the inside of the border
+        let mut img = Image::new(40, 22);
+        list.draw(&mut img, r, &style);
+        assert_eq!(img[(0, 21)], style.border);
+        assert_eq!(img[(2, 2)], style.accent);
+        assert_eq!(img.clip(), None);
+
+        let mut img = Image::new(9, 9);
+        Label::new("a").with_align(Anchor::BottomRight).draw(&mut img, Rect::new(vec2!(0, 0), vec2!(9, 9)), &style);
+        assert!((0..9).all(|x| img[(x, 0)] == Color::BLACK));
+        assert!((0..9).any(|x| img[(x, 8)] == style.text));
+    }
+
+
+    #[test]
+    fn damage_tracking() {
+        let (mut rdr, output) = Renderer::headless((20, 20)).unwrap();
+        let sent = |output: &rds::HeadlessOutput| String::from_utf8(output.take_bytes()).unwrap().matches('H').count();
+        rdr.with_frame(|frame| {
+            frame.clear_screen(Color::BLACK);
+            frame.draw_rect(Rect::new((2, 2), (2, 2)), Color::RED);
+        }).unwrap();
+        rdr.screenshot().unwrap();
+        output.take_bytes();
+
+        // the sprite moves: its previous position is erased by the clear
+        rdr.with_frame(|frame| {
+            frame.clear_screen(Color::BLACK);
+            frame.draw_rect(Rect::new((10, 10), (2, 2)), Color::RED);
+        }).unwrap();
+        rdr.screenshot().unwrap();
+        assert_eq!(sent(&output), 2);
+
+        // cells are redrawn as pixels once cleared
+        rdr.begin_draw().unwrap();
+        rdr.draw_cell((15, 0), 'x', Color::WHITE, Color::BLUE);
+        rdr.end_draw().unwrap();
+        rdr.screenshot().unwrap();
+        assert_eq!(sent(&output), 1);
+        rdr.begin_draw().unwrap();
+        rdr.clear_cells();
+        rdr.end_draw().unwrap();
+        rdr.screenshot().unwrap();
+        assert_eq!(sent(&output), 1);
+
+        // a clear with another color changes the whole screen
+        rdr.with_frame(|frame| frame.clear_screen(Color::BLUE)).unwrap();
+        rdr.screenshot().unwrap();
+        assert_eq!(sent(&output), 1);
+        let screen = rdr.screenshot().unwrap();
+        assert!(screen.pixels().all(|c| *c == Color::BLUE));
+
+        // re-creating a layer changes the order of the layers
+        rdr.with_frame(|frame| {
+            frame.create_layer("a", 1);
+            frame.create_layer("b", 2);
+            frame.set_layer("a");
+            frame.draw_point((0, 0), Color::RED);
+            frame.set_layer("b");
+            frame.draw_point((0, 0), Color::GREEN);
+        }).unwrap();
+        rdr.screenshot().unwrap();
+        output.take_bytes();
+        rdr.with_frame(|frame| frame.create_layer("a", 3)).unwrap();
+        rdr.screenshot().unwrap();
+        // only the point changes, and it is now red
+        assert_eq!(output.take_bytes(), b"\x1b[1;1H\x1b[91m\xe2\x96\x80");
+    }
+
+
+    #[test]
+    fn row_blits() {
+        let src = Image::from_noise((7, 5), &Noise::new(NoiseKind::Value, 3).with_frequency(0.7), |v| Color::rgb((v * 255.) as u8, 0, 40));
+        let key = src[(3, 2)];
+        let flips = [Flip::None, Flip::Horizontal, Flip::Vertical, Flip::Both];
+        for (n, flip) in flips.into_iter().enumerate() {
+            for (pos, size, offset) in [((-2, 1), (7, 5), (0, 0)), ((3, 3), (6, 4), (2, -1)), ((0, 0), (12, 12), (-3, 2))] {
+                let (pos, size, offset) = (Vec2::point(pos), Vec2::point(size), Vec2::point(offset));
+                let mut img = Image::filled(10, 8, Color::RED);
+                if n % 2 == 1 {
+                    img.set_clip(((1, 2), (6, 5)));
+                }
+                let blank = img.clone();
+                let mut expected = img.clone();
+                img.image_flipped(&src, pos, size, offset, flip, Some(key), BlendMode::Normal);
+
+                // pixel by pixel
+                let (h, v) = flip.axes();
+                for y in 0..size.y {
+                    for x in 0..size.x {
+                        let s = vec2!(offset.x + if h { size.x - 1 - x } else { x }, offset.y + if v { size.y - 1 - y } else { y });
+                        match src.get(s) {
+                            Some(c) if c != key => { expected.set(pos + vec2!(x, y), c); }
+                            _ => ()
+                        }
+                    }
+                }
+                if let Some(clip) = img.clip() {
+                    let red = Image::filled(10, 8, Color::RED);
+                    for (p, c) in red.enumerate_pixels() {
+                        if !clip.contains(p) {
+                            expected.set(p, *c);
+                        }
+                    }
+                }
+                assert!(img.as_slice() == expected.as_slice(), "{:?} {:?} {:?} {:?}", flip, pos, size, offset);
+
+                // copied rows, a negative size mirrors the image
+                let (mut blended, mut copied) = (blank.clone(), blank);
+                blended.image_flipped(&src, pos, size, offset, flip, None, BlendMode::Normal);
+                let end = pos + size - vec2!(1, 1);
+                copied.image_copy(&src,
+                    vec2!(if h { end.x } else { pos.x }, if v { end.y } else { pos.y }),
+                    vec2!(if h { -size.x } else { size.x }, if v { -size.y } else { size.y }),
+                    offset
+                );
+                assert!(copied.as_slice() == blended.as_slice(), "{:?} {:?} {:?} {:?}", flip, pos, size, offset);
+            }
+        }
+
+        let mut img = Image::new(4, 4);
+        img.image(&src, (0, 0), (-2, 2), (0, 0), None, BlendMode::Normal);
+        img.set_clip(((1, 1), (2, 2)));
+        img.clear(Color::BLUE);
+        assert_eq!(img.pixels().filter(|c| **c == Color::BLUE).count(), 4);
+        assert_eq!(img[(1, 1)], Color::BLUE);
+    }
+
+
+    #[test]
+    fn debug_console() {
+        let (mut rdr, output) = Renderer::headless((12, 8)).unwrap();
+        let text = |output: &rds::HeadlessOutput| String::from_utf8(output.take_bytes()).unwrap();
+        rdr.log("hidden");
+        rdr.with_frame(|frame| frame.clear_screen(Color::RED)).unwrap();
+        rdr.screenshot().unwrap();
+        assert!(!text(&output).contains("hidden"));
+
+        rdr.log("first\nsecond");
+        rdr.log("third");
+        rdr.set_console_lines(2);
+        rdr.show_console(true);
+        rdr.with_frame(|frame| frame.clear_screen(Color::RED)).unwrap();
+        let screen = rdr.screenshot().unwrap();
+        let out = text(&output);
+        assert!(out.contains("second") && out.contains("third") && !out.contains("first"));
+        // the console is not part of the frame
+        assert!(screen.pixels().all(|c| *c == Color::RED));
+
+        // logged while shown
+        rdr.log("XYZW");
+        rdr.with_frame(|_| ()).unwrap();
+        rdr.screenshot().unwrap();
+        assert!(text(&output).contains("XYZW"));
+
+        // the pixels are redrawn once hidden
+        rdr.toggle_console();
+        rdr.with_frame(|_| ()).unwrap();
+        rdr.screenshot().unwrap();
+        let out = text(&output);
+        assert!(!out.contains("XYZW"));
+        assert_eq!(out.matches(' ').count(), 2 * 12);
+
+        // more lines than the terminal covers it all
+        rdr.set_console_lines(usize::MAX);
+        rdr.toggle_console();
+        rdr.with_frame(|_| ()).unwrap();
+        rdr.screenshot().unwrap();
+        let out = text(&output);
+        assert!(out.contains("first") && out.contains("XYZW"));
     }
 }
@@ -27,6 +27,9 @@
 
 use std::ops::{Add, Sub, AddAssign, SubAssign, Mul, MulAssign, Div, DivAssign};
 
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
 #[macro_export]
 macro_rules! vec2 {
     ($x:expr, $y:expr) => {Vec2::new($x, $y)};
@@ -37,6 +40,7 @@ macro_rules! vec2 {
 /// Two dimentional vector. Has math operations implemented.
 /// Can be instanced with the macro `vec2!`.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Vec2 {
     pub x: i32,
     pub y: i32
@@ -69,6 +73,267 @@ impl Vec2 {
 
 
 
+/// Rectangle defined by the position of its top left corner and its size.
+/// 
+/// The rectangle covers the pixels from `pos` included to `pos + size` excluded. A negative size
+/// extends the rectangle to the left or to the top of `pos` (see `normalized`).
+/// Can be built from a `(pos, size)` pair.
+/// 
+/// # Usage
+/// 
+/// ```
+/// let player = Rect::new(player_pos, (8, 16));
+/// if player.intersects(&wall) {
+///     ...
+/// }
+/// img.rect(player, Color::RED);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub pos: Vec2,
+    pub size: Vec2
+}
+
+
+impl Rect {
+
+    pub fn new<A, B>(pos: A, size: B) -> Self 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>
+    {
+        Rect {
+            pos: *pos.as_ref(),
+            size: *size.as_ref()
+        }
+    }
+
+
+    /// Rectangle between the corners `a` and `b`, from the top left one included
+    /// to the bottom right one excluded.
+    pub fn from_corners<A, B>(a: A, b: B) -> Self 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>
+    {
+        let (a, b) = (*a.as_ref(), *b.as_ref());
+        let pos = vec2!(a.x.min(b.x), a.y.min(b.y));
+        Rect::new(pos, vec2!(a.x.max(b.x), a.y.max(b.y)) - pos)
+    }
+
+
+    /// Returns the position of the bottom right corner, excluded from the rectangle.
+    pub fn end(&self) -> Vec2 {
+        self.pos + self.size
+    }
+
+
+    /// Returns the same rectangle with a positive size: a negative size goes from `pos` included
+    /// towards the top left (eg. `Rect::new((5, 5), (-2, 1))` covers the pixels (4, 5) and (5, 5)).
+    pub fn normalized(&self) -> Self {
+        let mut r = *self;
+        if r.size.x < 0 {
+            r.pos.x += r.size.x + 1;
+            r.size.x = -r.size.x;
+        }
+        if r.size.y < 0 {
+            r.pos.y += r.size.y + 1;
+            r.size.y = -r.size.y;
+        }
+        r
+    }
+
+
+    /// Returns true if the rectangle does not contain any pixel.
+    pub fn is_empty(&self) -> bool {
+        self.size.x == 0 || self.size.y == 0
+    }
+
+
+    /// Returns true if the point `p` is inside the rectangle.
+    pub fn contains<A>(&self, p: A) -> bool 
+        where A: AsRef<Vec2>
+    {
+        let (r, p) = (self.normalized(), p.as_ref());
+        p.x >= r.pos.x && p.y >= r.pos.y && p.x < r.end().x && p.y < r.end().y
+    }
+
+
+    /// Returns true if the two rectangles share at least a pixel.
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.intersection(other).is_some()
+    }
+
+
+    /// Returns the part shared by the two rectangles, or None if they do not overlap.
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let (a, b) = (self.normalized(), other.normalized());
+        let pos = vec2!(a.pos.x.max(b.pos.x), a.pos.y.max(b.pos.y));
+        let end = vec2!(a.end().x.min(b.end().x), a.end().y.min(b.end().y));
+        if end.x <= pos.x || end.y <= pos.y {
+            None
+        } else {
+            Some(Rect::from_corners(pos, end))
+        }
+    }
+
+
+    /// Returns the smallest rectangle that contains both rectangles.
+    /// An empty rectangle is ignored.
+    pub fn union(&self, other: &Rect) -> Rect {
+        let (a, b) = (self.normalized(), other.normalized());
+        if a.is_empty() {
+            return b;
+        }
+        if b.is_empty() {
+            return a;
+        }
+        let pos = vec2!(a.pos.x.min(b.pos.x), a.pos.y.min(b.pos.y));
+        let end = vec2!(a.end().x.max(b.end().x), a.end().y.max(b.end().y));
+        Rect::from_corners(pos, end)
+    }
+}
+
+
+impl<A, B> From<(A, B)> for Rect 
+    where A: AsRef<Vec2>, B: AsRef<Vec2>
+{
+    fn from(r: (A, B)) -> Self {
+        Rect::new(r.0, r.1)
+    }
+}
+
+
+
+/// Two dimentional affine transform (translation, rotation and scale), as the matrix
+/// ```text
+/// | a  c  tx |
+/// | b  d  ty |
+/// ```
+/// A point `p` is transformed to `(a * p.x + c * p.y + tx, b * p.x + d * p.y + ty)`.
+/// Angles are in radians and go clockwise (as y goes down).
+/// 
+/// # Usage
+/// 
+/// ```
+/// // the sprite is scaled twice, rotated around its top left corner and then placed at `pos`
+/// let t = Transform2::scale(2., 2.).then(&Transform2::rotation(angle)).then(&Transform2::translation(pos));
+/// img.draw_transformed(&sprite, &t);
+/// 
+/// // same as above
+/// let t = Transform2::new(pos, angle, (2., 2.));
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transform2 {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+    pub tx: f32,
+    pub ty: f32
+}
+
+
+impl Transform2 {
+
+    pub const IDENTITY: Transform2 = Transform2 { a: 1., b: 0., c: 0., d: 1., tx: 0., ty: 0. };
+
+
+    /// Transform that scales by `scale`, then rotates by `angle` and then translates by `pos`.
+    pub fn new<A>(pos: A, angle: f32, scale: (f32, f32)) -> Self 
+        where A: AsRef<Vec2>
+    {
+        Transform2::scale(scale.0, scale.1)
+            .then(&Transform2::rotation(angle))
+            .then(&Transform2::translation(pos))
+    }
+
+
+    pub fn translation<A>(pos: A) -> Self 
+        where A: AsRef<Vec2>
+    {
+        let pos = pos.as_ref();
+        Transform2 { tx: pos.x as f32, ty: pos.y as f32, ..Transform2::IDENTITY }
+    }
+
+
+    /// Rotation of `angle` radians around the origin.
+    pub fn rotation(angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Transform2 { a: cos, b: sin, c: -sin, d: cos, tx: 0., ty: 0. }
+    }
+
+
+    pub fn scale(x: f32, y: f32) -> Self {
+        Transform2 { a: x, d: y, ..Transform2::IDENTITY }
+    }
+
+
+    /// Returns the transform that applies `self` and then `other`.
+    pub fn then(&self, other: &Transform2) -> Self {
+        Transform2 {
+            a: other.a * self.a + other.c * self.b,
+            b: other.b * self.a + other.d * self.b,
+            c: other.a * self.c + other.c * self.d,
+            d: other.b * self.c + other.d * self.d,
+            tx: other.a * self.tx + other.c * self.ty + other.tx,
+            ty: other.b * self.tx + other.d * self.ty + other.ty
+        }
+    }
+
+
+    /// Returns the transform that cancels `self`, or None if it flattens the plane (eg. a scale of 0).
+    pub fn inverse(&self) -> Option<Self> {
+        let det = self.a * self.d - self.b * self.c;
+        if det.abs() < f32::EPSILON {
+            return None;
+        }
+        let (a, b, c, d) = (self.d / det, -self.b / det, -self.c / det, self.a / det);
+        Some(Transform2 {
+            a, b, c, d,
+            tx: -(a * self.tx + c * self.ty),
+            ty: -(b * self.tx + d * self.ty)
+        })
+    }
+
+
+    /// Transforms the point `(x, y)` without rounding.
+    pub fn apply_f32(&self, x: f32, y: f32) -> (f32, f32) {
+        (self.a * x + self.c * y + self.tx, self.b * x + self.d * y + self.ty)
+    }
+
+
+    /// Transforms the point `p`, rounded to the nearest pixel.
+    pub fn apply<A>(&self, p: A) -> Vec2 
+        where A: AsRef<Vec2>
+    {
+        let p = p.as_ref();
+        let (x, y) = self.apply_f32(p.x as f32, p.y as f32);
+        vec2!(x.round() as i32, y.round() as i32)
+    }
+
+
+    /// Transforms all the `points`, eg. to draw a transformed shape with `Image::polygon`.
+    pub fn apply_all(&self, points: &[Vec2]) -> Vec<Vec2> {
+        points.iter().map(|p| self.apply(p)).collect()
+    }
+}
+
+
+impl Default for Transform2 {
+    fn default() -> Self {
+        Transform2::IDENTITY
+    }
+}
+
+
+impl Mul for Transform2 {
+    type Output = Self;
+
+    /// `a * b` applies `b` and then `a`, as with matrices.
+    fn mul(self, rhs: Self) -> Self::Output {
+        rhs.then(&self)
+    }
+}
+
+
+
 impl Add for Vec2 {
     type Output = Self;
 
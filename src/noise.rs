@@ -0,0 +1,223 @@
+/*
+
+    MIT License
+    
+    Copyright (c) 2022 Siandfrance
+    
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+    
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+    
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+
+*/
+
+
+use crate::math::Vec2;
+
+use std::f32::consts::FRAC_1_SQRT_2;
+
+
+/// Algorithm used by a `Noise` generator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NoiseKind {
+    /// Random values on a grid, smoothly interpolated (blocky looking).
+    Value,
+    /// Gradient noise on a square grid.
+    #[default]
+    Perlin,
+    /// Gradient noise on a triangular grid, with less directional artifacts than Perlin noise.
+    Simplex
+}
+
+
+/// Coherent 2D noise, for procedural terrain and textures. Values are between 0 and 1 and the same
+/// seed always gives the same noise.
+/// 
+/// Several octaves of noise with doubling frequencies can be summed (fractal noise) to add details.
+/// 
+/// # Usage
+/// 
+/// ```
+/// let noise = Noise::new(NoiseKind::Perlin, 42).with_frequency(0.05).with_octaves(4, 0.5);
+/// let height = noise.get(x as f32, y as f32);
+/// let terrain = Image::from_noise((80, 48), &noise, |h| if h < 0.4 { Color::BLUE } else { Color::GREEN });
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Noise {
+    kind: NoiseKind,
+    seed: u32,
+    frequency: f32,
+    octaves: u32,
+    persistence: f32
+}
+
+
+impl Noise {
+
+    /// Creates a noise generator of one octave, with a frequency of 1 (one grid cell per unit).
+    pub fn new(kind: NoiseKind, seed: u32) -> Self {
+        Self { kind, seed, frequency: 1., octaves: 1, persistence: 0.5 }
+    }
+
+
+    /// Returns the generator with the frequency `frequency` (number of grid cells per unit) for the first octave.
+    pub fn with_frequency(mut self, frequency: f32) -> Self {
+        self.frequency = frequency;
+        self
+    }
+
+
+    /// Returns the generator summing `octaves` octaves, the amplitude of each octave is the amplitude of the previous
+    /// one multiplied by `persistence`.
+    pub fn with_octaves(mut self, octaves: u32, persistence: f32) -> Self {
+        self.octaves = octaves.max(1);
+        self.persistence = persistence;
+        self
+    }
+
+
+    /// Returns the value of the noise at (`x`, `y`), between 0 and 1.
+    pub fn get(&self, x: f32, y: f32) -> f32 {
+        let mut sum = 0.;
+        let mut total = 0.;
+        let mut amplitude = 1.;
+        let mut frequency = self.frequency;
+        for octave in 0..self.octaves {
+            let seed = self.seed.wrapping_add(octave.wrapping_mul(0x9e3779b9));
+            let (x, y) = (x * frequency, y * frequency);
+            let v = match self.kind {
+                NoiseKind::Value => value(x, y, seed),
+                NoiseKind::Perlin => perlin(x, y, seed),
+                NoiseKind::Simplex => simplex(x, y, seed)
+            };
+            sum += v * amplitude;
+            total += amplitude;
+            amplitude *= self.persistence;
+            frequency *= 2.;
+        }
+        if total == 0. {
+            return 0.5;
+        }
+        ((sum / total + 1.) / 2.).clamp(0., 1.)
+    }
+
+
+    /// Returns the values of the noise at the integer coordinates of a rectangle of size `size`, row by row.
+    pub fn field<A>(&self, size: A) -> Vec<f32> 
+        where A: AsRef<Vec2>
+    {
+        let size = size.as_ref();
+        (0..size.y.max(0))
+            .flat_map(|y| (0..size.x.max(0)).map(move |x| (x, y)))
+            .map(|(x, y)| self.get(x as f32, y as f32))
+            .collect()
+    }
+}
+
+
+/// Gradients of the Perlin and simplex noises
+const GRADIENTS: [(f32, f32); 8] = [
+    (1., 0.), (-1., 0.), (0., 1.), (0., -1.),
+    (FRAC_1_SQRT_2, FRAC_1_SQRT_2), (-FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+    (FRAC_1_SQRT_2, -FRAC_1_SQRT_2), (-FRAC_1_SQRT_2, -FRAC_1_SQRT_2)
+];
+
+
+/// Returns a pseudo random number for the grid point (`x`, `y`).
+fn hash(x: i32, y: i32, seed: u32) -> u32 {
+    let mut h = seed ^ (x as u32).wrapping_mul(0x27d4eb2d) ^ (y as u32).wrapping_mul(0x165667b1);
+    h = (h ^ (h >> 15)).wrapping_mul(0x2c1b3c6d);
+    h = (h ^ (h >> 12)).wrapping_mul(0x297a2d39);
+    h ^ (h >> 15)
+}
+
+
+/// Returns the dot product of the gradient of the grid point (`x`, `y`) with (`dx`, `dy`).
+fn gradient(x: i32, y: i32, seed: u32, dx: f32, dy: f32) -> f32 {
+    let (gx, gy) = GRADIENTS[(hash(x, y, seed) & 7) as usize];
+    gx * dx + gy * dy
+}
+
+
+/// Smooth interpolation curve with null first and second derivatives at 0 and 1.
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6. - 15.) + 10.)
+}
+
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+
+/// Value noise at (`x`, `y`), between -1 and 1.
+fn value(x: f32, y: f32, seed: u32) -> f32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (i, j) = (x0 as i32, y0 as i32);
+    let (u, v) = (fade(x - x0), fade(y - y0));
+    let corner = |i, j| hash(i, j, seed) as f32 / u32::MAX as f32 * 2. - 1.;
+    lerp(
+        lerp(corner(i, j), corner(i + 1, j), u),
+        lerp(corner(i, j + 1), corner(i + 1, j + 1), u),
+        v
+    )
+}
+
+
+/// Perlin noise at (`x`, `y`), between -1 and 1.
+fn perlin(x: f32, y: f32, seed: u32) -> f32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (i, j) = (x0 as i32, y0 as i32);
+    let (dx, dy) = (x - x0, y - y0);
+    let (u, v) = (fade(dx), fade(dy));
+    let n = lerp(
+        lerp(gradient(i, j, seed, dx, dy), gradient(i + 1, j, seed, dx - 1., dy), u),
+        lerp(gradient(i, j + 1, seed, dx, dy - 1.), gradient(i + 1, j + 1, seed, dx - 1., dy - 1.), u),
+        v
+    );
+    // unit gradients give values between -sqrt(2)/2 and sqrt(2)/2
+    (n * std::f32::consts::SQRT_2).clamp(-1., 1.)
+}
+
+
+/// Simplex noise at (`x`, `y`), between -1 and 1.
+fn simplex(x: f32, y: f32, seed: u32) -> f32 {
+    const F2: f32 = 0.3660254; // (sqrt(3) - 1) / 2
+    const G2: f32 = 0.21132487; // (3 - sqrt(3)) / 6
+
+    // cell of the skewed grid and first corner
+    let s = (x + y) * F2;
+    let (i, j) = ((x + s).floor() as i32, (y + s).floor() as i32);
+    let t = (i + j) as f32 * G2;
+    let (x0, y0) = (x - (i as f32 - t), y - (j as f32 - t));
+
+    // the second corner depends on the triangle of the cell
+    let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+    let corners = [
+        (i, j, x0, y0),
+        (i + i1, j + j1, x0 - i1 as f32 + G2, y0 - j1 as f32 + G2),
+        (i + 1, j + 1, x0 - 1. + 2. * G2, y0 - 1. + 2. * G2)
+    ];
+
+    let n: f32 = corners.iter()
+        .map(|&(ci, cj, dx, dy)| {
+            let t = 0.5 - dx * dx - dy * dy;
+            if t < 0. { 0. } else { t * t * t * t * gradient(ci, cj, seed, dx, dy) }
+        })
+        .sum();
+    // scales the maximum of the sum to about 1
+    (n * 99.2).clamp(-1., 1.)
+}
@@ -0,0 +1,222 @@
+/*
+
+    MIT License
+    
+    Copyright (c) 2022 Siandfrance
+    
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+    
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+    
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+
+*/
+
+use crate::math::Vec2;
+use crate::img::Color;
+
+use std::time::Duration;
+
+
+/// Particle of an `Emitter`, positions are in pixels and velocities in pixels per second.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Particle {
+    pub pos: (f32, f32),
+    pub vel: (f32, f32),
+    /// Time since the particle was spawned, in seconds
+    pub age: f32
+}
+
+
+/// Spawns particles at a given rate and moves them until the end of their lifetime.
+/// Particles are drawn as points, with a color that changes over their life.
+/// 
+/// # Usage
+/// 
+/// ```
+/// let mut sparks = Emitter::new((40., 20.))
+///     .with_rate(30.)
+///     .with_lifetime(Duration::from_secs(1))
+///     .with_velocity((0., -20.), (10., 5.))
+///     .with_gravity((0., 30.))
+///     .with_colors(&[Color::YELLOW, Color::ORANGE, Color::DARK_RED]);
+/// 
+/// loop {
+///     sparks.update(dt);
+///     rdr.begin_draw();
+///     rdr.draw_particles(&sparks);
+///     rdr.end_draw();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Emitter {
+    /// Where the particles are spawned
+    pub pos: (f32, f32),
+    /// Number of particles spawned per second
+    pub rate: f32,
+    pub lifetime: Duration,
+    /// Initial velocity of the particles
+    pub velocity: (f32, f32),
+    /// Maximum random offset added to the initial velocity on each axis
+    pub spread: (f32, f32),
+    /// Acceleration of the particles
+    pub gravity: (f32, f32),
+    /// Colors over the life of the particles, evenly spaced
+    pub colors: Vec<Color>,
+
+    particles: Vec<Particle>,
+    /// Fraction of particle not spawned yet
+    pending: f32,
+    seed: u64
+}
+
+
+impl Emitter {
+
+    /// Creates an emitter at `pos` spawning 10 white particles per second, that live 1 second and do not move.
+    pub fn new(pos: (f32, f32)) -> Self {
+        Self {
+            pos,
+            rate: 10.,
+            lifetime: Duration::from_secs(1),
+            velocity: (0., 0.),
+            spread: (0., 0.),
+            gravity: (0., 0.),
+            colors: vec![Color::WHITE],
+            particles: Vec::new(),
+            pending: 0.,
+            seed: 0x2545_f491_4f6c_dd1d
+        }
+    }
+
+
+    /// Returns the emitter spawning `rate` particles per second.
+    pub fn with_rate(mut self, rate: f32) -> Self {
+        self.rate = rate;
+        self
+    }
+
+
+    /// Returns the emitter with particles living `lifetime`.
+    pub fn with_lifetime(mut self, lifetime: Duration) -> Self {
+        self.lifetime = lifetime;
+        self
+    }
+
+
+    /// Returns the emitter with particles starting at `velocity`, plus a random offset up to `spread` on each axis.
+    pub fn with_velocity(mut self, velocity: (f32, f32), spread: (f32, f32)) -> Self {
+        self.velocity = velocity;
+        self.spread = spread;
+        self
+    }
+
+
+    /// Returns the emitter with particles accelerated by `gravity`.
+    pub fn with_gravity(mut self, gravity: (f32, f32)) -> Self {
+        self.gravity = gravity;
+        self
+    }
+
+
+    /// Returns the emitter with particles going through `colors` during their life.
+    pub fn with_colors(mut self, colors: &[Color]) -> Self {
+        self.colors = colors.to_vec();
+        self
+    }
+
+
+    /// Returns the emitter with a random generator initialized with `seed` (for reproducible effects).
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed.max(1);
+        self
+    }
+
+
+    /// Moves the particles by `dt`, removes the dead ones and spawns the new ones.
+    pub fn update(&mut self, dt: Duration) {
+        let dt = dt.as_secs_f32();
+        let lifetime = self.lifetime.as_secs_f32();
+        let (gx, gy) = self.gravity;
+        for p in self.particles.iter_mut() {
+            p.vel = (p.vel.0 + gx * dt, p.vel.1 + gy * dt);
+            p.pos = (p.pos.0 + p.vel.0 * dt, p.pos.1 + p.vel.1 * dt);
+            p.age += dt;
+        }
+        self.particles.retain(|p| p.age < lifetime);
+
+        self.pending += self.rate.max(0.) * dt;
+        let count = self.pending.floor();
+        self.pending -= count;
+        self.burst(count as usize);
+    }
+
+
+    /// Spawns `n` particles at once.
+    pub fn burst(&mut self, n: usize) {
+        for _ in 0..n {
+            let vel = (
+                self.velocity.0 + self.spread.0 * self.random(),
+                self.velocity.1 + self.spread.1 * self.random()
+            );
+            self.particles.push(Particle { pos: self.pos, vel, age: 0. });
+        }
+    }
+
+
+    /// Returns the living particles.
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+
+    /// Removes all the particles.
+    pub fn clear(&mut self) {
+        self.particles.clear();
+        self.pending = 0.;
+    }
+
+
+    /// Returns the color of a particle of age `age` (in seconds).
+    pub fn color_at(&self, age: f32) -> Color {
+        let t = (age / self.lifetime.as_secs_f32()).clamp(0., 1.);
+        match self.colors.len() {
+            0 => Color::WHITE,
+            1 => self.colors[0],
+            n => {
+                let x = t * (n - 1) as f32;
+                let i = (x as usize).min(n - 2);
+                self.colors[i].lerp(self.colors[i + 1], x - i as f32)
+            }
+        }
+    }
+
+
+    /// Returns the pixel and the color of each particle.
+    pub fn points(&self) -> Vec<(Vec2, Color)> {
+        self.particles.iter()
+            .map(|p| (vec2!(p.pos.0.floor() as i32, p.pos.1.floor() as i32), self.color_at(p.age)))
+            .collect()
+    }
+
+
+    /// Returns a random number between -1 and 1 (xorshift).
+    fn random(&mut self) -> f32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        (self.seed >> 40) as f32 / (1u64 << 23) as f32 - 1.
+    }
+}
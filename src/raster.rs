@@ -0,0 +1,201 @@
+/*
+
+    MIT License
+    
+    Copyright (c) 2022 Siandfrance
+    
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+    
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+    
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+
+*/
+
+
+// Triangle rasterization with depth testing, for simple 3D scenes (see `Image::triangle_depth` and
+// `Image::triangle_textured`).
+
+
+use crate::math::{Vec2, Rect};
+use crate::img::Color;
+
+
+/// Vertex of a triangle drawn with `Image::triangle_depth` or `Image::triangle_textured`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vertex {
+    /// Position on the image, in pixels
+    pub pos: (f32, f32),
+    /// Depth of the vertex, smaller values are closer to the camera
+    pub z: f32,
+    /// Color of the vertex, interpolated with `Shading::Gouraud`
+    pub color: Color,
+    /// Texture coordinates of the vertex, (0, 0) is the top left corner of the texture and (1, 1) the bottom right one
+    pub uv: (f32, f32)
+}
+
+
+impl Vertex {
+
+    /// Creates a vertex at `pos` on the image with the depth `z`.
+    pub const fn new(pos: (f32, f32), z: f32, color: Color) -> Self {
+        Self { pos, z, color, uv: (0., 0.) }
+    }
+
+
+    /// Returns the vertex with the texture coordinates `uv`.
+    pub const fn with_uv(mut self, uv: (f32, f32)) -> Self {
+        self.uv = uv;
+        self
+    }
+
+
+    /// Projects the point `p` of the scene (x to the right, y up, z in front of the camera) seen by a perspective
+    /// camera at the origin on an image of size `size`. `focal` is the distance from the camera to the image, in pixels.
+    /// 
+    /// Returns None if the point is behind the camera.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// // 90° horizontal field of view
+    /// let v = Vertex::project((x, y, z), Color::RED, img.size().x as f32 / 2., img.size());
+    /// ```
+    pub fn project<A>(p: (f32, f32, f32), color: Color, focal: f32, size: A) -> Option<Self>
+        where A: AsRef<Vec2>
+    {
+        let size = size.as_ref();
+        let (x, y, z) = p;
+        if z <= f32::EPSILON {
+            return None;
+        }
+        let pos = (size.x as f32 / 2. + x * focal / z, size.y as f32 / 2. - y * focal / z);
+        Some(Self { pos, z, color, uv: (0., 0.) })
+    }
+}
+
+
+/// How the pixels of a triangle drawn with `Image::triangle_depth` are colored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Shading {
+    /// The whole triangle has the color of its first vertex.
+    #[default]
+    Flat,
+    /// The colors of the vertices are interpolated over the triangle.
+    Gouraud
+}
+
+
+/// Depth of the closest surface drawn on each pixel of an image, so that the triangles hidden by the ones already
+/// drawn are not drawn.
+/// 
+/// # Usage
+/// 
+/// ```
+/// let mut depth = DepthBuffer::new(img.size());
+/// loop {
+///     img.clear(Color::BLACK);
+///     depth.clear();
+///     for [a, b, c] in &mesh {
+///         img.triangle_depth(&mut depth, [*a, *b, *c], Shading::Gouraud);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct DepthBuffer {
+    data: Vec<f32>,
+    size: Vec2
+}
+
+
+impl DepthBuffer {
+
+    /// Creates a depth buffer of size `size` where nothing was drawn.
+    pub fn new<A>(size: A) -> Self 
+        where A: AsRef<Vec2>
+    {
+        let size = size.as_ref();
+        let size = vec2!(size.x.max(0), size.y.max(0));
+        Self { data: vec![f32::INFINITY; (size.x * size.y) as usize], size }
+    }
+
+
+    /// Returns the size of the depth buffer.
+    pub fn size(&self) -> Vec2 {
+        self.size
+    }
+
+
+    /// Forgets all the depths, for the next frame.
+    pub fn clear(&mut self) {
+        self.data.fill(f32::INFINITY);
+    }
+
+
+    /// Returns the depth of the closest surface drawn at `p` (infinity if nothing was drawn),
+    /// or None if `p` is out of the buffer.
+    pub fn get<A>(&self, p: A) -> Option<f32>
+        where A: AsRef<Vec2>
+    {
+        let p = p.as_ref();
+        if p.x < 0 || p.y < 0 || p.x >= self.size.x || p.y >= self.size.y {
+            None
+        } else {
+            Some(self.data[(p.x + p.y * self.size.x) as usize])
+        }
+    }
+
+
+    /// Records the depth `z` at `p` if it is closer than the depth already there.
+    /// Returns true if the pixel should be drawn.
+    pub(crate) fn test(&mut self, p: Vec2, z: f32) -> bool {
+        match self.get(p) {
+            Some(d) if z < d => {
+                self.data[(p.x + p.y * self.size.x) as usize] = z;
+                true
+            }
+            _ => false
+        }
+    }
+}
+
+
+/// Calls `fragment` for each pixel of `bounds` whose center is inside the triangle `pts`, with the barycentric
+/// coordinates of the center (the weight of each vertex).
+pub(crate) fn rasterize<F>(pts: [(f32, f32); 3], bounds: Rect, mut fragment: F)
+    where F: FnMut(Vec2, [f32; 3])
+{
+    let edge = |a: (f32, f32), b: (f32, f32), p: (f32, f32)| (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0);
+    let area = edge(pts[0], pts[1], pts[2]);
+    if area.abs() < f32::EPSILON || pts.iter().any(|p| !p.0.is_finite() || !p.1.is_finite()) {
+        return;
+    }
+
+    let min_x = pts.iter().map(|p| p.0).fold(f32::INFINITY, f32::min).floor() as i32;
+    let max_x = pts.iter().map(|p| p.0).fold(f32::NEG_INFINITY, f32::max).ceil() as i32;
+    let min_y = pts.iter().map(|p| p.1).fold(f32::INFINITY, f32::min).floor() as i32;
+    let max_y = pts.iter().map(|p| p.1).fold(f32::NEG_INFINITY, f32::max).ceil() as i32;
+    let end = bounds.end();
+
+    for y in min_y.max(bounds.pos.y)..max_y.min(end.y) {
+        for x in min_x.max(bounds.pos.x)..max_x.min(end.x) {
+            let c = (x as f32 + 0.5, y as f32 + 0.5);
+            let w = [edge(pts[1], pts[2], c) / area, edge(pts[2], pts[0], c) / area, edge(pts[0], pts[1], c) / area];
+            if w.iter().all(|w| *w >= 0.) {
+                fragment(vec2!(x, y), w);
+            }
+        }
+    }
+}
@@ -27,24 +27,38 @@
 
 extern crate libc;
 
-use crate::math::Vec2;
-use crate::img::{Image, Color};
-use crate::input::Input;
+use crate::math::{Vec2, Rect};
+use crate::img::{self, Image, Color, Palette, Rgba, Blend, BlendMode, Filter, Flip, StreamedImage, RleImage};
+use crate::input::{self, Input};
+use crate::glyph;
+use crate::terminfo;
+use crate::dither;
+use crate::record::{Recording, Cast, CastWriter};
+use crate::backend::{TerminalBackend, TermiosBackend, WriterBackend, term_size};
+use crate::sprite::{SpriteSheet, FrameKey, Animation};
+use crate::particles::Emitter;
+use crate::text;
+#[cfg(feature = "ttf")]
+use crate::text::Font;
 
 use termios::*;
 
 use std::mem;
+use std::ops::{Deref, DerefMut, Range};
+use std::fmt;
+use std::error::Error;
+use std::collections::{HashMap, HashSet, VecDeque};
 
-use std::io::{stdout, Write};
+use std::io::{self, stdout, Write};
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
 
 use std::thread;
-use std::sync::{mpsc, Barrier, Arc, Mutex};
-
-use std::io::stdin;
-use std::os::unix::io::AsRawFd;
-
-const NCCS: usize = 32;
+use std::sync::{mpsc, Barrier, Arc, Mutex, Condvar};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
+use std::os::unix::io::RawFd;
 
 /// csi macro rule
 macro_rules! csi {
@@ -55,410 +69,4402 @@ macro_rules! csi {
 /// Commands that are sent to the rendering server by the Renderer singleton.
 enum RenderingDirective {
     DrawLine(Vec2, Vec2, Color),
-    DrawRect(Vec2, Vec2, Color),
-    DrawRectBoudary(Vec2, Vec2, Color),
+    DrawRect(Rect, Color),
+    DrawRectBoudary(Rect, Color),
     DrawEllipseBoudary(Vec2, Vec2, Color),
+    DrawEllipse(Vec2, Vec2, Color),
+    DrawCircleBoundary(Vec2, i32, Color),
+    DrawCircle(Vec2, i32, Color),
+    DrawArc(Vec2, i32, f32, f32, Color),
+    DrawTriangle(Vec2, Vec2, Vec2, Color),
+    DrawPolygon(Vec<Vec2>, Color),
+    DrawPath(Vec<Vec2>, bool, Color),
+    DrawText(Vec2, String, Color),
+    #[cfg(feature = "ttf")]
+    DrawTtfText(Vec2, String, Arc<Font>, f32, Color),
     DrawPoint(Vec2, Color),
+    DrawPoints(Vec<(Vec2, Color)>),
+    DrawRectGradient(Vec2, Vec2, Color, Color, f32),
+    DrawEllipseGradient(Vec2, Vec2, Color, Color),
+    Shade(Rect, Box<dyn Fn(Vec2, Color) -> Color + Send>),
 
-    DrawImage(Arc<Mutex<Image>>, Vec2, Vec2, Vec2, Option<Color>),
-    DrawWholeImageAlpha(Arc<Mutex<Image>>, Vec2, Color),
-    DrawWholeImage(Arc<Mutex<Image>>, Vec2),
+    DrawImage(Arc<Image>, Vec2, Vec2, Vec2, Flip, Option<Color>, BlendMode),
+    DrawWholeImageAlpha(Arc<Image>, Vec2, Color),
+    DrawWholeImage(Arc<Image>, Vec2),
+    DrawStreamedImage(Arc<Mutex<StreamedImage>>, Vec2),
+    DrawRgbaImage(Arc<Image<Rgba>>, Vec2, Vec2, Vec2),
+    DrawScaledImage(Arc<Image>, Rect, Rect, Filter, Flip, Option<Color>, BlendMode),
+    DrawScaledRgbaImage(Arc<Image<Rgba>>, Rect, Rect, Flip),
+
+    UploadTexture(TextureId, Image),
+    FreeTexture(TextureId),
+    DrawTexture(TextureId, Vec2, Vec2, Vec2, Option<Color>, BlendMode),
+    DrawBatch(Vec<RenderingDirective>),
 
     ClearScreen(Color),
 
-    UpdateScreenSize(Vec2),
-    BeginFrame,
-    PushFrame
-}
+    SetCells(Rect, Vec<Cell>),
+    ClearCells,
 
+    SetPalette(Option<Palette>),
+    SetPaletteColor(u8, Color),
+    RotatePalette(Range<usize>, i32),
+    SetPrevFrameCompression(bool),
 
-/// This is the core of the library. It will send commands to the rendering server to print on screen.
-/// 
-/// # Usage
-/// 
-/// ```
-/// // get the renderer
-/// let rdr = Renderer::get();
-/// 
-/// ...
-/// 
-/// // start drawing on a frame
-/// rdr.begin_draw();
-/// 
-/// ... // use drawing functions (eg. draw_rect, draw_point...)
-/// 
-/// rdr.end_draw(); // this pushes the frame to the screen
-/// 
-/// ...
-/// 
-/// Renderer::exit(); // to quit the program and reset terminal settings
-/// ```
-/// 
-/// Screen coordinates start in the top left at (0, 0)
-pub struct Renderer {
-    termios: Termios,
-    default_c_lflags: u32,
-    default_c_cc: [u8; NCCS],
+    RingBell,
+    SetCursor(Option<Vec2>),
+    SetTitle(u8, String),
+    SetRenderMode(RenderMode),
+    SetCamera(Camera),
 
-    building_frame: bool,
-    prev_screen_size: Vec2,
+    CreateLayer(String, i32),
+    RemoveLayer(String),
+    SetLayer(String),
+    SetLayerZ(String, i32),
+    SetLayerOpacity(String, f32),
+    SetLayerBlendMode(String, BlendMode),
+    ClearLayer,
+    SetConfig(RendererConfig),
 
-    _server_handle: Option<thread::JoinHandle<()>>,
-    sender: mpsc::Sender<RenderingDirective>,
+    TakeFrame(mpsc::Sender<Image>),
+    PutFrame(Image),
+    Screenshot(mpsc::Sender<Image>),
+    StartRecording(Recording),
+    StopRecording(mpsc::Sender<Result<(), String>>),
+    StartCast(Cast),
+    StopCast(mpsc::Sender<Result<(), String>>),
 
-    frame_barrier: Arc<Barrier>
+    UpdateScreenSize(Vec2, Vec2),
+    SetLetterbox(Letterbox),
+    SetScaling(Option<Scaling>),
+    Log(String),
+    ShowConsole(bool),
+    ToggleConsole,
+    SetConsoleLines(usize),
+
+    BeginFrame,
+    PushFrame,
+    PushRegion(Rect)
 }
 
 
-/// Renderer singleton
-static mut RENDERER: Option<Renderer> = None;
+impl RenderingDirective {
 
+    /// Returns a rectangle containing the pixels modified by the directive on the screen (empty if it
+    /// does not modify any), or None if it is not known and the whole screen may have changed.
+    /// The directive must already be in screen coordinates (see `Camera::apply`).
+    fn bounds(&self) -> Option<Rect> {
+        use RenderingDirective::*;
+        // the shapes draw their end point, one more pixel covers rounding
+        let around = |points: &mut dyn Iterator<Item = Vec2>| points
+            .map(|p| Rect::new(p - vec2!(1, 1), vec2!(3, 3)))
+            .reduce(|a, b| a.union(&b))
+            .or(Some(Rect::new(Vec2::ZERO, Vec2::ZERO)));
+        let extent = |c: Vec2, s: Vec2| {
+            let s = vec2!(s.x.abs(), s.y.abs());
+            around(&mut [c - s, c + s].into_iter())
+        };
 
-impl Renderer {
+        match self {
+            DrawLine(p1, p2, _) => around(&mut [*p1, *p2].into_iter()),
+            DrawRect(r, _) | Shade(r, _) => Some(r.normalized()),
+            DrawRectBoudary(r, _) => around(&mut [r.pos, r.end()].into_iter()),
+            DrawEllipseBoudary(c, s, _) | DrawEllipse(c, s, _) | DrawEllipseGradient(c, s, _, _) => extent(*c, *s),
+            DrawCircleBoundary(c, r, _) | DrawCircle(c, r, _) | DrawArc(c, r, _, _, _) => extent(*c, vec2!(*r, *r)),
+            DrawTriangle(p0, p1, p2, _) => around(&mut [*p0, *p1, *p2].into_iter()),
+            DrawPolygon(pts, _) | DrawPath(pts, _, _) => around(&mut pts.iter().copied()),
+            DrawText(pos, s, _) => Some(Rect::new(*pos, text::text_size(s))),
+            DrawPoint(p, _) => around(&mut std::iter::once(*p)),
+            DrawPoints(pts) => around(&mut pts.iter().map(|(p, _)| *p)),
+            DrawRectGradient(pos, size, _, _, _) => Some(Rect::new(pos, size).normalized()),
 
-    /// Creates the Input singleton, will only be called once
-    fn init() -> Renderer {
-        let stdinfd = stdin().as_raw_fd();
+            DrawImage(_, pos, size, _, _, _, _) | DrawRgbaImage(_, pos, size, _) => Some(Rect::new(pos, size).normalized()),
+            DrawWholeImageAlpha(img, pos, _) | DrawWholeImage(img, pos) => Some(Rect::new(pos, img.size())),
+            DrawStreamedImage(img, pos) => Some(Rect::new(pos, img.lock().unwrap().size())),
+            DrawScaledImage(_, _, dst, _, _, _, _) | DrawScaledRgbaImage(_, _, dst, _) => {
+                let dst = dst.normalized();
+                Some(Rect::new(dst.pos - vec2!(1, 1), dst.size + vec2!(2, 2)))
+            }
 
-        let mut termios = match Termios::from_fd(stdinfd) {
-            Ok(t)  => t,
-            Err(_) => panic!("Could not read stdin fd")
-        };
+            // state changes that do not modify the composed screen
+            SetLayer(_) | SetCamera(_) | UploadTexture(_, _) | FreeTexture(_) |
+            RingBell | SetCursor(_) | SetTitle(_, _) | TakeFrame(_) | Screenshot(_) | StartRecording(_) | StopRecording(_) |
+            StopCast(_) | Log(_) | ShowConsole(_) | ToggleConsole | SetConsoleLines(_) | BeginFrame | PushFrame | PushRegion(_) =>
+                Some(Rect::new(Vec2::ZERO, Vec2::ZERO)),
 
-        // save and update settings
-        let default_c_lflags = termios.c_lflag;
-        let default_c_cc = termios.c_cc;
+            _ => None
+        }
+    }
+}
 
-        termios.c_lflag &= !(ECHO | ICANON | ISIG);
-        termios.c_cc[VMIN] = 1;
-        termios.c_cc[VTIME] = 0;
 
-        tcsetattr(stdinfd, TCSANOW, &mut termios).expect("could not set stdin attributes");
-        
-        print!("{}{}", 
-            csi!("?25l"),                                   // hide cursor
-            csi!("?1049h")                                 // use alternate screen buffer
-        );
-        stdout().flush().expect("Could not write to stdout"); 
+/// Maximum number of separate damaged rectangles, beyond it they are merged into their bounding box
+const MAX_DAMAGE_RECTS: usize = 16;
 
-        // setup and start server
-        let (rx, tx) = mpsc::channel();
-        let barrier = Arc::new(Barrier::new(2));
-        let frame_barrier = Arc::clone(&barrier);
 
-        let handle = thread::spawn(move || {
-            let mut screen_size = Renderer::get_size();
-            let mut screen: Image = Image::new(0, 0);
-            let mut prev_screen: Image = Image::new(0, 0);
+/// Parts of the screen modified since the last `PushFrame`, so that only they are diffed with the previous frame.
+struct Damage {
+    rects: Vec<Rect>,
+    full: bool,
+    /// Parts drawn since the screen was cleared with `clear_color`, that change when it is cleared again
+    drawn: Vec<Rect>,
+    clear_color: Option<Color>
+}
+
 
-            let mut back: Color = Color::BLACK;
-            let mut fore: Color = Color::BLACK;
-            print!("{:-}{:+}", back, fore);
+impl Damage {
 
+    /// The whole screen is damaged, for the first frame.
+    fn new() -> Self {
+        Self {
+            rects: Vec::new(),
+            full: true,
+            drawn: Vec::new(),
+            clear_color: None
+        }
+    }
 
-            loop {
-                match tx.recv().expect("RenderingServer channel was destroyed") {
-                    RenderingDirective::DrawLine(p1, p2, c) => screen.line(p1, p2, c),
-                    RenderingDirective::DrawRect(p, s, c) => screen.rect(p, s, c),
-                    RenderingDirective::DrawRectBoudary(p, s, c) => screen.rect_boudary(p, s, c),
-                    RenderingDirective::DrawEllipseBoudary(center, s, c) => screen.ellipse_boundary(center, s, c),
-                    RenderingDirective::DrawPoint(p, c) => screen.point(p, c),
 
-                    RenderingDirective::DrawImage(img, pos, size, off, alpha) => screen.image(&(*img.lock().unwrap()), pos, size, off, alpha),
-                    RenderingDirective::DrawWholeImageAlpha(img, pos, alpha) => screen.whole_image_alpha(&(*img.lock().unwrap()), pos, alpha),
-                    RenderingDirective::DrawWholeImage(img, pos) => screen.whole_image(&(*img.lock().unwrap()), pos),
+    /// Adds `r` to `rects`, overlapping rectangles are merged so that no cell is printed twice.
+    fn merge(rects: &mut Vec<Rect>, r: Rect) {
+        let mut r = r.normalized();
+        if r.is_empty() {
+            return;
+        }
+        while let Some(i) = rects.iter().position(|other| other.intersects(&r)) {
+            r = r.union(&rects.swap_remove(i));
+        }
+        rects.push(r);
+        if rects.len() > MAX_DAMAGE_RECTS {
+            let all = rects.iter().fold(r, |a, b| a.union(b));
+            *rects = vec![all];
+        }
+    }
 
-                    RenderingDirective::ClearScreen(c) => screen.clear(c),
 
-                    RenderingDirective::UpdateScreenSize(size) => {
-                        screen_size = size;
-                        screen.raw_resize(size); // TODO: raw_resize
+    /// Marks the pixels modified by `directive` (in screen coordinates), `main` is true if it is drawn on the main layer.
+    fn track(&mut self, directive: &RenderingDirective, main: bool) {
+        match (directive, directive.bounds()) {
+            // clearing again with the same color only erases what was drawn since
+            (RenderingDirective::ClearScreen(c), _) if main => {
+                if self.clear_color == Some(*c) {
+                    for r in std::mem::take(&mut self.drawn) {
+                        Damage::merge(&mut self.rects, r);
                     }
+                } else {
+                    self.full = true;
+                    self.drawn.clear();
+                }
+                self.clear_color = Some(*c);
+            }
+            (_, Some(r)) => {
+                Damage::merge(&mut self.rects, r);
+                Damage::merge(&mut self.drawn, r);
+            }
+            (_, None) => self.damage_all()
+        }
+    }
 
-                    RenderingDirective::BeginFrame => {frame_barrier.wait(); ()},
-                    RenderingDirective::PushFrame => {
-                        // position cursor
-                        print!("\x1b[H");
 
-                        let mut skiped = false;
+    /// Marks the whole screen as modified.
+    fn damage_all(&mut self) {
+        self.full = true;
+        self.clear_color = None;
+        self.drawn.clear();
+    }
 
-                        for j in (0..screen_size.y).step_by(2) {
-                            for i in 0..screen_size.x {
-                                let pos1 = vec2!(i, j);
-                                let pos2 = vec2!(i, j + 1);
 
-                                if screen.size() == prev_screen.size() && screen[pos1] == prev_screen[pos1] && screen[pos2] == prev_screen[pos2] {
-                                    skiped = true;
-                                    continue;
-                                }
-                                
-                                // update color
-                                if screen[pos1] != back && screen[pos1] != fore && screen[pos2] == back {
-                                    fore = screen[pos1];
-                                    print!("{:+}", fore);
-                                } else if screen[pos1] != back && screen[pos1] != fore && screen[pos2] == fore {
-                                    back = screen[pos1];
-                                    print!("{:-}", back);
-                                } else if screen[pos2] != back && screen[pos2] != fore && screen[pos1] == back {
-                                    fore = screen[pos2];
-                                    print!("{:+}", fore);
-                                } else if screen[pos2] != back && screen[pos2] != fore && screen[pos1] == fore {
-                                    back = screen[pos2];
-                                    print!("{:-}", back);
-                                } else if screen[pos1] != back && screen[pos1] != fore && screen[pos2] != back && screen[pos2] != fore {
-                                    fore = screen[pos1];
-                                    back = screen[pos2];
-                                    print!("{:+}", fore);
-                                    print!("{:-}", back);
-                                }
+    /// Returns the damaged rectangles (None if the whole screen is damaged) and resets the damage.
+    fn take(&mut self) -> Option<Vec<Rect>> {
+        let full = std::mem::replace(&mut self.full, false);
+        let rects = std::mem::take(&mut self.rects);
+        if full { None } else { Some(rects) }
+    }
+}
 
-                                if skiped {
-                                    print!("\x1b[{};{}H", j/2 + 1, i + 1);
-                                    skiped = false;
-                                }
 
-                                // print pixel
-                                if screen[pos1] == back && screen[pos2] == back {
-                                    print!(" ");
-                                } else if screen[pos1] == back && screen[pos2] == fore {
-                                    print!("▄");
-                                } else if screen[pos1] == fore && screen[pos2] == back {
-                                    print!("▀");
-                                } else if screen[pos1] == fore && screen[pos2] == fore {
-                                    print!("█");
-                                }
-                            }
-                        }
-                        stdout().flush().expect("Could not write to stdout");
-                        prev_screen = screen.clone();
-                    }
-                }
-            }
-        });
+/// Number of logged lines kept by the console
+const CONSOLE_HISTORY: usize = 256;
 
-        Renderer {
-            termios: termios,
-            default_c_lflags: default_c_lflags,
-            default_c_cc: default_c_cc,
 
-            building_frame: false,
-            prev_screen_size: Vec2::ZERO,
+/// Colors of the console
+const CONSOLE_FG: Color = Color::hex(0xd0d0d0);
+const CONSOLE_BG: Color = Color::hex(0x202020);
 
-            _server_handle: Some(handle),
-            sender: rx,
 
-            frame_barrier: barrier
+/// Lines logged with `Renderer::log`, drawn with cells over the top of the frame while the console is shown
+struct Console {
+    lines: VecDeque<String>,
+    shown: bool,
+    /// Number of lines shown
+    height: usize
+}
+
+
+impl Console {
+
+    fn new() -> Self {
+        Self {
+            lines: VecDeque::new(),
+            shown: false,
+            height: 8
         }
     }
 
 
-    /// Exits the program and reset terminal setttings (should be called before the program ends).
-    pub fn exit() {
-        unsafe {
-            RENDERER = None;
+    /// Adds the lines of `msg`, the oldest ones are forgotten.
+    fn log(&mut self, msg: &str) {
+        for line in msg.split('\n') {
+            if self.lines.len() == CONSOLE_HISTORY {
+                self.lines.pop_front();
+            }
+            self.lines.push_back(line.chars().map(|c| if c.is_control() { ' ' } else { c }).collect());
         }
     }
 
 
-    /// Returns the Renderer instance.
-    pub fn get() -> &'static mut Renderer {
-        unsafe {
-            match &mut RENDERER {
-                None => { // construct the renderer, and initialize
-                    RENDERER = Some(Renderer::init());
-                    Renderer::get()
+    /// Returns the rows of cells covered by the console when it is shown over `cells`.
+    fn rows(&self, cells: &Image<Option<Cell>>) -> Rect {
+        Rect::new(Vec2::ZERO, vec2!(cells.size().x, i32::try_from(self.height).unwrap_or(i32::MAX).min(cells.size().y)))
+    }
+
+
+    /// Returns `cells` with the last lines over its top rows, or None if the console is hidden.
+    fn overlay(&self, cells: &Image<Option<Cell>>) -> Option<Image<Option<Cell>>> {
+        if !self.shown {
+            return None;
+        }
+        let mut overlay = cells.clone();
+        let rows = self.rows(cells);
+        let first = self.lines.len().saturating_sub(rows.size.y as usize);
+        for j in 0..rows.size.y {
+            let mut chars = self.lines.get(first + j as usize).map(|l| l.chars()).into_iter().flatten();
+            let mut i = 0;
+            while i < rows.size.x {
+                let cell = Cell::new(chars.next().unwrap_or(' '), CONSOLE_FG, CONSOLE_BG);
+                if glyph::is_wide(cell.ch) {
+                    if i + 1 == rows.size.x {
+                        overlay.put((i, j), Some(Cell::new(' ', CONSOLE_FG, CONSOLE_BG)));
+                        break;
+                    }
+                    overlay.put((i + 1, j), Some(cell.continuation()));
+                    overlay.put((i, j), Some(cell));
+                    i += 2;
+                } else {
+                    overlay.put((i, j), Some(cell));
+                    i += 1;
                 }
-                Some(r) => r
             }
         }
+        Some(overlay)
     }
+}
 
 
-    /// Returns the screen dimension.
-    /// ```
-    /// let size = Renderer::get_size();
-    /// 
-    /// size.x // width of the screen
-    /// size.y // height of the screen
-    /// ```
-    pub fn get_size() -> Vec2 {
-        unsafe {
-            let mut size: TermSize = mem::zeroed();
-            libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size as *mut _);
-            vec2!(size.col as i32, 2 * size.row as i32)
-        }
+/// A terminal cell: a character, its foreground and background colors and its text attributes.
+/// 
+/// Cells are drawn over the pixels of the frame (see `Renderer::set_cells`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub ch: char,
+    pub fg: Color,
+    pub bg: Color,
+    pub attrs: Attributes
+}
+
+
+impl Cell {
+
+    /// Creates a cell without attributes.
+    pub const fn new(ch: char, fg: Color, bg: Color) -> Self {
+        Self { ch, fg, bg, attrs: Attributes::NONE }
     }
 
-    
-    /// panics if we are not in a draw loop
-    fn can_draw(&self) {
-        if !self.building_frame { panic!("drawing outside of a frame build (call begin_draw)"); }
+
+    /// Returns the cell covered by the right half of this cell when its character is two columns wide.
+    const fn continuation(&self) -> Self {
+        Self { ch: WIDE_CONTINUATION, ..*self }
     }
+}
 
 
-    /// Starts drawing a frame.
-    /// 
-    /// Will panic if called twice before an end_draw
-    pub fn begin_draw(&mut self) {
-        if self.building_frame {
-            panic!("begin_draw called when already building a frame");
-        }
-        self.building_frame = true;
-        let new_size = Renderer::get_size();
-        if self.prev_screen_size != new_size {
-            self.sender.send(RenderingDirective::UpdateScreenSize(new_size)).expect("Rendering thread stoped");
-            self.prev_screen_size = new_size;
+/// Character of the cells covered by the right half of a wide character (nothing is printed there)
+const WIDE_CONTINUATION: char = '\0';
+
+
+/// Text attributes of a cell, emitted with SGR codes. Terminals may not support all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Attributes {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    /// Swaps the foreground and background colors.
+    pub reverse: bool
+}
+
+
+impl Attributes {
+    pub const NONE: Attributes = Attributes { bold: false, italic: false, underline: false, reverse: false };
+
+
+    /// Writes the SGR codes that go from the attributes `prev` to `self`, leaving the colors unchanged.
+    fn write_diff<W>(&self, prev: &Attributes, out: &mut W) -> io::Result<()>
+        where W: Write
+    {
+        let codes = [
+            (self.bold, prev.bold, 1, 22),
+            (self.italic, prev.italic, 3, 23),
+            (self.underline, prev.underline, 4, 24),
+            (self.reverse, prev.reverse, 7, 27)
+        ];
+        for (set, was_set, on, off) in codes {
+            if set != was_set {
+                write!(out, "{}", format_args!(csi!("{}m"), if set { on } else { off }))?;
+            }
         }
+        Ok(())
+    }
+}
 
-        self.sender.send(RenderingDirective::BeginFrame).expect("Rendering thread stoped");
-        self.frame_barrier.wait();
+
+/// Style of the text drawn in the cells layer, see `Renderer::overlay_text`.
+/// 
+/// # Usage
+/// 
+/// ```
+/// let style = TextStyle::new(Color::YELLOW, Color::BLACK).bold().underline();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextStyle {
+    pub fg: Color,
+    pub bg: Color,
+    pub attrs: Attributes
+}
+
+
+impl TextStyle {
+
+    /// Creates a style with the foreground color `fg` and the background color `bg`, without attributes.
+    pub const fn new(fg: Color, bg: Color) -> Self {
+        Self { fg, bg, attrs: Attributes::NONE }
     }
 
 
-    /// Ends drawing a frame and pushes it to the screen.
-    pub fn end_draw(&mut self) {
-        if !self.building_frame {
-            panic!("end_draw called when already building a frame");
-        }
-        self.building_frame = false;
-        self.sender.send(RenderingDirective::PushFrame).expect("Rendering thread stoped");
+    /// Returns this style in bold.
+    pub const fn bold(mut self) -> Self {
+        self.attrs.bold = true;
+        self
     }
 
 
-    /// Sets all the pixels' color in the screen to `c`.
-    pub fn clear_screen(&mut self, c: Color) {
-        self.can_draw();
-        self.sender.send(RenderingDirective::ClearScreen(c)).expect("Rendering thread stoped");
+    /// Returns this style in italic.
+    pub const fn italic(mut self) -> Self {
+        self.attrs.italic = true;
+        self
     }
 
 
-    /// Draws a line of color `c` between `p1` and `p2`.
-    pub fn draw_line<A, B>(&mut self, p1: A, p2: B, c: Color) 
-        where A: AsRef<Vec2>, B: AsRef<Vec2>
-    {
-        self.can_draw();
-        self.sender.send(RenderingDirective::DrawLine(*p1.as_ref(), *p2.as_ref(), c))
-            .expect("Rendering thread stoped");
+    /// Returns this style underlined.
+    pub const fn underline(mut self) -> Self {
+        self.attrs.underline = true;
+        self
     }
 
 
-    /// Draws a rectangle of color `c` and of size `s`. 
-    /// `p` is the coordinate of the top left corner of the rectangle.
-    pub fn draw_rect<A, B>(&mut self, p: A, s: B, c: Color) 
-        where A: AsRef<Vec2>, B: AsRef<Vec2>
-    {
-        self.can_draw();
-        self.sender.send(RenderingDirective::DrawRect(*p.as_ref(), *s.as_ref(), c))
-            .expect("Rendering thread stoped");
+    /// Returns this style with the foreground and background colors swapped by the terminal.
+    pub const fn reverse(mut self) -> Self {
+        self.attrs.reverse = true;
+        self
     }
 
 
-    /// Same as `draw_rect` but draws only the four sides of the rectangle.
-    pub fn draw_rect_boundary<A, B>(&mut self, p: A, s: B, c: Color) 
-        where A: AsRef<Vec2>, B: AsRef<Vec2>
-    {
-        self.can_draw();
-        self.sender.send(RenderingDirective::DrawRectBoudary(*p.as_ref(), *s.as_ref(), c))
-            .expect("Rendering thread stoped");
+    /// Returns the cell with the character `ch` in this style.
+    pub const fn cell(&self, ch: char) -> Cell {
+        Cell { ch, fg: self.fg, bg: self.bg, attrs: self.attrs }
     }
+}
 
 
-    /// Draws an ellipse of color `col`. `c` is the center of the ellipse and `s` is the size of the rectangle
-    /// in which the ellipse is inscribed.
-    pub fn draw_ellipse_boundary<A, B>(&mut self, c: A, s: B, col: Color) 
-        where A: AsRef<Vec2>, B: AsRef<Vec2>
-    {
-        self.can_draw();
-        self.sender.send(RenderingDirective::DrawEllipseBoudary(*c.as_ref(), *s.as_ref(), col))
-            .expect("Rendering thread stoped");
+/// How the pixels are drawn in the terminal cells, see `Renderer::set_render_mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Half blocks (▀, ▄): a cell is 1x2 pixels, colors are exact. This is the default.
+    HalfBlock,
+    /// Quadrant blocks (▘, ▚, ▙, ...): a cell is 2x2 pixels, but can only have two colors.
+    Quadrant,
+    /// Sextant blocks (🬀, 🬔, ...): a cell is 2x3 pixels, but can only have two colors.
+    /// Requires a font that supports the "Symbols for Legacy Computing" block.
+    Sextant,
+    /// Braille characters: a cell is 2x4 pixels, but can only have two colors.
+    Braille
+}
+
+
+impl RenderMode {
+
+    /// Returns the number of pixels in a terminal cell.
+    pub fn cell_size(&self) -> Vec2 {
+        match self {
+            RenderMode::HalfBlock => vec2!(1, 2),
+            RenderMode::Quadrant => vec2!(2, 2),
+            RenderMode::Sextant => vec2!(2, 3),
+            RenderMode::Braille => vec2!(2, 4)
+        }
     }
+}
 
 
-    /// Sets the color of the pixel at `p` to `c`.
-    pub fn draw_point<A>(&mut self, p: A, c: Color) 
-        where A: AsRef<Vec2>
-    {
-        self.can_draw();
-        self.sender.send(RenderingDirective::DrawPoint(*p.as_ref(), c)).expect("Rendering thread stoped");
+/// Handle to an image stored on the rendering thread, returned by `Renderer::upload_texture`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureId(u64);
+
+
+/// Draw call that can be sent with others in a single message with `Renderer::submit`.
+/// Each command draws like the `Renderer` function of the same name.
+#[derive(Clone)]
+pub enum DrawCommand {
+    /// See `Renderer::clear_screen`.
+    Clear(Color),
+    /// See `Renderer::draw_line`.
+    Line(Vec2, Vec2, Color),
+    /// See `Renderer::draw_rect`.
+    Rect(Rect, Color),
+    /// See `Renderer::draw_rect_boundary`.
+    RectBoundary(Rect, Color),
+    /// See `Renderer::draw_ellipse`.
+    Ellipse(Vec2, Vec2, Color),
+    /// See `Renderer::draw_ellipse_boundary`.
+    EllipseBoundary(Vec2, Vec2, Color),
+    /// See `Renderer::draw_circle`.
+    Circle(Vec2, i32, Color),
+    /// See `Renderer::draw_circle_boundary`.
+    CircleBoundary(Vec2, i32, Color),
+    /// See `Renderer::draw_arc`.
+    Arc(Vec2, i32, f32, f32, Color),
+    /// See `Renderer::draw_triangle`.
+    Triangle(Vec2, Vec2, Vec2, Color),
+    /// See `Renderer::draw_polygon`.
+    Polygon(Vec<Vec2>, Color),
+    /// See `Renderer::draw_polyline`.
+    Polyline(Vec<Vec2>, Color),
+    /// See `Renderer::draw_polygon_boundary`.
+    PolygonBoundary(Vec<Vec2>, Color),
+    /// See `Renderer::draw_text`.
+    Text(Vec2, String, Color),
+    /// See `Renderer::draw_point`.
+    Point(Vec2, Color),
+    /// See `Renderer::draw_points`.
+    Points(Vec<(Vec2, Color)>),
+    /// See `Renderer::draw_image_flipped`.
+    Image(Arc<Image>, Vec2, Vec2, Vec2, Flip, Option<Color>, BlendMode),
+    /// See `Renderer::draw_rgba_image`.
+    RgbaImage(Arc<Image<Rgba>>, Vec2, Vec2, Vec2),
+    /// See `Renderer::draw_scaled_image`.
+    ScaledImage(Arc<Image>, Rect, Rect, Filter, BlendMode),
+    /// See `Renderer::draw_texture`.
+    Texture(TextureId, Vec2, Vec2, Vec2, Option<Color>, BlendMode)
+}
+
+
+impl From<DrawCommand> for RenderingDirective {
+
+    fn from(command: DrawCommand) -> Self {
+        use RenderingDirective::*;
+
+        match command {
+            DrawCommand::Clear(c) => ClearScreen(c),
+            DrawCommand::Line(p1, p2, c) => DrawLine(p1, p2, c),
+            DrawCommand::Rect(r, c) => DrawRect(r, c),
+            DrawCommand::RectBoundary(r, c) => DrawRectBoudary(r, c),
+            DrawCommand::Ellipse(center, s, c) => DrawEllipse(center, s, c),
+            DrawCommand::EllipseBoundary(center, s, c) => DrawEllipseBoudary(center, s, c),
+            DrawCommand::Circle(center, r, c) => DrawCircle(center, r, c),
+            DrawCommand::CircleBoundary(center, r, c) => DrawCircleBoundary(center, r, c),
+            DrawCommand::Arc(center, r, start, end, c) => DrawArc(center, r, start, end, c),
+            DrawCommand::Triangle(p0, p1, p2, c) => DrawTriangle(p0, p1, p2, c),
+            DrawCommand::Polygon(points, c) => DrawPolygon(points, c),
+            DrawCommand::Polyline(points, c) => DrawPath(points, false, c),
+            DrawCommand::PolygonBoundary(points, c) => DrawPath(points, true, c),
+            DrawCommand::Text(pos, text, c) => DrawText(pos, text, c),
+            DrawCommand::Point(p, c) => DrawPoint(p, c),
+            DrawCommand::Points(points) => DrawPoints(points),
+            DrawCommand::Image(img, pos, size, off, flip, alpha, mode) => DrawImage(img, pos, size, off, flip, alpha, mode),
+            DrawCommand::RgbaImage(img, pos, size, off) => DrawRgbaImage(img, pos, size, off),
+            DrawCommand::ScaledImage(img, src, dst, filter, mode) => DrawScaledImage(img, src, dst, filter, Flip::None, None, mode),
+            DrawCommand::Texture(id, pos, size, off, alpha, mode) => DrawTexture(id, pos, size, off, alpha, mode)
+        }
     }
+}
 
 
-    /// Draws an image at position `pos`. 
-    /// 
-    /// Negative size results in flipped image. Alpha is used to ignore a given color while drawing.
-    pub fn draw_image<A, B, C>(&mut self, 
-        img: Arc<Mutex<Image>>, pos: A, size: B, offset: C, alpha: Option<Color>) 
-        where A: AsRef<Vec2>, B: AsRef<Vec2>, C: AsRef<Vec2>
+/// Transform from world coordinates to screen coordinates, applied to the draw calls
+/// (see `Renderer::set_camera`).
+/// 
+/// A point `p` of the world is drawn at `(p - pos) * zoom`: `pos` is the point of the world
+/// in the top left corner of the screen. The zoom scales the shapes and the images, but lines
+/// stay one pixel wide and bitmap text keeps its size. Cells are not affected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    pub pos: Vec2,
+    pub zoom: f32
+}
+
+
+impl Camera {
+
+    /// Camera that does not change the coordinates.
+    pub const IDENTITY: Camera = Camera { pos: Vec2::ZERO, zoom: 1. };
+
+
+    /// Creates a camera with the point `pos` of the world in the top left corner of the screen.
+    pub fn new<A>(pos: A, zoom: f32) -> Self 
+        where A: AsRef<Vec2>
     {
-        self.can_draw();
-        self.sender.send(RenderingDirective::DrawImage(img, *pos.as_ref(), *size.as_ref(), *offset.as_ref(), alpha))
-            .expect("Rendering thread stoped");
+        Self { pos: *pos.as_ref(), zoom }
     }
 
 
-    /// Draws the whole image at `pos`, ignoring the color `alpha`.
-    /// 
-    /// Equivalent to:
-    /// ```
-    /// rdr.image(img, pos, img.size(), Vec2::ZERO, Some(alpha));
-    /// ```
-    pub fn draw_whole_image_alpha<A>(&mut self, img: Arc<Mutex<Image>>, pos: A, alpha: Color) 
+    /// Converts the point `p` of the world to screen coordinates.
+    pub fn to_screen<A>(self, p: A) -> Vec2 
         where A: AsRef<Vec2>
     {
-        self.can_draw();
-        self.sender.send(RenderingDirective::DrawWholeImageAlpha(img, *pos.as_ref(), alpha))
-            .expect("Rendering thread stoped");
+        let p = *p.as_ref() - self.pos;
+        vec2!(self.scale(p.x), self.scale(p.y))
     }
 
 
-    /// Draws the whole image at `pos`.
-    /// 
-    /// Equivalent to:
-    /// ```
-    /// rdr.image(img, pos, img.size(), Vec2::ZERO, None);
-    /// ```
-    pub fn draw_whole_image<A>(&mut self, img: Arc<Mutex<Image>>, pos: A) 
+    /// Converts the point `p` of the screen to world coordinates.
+    pub fn to_world<A>(self, p: A) -> Vec2 
         where A: AsRef<Vec2>
     {
-        self.can_draw();
-        self.sender.send(RenderingDirective::DrawWholeImage(img, *pos.as_ref())).expect("Rendering thread stoped");
+        let p = p.as_ref();
+        self.pos + vec2!((p.x as f32 / self.zoom).floor() as i32, (p.y as f32 / self.zoom).floor() as i32)
+    }
+
+
+    /// Scales the length `l`.
+    fn scale(&self, l: i32) -> i32 {
+        (l as f32 * self.zoom).round() as i32
     }
 
 
+    /// Scales the size `s`.
+    fn scale_size(&self, s: Vec2) -> Vec2 {
+        vec2!(self.scale(s.x), self.scale(s.y))
+    }
 
-    /// Rings the terminal bell. Can only be called during the creation of a frame
-    /// 
-    /// Technical note: the bell will ring when calling `end_draw`
-    pub fn ring_bell(&self) {
-        self.can_draw();
-        print!("\x07");
+
+    /// Converts the rectangle of the world at `pos` of size `size`, adjacent rectangles stay adjacent.
+    fn to_screen_rect(self, pos: Vec2, size: Vec2) -> Rect {
+        let p = self.to_screen(pos);
+        Rect::new(p, self.to_screen(pos + size) - p)
+    }
+
+
+    /// Converts the coordinates of a drawing directive to screen coordinates.
+    fn apply(&self, directive: RenderingDirective) -> RenderingDirective {
+        use RenderingDirective::*;
+        if *self == Camera::IDENTITY {
+            return directive;
+        }
+
+        let p = |p: Vec2| self.to_screen(p);
+        let points = |points: Vec<Vec2>| points.into_iter().map(|q| self.to_screen(q)).collect();
+        match directive {
+            DrawLine(p1, p2, c) => DrawLine(p(p1), p(p2), c),
+            DrawRect(r, c) => DrawRect(self.to_screen_rect(r.pos, r.size), c),
+            DrawRectBoudary(r, c) => DrawRectBoudary(self.to_screen_rect(r.pos, r.size), c),
+            DrawEllipseBoudary(center, s, c) => DrawEllipseBoudary(p(center), self.scale_size(s), c),
+            DrawEllipse(center, s, c) => DrawEllipse(p(center), self.scale_size(s), c),
+            DrawCircleBoundary(center, r, c) => DrawCircleBoundary(p(center), self.scale(r), c),
+            DrawCircle(center, r, c) => DrawCircle(p(center), self.scale(r), c),
+            DrawArc(center, r, start, end, c) => DrawArc(p(center), self.scale(r), start, end, c),
+            DrawTriangle(p0, p1, p2, c) => DrawTriangle(p(p0), p(p1), p(p2), c),
+            DrawPolygon(pts, c) => DrawPolygon(points(pts), c),
+            DrawPath(pts, closed, c) => DrawPath(points(pts), closed, c),
+            DrawText(pos, text, c) => DrawText(p(pos), text, c),
+            #[cfg(feature = "ttf")]
+            DrawTtfText(pos, text, font, px, c) => DrawTtfText(p(pos), text, font, px * self.zoom, c),
+            DrawPoint(q, c) => DrawPoint(p(q), c),
+            DrawPoints(pts) => DrawPoints(pts.into_iter().map(|(q, c)| (p(q), c)).collect()),
+            DrawRectGradient(pos, s, c1, c2, angle) => {
+                let r = self.to_screen_rect(pos, s);
+                DrawRectGradient(r.pos, r.size, c1, c2, angle)
+            }
+            DrawEllipseGradient(center, s, c1, c2) => DrawEllipseGradient(p(center), self.scale_size(s), c1, c2),
+            DrawStreamedImage(img, pos) => DrawStreamedImage(img, p(pos)),
+
+            // images are drawn with the scaled blit, except when they are only moved
+            DrawImage(img, pos, size, off, flip, alpha, mode) if self.zoom == 1. => DrawImage(img, p(pos), size, off, flip, alpha, mode),
+            DrawImage(img, pos, size, off, flip, alpha, mode) => {
+                let (pos, size, unflip) = img::unflip(pos, size);
+                DrawScaledImage(img, Rect::new(off, size), self.to_screen_rect(pos, size), Filter::Nearest, flip.then(unflip), alpha, mode)
+            }
+            DrawWholeImageAlpha(img, pos, alpha) => {
+                let size = img.size();
+                self.apply(DrawImage(img, pos, size, Vec2::ZERO, Flip::None, Some(alpha), BlendMode::Normal))
+            }
+            DrawWholeImage(img, pos) => {
+                let size = img.size();
+                self.apply(DrawImage(img, pos, size, Vec2::ZERO, Flip::None, None, BlendMode::Normal))
+            }
+            DrawRgbaImage(img, pos, size, off) if self.zoom == 1. => DrawRgbaImage(img, p(pos), size, off),
+            DrawRgbaImage(img, pos, size, off) => {
+                let (pos, size, flip) = img::unflip(pos, size);
+                DrawScaledRgbaImage(img, Rect::new(off, size), self.to_screen_rect(pos, size), flip)
+            }
+            DrawScaledImage(img, src, dst, filter, flip, alpha, mode) =>
+                DrawScaledImage(img, src, self.to_screen_rect(dst.pos, dst.size), filter, flip, alpha, mode),
+            DrawScaledRgbaImage(img, src, dst, flip) => DrawScaledRgbaImage(img, src, self.to_screen_rect(dst.pos, dst.size), flip),
+
+            directive => directive
+        }
     }
 }
 
 
-impl Drop for Renderer {
+impl Default for Camera {
+    fn default() -> Self {
+        Camera::IDENTITY
+    }
+}
 
-    /// When the renderer singleton is droped, reset terminal settings and exit.
-    fn drop(&mut self) {
-        // return settings to default
-        self.termios.c_cc = self.default_c_cc;
-        self.termios.c_lflag = self.default_c_lflags;
 
-        print!("{}{}",
-            csi!("?25h"),                                   // show cursor
-            csi!("?1049l")                                  // use main screen buffer
-        );
-        stdout().flush().expect("Could not write to stdout");
-        Input::disable_mouse();
+/// How frames are sent to the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputProtocol {
+    /// Frames are drawn with characters (see `RenderMode`), works on all terminals.
+    Text,
+    /// Frames are sent as images with the iTerm2 inline image protocol (OSC 1337).
+    /// Every pixel keeps its color, whatever the `RenderMode` (which still sets the resolution).
+    ITerm2
+}
+
+
+/// Colors the terminal can display. Frame pixels are converted to the closest color available.
+#[derive(Debug, Clone, Copy, Eq)]
+pub enum ColorDepth {
+    /// 24 bits RGB colors.
+    TrueColor,
+    /// The xterm 256 colors palette (see `Color::to_ansi256`).
+    Ansi256,
+    /// The 16 ANSI colors, for basic terminals and serial consoles.
+    /// The function gives the index (0 to 15) of the ANSI color used for a pixel,
+    /// `Color::to_ansi16` picks the closest one.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// // only use the 8 dark colors
+    /// let config = RendererConfig { colors: ColorDepth::Ansi16(|c| c.to_ansi16() % 8), ..RendererConfig::detect() };
+    /// ```
+    Ansi16(fn(Color) -> u8),
+    /// The 24 grays of the xterm 256 colors palette, pixels are converted to their luminance.
+    /// Useful for e-ink terminals or to check that a program does not rely on colors only.
+    Grayscale
+}
 
-        std::process::exit(0);
+
+// the mappings of `Ansi16` are compared by address
+impl PartialEq for ColorDepth {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (ColorDepth::Ansi16(f), ColorDepth::Ansi16(g)) => std::ptr::fn_addr_eq(*f, *g),
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other)
+        }
     }
 }
 
 
-struct TermSize {
-    row: libc::c_ushort,
-    col: libc::c_ushort,
-    _x : libc::c_ushort,
-    _y : libc::c_ushort
+impl ColorDepth {
+
+    /// Returns the color that will actually be displayed for `c`.
+    pub fn quantize(&self, c: Color) -> Color {
+        match self {
+            ColorDepth::TrueColor => c,
+            ColorDepth::Ansi256 => Color::from_ansi256(c.to_ansi256()),
+            ColorDepth::Ansi16(f) => Color::from_ansi256(f(c) % 16),
+            ColorDepth::Grayscale => Color::from_ansi256(232 + ((c.luminance() as i32 - 3) / 10).clamp(0, 23) as u8)
+        }
+    }
+
+
+    /// Returns the usual distance between two displayable colors, used as the amplitude of ordered dithering.
+    fn step(&self) -> f32 {
+        match self {
+            ColorDepth::TrueColor => 0.,
+            ColorDepth::Ansi256 => 40.,
+            ColorDepth::Ansi16(_) => 128.,
+            ColorDepth::Grayscale => 10.
+        }
+    }
+}
+
+
+/// How pixels are dithered when the terminal can not display all the colors (see `ColorDepth`)
+/// or when an image is reduced to a palette (see `Image::quantize`), so that gradients do not show bands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dithering {
+    /// Pixels are replaced by the closest color.
+    #[default]
+    None,
+    /// Bayer matrix dithering, stable between frames (a pixel that does not change keeps its color).
+    Ordered,
+    /// Floyd–Steinberg error diffusion, looks better on still images but a change can affect the
+    /// color of the pixels around.
+    FloydSteinberg
+}
+
+
+/// Output settings of a Renderer, see `Renderer::set_config`.
+/// 
+/// # Usage
+/// 
+/// ```
+/// let mut config = RendererConfig::detect();
+/// config.protocol = OutputProtocol::Text; // never use inline images
+/// Renderer::get().set_config(config);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RendererConfig {
+    pub protocol: OutputProtocol,
+    pub colors: ColorDepth,
+    pub dithering: Dithering,
+    /// Draws the flat cells whose color can not be displayed with a shade (░, ▒, ▓) mixing two
+    /// displayable colors, in `RenderMode::HalfBlock`. Has no effect with `ColorDepth::TrueColor`.
+    pub shades: bool
+}
+
+
+impl RendererConfig {
+
+    /// Returns the settings best suited for the current terminal, using the environment variables.
+    /// This is what renderers use by default.
+    pub fn detect() -> Self {
+        let var = |name| std::env::var(name).unwrap_or_default();
+
+        // tmux does not forward the inline images
+        let iterm = (var("TERM_PROGRAM") == "iTerm.app" || var("LC_TERMINAL") == "iTerm2") && var("TMUX").is_empty();
+
+        // COLORTERM is the only way to know if truecolor is supported, terminfo only tells about palettes
+        let colorterm = var("COLORTERM");
+        let colors = if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorDepth::TrueColor
+        } else {
+            match terminfo::max_colors(&var("TERM")) {
+                Some(n) if n >= 256 => ColorDepth::Ansi256,
+                Some(n) if n >= 8 => ColorDepth::Ansi16(|c| c.to_ansi16()),
+                _ => ColorDepth::TrueColor
+            }
+        };
+
+        RendererConfig {
+            protocol: if iterm { OutputProtocol::ITerm2 } else { OutputProtocol::Text },
+            colors,
+            dithering: Dithering::None,
+            shades: false
+        }
+    }
+}
+
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        RendererConfig::detect()
+    }
+}
+
+
+/// How the terminal is filled around the frame when the frame is smaller than the terminal
+/// (eg. with `Renderer::set_fixed_size`), see `Renderer::set_letterbox`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Letterbox {
+    /// Color of the cells around the frame.
+    pub color: Color,
+    /// Whether the frame is centered in the terminal, it is in the top left corner otherwise.
+    pub center: bool
+}
+
+
+impl Default for Letterbox {
+    fn default() -> Self {
+        Letterbox { color: Color::BLACK, center: false }
+    }
+}
+
+
+/// How a frame of logical size is scaled to the size of the terminal, see `Renderer::set_logical_size`.
+/// Pixels are scaled with the nearest neighbour, the terminal is filled around the frame with the letterbox.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Scaling {
+    /// Each pixel is drawn as a square of the largest size that fits in the terminal (sharp, but can leave margins).
+    /// If the terminal is smaller than the frame, the frame is downscaled as with `Fit`.
+    #[default]
+    Integer,
+    /// The frame is scaled as much as possible while keeping its aspect ratio.
+    Fit,
+    /// The frame covers the whole terminal, the aspect ratio is not kept.
+    Stretch
+}
+
+
+impl Scaling {
+
+    /// Returns the size of a frame of size `size` scaled to fit in `target`.
+    pub(crate) fn scaled_size(&self, size: Vec2, target: Vec2) -> Vec2 {
+        if size.x <= 0 || size.y <= 0 {
+            return size;
+        }
+        match self {
+            Scaling::Integer => match (target.x / size.x).min(target.y / size.y) {
+                0 => Scaling::Fit.scaled_size(size, target),
+                k => size * k
+            },
+            Scaling::Fit => {
+                let k = (target.x as f32 / size.x as f32).min(target.y as f32 / size.y as f32);
+                vec2!(((size.x as f32 * k) as i32).max(1), ((size.y as f32 * k) as i32).max(1))
+            },
+            Scaling::Stretch => vec2!(target.x.max(1), target.y.max(1))
+        }
+    }
+}
+
+
+/// Defines when frames should be drawn, see `Renderer::wait_redraw`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedrawMode {
+    /// Frames are drawn continuously, `wait_redraw` never blocks.
+    Continuous,
+    /// Frames are only drawn when an input event occurs, when the redraw timer runs out,
+    /// when the screen is resized or after a call to `Renderer::request_redraw`.
+    OnEvent
+}
+
+
+/// Frame buffer of the rendering server.
+#[derive(Clone)]
+enum Framebuffer {
+    /// One Color per pixel
+    Rgb(Image),
+    /// One palette index per pixel, and the palette
+    Indexed(Image<u8>, Palette)
+}
+
+
+impl Framebuffer {
+
+    fn size(&self) -> Vec2 {
+        match self {
+            Framebuffer::Rgb(img) => img.size(),
+            Framebuffer::Indexed(img, _) => img.size()
+        }
+    }
+
+
+    fn raw_resize(&mut self, size: Vec2) {
+        match self {
+            Framebuffer::Rgb(img) => img.raw_resize(size),
+            Framebuffer::Indexed(img, _) => img.raw_resize(size)
+        }
+    }
+
+
+    /// Returns the framebuffer scaled to `size` with the nearest neighbour.
+    fn scaled(&self, size: Vec2) -> Framebuffer {
+        match self {
+            Framebuffer::Rgb(img) => Framebuffer::Rgb(img.scaled(size, Filter::Nearest)),
+            Framebuffer::Indexed(img, palette) => Framebuffer::Indexed(img.scaled(size, Filter::Nearest), palette.clone())
+        }
+    }
+
+
+    /// Returns the color that is displayed at `p`.
+    fn color_at(&self, p: Vec2) -> Color {
+        match self {
+            Framebuffer::Rgb(img) => img.at(p),
+            Framebuffer::Indexed(img, palette) => palette.color(img.at(p))
+        }
+    }
+
+
+    fn palette(&self) -> Option<&Palette> {
+        match self {
+            Framebuffer::Rgb(_) => None,
+            Framebuffer::Indexed(_, palette) => Some(palette)
+        }
+    }
+
+
+    /// Returns the displayed colors as an Image.
+    fn to_rgb(&self) -> Image {
+        match self {
+            Framebuffer::Rgb(img) => img.clone(),
+            Framebuffer::Indexed(img, palette) => img.map(|i| palette.color(i))
+        }
+    }
+
+
+    /// Moves the displayed colors out as an Image, the framebuffer is not valid until `put_rgb` is called.
+    fn take_rgb(&mut self) -> Image {
+        match self {
+            Framebuffer::Rgb(img) => mem::replace(img, Image::new(0, 0)),
+            Framebuffer::Indexed(img, palette) => img.map(|i| palette.color(i))
+        }
+    }
+
+
+    /// Replaces the displayed colors by `img`, that are mapped to the nearest palette color in indexed mode.
+    fn put_rgb(&mut self, img: Image) {
+        match self {
+            Framebuffer::Rgb(dst) => *dst = img,
+            Framebuffer::Indexed(dst, palette) => *dst = img.map(|c| palette.nearest(c))
+        }
+    }
+
+
+    /// Copies `region` of `src` in `self`, both framebuffers should have the same size, type and palette.
+    fn copy_region(&mut self, src: &Framebuffer, region: Rect) {
+        match (self, src) {
+            (Framebuffer::Rgb(dst), Framebuffer::Rgb(src)) => dst.image_copy(src, region.pos, region.size, region.pos),
+            (Framebuffer::Indexed(dst, _), Framebuffer::Indexed(src, _)) => dst.image_copy(src, region.pos, region.size, region.pos),
+            _ => panic!("framebuffer type mismatch")
+        }
+    }
+
+
+    /// Applies a drawing directive.
+    fn draw(&mut self, directive: RenderingDirective) {
+        match (self, directive) {
+            (Framebuffer::Rgb(img), RenderingDirective::Shade(r, shader)) => img.shade_rect(r, shader),
+            (Framebuffer::Indexed(img, palette), RenderingDirective::Shade(r, shader)) => img.shade_rect(r, |p, i| {
+                palette.nearest(shader(p, palette.color(i)))
+            }),
+            // the colors are drawn as is, the rows are copied instead of blended
+            (Framebuffer::Rgb(img), RenderingDirective::DrawWholeImage(src, pos)) => img.image_copy(&src, pos, src.size(), Vec2::ZERO),
+            (Framebuffer::Rgb(img), RenderingDirective::DrawImage(src, pos, size, off, Flip::None, None, BlendMode::Normal)) =>
+                img.image_copy(&src, pos, size, off),
+            (Framebuffer::Rgb(img), directive) => draw_directive(img, directive, |c| c, |dst, src, mode| dst.blend_mode(src, mode)),
+            (Framebuffer::Indexed(img, palette), directive) => draw_directive(img, directive,
+                |c| palette.nearest(c),
+                |dst, src, mode| palette.nearest(palette.color(dst).blend_mode(src, mode))
+            )
+        }
+    }
+}
+
+
+/// Name of the layer that is always at the bottom, see `Renderer::create_layer`.
+pub const MAIN_LAYER: &str = "main";
+
+
+/// Layer drawn over the main framebuffer, transparent where nothing was drawn.
+struct Layer {
+    name: String,
+    z: i32,
+    opacity: f32,
+    mode: BlendMode,
+    image: Image<Rgba>
+}
+
+
+/// Layers of the rendering server, they are composited over the main framebuffer (by increasing z)
+/// when a frame is pushed.
+struct Layers {
+    /// Sorted by z
+    layers: Vec<Layer>,
+    /// Index of the layer that is drawn into, `None` for the main layer
+    current: Option<usize>,
+    /// The main layer is drawn over black with this opacity
+    main_opacity: f32
+}
+
+
+impl Layers {
+
+    fn new() -> Self {
+        Self {
+            layers: Vec::new(),
+            current: None,
+            main_opacity: 1.
+        }
+    }
+
+
+    fn index_of(&self, name: &str) -> Option<usize> {
+        self.layers.iter().position(|l| l.name == name)
+    }
+
+
+    /// Sorts the layers by z, layers with the same z stay in creation order.
+    fn sort(&mut self) {
+        let current = self.current.map(|i| self.layers[i].name.clone());
+        self.layers.sort_by_key(|l| l.z);
+        self.current = current.and_then(|name| self.index_of(&name));
+    }
+
+
+    /// Resizes the layers to `size`, clearing them.
+    fn resize(&mut self, size: Vec2) {
+        for layer in self.layers.iter_mut() {
+            layer.image = Image::filled(size.x as usize, size.y as usize, Rgba::TRANSPARENT);
+        }
+    }
+
+
+    /// Applies a layer directive or draws in the current layer.
+    fn apply(&mut self, screen: &mut Framebuffer, directive: RenderingDirective) {
+        match directive {
+            RenderingDirective::CreateLayer(name, z) => {
+                let size = screen.size();
+                match self.index_of(&name) {
+                    Some(i) => self.layers[i].z = z,
+                    None => self.layers.push(Layer {
+                        name,
+                        z,
+                        opacity: 1.,
+                        mode: BlendMode::Normal,
+                        image: Image::filled(size.x as usize, size.y as usize, Rgba::TRANSPARENT)
+                    })
+                }
+                self.sort();
+            }
+            RenderingDirective::RemoveLayer(name) => {
+                if let Some(i) = self.index_of(&name) {
+                    let current = self.current.filter(|c| *c != i).map(|c| self.layers[c].name.clone());
+                    self.layers.remove(i);
+                    self.current = current.and_then(|name| self.index_of(&name));
+                }
+            }
+            RenderingDirective::SetLayer(name) => self.current = self.index_of(&name),
+            RenderingDirective::SetLayerZ(name, z) => {
+                if let Some(i) = self.index_of(&name) {
+                    self.layers[i].z = z;
+                    self.sort();
+                }
+            }
+            RenderingDirective::SetLayerOpacity(name, opacity) => {
+                let opacity = opacity.clamp(0., 1.);
+                match self.index_of(&name) {
+                    Some(i) => self.layers[i].opacity = opacity,
+                    None if name == MAIN_LAYER => self.main_opacity = opacity,
+                    None => ()
+                }
+            }
+            RenderingDirective::SetLayerBlendMode(name, mode) => {
+                if let Some(i) = self.index_of(&name) {
+                    self.layers[i].mode = mode;
+                }
+            }
+            RenderingDirective::ClearLayer => match self.current {
+                Some(i) => self.layers[i].image.clear(Rgba::TRANSPARENT),
+                None => screen.draw(RenderingDirective::ClearScreen(Color::BLACK))
+            }
+
+            // the shader changes the color of the pixels of the layer, not their alpha
+            RenderingDirective::Shade(r, shader) if self.current.is_some() => {
+                let i = self.current.unwrap();
+                self.layers[i].image.shade_rect(r, |p, c| {
+                    let color = shader(p, Color::rgb(c.r, c.g, c.b));
+                    Rgba::new(color.r, color.g, color.b, c.a)
+                });
+            }
+
+            directive => match self.current {
+                Some(i) => draw_directive(&mut self.layers[i].image, directive, |c| c.into(), |dst, src, mode| dst.over(src, mode)),
+                None => screen.draw(directive)
+            }
+        }
+    }
+
+
+    /// Returns the main framebuffer with the layers drawn over it, `None` if there is nothing to compose.
+    fn compose(&self, screen: &Framebuffer) -> Option<Framebuffer> {
+        if self.layers.is_empty() && self.main_opacity == 1. {
+            return None;
+        }
+        let mut result = screen.clone();
+        if self.main_opacity < 1. {
+            let black = Color::BLACK.with_alpha((255. * (1. - self.main_opacity)).round() as u8);
+            match &mut result {
+                Framebuffer::Rgb(img) => *img = img.map(|c| c.blend(black)),
+                Framebuffer::Indexed(img, palette) => *img = img.map(|i| palette.nearest(palette.color(i).blend(black)))
+            }
+        }
+        for layer in self.layers.iter().filter(|l| l.opacity > 0.) {
+            let size = layer.image.size();
+            let (opacity, mode) = (layer.opacity, layer.mode);
+            let src = |c: Rgba| Rgba { a: (c.a as f32 * opacity).round() as u8, ..c };
+            match &mut result {
+                Framebuffer::Rgb(img) => img.image_blend(&layer.image, Vec2::ZERO, size, Vec2::ZERO, None,
+                    |dst, c| dst.blend_mode(src(c), mode)
+                ),
+                Framebuffer::Indexed(img, palette) => img.image_blend(&layer.image, Vec2::ZERO, size, Vec2::ZERO, None,
+                    |dst, c| palette.nearest(palette.color(dst).blend_mode(src(c), mode))
+                )
+            }
+        }
+        Some(result)
+    }
+}
+
+
+/// Last frame pushed to the screen, used to only print the cells that changed.
+enum PrevFrame {
+    Raw(Framebuffer),
+    /// Only stores the displayed colors, uses less memory on big terminals
+    Rle(RleImage)
+}
+
+
+impl PrevFrame {
+
+    fn size(&self) -> Vec2 {
+        match self {
+            PrevFrame::Raw(fb) => fb.size(),
+            PrevFrame::Rle(img) => img.size()
+        }
+    }
+
+
+    /// Returns an iterator over the colors of row `y`, starting at `x`. The iterator never ends.
+    fn row(&self, x: i32, y: i32) -> Box<dyn Iterator<Item = Color> + '_> {
+        match self {
+            PrevFrame::Raw(fb) => Box::new((x..).map(move |i| fb.color_at(vec2!(i, y)))),
+            PrevFrame::Rle(img) => Box::new(img.row(x, y))
+        }
+    }
+
+
+    /// Forgets the last pushed frame, so that the next one is fully redrawn.
+    fn clear(&mut self) {
+        match self {
+            PrevFrame::Raw(fb) => *fb = Framebuffer::Rgb(Image::new(0, 0)),
+            PrevFrame::Rle(img) => *img = RleImage::from_fn(Vec2::ZERO, |_| Color::BLACK)
+        }
+    }
+
+
+    /// Stores `screen` as the last pushed frame.
+    fn update(&mut self, screen: &Framebuffer) {
+        match self {
+            PrevFrame::Raw(fb) => *fb = screen.clone(),
+            PrevFrame::Rle(img) => *img = RleImage::from_fn(screen.size(), |p| screen.color_at(p))
+        }
+    }
+
+
+    /// Returns the palette indices of the last pushed frame and of `screen`, if both are indexed with the same palette.
+    fn indices<'a>(&'a self, screen: &'a Framebuffer) -> Option<(&'a Image<u8>, &'a Image<u8>)> {
+        match (self, screen) {
+            (PrevFrame::Raw(Framebuffer::Indexed(prev, prev_palette)), Framebuffer::Indexed(img, palette)) if prev_palette == palette =>
+                Some((prev, img)),
+            _ => None
+        }
+    }
+
+
+    /// Returns true if only a region of `screen` can be stored with `update_region`.
+    fn can_update_region(&self, screen: &Framebuffer) -> bool {
+        self.size() == screen.size() && match self {
+            PrevFrame::Raw(fb) => fb.palette() == screen.palette(),
+            PrevFrame::Rle(_) => true
+        }
+    }
+
+
+    /// Stores `region` of `screen` as pushed.
+    fn update_region(&mut self, screen: &Framebuffer, region: Rect) {
+        match self {
+            PrevFrame::Raw(fb) => fb.copy_region(screen, region),
+            PrevFrame::Rle(img) => {
+                for y in region.pos.y..(region.pos.y + region.size.y) {
+                    img.set_row_span(y, region.pos.x, region.pos.x + region.size.x, |p| screen.color_at(p));
+                }
+            }
+        }
+    }
+}
+
+
+/// Draw commands recorded for an offscreen rendering or for a frame of the screen,
+/// see `Renderer::render_to_image` and `Renderer::with_frame`.
+/// 
+/// Has the same drawing functions as the Renderer.
+pub struct Frame {
+    directives: Vec<RenderingDirective>
+}
+
+
+impl Frame {
+
+    pub(crate) fn new() -> Self {
+        Self {
+            directives: Vec::new()
+        }
+    }
+
+
+    /// Sets all the pixels' color in the screen to `c`.
+    pub fn clear_screen(&mut self, c: Color) {
+        self.directives.push(RenderingDirective::ClearScreen(c));
+    }
+
+
+    /// Draws a line of color `c` between `p1` and `p2`.
+    pub fn draw_line<A, B>(&mut self, p1: A, p2: B, c: Color) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>
+    {
+        self.directives.push(RenderingDirective::DrawLine(*p1.as_ref(), *p2.as_ref(), c));
+    }
+
+
+    /// Draws the rectangle `r` filled with the color `c` (see `Image::rect`).
+    pub fn draw_rect<R>(&mut self, r: R, c: Color) 
+        where R: Into<Rect>
+    {
+        self.directives.push(RenderingDirective::DrawRect(r.into(), c));
+    }
+
+
+    /// Same as `draw_rect` but draws only the four sides of the rectangle.
+    pub fn draw_rect_boundary<R>(&mut self, r: R, c: Color) 
+        where R: Into<Rect>
+    {
+        self.directives.push(RenderingDirective::DrawRectBoudary(r.into(), c));
+    }
+
+
+    /// Draws an ellipse of color `col`. `c` is the center of the ellipse and `s` is the size of the rectangle
+    /// in which the ellipse is inscribed.
+    pub fn draw_ellipse_boundary<A, B>(&mut self, c: A, s: B, col: Color) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>
+    {
+        self.directives.push(RenderingDirective::DrawEllipseBoudary(*c.as_ref(), *s.as_ref(), col));
+    }
+
+
+    /// Draws a filled ellipse of color `col`. `c` is the center of the ellipse and `s` is the size of the rectangle
+    /// in which the ellipse is inscribed.
+    pub fn draw_ellipse<A, B>(&mut self, c: A, s: B, col: Color) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>
+    {
+        self.directives.push(RenderingDirective::DrawEllipse(*c.as_ref(), *s.as_ref(), col));
+    }
+
+
+    /// Draws a circle of color `col`, of center `c` and of radius `r`.
+    pub fn draw_circle_boundary<A>(&mut self, c: A, r: i32, col: Color) 
+        where A: AsRef<Vec2>
+    {
+        self.directives.push(RenderingDirective::DrawCircleBoundary(*c.as_ref(), r, col));
+    }
+
+
+    /// Draws a filled circle of color `col`, of center `c` and of radius `r`.
+    pub fn draw_circle<A>(&mut self, c: A, r: i32, col: Color) 
+        where A: AsRef<Vec2>
+    {
+        self.directives.push(RenderingDirective::DrawCircle(*c.as_ref(), r, col));
+    }
+
+
+    /// Draws the part of the circle of center `c` and of radius `r` that goes from `start` to `end` (in radians).
+    pub fn draw_arc<A>(&mut self, c: A, r: i32, start: f32, end: f32, col: Color) 
+        where A: AsRef<Vec2>
+    {
+        self.directives.push(RenderingDirective::DrawArc(*c.as_ref(), r, start, end, col));
+    }
+
+
+    /// Draws a filled triangle of color `c` with the vertices `p0`, `p1` and `p2`.
+    pub fn draw_triangle<A, B, C>(&mut self, p0: A, p1: B, p2: C, c: Color) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>, C: AsRef<Vec2>
+    {
+        self.directives.push(RenderingDirective::DrawTriangle(*p0.as_ref(), *p1.as_ref(), *p2.as_ref(), c));
+    }
+
+
+    /// Draws a filled polygon of color `c` with the vertices `points` (even-odd rule).
+    pub fn draw_polygon(&mut self, points: &[Vec2], c: Color) {
+        self.directives.push(RenderingDirective::DrawPolygon(points.to_vec(), c));
+    }
+
+
+    /// Draws lines of color `c` between the consecutive points of `points`.
+    pub fn draw_polyline(&mut self, points: &[Vec2], c: Color) {
+        self.directives.push(RenderingDirective::DrawPath(points.to_vec(), false, c));
+    }
+
+
+    /// Draws the sides of the polygon with the vertices `points`.
+    pub fn draw_polygon_boundary(&mut self, points: &[Vec2], c: Color) {
+        self.directives.push(RenderingDirective::DrawPath(points.to_vec(), true, c));
+    }
+
+
+    /// Draws `text` with the embedded bitmap font, `pos` is the top left corner of the text.
+    pub fn draw_text<A>(&mut self, pos: A, text: &str, c: Color) 
+        where A: AsRef<Vec2>
+    {
+        self.directives.push(RenderingDirective::DrawText(*pos.as_ref(), text.to_string(), c));
+    }
+
+
+    /// Draws `text` with `font` at a size of `px` pixels, `pos` is the top left corner of the text.
+    /// Only available with the `ttf` feature.
+    #[cfg(feature = "ttf")]
+    pub fn draw_ttf_text<A>(&mut self, pos: A, text: &str, font: Arc<Font>, px: f32, c: Color) 
+        where A: AsRef<Vec2>
+    {
+        self.directives.push(RenderingDirective::DrawTtfText(*pos.as_ref(), text.to_string(), font, px, c));
+    }
+
+
+    /// Sets the color of the pixel at `p` to `c`.
+    pub fn draw_point<A>(&mut self, p: A, c: Color) 
+        where A: AsRef<Vec2>
+    {
+        self.directives.push(RenderingDirective::DrawPoint(*p.as_ref(), c));
+    }
+
+
+    /// Sets the color of each pixel of `points`.
+    pub fn draw_points(&mut self, points: &[(Vec2, Color)]) {
+        self.directives.push(RenderingDirective::DrawPoints(points.to_vec()));
+    }
+
+
+    /// Draws the particles of `emitter` (see `particles::Emitter`).
+    pub fn draw_particles(&mut self, emitter: &Emitter) {
+        self.directives.push(RenderingDirective::DrawPoints(emitter.points()));
+    }
+
+
+    /// Replaces the color of each pixel of `region` by `shader(pos, color)`, see `Renderer::shade`.
+    pub fn shade<R, F>(&mut self, region: R, shader: F) 
+        where R: Into<Rect>, F: Fn(Vec2, Color) -> Color + Send + 'static
+    {
+        self.directives.push(RenderingDirective::Shade(region.into(), Box::new(shader)));
+    }
+
+
+    /// Draws a rectangle of size `s` filled with a linear gradient from `c1` to `c2`.
+    /// `p` is the coordinate of the top left corner of the rectangle, `angle` is the direction of the gradient
+    /// in radians (0 goes from left to right, PI/2 from top to bottom).
+    pub fn draw_rect_gradient<A, B>(&mut self, p: A, s: B, c1: Color, c2: Color, angle: f32) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>
+    {
+        self.directives.push(RenderingDirective::DrawRectGradient(*p.as_ref(), *s.as_ref(), c1, c2, angle));
+    }
+
+
+    /// Draws an ellipse filled with a radial gradient, from `c1` at the center to `c2` on the boundary.
+    /// `c` is the center of the ellipse and `s` is the size of the rectangle in which the ellipse is inscribed.
+    pub fn draw_ellipse_gradient<A, B>(&mut self, c: A, s: B, c1: Color, c2: Color) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>
+    {
+        self.directives.push(RenderingDirective::DrawEllipseGradient(*c.as_ref(), *s.as_ref(), c1, c2));
+    }
+
+
+    /// Draws an image at position `pos`. 
+    /// 
+    /// Negative size results in flipped image. Alpha is used to ignore a given color while drawing.
+    /// The image is combined with the frame using `mode` (eg. `BlendMode::Add` for lights).
+    pub fn draw_image<A, B, C>(&mut self, 
+        img: Arc<Image>, pos: A, size: B, offset: C, alpha: Option<Color>, mode: BlendMode) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>, C: AsRef<Vec2>
+    {
+        self.directives.push(RenderingDirective::DrawImage(img, *pos.as_ref(), *size.as_ref(), *offset.as_ref(), Flip::None, alpha, mode));
+    }
+
+
+    /// Draws the frame `frame` of `sheet` at `pos`. Panics if the frame does not exist.
+    pub fn draw_sprite<K, A>(&mut self, sheet: &SpriteSheet, frame: K, pos: A) 
+        where K: FrameKey, A: AsRef<Vec2>
+    {
+        let rect = sheet.frame(frame).expect("no such frame in the sprite sheet");
+        self.draw_image(sheet.image(), pos, rect.size, rect.pos, None, BlendMode::Normal);
+    }
+
+
+    /// Draws the frame of `anim` shown `elapsed` after the start of the animation at `pos`.
+    pub fn draw_animation<A>(&mut self, anim: &Animation, pos: A, elapsed: Duration) 
+        where A: AsRef<Vec2>
+    {
+        self.draw_sprite(anim.sheet(), anim.frame_at(elapsed), pos);
+    }
+
+
+    /// Draws the part of an image at `offset` of size `size` at position `pos`, mirrored according to `flip`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_image_flipped<A, B, C>(&mut self, 
+        img: Arc<Image>, pos: A, size: B, offset: C, flip: Flip, alpha: Option<Color>, mode: BlendMode) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>, C: AsRef<Vec2>
+    {
+        self.directives.push(RenderingDirective::DrawImage(img, *pos.as_ref(), *size.as_ref(), *offset.as_ref(), flip, alpha, mode));
+    }
+
+
+    /// Draws the part `src` of an image stretched over the rectangle `dst` of the frame,
+    /// pixels are sampled using `filter`.
+    pub fn draw_scaled_image(&mut self, img: Arc<Image>, src: Rect, dst: Rect, filter: Filter, mode: BlendMode) {
+        self.directives.push(RenderingDirective::DrawScaledImage(img, src, dst, filter, Flip::None, None, mode));
+    }
+
+
+    /// Sets the camera applied to the next draw calls of the frame (see `Camera`).
+    pub fn set_camera(&mut self, camera: Camera) {
+        self.directives.push(RenderingDirective::SetCamera(camera));
+    }
+
+
+    /// Creates a transparent layer named `name` drawn over the main layer, by increasing `z`
+    /// (see `Renderer::create_layer`).
+    pub fn create_layer(&mut self, name: &str, z: i32) {
+        self.directives.push(RenderingDirective::CreateLayer(name.to_string(), z));
+    }
+
+
+    /// Removes the layer named `name`.
+    pub fn remove_layer(&mut self, name: &str) {
+        self.directives.push(RenderingDirective::RemoveLayer(name.to_string()));
+    }
+
+
+    /// The next draw calls of the frame go to the layer named `name` (`MAIN_LAYER` for the main layer).
+    pub fn set_layer(&mut self, name: &str) {
+        self.directives.push(RenderingDirective::SetLayer(name.to_string()));
+    }
+
+
+    /// Changes the z of the layer named `name`.
+    pub fn set_layer_z(&mut self, name: &str, z: i32) {
+        self.directives.push(RenderingDirective::SetLayerZ(name.to_string(), z));
+    }
+
+
+    /// Makes the current layer fully transparent (the main layer is cleared to black).
+    pub fn clear_layer(&mut self) {
+        self.directives.push(RenderingDirective::ClearLayer);
+    }
+
+
+    /// Sets the opacity (between 0 and 1) of the layer named `name` (see `Renderer::set_layer_opacity`).
+    pub fn set_layer_opacity(&mut self, name: &str, opacity: f32) {
+        self.directives.push(RenderingDirective::SetLayerOpacity(name.to_string(), opacity));
+    }
+
+
+    /// Sets how the layer named `name` is combined with the layers below.
+    pub fn set_layer_blend_mode(&mut self, name: &str, mode: BlendMode) {
+        self.directives.push(RenderingDirective::SetLayerBlendMode(name.to_string(), mode));
+    }
+
+
+    /// Draws the whole image at `pos`, ignoring the color `alpha`.
+    /// 
+    /// Equivalent to:
+    /// ```
+    /// frame.draw_image(img, pos, img.size(), Vec2::ZERO, Some(alpha), BlendMode::Normal);
+    /// ```
+    pub fn draw_whole_image_alpha<A>(&mut self, img: Arc<Image>, pos: A, alpha: Color) 
+        where A: AsRef<Vec2>
+    {
+        self.directives.push(RenderingDirective::DrawWholeImageAlpha(img, *pos.as_ref(), alpha));
+    }
+
+
+    /// Draws the whole image at `pos`.
+    /// 
+    /// Equivalent to:
+    /// ```
+    /// frame.draw_image(img, pos, img.size(), Vec2::ZERO, None, BlendMode::Normal);
+    /// ```
+    pub fn draw_whole_image<A>(&mut self, img: Arc<Image>, pos: A) 
+        where A: AsRef<Vec2>
+    {
+        self.directives.push(RenderingDirective::DrawWholeImage(img, *pos.as_ref()));
+    }
+
+
+    /// Draws the rows of a streamed image that were already received at `pos` (see `img::image_stream`).
+    pub fn draw_streamed_image<A>(&mut self, img: Arc<Mutex<StreamedImage>>, pos: A) 
+        where A: AsRef<Vec2>
+    {
+        self.directives.push(RenderingDirective::DrawStreamedImage(img, *pos.as_ref()));
+    }
+
+
+    /// Draws an image with an alpha channel at position `pos`, blending it with the frame.
+    /// 
+    /// Negative size results in flipped image.
+    pub fn draw_rgba_image<A, B, C>(&mut self, img: Arc<Image<Rgba>>, pos: A, size: B, offset: C) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>, C: AsRef<Vec2>
+    {
+        self.directives.push(RenderingDirective::DrawRgbaImage(img, *pos.as_ref(), *size.as_ref(), *offset.as_ref()));
+    }
+}
+
+
+/// Applies a drawing directive on `screen`, colors are converted to pixels using `f`
+/// and colors are combined with the pixels below using `blend`.
+fn draw_directive<P, F, G>(screen: &mut Image<P>, directive: RenderingDirective, f: F, blend: G)
+    where P: Copy + PartialEq + Default + Blend<P>, F: Fn(Color) -> P, G: Fn(P, Rgba, BlendMode) -> P
+{
+    match directive {
+        RenderingDirective::DrawLine(p1, p2, c) => screen.line(p1, p2, f(c)),
+        RenderingDirective::DrawRect(r, c) => screen.rect(r, f(c)),
+        RenderingDirective::DrawRectBoudary(r, c) => screen.rect_boudary(r, f(c)),
+        RenderingDirective::DrawEllipseBoudary(center, s, c) => screen.ellipse_boundary(center, s, f(c)),
+        RenderingDirective::DrawEllipse(center, s, c) => screen.ellipse(center, s, f(c)),
+        RenderingDirective::DrawCircleBoundary(center, r, c) => screen.circle_boundary(center, r, f(c)),
+        RenderingDirective::DrawCircle(center, r, c) => screen.circle(center, r, f(c)),
+        RenderingDirective::DrawArc(center, r, start, end, c) => screen.arc(center, r, start, end, f(c)),
+        RenderingDirective::DrawTriangle(p0, p1, p2, c) => screen.triangle(p0, p1, p2, f(c)),
+        RenderingDirective::DrawPolygon(points, c) => screen.polygon(&points, f(c)),
+        RenderingDirective::DrawPath(points, closed, c) => screen.path(&points, closed, f(c)),
+        RenderingDirective::DrawText(pos, text, c) => screen.text(pos, &text, f(c)),
+        #[cfg(feature = "ttf")]
+        RenderingDirective::DrawTtfText(pos, text, font, px, c) =>
+            screen.ttf_text_blend(pos, &text, &font, px, c, |dst, src| blend(dst, src, BlendMode::Normal)),
+        RenderingDirective::DrawPoint(p, c) => screen.point(p, f(c)),
+        RenderingDirective::DrawPoints(points) => {
+            for (p, c) in points {
+                screen.point(p, f(c));
+            }
+        }
+        RenderingDirective::DrawRectGradient(p, s, c1, c2, angle) => screen.rect_gradient_map(p, s, c1, c2, angle, &f),
+        RenderingDirective::DrawEllipseGradient(center, s, c1, c2) => screen.ellipse_gradient_map(center, s, c1, c2, &f),
+
+        RenderingDirective::DrawImage(img, pos, size, off, Flip::None, alpha, BlendMode::Normal) =>
+            screen.image_map(&img, pos, size, off, alpha, &f),
+        RenderingDirective::DrawImage(img, pos, size, off, Flip::None, alpha, mode) =>
+            screen.image_blend(&img, pos, size, off, alpha, |dst, src| blend(dst, src.into(), mode)),
+        RenderingDirective::DrawImage(img, pos, size, off, flip, alpha, mode) =>
+            screen.image_blend_flip(&img, pos, size, off, flip, alpha, |dst, src| blend(dst, src.into(), mode)),
+        RenderingDirective::DrawWholeImageAlpha(img, pos, alpha) => {
+            screen.image_map(&img, pos, img.size(), Vec2::ZERO, Some(alpha), &f);
+        }
+        RenderingDirective::DrawWholeImage(img, pos) => {
+            screen.image_map(&img, pos, img.size(), Vec2::ZERO, None, &f);
+        }
+        RenderingDirective::DrawStreamedImage(img, pos) => screen.streamed_image_map(&img.lock().unwrap(), pos, &f),
+        RenderingDirective::DrawRgbaImage(img, pos, size, off) =>
+            screen.image_blend(&img, pos, size, off, None, |dst, src| blend(dst, src, BlendMode::Normal)),
+        RenderingDirective::DrawScaledImage(img, src, dst, filter, flip, alpha, mode) =>
+            screen.scaled_image_blend(&img, src, dst, filter, flip, |dst, c| match mode {
+                _ if alpha == Some(c) => dst,
+                BlendMode::Normal => f(c),
+                mode => blend(dst, c.into(), mode)
+            }),
+        RenderingDirective::DrawScaledRgbaImage(img, src, dst, flip) =>
+            screen.scaled_image_blend(&img, src, dst, Filter::Nearest, flip, |dst, c| blend(dst, c, BlendMode::Normal)),
+
+        RenderingDirective::ClearScreen(c) => screen.clear(f(c)),
+
+        _ => panic!("not a drawing directive")
+    }
+}
+
+
+/// Errors returned by the frame functions of the renderer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderError {
+    /// A drawing function or `end_draw` was called outside of a frame build.
+    NotDrawing,
+    /// `begin_draw` was called while a frame was already being built.
+    AlreadyDrawing,
+    /// The rendering thread has stopped.
+    ThreadStopped,
+    /// The terminal could not be written to, the next frame is fully redrawn.
+    Output(io::ErrorKind)
+}
+
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenderError::NotDrawing => write!(f, "drawing outside of a frame build (call begin_draw)"),
+            RenderError::AlreadyDrawing => write!(f, "begin_draw called when already building a frame"),
+            RenderError::ThreadStopped => write!(f, "rendering thread stopped"),
+            RenderError::Output(kind) => write!(f, "could not write to the terminal: {}", kind)
+        }
+    }
+}
+
+
+impl Error for RenderError {}
+
+
+impl From<RenderError> for String {
+    fn from(e: RenderError) -> String {
+        e.to_string()
+    }
+}
+
+
+/// This is the core of the library. It will send commands to the rendering server to print on screen.
+/// 
+/// # Usage
+/// 
+/// ```
+/// // get the renderer
+/// let rdr = Renderer::get();
+/// 
+/// ...
+/// 
+/// // start drawing on a frame
+/// rdr.begin_draw()?;
+/// 
+/// ... // use drawing functions (eg. draw_rect, draw_point...)
+/// 
+/// rdr.end_draw()?; // this pushes the frame to the screen
+/// 
+/// ...
+/// 
+/// Renderer::exit(); // to reset terminal settings (or hold a RendererGuard)
+/// ```
+/// 
+/// Screen coordinates start in the top left at (0, 0)
+/// 
+/// Drawing functions do not fail: the first error they run into (drawing outside of a frame,
+/// stopped rendering thread) is kept and returned by the next `end_draw` or `begin_draw`.
+pub struct Renderer {
+    backend: Box<dyn TerminalBackend>,
+    main: bool,
+    render_mode: RenderMode,
+    config: RendererConfig,
+    camera: Camera,
+    layers: HashSet<String>,
+    textures: HashMap<TextureId, Vec2>,
+    next_texture: u64,
+    cursor: Vec2,
+    cursor_shown: bool,
+    logical_size: Option<Vec2>,
+    fixed_size: Option<Vec2>,
+
+    building_frame: bool,
+    prev_screen_size: Vec2,
+    prev_term_size: Vec2,
+    error: Option<RenderError>,
+    frame: Option<Image>,
+
+    redraw_mode: RedrawMode,
+    redraw_timer: Option<Duration>,
+    target_fps: Option<u32>,
+    last_frame: Option<Instant>,
+    created: Instant,
+    last_push: Option<Instant>,
+    delta_time: Duration,
+
+    _server_handle: Option<thread::JoinHandle<()>>,
+    sender: mpsc::Sender<RenderingDirective>,
+
+    frame_barrier: Arc<Barrier>,
+    /// First error met by the rendering thread while writing to the terminal
+    output_error: Arc<Mutex<Option<io::Error>>>
+}
+
+
+/// ANSI output of a Renderer created with `Renderer::headless`.
+#[derive(Clone)]
+pub struct HeadlessOutput {
+    bytes: Arc<Mutex<Vec<u8>>>
+}
+
+
+impl HeadlessOutput {
+
+    /// Returns a copy of everything written by the renderer so far.
+    pub fn bytes(&self) -> Vec<u8> {
+        self.bytes.lock().unwrap().clone()
+    }
+
+
+    /// Returns everything written by the renderer since the last call, and clears it.
+    pub fn take_bytes(&self) -> Vec<u8> {
+        mem::take(&mut *self.bytes.lock().unwrap())
+    }
+}
+
+
+impl Write for HeadlessOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+
+/// Configures and creates a Renderer that is owned by the application, see `Renderer::builder`.
+pub struct RendererBuilder {
+    terminal: Option<PathBuf>,
+    writer: Option<(Box<dyn Write + Send>, Vec2)>,
+    render_mode: RenderMode,
+    config: RendererConfig,
+    redraw_mode: RedrawMode,
+    target_fps: Option<u32>
+}
+
+
+impl RendererBuilder {
+
+    /// Draws on the terminal at `path` (eg. `/dev/tty` or `/dev/pts/3`) instead of stdout.
+    pub fn terminal<P>(mut self, path: P) -> Self 
+        where P: AsRef<Path>
+    {
+        self.terminal = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+
+    /// Writes the frames to `writer` instead of a terminal, `size` is the size of the screen in cells
+    /// (see `Renderer::from_writer`).
+    pub fn writer<W, A>(mut self, writer: W, size: A) -> Self 
+        where W: Write + Send + 'static, A: AsRef<Vec2>
+    {
+        self.writer = Some((Box::new(writer), *size.as_ref()));
+        self
+    }
+
+
+    /// Sets how the pixels are drawn in the terminal cells (see `Renderer::set_render_mode`).
+    pub fn render_mode(mut self, mode: RenderMode) -> Self {
+        self.render_mode = mode;
+        self
+    }
+
+
+    /// Sets the colors used in the output, instead of the detected ones.
+    pub fn color_mode(mut self, colors: ColorDepth) -> Self {
+        self.config.colors = colors;
+        self
+    }
+
+
+    /// Sets the output settings, instead of the detected ones (see `RendererConfig::detect`).
+    pub fn config(mut self, config: RendererConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+
+    /// Sets the redraw mode (see `Renderer::set_redraw_mode`).
+    pub fn redraw_mode(mut self, mode: RedrawMode) -> Self {
+        self.redraw_mode = mode;
+        self
+    }
+
+
+    /// Limits the number of frames per second (see `Renderer::set_target_fps`).
+    pub fn target_fps(mut self, fps: u32) -> Self {
+        self.target_fps = Some(fps);
+        self
+    }
+
+
+    /// Sets up the terminal and starts the rendering thread.
+    pub fn build(self) -> Result<Renderer, String> {
+        let mut rdr = match (self.writer, &self.terminal) {
+            (Some((writer, size)), _) => Renderer::from_writer(writer, size)?,
+            (None, Some(path)) => Renderer::open(path)?,
+            (None, None) => Renderer::with_backend(TermiosBackend::stdio())?
+        };
+        rdr.set_render_mode(self.render_mode);
+        rdr.set_config(self.config);
+        rdr.set_redraw_mode(self.redraw_mode);
+        rdr.set_target_fps(self.target_fps);
+        Ok(rdr)
+    }
+}
+
+
+/// Renderer singleton
+static mut RENDERER: Option<Renderer> = None;
+
+/// Incremented each time the singleton is created, so that a `RendererGuard` knows if it still holds it
+static RENDERER_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+
+/// Set when a redraw is requested, used in `RedrawMode::OnEvent`
+static REDRAW_REQUEST: (Mutex<bool>, Condvar) = (Mutex::new(false), Condvar::new());
+
+
+/// Set with `Renderer::use_controlling_terminal`
+static USE_CONTROLLING_TERMINAL: AtomicBool = AtomicBool::new(false);
+
+
+/// File descriptor of the terminal the Renderer singleton draws on
+static MAIN_OUTPUT_FD: AtomicI32 = AtomicI32::new(libc::STDOUT_FILENO);
+
+
+/// Cell size of the render mode of the Renderer singleton
+static MAIN_CELL_SIZE: Mutex<Vec2> = Mutex::new(vec2!(1, 2));
+
+
+/// Fixed size of the Renderer singleton, see `Renderer::set_fixed_size`
+static MAIN_FIXED_SIZE: Mutex<Option<Vec2>> = Mutex::new(None);
+
+
+/// Logical size of the Renderer singleton, see `Renderer::set_logical_size`
+static MAIN_LOGICAL_SIZE: Mutex<Option<Vec2>> = Mutex::new(None);
+
+
+/// Exit signal (SIGINT or SIGTERM) received since the Renderer singleton was created, 0 if there is none
+static EXIT_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+
+/// Set once SIGINT or SIGTERM was actually delivered, a Ctrl+C read as a key does not count
+static SIGNAL_DELIVERED: AtomicBool = AtomicBool::new(false);
+
+
+/// Set once the exit signal was reported as `InputEvent::Quit`
+static QUIT_REPORTED: AtomicBool = AtomicBool::new(false);
+
+
+/// Set by SIGWINCH, until the resize is reported as `InputEvent::Resize`
+static RESIZED: AtomicBool = AtomicBool::new(false);
+
+
+/// Set when the console key is pressed (see `Input::set_console_key`), until the next `begin_draw`
+static CONSOLE_TOGGLED: AtomicBool = AtomicBool::new(false);
+
+
+/// Terminal settings restored by the signal handler when the program is killed
+static mut SAVED_TERMIOS: Option<(RawFd, Termios)> = None;
+
+
+/// Interval at which the screen size is checked while waiting for a redraw
+const RESIZE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+
+impl Renderer {
+
+    /// Creates the Renderer singleton, will only be called once
+    fn init() -> Renderer {
+        let mut backend = if uses_controlling_terminal() {
+            match TermiosBackend::open("/dev/tty") {
+                Ok(b)  => b,
+                Err(e) => panic!("Could not open /dev/tty: {}", e)
+            }
+        } else {
+            TermiosBackend::stdio()
+        };
+        MAIN_OUTPUT_FD.store(backend.output_fd(), Ordering::Relaxed);
+
+        let rdr = backend.setup().and_then(|_| {
+            if let Some((fd, saved)) = backend.saved() {
+                install_signal_handlers(fd, saved);
+            }
+            Renderer::start(Box::new(backend), true)
+        });
+        match rdr {
+            Ok(r)  => r,
+            Err(e) => panic!("{}", e)
+        }
+    }
+
+
+    /// Makes the Renderer and Input singletons use the controlling terminal (`/dev/tty`)
+    /// instead of stdin and stdout, so that the program can be used in a pipeline
+    /// (eg. `producer | mygame | consumer`) while still drawing on the terminal.
+    /// 
+    /// Must be called before `Renderer::get` and `Input::get`.
+    pub fn use_controlling_terminal() {
+        USE_CONTROLLING_TERMINAL.store(true, Ordering::Relaxed);
+    }
+
+
+    /// Creates a renderer on another terminal than the one the program runs in (eg. `/dev/pts/3`).
+    /// It has its own rendering thread and terminal settings, that are reset when it is droped.
+    /// 
+    /// Input events are still read from stdin (see `Input`).
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// let mut debug_rdr = Renderer::open("/dev/pts/3").unwrap();
+    /// 
+    /// debug_rdr.begin_draw();
+    /// ...
+    /// debug_rdr.end_draw();
+    /// ```
+    pub fn open<P>(path: P) -> Result<Renderer, String>
+        where P: AsRef<Path>
+    {
+        Renderer::with_backend(TermiosBackend::open(path)?)
+    }
+
+
+    /// Creates a Renderer that writes the frames to `writer` (eg. a file, a pipe or a TCP stream) instead of a
+    /// terminal, the terminal settings are not changed. `size` is the size of the screen in cells (columns, rows).
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// let stream = TcpStream::connect("127.0.0.1:4000")?;
+    /// let mut rdr = Renderer::from_writer(stream, (80, 24))?;
+    /// 
+    /// rdr.begin_draw()?;
+    /// ...
+    /// rdr.end_draw()?;
+    /// ```
+    pub fn from_writer<W, A>(writer: W, size: A) -> Result<Renderer, String>
+        where W: Write + Send + 'static, A: AsRef<Vec2>
+    {
+        Renderer::with_backend(WriterBackend::new(writer, size))
+    }
+
+
+    /// Creates a Renderer that draws on `backend` (see `backend::TerminalBackend`).
+    pub fn with_backend<B>(mut backend: B) -> Result<Renderer, String>
+        where B: TerminalBackend + 'static
+    {
+        backend.setup()?;
+        Renderer::start(Box::new(backend), false)
+    }
+
+
+    /// Creates a Renderer that draws in memory, without a terminal: the ANSI output is kept in the returned
+    /// `HeadlessOutput`, and the pixels can be read with `screenshot`. `size` is the size of the screen in cells.
+    /// Useful to test drawing code where there is no TTY (eg. in CI).
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// let (mut rdr, output) = Renderer::headless((40, 12))?;
+    /// 
+    /// rdr.begin_draw()?;
+    /// rdr.draw_rect(Rect::new((0, 0), (4, 4)), Color::RED);
+    /// rdr.end_draw()?;
+    /// 
+    /// assert_eq!(rdr.screenshot()?[(1, 1)], Color::RED);
+    /// assert!(!output.bytes().is_empty());
+    /// ```
+    pub fn headless<A>(size: A) -> Result<(Renderer, HeadlessOutput), String>
+        where A: AsRef<Vec2>
+    {
+        let output = HeadlessOutput { bytes: Arc::new(Mutex::new(Vec::new())) };
+        let rdr = Renderer::from_writer(output.clone(), size)?;
+        Ok((rdr, output))
+    }
+
+
+    /// Starts the rendering thread on `backend`, that is already set up.
+    fn start(backend: Box<dyn TerminalBackend>, main: bool) -> Result<Renderer, String> {
+        let cast: Arc<Mutex<Option<Cast>>> = Arc::new(Mutex::new(None));
+        let mut out = match backend.writer() {
+            Ok(w)  => FrameWriter::new(Box::new(CastWriter { out: w, cast: Arc::clone(&cast) })),
+            Err(e) => return Err(format!("{}", e))
+        };
+        let initial_size = screen_size(backend.size(), RenderMode::HalfBlock.cell_size());
+
+        let init = write!(out, "{}{}", 
+            csi!("?25l"),                                   // hide cursor
+            csi!("?1049h")                                 // use alternate screen buffer
+        ).and_then(|_| out.flush());
+        if let Err(e) = init {
+            return Err(format!("{}", e));
+        }
+
+        let config = RendererConfig::detect();
+
+        // setup and start server
+        let (rx, tx) = mpsc::channel();
+        let barrier = Arc::new(Barrier::new(2));
+        let frame_barrier = Arc::clone(&barrier);
+        let output_error = Arc::new(Mutex::new(None));
+        let errors = Arc::clone(&output_error);
+
+        let handle = thread::spawn(move || {
+            let mut screen_size = initial_size;
+            let mut screen = Framebuffer::Rgb(Image::new(0, 0));
+            let mut prev_screen = PrevFrame::Raw(Framebuffer::Rgb(Image::new(0, 0)));
+            let mut cells: Image<Option<Cell>> = Image::filled(0, 0, None);
+            let mut prev_cells: Image<Option<Cell>> = Image::filled(0, 0, None);
+            let mut camera = Camera::IDENTITY;
+            let mut layers = Layers::new();
+            let mut textures: HashMap<TextureId, Arc<Image>> = HashMap::new();
+            let mut recording: Option<Recording> = None;
+            let mut damage = Damage::new();
+            let mut console = Console::new();
+
+            let mut term = TermWriter {
+                out,
+                span: String::new(),
+                back: Color::BLACK,
+                fore: Color::BLACK,
+                attrs: Attributes::NONE,
+                mode: RenderMode::HalfBlock,
+                config,
+                cursor: None,
+                term_size: Vec2::ZERO,
+                letterbox: Letterbox::default(),
+                scaling: None,
+                origin: Vec2::ZERO
+            };
+            report(&errors, term.write_color(term.back, false).and_then(|_| term.write_color(term.fore, true)));
+
+
+            // stops when the renderer is droped
+            while let Ok(directive) = tx.recv() {
+                if let (Some(rec), RenderingDirective::PushFrame | RenderingDirective::PushRegion(_)) = (&mut recording, &directive) {
+                    let composed = layers.compose(&screen);
+                    rec.push(composed.as_ref().unwrap_or(&screen).to_rgb());
+                }
+
+                match directive {
+                    RenderingDirective::SetPalette(palette) => {
+                        damage.damage_all();
+                        let rgb = screen.to_rgb();
+                        screen = match palette {
+                            None => Framebuffer::Rgb(rgb),
+                            Some(palette) => Framebuffer::Indexed(rgb.map(|c| palette.nearest(c)), palette)
+                        };
+                    }
+                    RenderingDirective::SetPaletteColor(i, c) => {
+                        damage.damage_all();
+                        if let Framebuffer::Indexed(_, palette) = &mut screen {
+                            palette.set_color(i, c);
+                        }
+                    }
+                    RenderingDirective::RotatePalette(range, n) => {
+                        damage.damage_all();
+                        if let Framebuffer::Indexed(_, palette) = &mut screen {
+                            palette.rotate(range, n);
+                        }
+                    }
+                    RenderingDirective::SetPrevFrameCompression(compress) => {
+                        // the next frame will be fully redrawn
+                        prev_screen = if compress {
+                            PrevFrame::Rle(RleImage::from_fn(Vec2::ZERO, |_| Color::BLACK))
+                        } else {
+                            PrevFrame::Raw(Framebuffer::Rgb(Image::new(0, 0)))
+                        };
+                    }
+
+                    RenderingDirective::SetCells(rect, new_cells) => {
+                        Damage::merge(&mut damage.rects, term.cell_pixels(rect));
+                        for (k, cell) in new_cells.into_iter().enumerate() {
+                            let k = k as i32;
+                            cells.put(rect.pos + vec2!(k % rect.size.x, k / rect.size.x), Some(cell));
+                        }
+                    }
+                    RenderingDirective::Log(msg) => {
+                        console.log(&msg);
+                        if console.shown {
+                            Damage::merge(&mut damage.rects, term.cell_pixels(console.rows(&cells)));
+                        }
+                    }
+                    RenderingDirective::ShowConsole(shown) => {
+                        Damage::merge(&mut damage.rects, term.cell_pixels(console.rows(&cells)));
+                        console.shown = shown;
+                    }
+                    RenderingDirective::ToggleConsole => {
+                        Damage::merge(&mut damage.rects, term.cell_pixels(console.rows(&cells)));
+                        console.shown = !console.shown;
+                    }
+                    RenderingDirective::SetConsoleLines(n) => {
+                        Damage::merge(&mut damage.rects, term.cell_pixels(console.rows(&cells)));
+                        console.height = n;
+                        Damage::merge(&mut damage.rects, term.cell_pixels(console.rows(&cells)));
+                    }
+                    RenderingDirective::ClearCells => {
+                        let used = cells.enumerate_pixels()
+                            .filter(|(_, c)| c.is_some())
+                            .fold(Rect::new(Vec2::ZERO, Vec2::ZERO), |r, (p, _)| r.union(&Rect::new(p, vec2!(1, 1))));
+                        Damage::merge(&mut damage.rects, term.cell_pixels(used));
+                        cells.clear(None);
+                    }
+
+                    RenderingDirective::TakeFrame(reply) => { let _ = reply.send(screen.take_rgb()); }
+                    RenderingDirective::PutFrame(img) => {
+                        screen.put_rgb(img);
+                        damage.damage_all();
+                    }
+                    RenderingDirective::StartRecording(rec) => recording = Some(rec),
+                    RenderingDirective::StopRecording(reply) => {
+                        let _ = reply.send(match recording.take() {
+                            Some(rec) => rec.finish(),
+                            None => Err("not recording".to_string())
+                        });
+                    }
+                    RenderingDirective::StartCast(mut c) => {
+                        let cell = term.mode.cell_size();
+                        report(&errors, term.out.flush());
+                        c.start(vec2!(screen_size.x / cell.x, screen_size.y / cell.y));
+                        *cast.lock().unwrap() = Some(c);
+
+                        // the cast starts with the terminal state, and the next frame is fully redrawn
+                        term.attrs = Attributes::NONE;
+                        report(&errors, write!(term.out, "{}{}", csi!("?25l"), csi!("0m"))
+                            .and_then(|_| term.write_color(term.back, false))
+                            .and_then(|_| term.write_color(term.fore, true)));
+                        prev_screen.clear();
+                        prev_cells = Image::filled(0, 0, None);
+                    }
+                    RenderingDirective::StopCast(reply) => {
+                        report(&errors, term.out.flush());
+                        let _ = reply.send(match cast.lock().unwrap().take() {
+                            Some(c) => c.finish(),
+                            None => Err("not recording a cast".to_string())
+                        });
+                    }
+                    RenderingDirective::Screenshot(reply) => {
+                        let composed = layers.compose(&screen);
+                        let _ = reply.send(composed.as_ref().unwrap_or(&screen).to_rgb());
+                    }
+
+                    RenderingDirective::RingBell => { report(&errors, write!(term.out, "\x07")); }
+                    RenderingDirective::SetTitle(kind, title) => {
+                        report(&errors, write!(term.out, "\x1b]{};{}\x07", kind, title).and_then(|_| term.out.flush()));
+                    }
+                    RenderingDirective::SetCursor(cursor) => {
+                        term.cursor = cursor;
+                        let result = match cursor {
+                            Some(_) => term.place_cursor(),
+                            None => write!(term.out, "{}", csi!("?25l"))
+                        };
+                        report(&errors, result.and_then(|_| term.out.flush()));
+                    }
+                    RenderingDirective::SetRenderMode(mode) => {
+                        term.mode = mode;
+                        let cell = mode.cell_size();
+                        let display = term.display_size(screen_size);
+                        cells = Image::filled((display.x / cell.x) as usize, (display.y / cell.y) as usize, None);
+                        report(&errors, term.update_layout(display));
+                        // the next frame will be fully redrawn
+                        prev_screen.clear();
+                        prev_cells = Image::filled(0, 0, None);
+                    }
+                    RenderingDirective::SetLetterbox(letterbox) => {
+                        term.letterbox = letterbox;
+                        // also redrawn if the layout could not be written
+                        if report(&errors, term.update_layout(term.display_size(screen_size))).unwrap_or(true) {
+                            prev_screen.clear();
+                            prev_cells = Image::filled(0, 0, None);
+                        }
+                    }
+                    RenderingDirective::SetScaling(scaling) => {
+                        term.scaling = scaling;
+                        let cell = term.mode.cell_size();
+                        let display = term.display_size(screen_size);
+                        cells = Image::filled((display.x / cell.x) as usize, (display.y / cell.y) as usize, None);
+                        report(&errors, term.update_layout(display));
+                        // the next frame will be fully redrawn
+                        prev_screen.clear();
+                        prev_cells = Image::filled(0, 0, None);
+                    }
+                    RenderingDirective::SetCamera(c) => camera = c,
+
+                    RenderingDirective::UploadTexture(id, img) => { textures.insert(id, Arc::new(img)); }
+                    RenderingDirective::FreeTexture(id) => { textures.remove(&id); }
+                    RenderingDirective::DrawBatch(directives) => {
+                        for directive in directives.into_iter().filter_map(|d| resolve_texture(&textures, d)) {
+                            let directive = camera.apply(directive);
+                            damage.track(&directive, layers.current.is_none());
+                            layers.apply(&mut screen, directive);
+                        }
+                    }
+                    directive @ RenderingDirective::DrawTexture(..) => {
+                        if let Some(directive) = resolve_texture(&textures, directive) {
+                            let directive = camera.apply(directive);
+                            damage.track(&directive, layers.current.is_none());
+                            layers.apply(&mut screen, directive);
+                        }
+                    }
+
+                    RenderingDirective::SetConfig(config) => {
+                        term.config = config;
+                        term.back = config.colors.quantize(term.back);
+                        term.fore = config.colors.quantize(term.fore);
+                        // the next frame will be fully redrawn
+                        report(&errors, term.write_color(term.back, false)
+                            .and_then(|_| term.write_color(term.fore, true))
+                            .and_then(|_| write!(term.out, "{}", csi!("2J"))));
+                        prev_screen.clear();
+                        prev_cells = Image::filled(0, 0, None);
+                    }
+
+                    RenderingDirective::UpdateScreenSize(size, term_size) => {
+                        damage.damage_all();
+                        screen_size = size;
+                        term.term_size = term_size;
+                        let display = term.display_size(size);
+                        if report(&errors, term.update_layout(display)).unwrap_or(true) {
+                            prev_screen.clear();
+                            prev_cells = Image::filled(0, 0, None);
+                        }
+                        screen.raw_resize(size); // TODO: raw_resize
+                        layers.resize(size);
+                        let cell = term.mode.cell_size();
+                        cells = Image::filled((display.x / cell.x) as usize, (display.y / cell.y) as usize, None);
+                        if let Some(cast) = cast.lock().unwrap().as_mut() {
+                            cast.resize(vec2!(display.x / cell.x, display.y / cell.y));
+                        }
+                    }
+
+                    RenderingDirective::BeginFrame => {frame_barrier.wait(); ()},
+                    RenderingDirective::PushFrame | RenderingDirective::PushRegion(_) if term.config.protocol == OutputProtocol::ITerm2 => {
+                        // the whole image is sent each time
+                        damage.take();
+                        let overlay = console.overlay(&cells);
+                        let cells = overlay.as_ref().unwrap_or(&cells);
+                        let composed = layers.compose(&screen);
+                        let composed = composed.as_ref().unwrap_or(&screen);
+                        let scaled = term.scale(composed);
+                        report(&errors, term.hide_cursor()
+                            .and_then(|_| term.print_inline_image(scaled.as_ref().unwrap_or(composed), cells))
+                            .and_then(|_| term.place_cursor())
+                            .and_then(|_| term.out.flush()));
+                    }
+                    RenderingDirective::PushFrame => {
+                        let overlay = console.overlay(&cells);
+                        let cells = overlay.as_ref().unwrap_or(&cells);
+                        let composed = layers.compose(&screen);
+                        let composed = composed.as_ref().unwrap_or(&screen);
+                        let scaled = term.scale(composed);
+                        let composed = scaled.as_ref().unwrap_or(composed);
+                        let dithered = dither_frame(composed, &term.config);
+                        let shown = dithered.as_ref().unwrap_or(composed);
+                        let mut result = term.hide_cursor();
+                        // scaling and dithering spread the changes, a resized or cleared previous frame can not be diffed
+                        match damage.take() {
+                            Some(rects) if scaled.is_none() && dithered.is_none() && prev_screen.can_update_region(shown) && prev_cells.size() == cells.size() => {
+                                for region in rects {
+                                    result = result.and_then(|_| term.push_region(shown, cells, &mut prev_screen, &mut prev_cells, region));
+                                }
+                            }
+                            _ => {
+                                result = result.and_then(|_| term.print_region(shown, cells, &prev_screen, &prev_cells, Rect::new(Vec2::ZERO, shown.size())).map(|_| ()));
+                                prev_screen.update(shown);
+                                prev_cells = cells.clone();
+                            }
+                        }
+                        if report(&errors, result.and_then(|_| term.place_cursor()).and_then(|_| term.out.flush())).is_none() {
+                            // the terminal does not show the previous frame
+                            prev_screen.clear();
+                            prev_cells = Image::filled(0, 0, None);
+                        }
+                    }
+                    RenderingDirective::PushRegion(region) => {
+                        let overlay = console.overlay(&cells);
+                        let cells = overlay.as_ref().unwrap_or(&cells);
+                        let composed = layers.compose(&screen);
+                        let composed = composed.as_ref().unwrap_or(&screen);
+                        let scaled = term.scale(composed);
+                        let composed = scaled.as_ref().unwrap_or(composed);
+                        let dithered = dither_frame(composed, &term.config);
+                        let shown = dithered.as_ref().unwrap_or(composed);
+                        let mut result = term.hide_cursor();
+                        // a scaled region does not match the pixels of the frame
+                        if !prev_screen.can_update_region(shown) || prev_cells.size() != cells.size() || scaled.is_some() {
+                            // the whole screen needs to be redrawn
+                            result = result.and_then(|_| term.print_region(shown, cells, &prev_screen, &prev_cells, Rect::new(Vec2::ZERO, shown.size())).map(|_| ()));
+                            prev_screen.update(shown);
+                            prev_cells = cells.clone();
+                        } else {
+                            result = result.and_then(|_| term.push_region(shown, cells, &mut prev_screen, &mut prev_cells, region));
+                        }
+                        if report(&errors, result.and_then(|_| term.place_cursor()).and_then(|_| term.out.flush())).is_none() {
+                            // the terminal does not show the previous frame
+                            prev_screen.clear();
+                            prev_cells = Image::filled(0, 0, None);
+                        }
+                    }
+
+                    directive => {
+                        let directive = camera.apply(directive);
+                        damage.track(&directive, layers.current.is_none());
+                        layers.apply(&mut screen, directive);
+                    }
+                }
+            }
+
+            if let Some(rec) = recording {
+                let _ = rec.finish();
+            }
+            let _ = term.out.flush();
+            if let Some(c) = cast.lock().unwrap().take() {
+                let _ = c.finish();
+            }
+        });
+
+        Ok(Renderer {
+            backend,
+            main,
+            render_mode: RenderMode::HalfBlock,
+            config,
+            camera: Camera::IDENTITY,
+            layers: HashSet::new(),
+            textures: HashMap::new(),
+            next_texture: 0,
+            cursor: Vec2::ZERO,
+            cursor_shown: false,
+            logical_size: None,
+            fixed_size: None,
+
+            building_frame: false,
+            prev_screen_size: Vec2::ZERO,
+            prev_term_size: Vec2::ZERO,
+            error: None,
+            frame: None,
+
+            redraw_mode: RedrawMode::Continuous,
+            redraw_timer: None,
+            target_fps: None,
+            last_frame: None,
+            created: Instant::now(),
+            last_push: None,
+            delta_time: Duration::ZERO,
+
+            _server_handle: Some(handle),
+            sender: rx,
+
+            frame_barrier: barrier,
+            output_error
+        })
+    }
+
+
+    /// Drops the Renderer singleton and reset terminal settings (should be called before the program ends).
+    /// The program keeps running, a later call to `Renderer::get` initializes a new renderer.
+    pub fn exit() {
+        unsafe {
+            RENDERER = None;
+        }
+    }
+
+
+    /// Returns a builder to create a Renderer owned by the application, instead of the singleton returned by
+    /// `Renderer::get`. The terminal is reset when it is droped, without exiting the program.
+    /// 
+    /// The static functions (eg. `Renderer::get_size`) are about the singleton, use the methods of the instance
+    /// instead (eg. `size`). Only one renderer should draw on a given terminal.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// let mut rdr = Renderer::builder()
+    ///     .target_fps(60)
+    ///     .color_mode(ColorDepth::Ansi256)
+    ///     .build()?;
+    /// 
+    /// rdr.begin_draw()?;
+    /// ...
+    /// rdr.end_draw()?;
+    /// ```
+    pub fn builder() -> RendererBuilder {
+        RendererBuilder {
+            terminal: None,
+            writer: None,
+            render_mode: RenderMode::HalfBlock,
+            config: RendererConfig::detect(),
+            redraw_mode: RedrawMode::Continuous,
+            target_fps: None
+        }
+    }
+
+
+    /// Returns true if the program was asked to quit with SIGINT (Ctrl+C) or SIGTERM.
+    /// 
+    /// The Renderer singleton catches those signals so that the application can stop drawing and reset the
+    /// terminal (eg. by droping its `RendererGuard`), they are also reported as `InputEvent::Quit`.
+    /// If a second signal is delivered, the terminal is restored and the program is killed (a Ctrl+C read
+    /// by `Input` while the terminal is in raw mode is not a signal, it only requests the exit).
+    pub fn exit_requested() -> bool {
+        EXIT_SIGNAL.load(Ordering::SeqCst) != 0
+    }
+
+
+    /// Returns the Renderer instance.
+    pub fn get() -> &'static mut Renderer {
+        unsafe {
+            match &mut RENDERER {
+                None => { // construct the renderer, and initialize
+                    RENDERER = Some(Renderer::init());
+                    RENDERER_GENERATION.fetch_add(1, Ordering::Relaxed);
+                    Renderer::get()
+                }
+                Some(r) => r
+            }
+        }
+    }
+
+
+    /// Returns the screen dimension.
+    /// ```
+    /// let size = Renderer::get_size();
+    /// 
+    /// size.x // width of the screen
+    /// size.y // height of the screen
+    /// ```
+    /// 
+    /// If a size was set with `set_fixed_size` or `set_logical_size`, it is returned instead of the size of the terminal.
+    pub fn get_size() -> Vec2 {
+        match (*MAIN_LOGICAL_SIZE.lock().unwrap(), *MAIN_FIXED_SIZE.lock().unwrap()) {
+            (Some(size), _) | (None, Some(size)) => size,
+            (None, None) => screen_size(term_size(MAIN_OUTPUT_FD.load(Ordering::Relaxed)), main_cell_size())
+        }
+    }
+
+
+    /// Returns the dimension of the terminal this renderer draws on (or its logical or fixed size,
+    /// see `set_logical_size` and `set_fixed_size`). Same as `Renderer::get_size` for the Renderer singleton.
+    pub fn size(&self) -> Vec2 {
+        match (self.logical_size, self.fixed_size) {
+            (Some(size), _) | (None, Some(size)) => size,
+            (None, None) => screen_size(self.backend.size(), self.render_mode.cell_size())
+        }
+    }
+
+
+    /// Frames are drawn at the logical size `size` whatever the size of the terminal, and are scaled to the terminal
+    /// according to `scaling` when they are pushed. The logical size is used from the next call to `begin_draw`.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// rdr.set_logical_size((160, 100), Scaling::Integer);
+    /// rdr.set_letterbox(Letterbox { color: Color::BLACK, center: true });
+    /// // the game always draws on 160x100 pixels
+    /// ```
+    pub fn set_logical_size<A>(&mut self, size: A, scaling: Scaling) 
+        where A: AsRef<Vec2>
+    {
+        self.logical_size = Some(*size.as_ref());
+        if self.main {
+            *MAIN_LOGICAL_SIZE.lock().unwrap() = self.logical_size;
+        }
+        self.send(RenderingDirective::SetScaling(Some(scaling)));
+    }
+
+
+    /// Frames are drawn at the size of the terminal again.
+    pub fn clear_logical_size(&mut self) {
+        self.logical_size = None;
+        if self.main {
+            *MAIN_LOGICAL_SIZE.lock().unwrap() = None;
+        }
+        self.send(RenderingDirective::SetScaling(None));
+    }
+
+
+    /// Returns the size of the frame being built, captured by `begin_draw` (or of the last frame between two frames,
+    /// zero before the first one). Unlike `Renderer::size`, it does not change if the terminal is resized during the frame.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// rdr.begin_draw()?;
+    /// let size = rdr.frame_size();
+    /// rdr.draw_rect(((0, size.y - 2), (size.x, 2)), Color::GRAY); // status bar
+    /// rdr.end_draw()?;
+    /// ```
+    pub fn frame_size(&self) -> Vec2 {
+        self.prev_screen_size
+    }
+
+
+    /// Forces the size of the screen to `size` instead of the size reported by the terminal
+    /// (eg. when rendering in a pipe or for a recording). The height should be even.
+    /// 
+    /// The new size is used from the next call to `begin_draw`.
+    pub fn set_fixed_size<A>(&mut self, size: A) 
+        where A: AsRef<Vec2>
+    {
+        self.fixed_size = Some(*size.as_ref());
+        if self.main {
+            *MAIN_FIXED_SIZE.lock().unwrap() = self.fixed_size;
+        }
+    }
+
+
+    /// Uses the size reported by the terminal again.
+    pub fn clear_fixed_size(&mut self) {
+        self.fixed_size = None;
+        if self.main {
+            *MAIN_FIXED_SIZE.lock().unwrap() = None;
+        }
+    }
+
+    
+    /// Returns false and records the error if we are not in a draw loop
+    fn can_draw(&mut self) -> bool {
+        if !self.building_frame {
+            self.error.get_or_insert(RenderError::NotDrawing);
+        }
+        self.building_frame
+    }
+
+
+    /// Sends a directive to the rendering thread, recording the error if it stopped.
+    /// The frame borrowed with `frame_mut` is given back first, so that the drawing order is kept.
+    fn send(&mut self, directive: RenderingDirective) {
+        let put = self.frame.take().map(RenderingDirective::PutFrame);
+        if put.into_iter().chain([directive]).any(|d| self.sender.send(d).is_err()) {
+            self.error.get_or_insert(RenderError::ThreadStopped);
+        }
+    }
+
+
+    /// Returns the first error recorded since the last check, if any.
+    fn take_error(&mut self) -> Result<(), RenderError> {
+        if let Some(e) = self.output_error.lock().unwrap().take() {
+            self.error.get_or_insert(RenderError::Output(e.kind()));
+        }
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => Ok(())
+        }
+    }
+
+
+    /// Starts drawing a frame.
+    /// 
+    /// Returns `RenderError::AlreadyDrawing` if called twice before an end_draw, or the error
+    /// recorded since the last frame (the frame is then not started).
+    pub fn begin_draw(&mut self) -> Result<(), RenderError> {
+        if self.building_frame {
+            return Err(RenderError::AlreadyDrawing);
+        }
+        self.take_error()?;
+
+        if let Some(fps) = self.target_fps.filter(|fps| *fps > 0) {
+            let next = self.last_frame.map(|t| t + Duration::from_secs(1) / fps);
+            if let Some(wait) = next.and_then(|t| t.checked_duration_since(Instant::now())) {
+                thread::sleep(wait);
+            }
+            self.last_frame = Some(Instant::now());
+        }
+
+        let new_size = self.size();
+        let term_size = self.backend.size();
+        if self.prev_screen_size != new_size || self.prev_term_size != term_size {
+            self.send(RenderingDirective::UpdateScreenSize(new_size, term_size));
+            self.prev_screen_size = new_size;
+            self.prev_term_size = term_size;
+        }
+
+        if CONSOLE_TOGGLED.swap(false, Ordering::SeqCst) {
+            self.send(RenderingDirective::ToggleConsole);
+        }
+
+        self.send(RenderingDirective::BeginFrame);
+        // a stopped rendering thread does not wait on the barrier
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.frame_barrier.wait();
+        // the previous frames are pushed, their errors are known
+        self.take_error()?;
+        self.building_frame = true;
+
+        Input::sample_frame();
+        Ok(())
+    }
+
+
+    /// Ends drawing a frame and pushes it to the screen.
+    /// Only the parts of the screen drawn since the previous frame are compared with it, so small changes
+    /// on a large terminal are cheap (clearing with the same color as the previous frame only erases what was drawn).
+    /// 
+    /// Returns the first error met while drawing the frame, if any.
+    pub fn end_draw(&mut self) -> Result<(), RenderError> {
+        if !self.building_frame {
+            return Err(RenderError::NotDrawing);
+        }
+        self.building_frame = false;
+        self.send(RenderingDirective::PushFrame);
+        self.update_clock();
+        self.take_error()
+    }
+
+
+    /// Measures the time since the previous frame was pushed.
+    fn update_clock(&mut self) {
+        let now = Instant::now();
+        self.delta_time = self.last_push.map_or(Duration::ZERO, |t| now - t);
+        self.last_push = Some(now);
+    }
+
+
+    /// Returns the time between the last two frames pushed (by `end_draw`, `push_region` or `with_frame`),
+    /// zero until two frames were pushed. Use it to move things at the same speed whatever the frame rate.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// player.x += speed * rdr.delta_time().as_secs_f32();
+    /// emitter.update(rdr.delta_time());
+    /// ```
+    pub fn delta_time(&self) -> Duration {
+        self.delta_time
+    }
+
+
+    /// Returns the time since the renderer was created.
+    pub fn elapsed(&self) -> Duration {
+        self.created.elapsed()
+    }
+
+
+    /// Ends drawing a frame and only pushes `region` to the screen, the rest of the frame is not updated.
+    /// Useful when the changes are known to be in a small part of the screen (eg. a blinking cursor).
+    /// 
+    /// If the screen was resized since the last push, the whole frame is pushed.
+    /// Errors are reported like `end_draw`.
+    pub fn push_region(&mut self, region: Rect) -> Result<(), RenderError> {
+        if !self.building_frame {
+            return Err(RenderError::NotDrawing);
+        }
+        self.building_frame = false;
+        self.send(RenderingDirective::PushRegion(region));
+        self.update_clock();
+        self.take_error()
+    }
+
+
+    /// Draws a frame with `f` and pushes it to the screen: `begin_draw` and `end_draw` are called around `f`,
+    /// so they can not be forgotten or called twice. Returns the value returned by `f`.
+    /// 
+    /// Returns the errors of `begin_draw` (`f` is then not called) and of `end_draw`.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// rdr.with_frame(|frame| {
+    ///     frame.clear_screen(Color::BLACK);
+    ///     frame.draw_text((1, 1), "Hello", Color::WHITE);
+    /// })?;
+    /// ```
+    pub fn with_frame<F, R>(&mut self, f: F) -> Result<R, RenderError>
+        where F: FnOnce(&mut Frame) -> R
+    {
+        self.begin_draw()?;
+        let mut frame = Frame::new();
+        let result = f(&mut frame);
+
+        for directive in frame.directives {
+            match &directive {
+                RenderingDirective::SetCamera(c) => self.camera = *c,
+                RenderingDirective::CreateLayer(name, _) => { self.layers.insert(name.clone()); }
+                RenderingDirective::RemoveLayer(name) => { self.layers.remove(name); }
+                _ => ()
+            }
+            self.send(directive);
+        }
+        self.end_draw().map(|_| result)
+    }
+
+
+    /// Gives direct access to the frame that is being built, to use any `Image` method on it.
+    /// The drawing functions called before are already applied, the ones called after are drawn over.
+    /// 
+    /// This is the main layer in screen coordinates: the camera and the current layer are ignored.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// rdr.begin_draw()?;
+    /// rdr.draw_rect(Rect::new((0, 0), (10, 10)), Color::RED);
+    /// 
+    /// let frame = rdr.frame_mut()?;
+    /// frame.convolve(&Kernel::emboss());
+    /// frame.flip_h();
+    /// 
+    /// rdr.end_draw()?;
+    /// ```
+    pub fn frame_mut(&mut self) -> Result<&mut Image, RenderError> {
+        if !self.building_frame {
+            return Err(RenderError::NotDrawing);
+        }
+
+        if self.frame.is_none() {
+            let (reply, recv) = mpsc::channel();
+            self.send(RenderingDirective::TakeFrame(reply));
+            match recv.recv() {
+                Ok(img) => self.frame = Some(img),
+                Err(_) => return Err(RenderError::ThreadStopped)
+            }
+        }
+        Ok(self.frame.as_mut().unwrap())
+    }
+
+
+    /// Starts recording the frames pushed to the screen into an animated image at `path`,
+    /// the format is chosen from the extension: `.gif` for GIF, `.png` or `.apng` for APNG.
+    /// 
+    /// The frames are encoded as they are pushed, the file is completed by `stop_recording` (or when the renderer is droped).
+    /// Starting a new recording discards the current one.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// rdr.start_recording("demo.gif")?;
+    /// 
+    /// for _ in 0..60 {
+    ///     rdr.begin_draw()?;
+    ///     ...
+    ///     rdr.end_draw()?;
+    /// }
+    /// 
+    /// rdr.stop_recording()?;
+    /// ```
+    pub fn start_recording<P>(&mut self, path: P) -> Result<(), String>
+        where P: AsRef<Path>
+    {
+        let rec = Recording::new(path)?;
+        self.send(RenderingDirective::StartRecording(rec));
+        Ok(())
+    }
+
+
+    /// Stops the recording started with `start_recording` and completes the file.
+    /// Returns an error (and removes the file) if no frame was pushed since the recording started.
+    pub fn stop_recording(&mut self) -> Result<(), String> {
+        let (reply, recv) = mpsc::channel();
+        self.send(RenderingDirective::StopRecording(reply));
+        match recv.recv() {
+            Ok(result) => result,
+            Err(_) => Err(RenderError::ThreadStopped.into())
+        }
+    }
+
+
+    /// Starts recording the output sent to the terminal into an asciinema v2 cast at `path`, with the time of each frame,
+    /// so that the session can be replayed exactly as it appeared (eg. with `asciinema play`).
+    /// The first frame of the cast is fully redrawn.
+    pub fn start_cast<P>(&mut self, path: P) -> Result<(), String>
+        where P: AsRef<Path>
+    {
+        let cast = Cast::create(path)?;
+        self.send(RenderingDirective::StartCast(cast));
+        Ok(())
+    }
+
+
+    /// Stops the cast started with `start_cast` and closes the file.
+    pub fn stop_cast(&mut self) -> Result<(), String> {
+        let (reply, recv) = mpsc::channel();
+        self.send(RenderingDirective::StopCast(reply));
+        match recv.recv() {
+            Ok(result) => result,
+            Err(_) => Err(RenderError::ThreadStopped.into())
+        }
+    }
+
+
+    /// Returns the content of the screen composited with the layers, as it is displayed.
+    /// Between two frames, this is the last pushed frame.
+    pub fn screenshot(&mut self) -> Result<Image, RenderError> {
+        let (reply, recv) = mpsc::channel();
+        self.send(RenderingDirective::Screenshot(reply));
+        recv.recv().map_err(|_| RenderError::ThreadStopped)
+    }
+
+
+    /// Sets all the pixels' color in the screen to `c`.
+    pub fn clear_screen(&mut self, c: Color) {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::ClearScreen(c));
+    }
+
+
+    /// Draws a line of color `c` between `p1` and `p2`.
+    pub fn draw_line<A, B>(&mut self, p1: A, p2: B, c: Color) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>
+    {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawLine(*p1.as_ref(), *p2.as_ref(), c));
+    }
+
+
+    /// Draws the rectangle `r` filled with the color `c`.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// rdr.draw_rect(Rect::new((2, 2), (10, 4)), Color::RED);
+    /// rdr.draw_rect(((2, 2), (10, 4)), Color::RED); // same as above
+    /// ```
+    pub fn draw_rect<R>(&mut self, r: R, c: Color) 
+        where R: Into<Rect>
+    {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawRect(r.into(), c));
+    }
+
+
+    /// Same as `draw_rect` but draws only the four sides of the rectangle.
+    pub fn draw_rect_boundary<R>(&mut self, r: R, c: Color) 
+        where R: Into<Rect>
+    {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawRectBoudary(r.into(), c));
+    }
+
+
+    /// Draws an ellipse of color `col`. `c` is the center of the ellipse and `s` is the size of the rectangle
+    /// in which the ellipse is inscribed.
+    pub fn draw_ellipse_boundary<A, B>(&mut self, c: A, s: B, col: Color) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>
+    {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawEllipseBoudary(*c.as_ref(), *s.as_ref(), col));
+    }
+
+
+    /// Draws a filled ellipse of color `col`. `c` is the center of the ellipse and `s` is the size of the rectangle
+    /// in which the ellipse is inscribed.
+    pub fn draw_ellipse<A, B>(&mut self, c: A, s: B, col: Color) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>
+    {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawEllipse(*c.as_ref(), *s.as_ref(), col));
+    }
+
+
+    /// Draws a circle of color `col`, of center `c` and of radius `r`.
+    pub fn draw_circle_boundary<A>(&mut self, c: A, r: i32, col: Color) 
+        where A: AsRef<Vec2>
+    {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawCircleBoundary(*c.as_ref(), r, col));
+    }
+
+
+    /// Draws a filled circle of color `col`, of center `c` and of radius `r`.
+    pub fn draw_circle<A>(&mut self, c: A, r: i32, col: Color) 
+        where A: AsRef<Vec2>
+    {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawCircle(*c.as_ref(), r, col));
+    }
+
+
+    /// Draws the part of the circle of center `c` and of radius `r` that goes from `start` to `end` (in radians).
+    /// Angles start on the right of the circle and go clockwise.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// // gauge filled at 75%
+    /// rdr.draw_arc((20, 20), 10, PI, PI + 0.75 * PI, Color::GREEN);
+    /// ```
+    pub fn draw_arc<A>(&mut self, c: A, r: i32, start: f32, end: f32, col: Color) 
+        where A: AsRef<Vec2>
+    {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawArc(*c.as_ref(), r, start, end, col));
+    }
+
+
+    /// Draws a filled triangle of color `c` with the vertices `p0`, `p1` and `p2`.
+    pub fn draw_triangle<A, B, C>(&mut self, p0: A, p1: B, p2: C, c: Color) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>, C: AsRef<Vec2>
+    {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawTriangle(*p0.as_ref(), *p1.as_ref(), *p2.as_ref(), c));
+    }
+
+
+    /// Draws a filled polygon of color `c` with the vertices `points`. The polygon can be concave or self-intersecting,
+    /// pixels are filled using the even-odd rule.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// // arrow
+    /// rdr.draw_polygon(&[vec2!(0, 2), vec2!(4, 2), vec2!(4, 0), vec2!(8, 4), vec2!(4, 8), vec2!(4, 6), vec2!(0, 6)], Color::RED);
+    /// ```
+    pub fn draw_polygon(&mut self, points: &[Vec2], c: Color) {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawPolygon(points.to_vec(), c));
+    }
+
+
+    /// Draws lines of color `c` between the consecutive points of `points`.
+    pub fn draw_polyline(&mut self, points: &[Vec2], c: Color) {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawPath(points.to_vec(), false, c));
+    }
+
+
+    /// Draws the sides of the polygon with the vertices `points` (`draw_polyline` that goes back to the first point).
+    pub fn draw_polygon_boundary(&mut self, points: &[Vec2], c: Color) {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawPath(points.to_vec(), true, c));
+    }
+
+
+    /// Draws `text` with the embedded bitmap font (see the `text` module), `pos` is the top left corner of the text.
+    /// Lines are separated by `\n`.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// let size = text::text_size("GAME OVER");
+    /// rdr.draw_text((Renderer::get_size() - size) / 2, "GAME OVER", Color::RED);
+    /// ```
+    pub fn draw_text<A>(&mut self, pos: A, text: &str, c: Color) 
+        where A: AsRef<Vec2>
+    {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawText(*pos.as_ref(), text.to_string(), c));
+    }
+
+
+    /// Draws `text` with `font` at a size of `px` pixels, `pos` is the top left corner of the text.
+    /// Lines are separated by `\n` and the edges of the glyphs are antialiased.
+    /// Only available with the `ttf` feature.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// let font = Arc::new(text::Font::load("title.ttf")?);
+    /// let size = font.text_size("GAME OVER", 32.);
+    /// rdr.draw_ttf_text((Renderer::get_size() - size) / 2, "GAME OVER", font.clone(), 32., Color::RED);
+    /// ```
+    #[cfg(feature = "ttf")]
+    pub fn draw_ttf_text<A>(&mut self, pos: A, text: &str, font: Arc<Font>, px: f32, c: Color) 
+        where A: AsRef<Vec2>
+    {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawTtfText(*pos.as_ref(), text.to_string(), font, px, c));
+    }
+
+
+    /// Sets the color of the pixel at `p` to `c`.
+    pub fn draw_point<A>(&mut self, p: A, c: Color) 
+        where A: AsRef<Vec2>
+    {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawPoint(*p.as_ref(), c));
+    }
+
+
+    /// Sets the color of each pixel of `points`, with a single command sent to the rendering thread.
+    pub fn draw_points(&mut self, points: &[(Vec2, Color)]) {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawPoints(points.to_vec()));
+    }
+
+
+    /// Draws the particles of `emitter` as a batch of points (see `particles::Emitter`).
+    pub fn draw_particles(&mut self, emitter: &Emitter) {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawPoints(emitter.points()));
+    }
+
+
+    /// Replaces the color of each pixel of `region` by `shader(pos, color)`, where `pos` is the position of the pixel
+    /// and `color` its current color, like a fragment shader. `region` is in screen coordinates (the camera is ignored)
+    /// and only the current layer is shaded.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// // vignette
+    /// let size = rdr.size();
+    /// rdr.shade(Rect::new(Vec2::ZERO, size), move |p, c| {
+    ///     let (dx, dy) = ((p.x - size.x / 2) as f32 / size.x as f32, (p.y - size.y / 2) as f32 / size.y as f32);
+    ///     c.darken(dx * dx + dy * dy)
+    /// });
+    /// ```
+    pub fn shade<R, F>(&mut self, region: R, shader: F) 
+        where R: Into<Rect>, F: Fn(Vec2, Color) -> Color + Send + 'static
+    {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::Shade(region.into(), Box::new(shader)));
+    }
+
+
+    /// Draws a rectangle of size `s` filled with a linear gradient from `c1` to `c2`.
+    /// `p` is the coordinate of the top left corner of the rectangle, `angle` is the direction of the gradient
+    /// in radians (0 goes from left to right, PI/2 from top to bottom).
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// // sky
+    /// rdr.draw_rect_gradient((0, 0), Renderer::get_size(), Color::DEEP_SKY_BLUE, Color::WHITE, std::f32::consts::FRAC_PI_2);
+    /// ```
+    pub fn draw_rect_gradient<A, B>(&mut self, p: A, s: B, c1: Color, c2: Color, angle: f32) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>
+    {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawRectGradient(*p.as_ref(), *s.as_ref(), c1, c2, angle));
+    }
+
+
+    /// Draws an ellipse filled with a radial gradient, from `c1` at the center to `c2` on the boundary.
+    /// `c` is the center of the ellipse and `s` is the size of the rectangle in which the ellipse is inscribed.
+    pub fn draw_ellipse_gradient<A, B>(&mut self, c: A, s: B, c1: Color, c2: Color) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>
+    {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawEllipseGradient(*c.as_ref(), *s.as_ref(), c1, c2));
+    }
+
+
+    /// Draws an image at position `pos`. 
+    /// 
+    /// Negative size results in flipped image. Alpha is used to ignore a given color while drawing.
+    /// The image is combined with the frame using `mode` (eg. `BlendMode::Add` for lights).
+    /// 
+    /// The render thread only reads the image, so it is shared without being copied or locked:
+    /// ```
+    /// let sprite = Arc::new(Image::load("sprite.png")?);
+    /// rdr.draw_image(Arc::clone(&sprite), pos, sprite.size(), Vec2::ZERO, None, BlendMode::Normal);
+    /// ```
+    pub fn draw_image<A, B, C>(&mut self, 
+        img: Arc<Image>, pos: A, size: B, offset: C, alpha: Option<Color>, mode: BlendMode) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>, C: AsRef<Vec2>
+    {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawImage(img, *pos.as_ref(), *size.as_ref(), *offset.as_ref(), Flip::None, alpha, mode));
+    }
+
+
+    /// Draws the frame `frame` (its index or its name) of `sheet` at `pos`.
+    /// 
+    /// Panics if the frame does not exist.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// let sheet = SpriteSheet::load("tiles.png", (8, 8))?;
+    /// rdr.draw_sprite(&sheet, 3, (0, 0));
+    /// ```
+    pub fn draw_sprite<K, A>(&mut self, sheet: &SpriteSheet, frame: K, pos: A) 
+        where K: FrameKey, A: AsRef<Vec2>
+    {
+        let rect = sheet.frame(frame).expect("no such frame in the sprite sheet");
+        self.draw_image(sheet.image(), pos, rect.size, rect.pos, None, BlendMode::Normal);
+    }
+
+
+    /// Draws the frame of `anim` shown `elapsed` after the start of the animation at `pos`
+    /// (see `sprite::Animation`).
+    pub fn draw_animation<A>(&mut self, anim: &Animation, pos: A, elapsed: Duration) 
+        where A: AsRef<Vec2>
+    {
+        self.draw_sprite(anim.sheet(), anim.frame_at(elapsed), pos);
+    }
+
+
+    /// Draws the part of an image at `offset` of size `size` at position `pos`, mirrored according to `flip`.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// let flip = if facing_left { Flip::Horizontal } else { Flip::None };
+    /// rdr.draw_image_flipped(sprite.clone(), pos, (16, 16), Vec2::ZERO, flip, None, BlendMode::Normal);
+    /// ```
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_image_flipped<A, B, C>(&mut self, 
+        img: Arc<Image>, pos: A, size: B, offset: C, flip: Flip, alpha: Option<Color>, mode: BlendMode) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>, C: AsRef<Vec2>
+    {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawImage(img, *pos.as_ref(), *size.as_ref(), *offset.as_ref(), flip, alpha, mode));
+    }
+
+
+    /// Draws the part `src` of an image stretched over the rectangle `dst` of the frame,
+    /// pixels are sampled using `filter`.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// // draws the whole sprite three times bigger
+    /// let size = sprite.size();
+    /// rdr.draw_scaled_image(sprite.clone(), Rect::new((0, 0), size), Rect::new(pos, size * 3), Filter::Nearest, BlendMode::Normal);
+    /// ```
+    pub fn draw_scaled_image(&mut self, img: Arc<Image>, src: Rect, dst: Rect, filter: Filter, mode: BlendMode) {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawScaledImage(img, src, dst, filter, Flip::None, None, mode));
+    }
+
+
+    /// Sets the camera applied to the next draw calls (see `Camera`), until another camera is set.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// rdr.begin_draw();
+    /// rdr.set_camera(Camera::new(player_pos - Renderer::get_size() / 2, 1.));
+    /// ... // draw the world in world coordinates
+    /// rdr.set_camera(Camera::IDENTITY);
+    /// ... // draw the UI in screen coordinates
+    /// rdr.end_draw();
+    /// ```
+    pub fn set_camera(&mut self, camera: Camera) {
+        self.camera = camera;
+        self.send(RenderingDirective::SetCamera(camera));
+    }
+
+
+    /// Returns the current camera.
+    pub fn get_camera(&self) -> Camera {
+        self.camera
+    }
+
+
+    /// Creates a layer named `name`. Layers are transparent images drawn over the main layer
+    /// (named `MAIN_LAYER`) by increasing `z` when the frame is pushed, they keep their content between frames.
+    /// If the layer already exists, only its z is changed.
+    /// 
+    /// Layers are cleared when the terminal is resized.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// rdr.create_layer("ui", 10);
+    /// 
+    /// rdr.begin_draw();
+    /// rdr.set_layer(MAIN_LAYER);
+    /// game.draw(&mut rdr);
+    /// rdr.set_layer("ui");
+    /// rdr.clear_layer();
+    /// ui.draw(&mut rdr);
+    /// rdr.end_draw();
+    /// ```
+    pub fn create_layer(&mut self, name: &str, z: i32) {
+        if name == MAIN_LAYER {
+            panic!("the main layer can not be created");
+        }
+        self.layers.insert(name.to_string());
+        self.send(RenderingDirective::CreateLayer(name.to_string(), z));
+    }
+
+
+    /// Removes the layer named `name`. If it was the current layer, the next draw calls go to the main layer.
+    pub fn remove_layer(&mut self, name: &str) {
+        self.layers.remove(name);
+        self.send(RenderingDirective::RemoveLayer(name.to_string()));
+    }
+
+
+    /// The next draw calls go to the layer named `name` (`MAIN_LAYER` for the main layer).
+    /// 
+    /// Panics if there is no such layer.
+    pub fn set_layer(&mut self, name: &str) {
+        if name != MAIN_LAYER && !self.layers.contains(name) {
+            panic!("no layer named {:?}", name);
+        }
+        self.send(RenderingDirective::SetLayer(name.to_string()));
+    }
+
+
+    /// Changes the z of the layer named `name`.
+    pub fn set_layer_z(&mut self, name: &str, z: i32) {
+        self.send(RenderingDirective::SetLayerZ(name.to_string(), z));
+    }
+
+
+    /// Makes the current layer fully transparent (the main layer is cleared to black).
+    pub fn clear_layer(&mut self) {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::ClearLayer);
+    }
+
+
+    /// Sets the opacity (between 0 and 1) of the layer named `name` when the layers are composited.
+    /// The main layer (`MAIN_LAYER`) is drawn over black with its opacity. Default is 1.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// // dims the game behind the pause menu, without redrawing it
+    /// rdr.set_layer_opacity(MAIN_LAYER, 0.4);
+    /// ```
+    pub fn set_layer_opacity(&mut self, name: &str, opacity: f32) {
+        self.send(RenderingDirective::SetLayerOpacity(name.to_string(), opacity));
+    }
+
+
+    /// Sets how the layer named `name` is combined with the layers below (eg. `BlendMode::Multiply` for a shadow layer).
+    /// Default is `BlendMode::Normal`, it has no effect on the main layer.
+    pub fn set_layer_blend_mode(&mut self, name: &str, mode: BlendMode) {
+        self.send(RenderingDirective::SetLayerBlendMode(name.to_string(), mode));
+    }
+
+
+    /// Draws the whole image at `pos`, ignoring the color `alpha`.
+    /// 
+    /// Equivalent to:
+    /// ```
+    /// rdr.image(img, pos, img.size(), Vec2::ZERO, Some(alpha), BlendMode::Normal);
+    /// ```
+    pub fn draw_whole_image_alpha<A>(&mut self, img: Arc<Image>, pos: A, alpha: Color) 
+        where A: AsRef<Vec2>
+    {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawWholeImageAlpha(img, *pos.as_ref(), alpha));
+    }
+
+
+    /// Draws the whole image at `pos`.
+    /// 
+    /// Equivalent to:
+    /// ```
+    /// rdr.image(img, pos, img.size(), Vec2::ZERO, None, BlendMode::Normal);
+    /// ```
+    pub fn draw_whole_image<A>(&mut self, img: Arc<Image>, pos: A) 
+        where A: AsRef<Vec2>
+    {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawWholeImage(img, *pos.as_ref()));
+    }
+
+
+    /// Draws the rows of a streamed image that were already received at `pos` (see `img::image_stream`).
+    pub fn draw_streamed_image<A>(&mut self, img: Arc<Mutex<StreamedImage>>, pos: A) 
+        where A: AsRef<Vec2>
+    {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawStreamedImage(img, *pos.as_ref()));
+    }
+
+
+    /// Draws an image with an alpha channel at position `pos`, blending it with the frame.
+    /// 
+    /// Negative size results in flipped image.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// let sprite = Arc::new(Image::load_rgba("sprite.png").unwrap());
+    /// 
+    /// rdr.draw_rgba_image(Arc::clone(&sprite), (10, 4), (16, 16), (0, 0));
+    /// ```
+    pub fn draw_rgba_image<A, B, C>(&mut self, img: Arc<Image<Rgba>>, pos: A, size: B, offset: C) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>, C: AsRef<Vec2>
+    {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawRgbaImage(img, *pos.as_ref(), *size.as_ref(), *offset.as_ref()));
+    }
+
+
+    /// Sends all the `commands` to the rendering thread in a single message, they are drawn in order.
+    /// This is much cheaper than one draw call per command for scenes with thousands of tiles or points.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// let commands: Vec<DrawCommand> = map.tiles()
+    ///     .map(|(pos, tile)| DrawCommand::Texture(tileset, pos * 8, (8, 8).into(), tile.offset(), None, BlendMode::Normal))
+    ///     .collect();
+    /// rdr.submit(&commands);
+    /// ```
+    pub fn submit(&mut self, commands: &[DrawCommand]) {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawBatch(commands.iter().cloned().map(RenderingDirective::from).collect()));
+    }
+
+
+    /// Moves `img` to the rendering thread and returns a handle to draw it with `draw_texture`.
+    /// Drawing a texture only sends its handle, which is cheaper than sharing the image for every draw call
+    /// (useful for static sprites and tilesets). Textures can be uploaded outside of a frame build.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// let tiles = rdr.upload_texture(Image::load("tiles.png")?);
+    /// 
+    /// rdr.begin_draw();
+    /// rdr.draw_texture(tiles, (0, 0), (8, 8), (16, 0), None, BlendMode::Normal);
+    /// rdr.end_draw();
+    /// ```
+    pub fn upload_texture(&mut self, img: Image) -> TextureId {
+        let id = TextureId(self.next_texture);
+        self.next_texture += 1;
+        self.textures.insert(id, img.size());
+        self.send(RenderingDirective::UploadTexture(id, img));
+        id
+    }
+
+
+    /// Removes the texture `id` from the rendering thread, drawing it afterwards does nothing.
+    pub fn free_texture(&mut self, id: TextureId) {
+        if self.textures.remove(&id).is_some() {
+            self.send(RenderingDirective::FreeTexture(id));
+        }
+    }
+
+
+    /// Returns the size of the texture `id`, None if there is no such texture.
+    pub fn texture_size(&self, id: TextureId) -> Option<Vec2> {
+        self.textures.get(&id).copied()
+    }
+
+
+    /// Same as `draw_image` but draws the texture `id` (see `upload_texture`). Does nothing if there is no such texture.
+    pub fn draw_texture<A, B, C>(&mut self, id: TextureId, pos: A, size: B, offset: C, alpha: Option<Color>, mode: BlendMode) 
+        where A: AsRef<Vec2>, B: AsRef<Vec2>, C: AsRef<Vec2>
+    {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::DrawTexture(id, *pos.as_ref(), *size.as_ref(), *offset.as_ref(), alpha, mode));
+    }
+
+
+    /// Draws the whole texture `id` at `pos`.
+    pub fn draw_whole_texture<A>(&mut self, id: TextureId, pos: A) 
+        where A: AsRef<Vec2>
+    {
+        if let Some(size) = self.texture_size(id) {
+            self.draw_texture(id, pos, size, Vec2::ZERO, None, BlendMode::Normal);
+        }
+    }
+
+
+
+    /// Draws a frame in an offscreen image of size `size` instead of the screen. 
+    /// The draw commands go through the same pipeline as the frames pushed to the screen.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// let img = Renderer::render_to_image((32, 32), |frame| {
+    ///     frame.clear_screen(Color::BLACK);
+    ///     frame.draw_line((0, 0), (31, 31), Color::WHITE);
+    /// });
+    /// ```
+    pub fn render_to_image<A, F>(size: A, f: F) -> Image
+        where A: AsRef<Vec2>, F: FnOnce(&mut Frame)
+    {
+        let size = size.as_ref();
+        let mut frame = Frame::new();
+        f(&mut frame);
+
+        let mut screen = Framebuffer::Rgb(Image::new(size.x.max(0) as usize, size.y.max(0) as usize));
+        let mut camera = Camera::IDENTITY;
+        let mut layers = Layers::new();
+        for directive in frame.directives {
+            match directive {
+                RenderingDirective::SetCamera(c) => camera = c,
+                directive => layers.apply(&mut screen, camera.apply(directive))
+            }
+        }
+        layers.compose(&screen).unwrap_or(screen).to_rgb()
+    }
+
+
+    /// Sets the redraw mode. Default is `RedrawMode::Continuous`.
+    pub fn set_redraw_mode(&mut self, mode: RedrawMode) {
+        self.redraw_mode = mode;
+    }
+
+
+    /// Returns the current redraw mode.
+    pub fn get_redraw_mode(&self) -> RedrawMode {
+        self.redraw_mode
+    }
+
+
+    /// Sets the maximum time `wait_redraw` will wait for in `RedrawMode::OnEvent`.
+    /// `None` means no timer (wait until an event occurs).
+    pub fn set_redraw_timer(&mut self, timer: Option<Duration>) {
+        self.redraw_timer = timer;
+    }
+
+
+    /// Limits the number of frames per second: `begin_draw` waits until `1 / fps` seconds passed since the
+    /// previous frame. `None` means no limit (default).
+    pub fn set_target_fps(&mut self, fps: Option<u32>) {
+        self.target_fps = fps;
+        self.last_frame = None;
+    }
+
+
+    /// Returns the frame rate limit.
+    pub fn get_target_fps(&self) -> Option<u32> {
+        self.target_fps
+    }
+
+
+    /// Asks for a redraw, waking up `wait_redraw`. Can be called from any thread.
+    pub fn request_redraw() {
+        let (lock, cvar) = &REDRAW_REQUEST;
+        *lock.lock().unwrap() = true;
+        cvar.notify_all();
+    }
+
+
+    /// Blocks until a frame needs to be drawn. Never blocks in `RedrawMode::Continuous`.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// rdr.set_redraw_mode(RedrawMode::OnEvent);
+    /// loop {
+    ///     rdr.wait_redraw();
+    ///     
+    ///     ... // handle input
+    /// 
+    ///     rdr.begin_draw();
+    ///     ...
+    ///     rdr.end_draw();
+    /// }
+    /// ```
+    pub fn wait_redraw(&self) {
+        if self.redraw_mode == RedrawMode::Continuous {
+            return;
+        }
+
+        let deadline = self.redraw_timer.map(|t| Instant::now() + t);
+        let (lock, cvar) = &REDRAW_REQUEST;
+        let mut requested = lock.lock().unwrap();
+
+        while !*requested {
+            let mut timeout = RESIZE_POLL_INTERVAL;
+            if let Some(deadline) = deadline {
+                let now = Instant::now();
+                if now >= deadline { break; }
+                timeout = timeout.min(deadline - now);
+            }
+
+            requested = cvar.wait_timeout(requested, timeout).unwrap().0;
+
+            if self.size() != self.prev_screen_size { break; }
+        }
+        *requested = false;
+    }
+
+
+    /// Sets the cells in `rect` (in cell coordinates, see `RenderMode::cell_size`).
+    /// `cells` are given row by row and are drawn over the pixels of the frame until `clear_cells` is called.
+    /// 
+    /// Characters should be one column wide (see `draw_cell` for wide characters). Cells are cleared when the terminal is resized.
+    /// 
+    /// Panics if the length of `cells` does not match the size of `rect`.
+    pub fn set_cells(&mut self, rect: Rect, cells: &[Cell]) {
+        if !self.can_draw() { return; }
+        if rect.size.x < 0 || rect.size.y < 0 || cells.len() != (rect.size.x * rect.size.y) as usize {
+            panic!("the number of cells does not match the size of the rect");
+        }
+        self.send(RenderingDirective::SetCells(rect, cells.to_vec()));
+    }
+
+
+    /// Draws the character `ch` in the cell `cell_pos` (in cell coordinates, see `RenderMode::cell_size`),
+    /// with the foreground color `fg` and the background color `bg`. The pixels under the cell are not drawn.
+    /// 
+    /// Any character can be used (box drawing, braille, emoji...). Wide characters also cover the next cell.
+    /// The cell stays over the pixels of the frame until `clear_cells` is called.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// rdr.draw_cell((0, 0), '┌', Color::WHITE, Color::BLACK);
+    /// rdr.draw_cell((1, 0), '🍎', Color::RED, Color::BLACK);
+    /// ```
+    pub fn draw_cell<A>(&mut self, cell_pos: A, ch: char, fg: Color, bg: Color) 
+        where A: AsRef<Vec2>
+    {
+        let cell = Cell::new(ch, fg, bg);
+        if glyph::is_wide(ch) {
+            self.set_cells(Rect::new(*cell_pos.as_ref(), (2, 1)), &[cell, cell.continuation()]);
+        } else {
+            self.set_cells(Rect::new(*cell_pos.as_ref(), (1, 1)), &[cell]);
+        }
+    }
+
+
+    /// Writes `text` in the cells layer with real terminal characters, starting at the cell `pos`
+    /// (in cell coordinates, see `RenderMode::cell_size`). Lines are separated by `\n`.
+    /// 
+    /// The text stays over the pixels of the frame until `clear_cells` is called.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// rdr.overlay_text((1, 0), &format!("Score: {}", score), TextStyle::new(Color::WHITE, Color::BLACK));
+    /// ```
+    pub fn overlay_text<A>(&mut self, pos: A, text: &str, style: TextStyle) 
+        where A: AsRef<Vec2>
+    {
+        let pos = pos.as_ref();
+        for (j, line) in text.split('\n').enumerate() {
+            let cells: Vec<Cell> = line.chars().map(|ch| style.cell(ch)).collect();
+            let rect = Rect::new(vec2!(pos.x, pos.y + j as i32), vec2!(cells.len() as i32, 1));
+            self.set_cells(rect, &cells);
+        }
+    }
+
+
+    /// Removes all the cells set with `set_cells`.
+    pub fn clear_cells(&mut self) {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::ClearCells);
+    }
+
+
+    /// Sets how pixels are drawn in the terminal cells. This changes the size of the screen
+    /// (see `RenderMode::cell_size`) from the next call to `begin_draw`. Default is `RenderMode::HalfBlock`.
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.render_mode = mode;
+        if self.main {
+            *MAIN_CELL_SIZE.lock().unwrap() = mode.cell_size();
+        }
+        self.send(RenderingDirective::SetRenderMode(mode));
+    }
+
+
+    /// Returns the current render mode.
+    pub fn get_render_mode(&self) -> RenderMode {
+        self.render_mode
+    }
+
+
+    /// Sets the output settings of the renderer. The default settings are given by `RendererConfig::detect`.
+    pub fn set_config(&mut self, config: RendererConfig) {
+        self.config = config;
+        self.send(RenderingDirective::SetConfig(config));
+    }
+
+
+    /// Returns the output settings of the renderer.
+    pub fn get_config(&self) -> RendererConfig {
+        self.config
+    }
+
+
+    /// Switches to the indexed mode: the frame is stored as indices in `palette` instead of colors.
+    /// This uses less memory and makes the diff faster on big terminals, and changing a color
+    /// of the palette (see `set_palette_color`) instantly changes all the pixels using it.
+    /// 
+    /// Colors that are drawn are replaced by the closest color in the palette.
+    /// 
+    /// Panics if the palette is empty or has more than 256 colors.
+    pub fn enable_indexed_mode<P: Into<Palette>>(&mut self, palette: P) {
+        let palette = palette.into();
+        if palette.is_empty() || palette.len() > 256 {
+            panic!("the palette should have between 1 and 256 colors");
+        }
+        self.send(RenderingDirective::SetPalette(Some(palette)));
+    }
+
+
+    /// Switches back to a frame of colors.
+    pub fn disable_indexed_mode(&mut self) {
+        self.send(RenderingDirective::SetPalette(None));
+    }
+
+
+    /// Sets the color at index `i` in the palette. Does nothing if not in indexed mode
+    /// or if `i` is out of the palette.
+    pub fn set_palette_color(&mut self, i: u8, c: Color) {
+        self.send(RenderingDirective::SetPaletteColor(i, c));
+    }
+
+
+    /// Rotates the colors of the palette at the indices `range` by `n` steps towards the higher indices
+    /// (see `Palette::rotate`). Does nothing if not in indexed mode.
+    /// 
+    /// Only the cells whose colors changed are sent to the terminal with the next frame, so palette cycling
+    /// animations (water, fire, ...) do not need to draw anything.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// // the colors 16 to 23 are the waves of the sea
+    /// rdr.rotate_palette(16..24, 1);
+    /// rdr.begin_draw();
+    /// rdr.end_draw();
+    /// ```
+    pub fn rotate_palette(&mut self, range: Range<usize>, n: i32) {
+        self.send(RenderingDirective::RotatePalette(range, n));
+    }
+
+
+    /// Stores the last pushed frame run-length encoded instead of as a raw buffer.
+    /// This reduces memory usage on very big terminals, at a small cost when diffing frames.
+    /// 
+    /// Changing this option redraws the whole screen on the next frame.
+    pub fn set_prev_frame_compression(&mut self, compress: bool) {
+        self.send(RenderingDirective::SetPrevFrameCompression(compress));
+    }
+
+
+    /// Sets how the terminal is filled around the frame when the frame is smaller than the terminal
+    /// (eg. with `Renderer::set_fixed_size`). Default is black, with the frame in the top left corner.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// rdr.set_fixed_size((160, 90));
+    /// rdr.set_letterbox(Letterbox { color: Color::DARK_SLATE_GRAY, center: true });
+    /// ```
+    pub fn set_letterbox(&mut self, letterbox: Letterbox) {
+        self.send(RenderingDirective::SetLetterbox(letterbox));
+    }
+
+
+    /// Shows the terminal cursor in the cell set with `set_cursor_cell` (the top left cell by default).
+    /// The cursor is hidden by default, it is put back in its cell after each frame.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// // command bar on the last row, the cursor follows the typed text
+    /// rdr.set_cursor_cell(1 + input.len() as i32, rows - 1);
+    /// rdr.show_cursor();
+    /// ```
+    pub fn show_cursor(&mut self) {
+        self.cursor_shown = true;
+        self.send(RenderingDirective::SetCursor(Some(self.cursor)));
+    }
+
+
+    /// Hides the terminal cursor.
+    pub fn hide_cursor(&mut self) {
+        self.cursor_shown = false;
+        self.send(RenderingDirective::SetCursor(None));
+    }
+
+
+    /// Moves the terminal cursor to the cell of column `x` and row `y` (in cells, not pixels).
+    pub fn set_cursor_cell(&mut self, x: i32, y: i32) {
+        self.cursor = vec2!(x, y);
+        if self.cursor_shown {
+            self.send(RenderingDirective::SetCursor(Some(self.cursor)));
+        }
+    }
+
+
+    /// Sets the title of the terminal window or tab. Control characters are removed from `title`.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// rdr.set_title(&format!("Snake - score: {}", score));
+    /// ```
+    pub fn set_title(&mut self, title: &str) {
+        self.send(RenderingDirective::SetTitle(2, title.chars().filter(|c| !c.is_control()).collect()));
+    }
+
+
+    /// Sets the icon name of the terminal window (shown by some terminals when the window is minimized).
+    /// Control characters are removed from `name`.
+    pub fn set_icon_name(&mut self, name: &str) {
+        self.send(RenderingDirective::SetTitle(1, name.chars().filter(|c| !c.is_control()).collect()));
+    }
+
+
+    /// Adds `msg` to the debug console, since printing to stdout would corrupt the screen.
+    /// Lines are separated by `\n`, and the last lines are shown over the top of the frame while the
+    /// console is shown (see `show_console` and `Input::set_console_key`). Can be called outside of a frame.
+    /// 
+    /// # Usage
+    /// 
+    /// ```
+    /// Input::set_console_key(Some(Key::F(12)));
+    /// rdr.log(&format!("player at {:?}", player.pos));
+    /// ```
+    pub fn log(&mut self, msg: &str) {
+        self.send(RenderingDirective::Log(msg.to_string()));
+    }
+
+
+    /// Shows or hides the debug console (see `log`) from the next frame. It is hidden by default.
+    pub fn show_console(&mut self, shown: bool) {
+        self.send(RenderingDirective::ShowConsole(shown));
+    }
+
+
+    /// Shows the debug console if it is hidden, hides it otherwise.
+    pub fn toggle_console(&mut self) {
+        self.send(RenderingDirective::ToggleConsole);
+    }
+
+
+    /// Sets the number of lines shown by the debug console (8 by default).
+    pub fn set_console_lines(&mut self, n: usize) {
+        self.send(RenderingDirective::SetConsoleLines(n));
+    }
+
+
+    /// Rings the terminal bell. Can only be called during the creation of a frame
+    /// 
+    /// Technical note: the bell will ring when calling `end_draw`
+    pub fn ring_bell(&mut self) {
+        if !self.can_draw() { return; }
+        self.send(RenderingDirective::RingBell);
+    }
+}
+
+
+impl Drop for Renderer {
+
+    /// When the renderer is droped, stops the rendering thread and reset terminal settings.
+    fn drop(&mut self) {
+        // disconnect the rendering thread and wait for the last frame to be written
+        let (sender, _) = mpsc::channel();
+        drop(mem::replace(&mut self.sender, sender));
+        if let Some(handle) = self._server_handle.take() {
+            let _ = handle.join();
+        }
+
+        // return settings to default
+        self.backend.restore();
+
+        if let Ok(mut out) = self.backend.writer() {
+            let _ = write!(out, "{}{}{}",
+                csi!("0m"),                                     // reset colors and attributes
+                csi!("?25h"),                                   // show cursor
+                csi!("?1049l")                                  // use main screen buffer
+            ).and_then(|_| out.flush());
+        }
+
+        if self.main {
+            // the drop can happen while unwinding from a panic, a write error must not panic again
+            let _ = write_to_terminal(input::DISABLE_MOUSE);
+            let _ = Input::disable_kitty_keyboard();
+            unsafe {
+                *std::ptr::addr_of_mut!(SAVED_TERMIOS) = None;
+            }
+        }
+    }
+}
+
+
+/// Owns the Renderer singleton for a scope: the terminal is reset when the guard is droped, including
+/// when unwinding from a panic. Derefs to the Renderer.
+/// 
+/// The guard does not start a new renderer: using it after `Renderer::exit` panics.
+/// 
+/// # Usage
+/// 
+/// ```
+/// let mut rdr = RendererGuard::new();
+/// 
+/// rdr.begin_draw()?;
+/// ...
+/// rdr.end_draw()?;
+/// 
+/// drop(rdr); // the terminal is restored here
+/// ```
+pub struct RendererGuard {
+    /// Generation of the singleton held by the guard
+    generation: usize
+}
+
+
+impl RendererGuard {
+
+    /// Initializes the Renderer singleton if needed and returns a guard over it.
+    pub fn new() -> RendererGuard {
+        Renderer::get();
+        RendererGuard { generation: RENDERER_GENERATION.load(Ordering::Relaxed) }
+    }
+
+
+    /// Returns the singleton if it is still the one the guard was created with.
+    fn renderer(&self) -> Option<*mut Renderer> {
+        if RENDERER_GENERATION.load(Ordering::Relaxed) != self.generation {
+            return None;
+        }
+        unsafe { (*std::ptr::addr_of_mut!(RENDERER)).as_mut().map(|r| r as *mut Renderer) }
+    }
+}
+
+
+impl Default for RendererGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+impl Deref for RendererGuard {
+    type Target = Renderer;
+
+    fn deref(&self) -> &Renderer {
+        let renderer = self.renderer().expect("the renderer of the guard was exited");
+        unsafe { &*renderer }
+    }
+}
+
+
+impl DerefMut for RendererGuard {
+    fn deref_mut(&mut self) -> &mut Renderer {
+        let renderer = self.renderer().expect("the renderer of the guard was exited");
+        unsafe { &mut *renderer }
+    }
+}
+
+
+impl Drop for RendererGuard {
+    fn drop(&mut self) {
+        // a renderer started after an exit is not the one of the guard
+        if self.renderer().is_some() {
+            Renderer::exit();
+        }
+    }
+}
+
+
+
+/// Buffers everything that is written until `flush`, so that a frame is sent with a single write.
+/// The buffer is kept between frames to avoid reallocations.
+struct FrameWriter {
+    buf: Vec<u8>,
+    sink: Box<dyn Write + Send>
+}
+
+
+impl FrameWriter {
+
+    fn new(sink: Box<dyn Write + Send>) -> Self {
+        FrameWriter { buf: Vec::new(), sink }
+    }
+}
+
+
+impl Write for FrameWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+
+    fn flush(&mut self) -> io::Result<()> {
+        let result = self.sink.write_all(&self.buf).and_then(|_| self.sink.flush());
+        self.buf.clear();
+        result
+    }
+}
+
+
+/// Writes the frames to the terminal, keeps track of the colors that are currently set.
+struct TermWriter {
+    out: FrameWriter,
+    /// Glyphs of the current run of cells, written at once when the colors or the cursor position change
+    span: String,
+    back: Color,
+    fore: Color,
+    attrs: Attributes,
+    mode: RenderMode,
+    config: RendererConfig,
+    /// Cell where the terminal cursor is shown after each frame, hidden if None
+    cursor: Option<Vec2>,
+    /// Size of the terminal in cells
+    term_size: Vec2,
+    letterbox: Letterbox,
+    /// Scaling of the frames to the terminal, they are printed as is if None
+    scaling: Option<Scaling>,
+    /// Cell of the terminal where the top left cell of the frame is printed
+    origin: Vec2
+}
+
+
+impl TermWriter {
+
+    /// Hides the cursor while a frame is printed, if it is shown.
+    fn hide_cursor(&mut self) -> io::Result<()> {
+        match self.cursor {
+            Some(_) => write!(self.out, "{}", csi!("?25l")),
+            None => Ok(())
+        }
+    }
+
+
+    /// Returns the size at which a frame of size `size` is printed.
+    fn display_size(&self, size: Vec2) -> Vec2 {
+        match self.scaling {
+            Some(scaling) => {
+                let cell = self.mode.cell_size();
+                scaling.scaled_size(size, vec2!(self.term_size.x * cell.x, self.term_size.y * cell.y))
+            },
+            None => size
+        }
+    }
+
+
+    /// Returns `screen` scaled to its display size, or None if it is printed as is.
+    fn scale(&self, screen: &Framebuffer) -> Option<Framebuffer> {
+        let size = self.display_size(screen.size());
+        if size == screen.size() { None } else { Some(screen.scaled(size)) }
+    }
+
+
+    /// Places a frame of size `size` (in pixels) in the terminal according to the letterbox.
+    /// If the frame does not cover the whole terminal, the terminal is cleared with the letterbox color
+    /// and true is returned: the next frame must be fully redrawn.
+    fn update_layout(&mut self, size: Vec2) -> io::Result<bool> {
+        let cell = self.mode.cell_size();
+        let frame = vec2!((size.x + cell.x - 1) / cell.x, (size.y + cell.y - 1) / cell.y);
+        let margin = vec2!((self.term_size.x - frame.x).max(0), (self.term_size.y - frame.y).max(0));
+        self.origin = if self.letterbox.center { margin / 2 } else { Vec2::ZERO };
+
+        if margin == Vec2::ZERO {
+            return Ok(false);
+        }
+        self.set_attributes(Attributes::NONE)?;
+        self.set_back(self.config.colors.quantize(self.letterbox.color))?;
+        write!(self.out, "{}", csi!("2J"))?;
+        Ok(true)
+    }
+
+
+    /// Moves the cursor to its cell and shows it, if it is shown.
+    fn place_cursor(&mut self) -> io::Result<()> {
+        match self.cursor {
+            Some(p) => write!(self.out, "\x1b[{};{}H{}", self.origin.y + p.y + 1, self.origin.x + p.x + 1, csi!("?25h")),
+            None => Ok(())
+        }
+    }
+
+    /// Diffs `screen` and `cells` against `prev_screen` and `prev_cells` in `region` and prints the modified cells.
+    /// 
+    /// Returns the region that was actually diffed (clipped to the screen and aligned on cells).
+    fn print_region(&mut self, screen: &Framebuffer, cells: &Image<Option<Cell>>, prev_screen: &PrevFrame, prev_cells: &Image<Option<Cell>>,
+        region: Rect) -> io::Result<Rect>
+    {
+        let size = screen.size();
+        let cell_size = self.mode.cell_size();
+        let (cw, ch) = (cell_size.x, cell_size.y);
+
+        // region in cells
+        let x0 = region.pos.x.max(0) / cw;
+        let y0 = region.pos.y.max(0) / ch;
+        let x1 = ((region.pos.x + region.size.x).min(size.x) + cw - 1) / cw;
+        let y1 = ((region.pos.y + region.size.y).min(size.y) + ch - 1) / ch;
+
+        if x0 >= x1 || y0 >= y1 {
+            return Ok(Rect::new(Vec2::ZERO, Vec2::ZERO));
+        }
+
+        let full_width = x0 == 0 && x1 * cw >= size.x;
+        let same_size = size == prev_screen.size() && cells.size() == prev_cells.size();
+        let mut skiped = true;
+        let mut pixels = [Color::BLACK; 8];
+        let mut raw = [Color::BLACK; 8];
+        // dithered frames (see `dither_frame`) only contain displayable colors
+        let dithered = self.config.dithering != Dithering::None;
+        // with the same palette, the indices are compared and the colors are only resolved for the changed cells
+        let indices = prev_screen.indices(screen);
+
+        for j in y0..y1 {
+            if !full_width {
+                skiped = true;
+            }
+            let mut prev_rows: Vec<_> = match indices {
+                Some(_) => Vec::new(),
+                None => (0..ch).map(|k| prev_screen.row(x0 * cw, j * ch + k)).collect()
+            };
+
+            for i in x0..x1 {
+                let unchanged = match indices {
+                    Some((prev, current)) => (0..ch).all(|k| (0..cw).all(|l| {
+                        let p = vec2!(i * cw + l, j * ch + k);
+                        prev.at(p) == current.at(p)
+                    })),
+                    None => {
+                        let mut unchanged = true;
+                        for k in 0..ch {
+                            for l in 0..cw {
+                                let c = screen.color_at(vec2!(i * cw + l, j * ch + k));
+                                unchanged &= prev_rows[k as usize].next().unwrap_or_default() == c;
+                                raw[(k * cw + l) as usize] = c;
+                            }
+                        }
+                        unchanged
+                    }
+                };
+
+                let cell = cells.at((i, j));
+
+                if same_size && cell == prev_cells.at((i, j)) && (cell.is_some() || unchanged) {
+                    skiped = true;
+                    continue;
+                }
+
+                if indices.is_some() {
+                    for k in 0..ch {
+                        for l in 0..cw {
+                            raw[(k * cw + l) as usize] = screen.color_at(vec2!(i * cw + l, j * ch + k));
+                        }
+                    }
+                }
+                for (pixel, c) in pixels.iter_mut().zip(raw) {
+                    *pixel = if dithered { c } else { self.config.colors.quantize(c) };
+                }
+
+                if skiped {
+                    self.end_span()?;
+                    write!(self.out, "\x1b[{};{}H", self.origin.y + j + 1, self.origin.x + i + 1)?;
+                    skiped = false;
+                }
+
+                if let Some(cell) = cell {
+                    if cell.ch == WIDE_CONTINUATION {
+                        // already covered by the wide character on its left
+                        skiped = true;
+                        continue;
+                    }
+                    self.print_cell(cell)?;
+                    continue;
+                }
+                self.set_attributes(Attributes::NONE)?;
+
+                match self.mode {
+                    RenderMode::HalfBlock => match self.shade(raw[0], raw[1], pixels[0], pixels[1]) {
+                        Some((glyph, fg, bg)) => {
+                            self.set_fore(fg)?;
+                            self.set_back(bg)?;
+                            self.span.push(glyph);
+                        }
+                        None => self.print_half_block(pixels[0], pixels[1])?
+                    },
+                    mode => {
+                        let (mask, fg, bg) = glyph::split_colors(&pixels[..(cw * ch) as usize]);
+                        self.set_back(bg)?;
+                        if mask != 0 {
+                            self.set_fore(fg)?;
+                        }
+                        let c = match mode {
+                            RenderMode::Quadrant => glyph::quadrant(mask),
+                            RenderMode::Sextant => glyph::sextant(mask),
+                            _ => glyph::braille(mask)
+                        };
+                        self.span.push(c);
+                    }
+                }
+            }
+        }
+        self.end_span()?;
+
+        Ok(Rect::new((x0 * cw, y0 * ch), ((x1 - x0) * cw, (y1 - y0) * ch)))
+    }
+
+
+    /// Returns the pixels of the frame covered by the cells of `region`.
+    fn cell_pixels(&self, region: Rect) -> Rect {
+        let cell = self.mode.cell_size();
+        Rect::new(vec2!(region.pos.x * cell.x, region.pos.y * cell.y), vec2!(region.size.x * cell.x, region.size.y * cell.y))
+    }
+
+
+    /// Prints the modified cells of `region` (see `print_region`) and stores them in the previous frame.
+    fn push_region(&mut self, screen: &Framebuffer, cells: &Image<Option<Cell>>, prev_screen: &mut PrevFrame, prev_cells: &mut Image<Option<Cell>>,
+        region: Rect) -> io::Result<()>
+    {
+        let region = self.print_region(screen, cells, prev_screen, prev_cells, region)?;
+        prev_screen.update_region(screen, region);
+        let cell = self.mode.cell_size();
+        let cell_region = Rect::new(
+            vec2!(region.pos.x / cell.x, region.pos.y / cell.y),
+            vec2!(region.size.x / cell.x, region.size.y / cell.y)
+        );
+        prev_cells.image_map(cells, cell_region.pos, cell_region.size, cell_region.pos, None, |c| c);
+        Ok(())
+    }
+
+
+    /// Sends the whole screen as an iTerm2 inline image, then prints `cells` over it.
+    fn print_inline_image(&mut self, screen: &Framebuffer, cells: &Image<Option<Cell>>) -> io::Result<()> {
+        let png = screen.to_rgb().encode_png().map_err(io::Error::other)?;
+        let cols = cells.size().x;
+        let rows = cells.size().y;
+
+        write!(self.out, "\x1b[{};{}H\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=0;doNotMoveCursor=1:{}\x07",
+            self.origin.y + 1, self.origin.x + 1, png.len(), cols, rows, base64(&png))?;
+
+        for j in 0..rows {
+            for i in 0..cols {
+                if let Some(cell) = cells.at((i, j)).filter(|cell| cell.ch != WIDE_CONTINUATION) {
+                    self.end_span()?;
+                    write!(self.out, "\x1b[{};{}H", self.origin.y + j + 1, self.origin.x + i + 1)?;
+                    self.print_cell(cell)?;
+                }
+            }
+        }
+        self.set_attributes(Attributes::NONE)?;
+        self.end_span()
+    }
+
+
+    /// Writes the glyphs of the current run.
+    fn end_span(&mut self) -> io::Result<()> {
+        if !self.span.is_empty() {
+            self.out.write_all(self.span.as_bytes())?;
+            self.span.clear();
+        }
+        Ok(())
+    }
+
+
+    /// Prints the character of `cell` with its colors and attributes.
+    fn print_cell(&mut self, cell: Cell) -> io::Result<()> {
+        self.set_attributes(cell.attrs)?;
+        self.set_fore(self.config.colors.quantize(cell.fg))?;
+        self.set_back(self.config.colors.quantize(cell.bg))?;
+        self.span.push(cell.ch);
+        Ok(())
+    }
+
+
+    /// Sets the text attributes if they are not already set.
+    fn set_attributes(&mut self, attrs: Attributes) -> io::Result<()> {
+        if attrs != self.attrs {
+            self.end_span()?;
+            attrs.write_diff(&self.attrs, &mut self.out)?;
+            self.attrs = attrs;
+        }
+        Ok(())
+    }
+
+
+    /// Writes the CSI that sets the foreground (if `fore`) or background color to `c`.
+    fn write_color(&mut self, c: Color, fore: bool) -> io::Result<()> {
+        self.end_span()?;
+        match (self.config.colors, fore) {
+            (ColorDepth::TrueColor, true) => write!(self.out, "{:+}", c),
+            (ColorDepth::TrueColor, false) => write!(self.out, "{:-}", c),
+            // pixels were quantized, so `to_ansi256` gives back the gray of the ramp
+            (ColorDepth::Ansi256 | ColorDepth::Grayscale, true) => write!(self.out, "{}", format_args!(csi!("38;5;{}m"), c.to_ansi256())),
+            (ColorDepth::Ansi256 | ColorDepth::Grayscale, false) => write!(self.out, "{}", format_args!(csi!("48;5;{}m"), c.to_ansi256())),
+            (ColorDepth::Ansi16(_), fore) => {
+                // pixels were quantized to one of the 16 colors, the mapping must not be applied twice
+                // bright colors use their own codes (90-97 and 100-107)
+                let i = c.to_ansi16();
+                let code = if fore { 30 } else { 40 } + if i < 8 { i } else { 60 + i - 8 };
+                write!(self.out, "{}", format_args!(csi!("{}m"), code))
+            }
+        }
+    }
+
+
+    /// Sets the foreground color if it is not already set, `c` must be displayable (see `ColorDepth::quantize`).
+    fn set_fore(&mut self, c: Color) -> io::Result<()> {
+        if c != self.fore {
+            self.fore = c;
+            self.write_color(c, true)?;
+        }
+        Ok(())
+    }
+
+
+    /// Sets the background color if it is not already set, `c` must be displayable (see `ColorDepth::quantize`).
+    fn set_back(&mut self, c: Color) -> io::Result<()> {
+        if c != self.back {
+            self.back = c;
+            self.write_color(c, false)?;
+        }
+        Ok(())
+    }
+
+
+    /// Prints a cell with `c1` on top and `c2` at the bottom, reusing the current colors when possible.
+    /// Returns the shade that approximates the half block cell of colors `c1` and `c2` (displayed as `q1` and `q2`),
+    /// if shades are enabled and the cell is displayed with a single color.
+    fn shade(&self, c1: Color, c2: Color, q1: Color, q2: Color) -> Option<(char, Color, Color)> {
+        if !self.config.shades || matches!(self.config.colors, ColorDepth::TrueColor) || q1 != q2 {
+            return None;
+        }
+        glyph::shade(Color::mix(&[c1, c2]), |c| self.config.colors.quantize(c))
+    }
+
+
+    fn print_half_block(&mut self, c1: Color, c2: Color) -> io::Result<()> {
+        // update color
+        if c1 != self.back && c1 != self.fore && c2 == self.back {
+            self.set_fore(c1)?;
+        } else if c1 != self.back && c1 != self.fore && c2 == self.fore {
+            self.set_back(c1)?;
+        } else if c2 != self.back && c2 != self.fore && c1 == self.back {
+            self.set_fore(c2)?;
+        } else if c2 != self.back && c2 != self.fore && c1 == self.fore {
+            self.set_back(c2)?;
+        } else if c1 != self.back && c1 != self.fore && c2 != self.back && c2 != self.fore {
+            self.set_fore(c1)?;
+            self.set_back(c2)?;
+        }
+
+        // print pixel
+        let glyph = if c1 == self.back && c2 == self.back {
+            ' '
+        } else if c1 == self.back && c2 == self.fore {
+            '▄'
+        } else if c1 == self.fore && c2 == self.back {
+            '▀'
+        } else {
+            '█'
+        };
+        self.span.push(glyph);
+        Ok(())
+    }
+}
+
+
+
+/// Replaces a texture draw by the draw of its image, None if the texture does not exist.
+/// The other directives are returned as is.
+fn resolve_texture(textures: &HashMap<TextureId, Arc<Image>>, directive: RenderingDirective) -> Option<RenderingDirective> {
+    match directive {
+        RenderingDirective::DrawTexture(id, pos, size, off, alpha, mode) => textures.get(&id)
+            .map(|img| RenderingDirective::DrawImage(Arc::clone(img), pos, size, off, Flip::None, alpha, mode)),
+        directive => Some(directive)
+    }
+}
+
+
+/// Records the first error met by the rendering thread in `slot`, it is returned by the next
+/// `Renderer::begin_draw` or `Renderer::end_draw` instead of stopping the thread.
+fn report<T>(slot: &Mutex<Option<io::Error>>, result: io::Result<T>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(e) => {
+            slot.lock().unwrap().get_or_insert(e);
+            None
+        }
+    }
+}
+
+
+/// Returns the frame dithered to the colors of `config`, or None if it is shown as is.
+fn dither_frame(screen: &Framebuffer, config: &RendererConfig) -> Option<Framebuffer> {
+    let colors = config.colors;
+    let dithered = match (config.dithering, colors) {
+        (Dithering::None, _) | (_, ColorDepth::TrueColor) => return None,
+        (Dithering::Ordered, _) => dither::ordered(&screen.to_rgb(), colors.step(), |c| colors.quantize(c)),
+        (Dithering::FloydSteinberg, _) => dither::floyd_steinberg(&screen.to_rgb(), |c| colors.quantize(c))
+    };
+    Some(Framebuffer::Rgb(dithered))
+}
+
+
+/// Returns true if `Renderer::use_controlling_terminal` was called.
+pub(crate) fn uses_controlling_terminal() -> bool {
+    USE_CONTROLLING_TERMINAL.load(Ordering::Relaxed)
+}
+
+
+/// Catches SIGINT and SIGTERM, `saved` are the settings to restore on `input_fd` if the program is killed.
+fn install_signal_handlers(input_fd: RawFd, saved: Termios) {
+    let handler = on_exit_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        *std::ptr::addr_of_mut!(SAVED_TERMIOS) = Some((input_fd, saved));
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+        libc::signal(libc::SIGWINCH, on_resize_signal as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+    EXIT_SIGNAL.store(0, Ordering::SeqCst);
+    SIGNAL_DELIVERED.store(false, Ordering::SeqCst);
+    QUIT_REPORTED.store(false, Ordering::SeqCst);
+}
+
+
+/// Records the exit signal so that the application can quit properly.
+/// If a signal was already delivered, the application is not responding to it: the terminal is restored
+/// and the program is killed. Only async-signal-safe functions are used here.
+extern "C" fn on_exit_signal(sig: libc::c_int) {
+    EXIT_SIGNAL.store(sig, Ordering::SeqCst);
+    if !SIGNAL_DELIVERED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let reset = concat!(
+        csi!("?1006l"), csi!("?1015l"), csi!("?1002l"), csi!("?1000l"),    // disable mouse
+        csi!("<u"),                                                         // disable kitty keyboard
+        csi!("0m"),                                                         // reset colors and attributes
+        csi!("?25h"),                                                       // show cursor
+        csi!("?1049l")                                                      // use main screen buffer
+    );
+    unsafe {
+        if let Some((fd, termios)) = *std::ptr::addr_of!(SAVED_TERMIOS) {
+            let _ = tcsetattr(fd, TCSANOW, &termios);
+        }
+        libc::write(MAIN_OUTPUT_FD.load(Ordering::Relaxed), reset.as_ptr() as *const libc::c_void, reset.len());
+        libc::_exit(128 + sig);
+    }
+}
+
+
+/// Records that the terminal was resized, reported by the Input singleton as `InputEvent::Resize`.
+extern "C" fn on_resize_signal(_: libc::c_int) {
+    RESIZED.store(true, Ordering::SeqCst);
+}
+
+
+/// Handles a Ctrl+C read by the Input singleton, that does not raise SIGINT since ISIG is disabled.
+/// The program is never killed from here, an application that handles `InputEvent::Quit` may just be slow to exit.
+pub(crate) fn interrupt() {
+    if unsafe { (*std::ptr::addr_of!(SAVED_TERMIOS)).is_some() } {
+        let _ = EXIT_SIGNAL.compare_exchange(0, libc::SIGINT, Ordering::SeqCst, Ordering::SeqCst);
+    }
+}
+
+
+/// Returns true the first time it is called after an exit signal was received.
+pub(crate) fn take_quit_event() -> bool {
+    EXIT_SIGNAL.load(Ordering::SeqCst) != 0 && !QUIT_REPORTED.swap(true, Ordering::SeqCst)
+}
+
+
+/// Returns true the first time it is called after the terminal was resized.
+pub(crate) fn take_resize_event() -> bool {
+    RESIZED.swap(false, Ordering::SeqCst)
+}
+
+
+/// Toggles the console at the next `begin_draw`, called by the input thread when the console key is pressed.
+pub(crate) fn toggle_console() {
+    CONSOLE_TOGGLED.fetch_xor(true, Ordering::SeqCst);
+    Renderer::request_redraw();
+}
+
+
+/// Writes `s` to the terminal used by the singletons (stdout or the controlling terminal).
+pub(crate) fn write_to_terminal(s: &str) -> io::Result<()> {
+    if uses_controlling_terminal() {
+        let mut tty = OpenOptions::new().write(true).open("/dev/tty")?;
+        tty.write_all(s.as_bytes())?;
+        tty.flush()
+    } else {
+        let mut out = stdout();
+        out.write_all(s.as_bytes())?;
+        out.flush()
+    }
+}
+
+
+/// Encodes `data` in base64 (with padding).
+pub(crate) fn base64(data: &[u8]) -> String {
+    const DIGITS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut result = String::with_capacity(4 * data.len().div_ceil(3));
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                result.push(DIGITS[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    result
+}
+
+
+/// Returns the number of pixels in a cell for the Renderer singleton.
+pub(crate) fn main_cell_size() -> Vec2 {
+    *MAIN_CELL_SIZE.lock().unwrap()
+}
+
+
+/// Returns the size in pixels of a terminal of `size` cells with cells of `cell` pixels.
+fn screen_size(size: Vec2, cell: Vec2) -> Vec2 {
+    vec2!(cell.x * size.x, cell.y * size.y)
 }
\ No newline at end of file
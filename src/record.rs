@@ -0,0 +1,365 @@
+/*
+
+    MIT License
+
+    Copyright (c) 2022 Siandfrance
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+
+*/
+
+// Recording of the pushed frames to an animated image (see `Renderer::start_recording`).
+
+
+use crate::math::Vec2;
+use crate::img::{Image, BlendMode};
+
+use image::{Rgba, RgbaImage, Frame, Delay};
+use image::codecs::gif::{GifEncoder, Repeat};
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write, Seek, SeekFrom, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+
+/// Delay given to the last frame of a recording
+const LAST_FRAME_DELAY: Duration = Duration::from_millis(100);
+
+
+/// Encoding of the recording, chosen from the extension of the file.
+#[derive(Clone, Copy)]
+enum Format {
+    Gif,
+    Apng
+}
+
+
+/// Encoder of a recording, the APNG header needs the size of the frames so it is written with the first one.
+enum Encoder {
+    Gif(GifEncoder<BufWriter<File>>),
+    Apng(Option<png::Writer<BufWriter<File>>>)
+}
+
+
+/// Frames pushed while recording, each one is encoded when the next one is pushed (to know its delay).
+pub(crate) struct Recording {
+    path: PathBuf,
+    file: File,
+    encoder: Encoder,
+    prev: Option<(Image, Instant)>,
+    frames: u32,
+    error: Option<String>
+}
+
+
+impl Recording {
+
+    /// Creates the file of the recording, `path` should end with `.gif`, `.png` or `.apng`.
+    pub(crate) fn new<P>(path: P) -> Result<Recording, String>
+        where P: AsRef<Path>
+    {
+        let path = path.as_ref();
+        let format = match path.extension().and_then(|e| e.to_str()) {
+            Some("gif") => Format::Gif,
+            Some("png") | Some("apng") => Format::Apng,
+            _ => return Err(format!("unsupported recording format: {}", path.display()))
+        };
+        // the APNG header is read back by `set_apng_frames`
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)
+            .map_err(|e| format!("{}", e))?;
+        let encoder = match format {
+            Format::Gif => {
+                let out = file.try_clone().map_err(|e| format!("{}", e))?;
+                let mut encoder = GifEncoder::new(BufWriter::new(out));
+                encoder.set_repeat(Repeat::Infinite).map_err(|e| format!("{}", e))?;
+                Encoder::Gif(encoder)
+            }
+            Format::Apng => Encoder::Apng(None)
+        };
+        Ok(Recording { path: path.to_path_buf(), file, encoder, prev: None, frames: 0, error: None })
+    }
+
+
+    /// Adds a frame, displayed from now until the next one.
+    /// Frames are cropped or extended to the size of the first one.
+    pub(crate) fn push(&mut self, frame: Image) {
+        let frame = match &self.prev {
+            Some((prev, _)) if prev.size() != frame.size() => {
+                let size = prev.size();
+                let mut fitted = Image::new(size.x as usize, size.y as usize);
+                fitted.image(&frame, Vec2::ZERO, size, Vec2::ZERO, None, BlendMode::Normal);
+                fitted
+            }
+            _ => frame
+        };
+        let now = Instant::now();
+        if let Some((prev, start)) = self.prev.replace((frame, now)) {
+            self.encode(&prev, now - start);
+        }
+    }
+
+
+    /// Encodes a frame, keeping the first error: it is returned by `finish`.
+    fn encode(&mut self, img: &Image, delay: Duration) {
+        if self.error.is_some() {
+            return;
+        }
+        let result = match &mut self.encoder {
+            Encoder::Gif(encoder) => encode_gif_frame(encoder, img, delay).map_err(|e| format!("{}", e)),
+            Encoder::Apng(writer) => encode_apng_frame(writer, &self.file, img, delay).map_err(|e| format!("{}", e))
+        };
+        match result {
+            Ok(()) => self.frames += 1,
+            Err(e) => self.error = Some(e)
+        }
+    }
+
+
+    /// Encodes the last frame and closes the file.
+    /// Nothing is written if no frame was pushed, the file is removed.
+    pub(crate) fn finish(mut self) -> Result<(), String> {
+        let Some((last, _)) = self.prev.take() else {
+            drop(self.encoder);
+            let _ = std::fs::remove_file(&self.path);
+            return Err("no frame was recorded".to_string());
+        };
+        self.encode(&last, LAST_FRAME_DELAY);
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+
+        match self.encoder {
+            // the trailer is written when the encoder is droped
+            Encoder::Gif(encoder) => drop(encoder),
+            Encoder::Apng(writer) => {
+                if let Some(writer) = writer {
+                    writer.finish().map_err(|e| format!("{}", e))?;
+                }
+                set_apng_frames(&mut self.file, self.frames).map_err(|e| format!("{}", e))?;
+            }
+        }
+        self.file.sync_all().map_err(|e| format!("{}", e))
+    }
+}
+
+
+/// asciinema v2 recording of the output of the renderer (see `Renderer::start_cast`).
+/// The bytes written are grouped in one event per flush, so that each frame is a single event.
+pub(crate) struct Cast {
+    file: BufWriter<File>,
+    start: Instant,
+    size: Vec2,
+    pending: Vec<u8>,
+    error: Option<io::Error>
+}
+
+
+impl Cast {
+
+    /// Creates the file of the recording, nothing is written until `start` is called.
+    pub(crate) fn create<P>(path: P) -> Result<Cast, String>
+        where P: AsRef<Path>
+    {
+        match File::create(path) {
+            Ok(file) => Ok(Cast { file: BufWriter::new(file), start: Instant::now(), size: Vec2::ZERO, pending: Vec::new(), error: None }),
+            Err(e) => Err(format!("{}", e))
+        }
+    }
+
+
+    /// Writes the header, `size` is the size of the terminal in cells. Timestamps start from now.
+    pub(crate) fn start(&mut self, size: Vec2) {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let header = format!("{{\"version\": 2, \"width\": {}, \"height\": {}, \"timestamp\": {}}}\n", size.x, size.y, timestamp);
+        self.start = Instant::now();
+        self.size = size;
+        self.check(|file| file.write_all(header.as_bytes()));
+    }
+
+
+    /// Records a resize of the terminal to `size` cells.
+    pub(crate) fn resize(&mut self, size: Vec2) {
+        if size == self.size {
+            return;
+        }
+        self.size = size;
+        self.event("r", &format!("{}x{}", size.x, size.y));
+    }
+
+
+    /// Writes the pending output as an event.
+    fn flush_pending(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let data = String::from_utf8_lossy(&self.pending).into_owned();
+        self.pending.clear();
+        self.event("o", &data);
+    }
+
+
+    fn event(&mut self, kind: &str, data: &str) {
+        let line = format!("[{:.6}, \"{}\", {}]\n", self.start.elapsed().as_secs_f64(), kind, json_string(data));
+        self.check(|file| file.write_all(line.as_bytes()));
+    }
+
+
+    /// Keeps the first error, it is returned by `finish`: the output to the terminal does not fail because of the cast.
+    fn check<F>(&mut self, f: F)
+        where F: FnOnce(&mut BufWriter<File>) -> io::Result<()>
+    {
+        if self.error.is_none() {
+            if let Err(e) = f(&mut self.file) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+
+    /// Writes the remaining output and closes the file.
+    pub(crate) fn finish(mut self) -> Result<(), String> {
+        self.flush_pending();
+        self.check(|file| file.flush());
+        match self.error {
+            Some(e) => Err(format!("{}", e)),
+            None => Ok(())
+        }
+    }
+}
+
+
+/// Writes to the terminal, and to the cast that is being recorded if there is one.
+pub(crate) struct CastWriter {
+    pub(crate) out: Box<dyn Write + Send>,
+    pub(crate) cast: Arc<Mutex<Option<Cast>>>
+}
+
+
+impl Write for CastWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.out.write(buf)?;
+        if let Some(cast) = self.cast.lock().unwrap().as_mut() {
+            cast.pending.extend_from_slice(&buf[..n]);
+        }
+        Ok(n)
+    }
+
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()?;
+        if let Some(cast) = self.cast.lock().unwrap().as_mut() {
+            cast.flush_pending();
+        }
+        Ok(())
+    }
+}
+
+
+/// Returns `s` as a JSON string literal.
+fn json_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 || c == '\x7f' => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c)
+        }
+    }
+    result.push('"');
+    result
+}
+
+
+fn encode_gif_frame(encoder: &mut GifEncoder<BufWriter<File>>, img: &Image, delay: Duration) -> Result<(), image::ImageError> {
+    let size = img.size();
+    let buffer = RgbaImage::from_fn(size.x as u32, size.y as u32, |x, y| {
+        let c = img[vec2!(x as i32, y as i32)];
+        Rgba([c.r, c.g, c.b, 255])
+    });
+    encoder.encode_frame(Frame::from_parts(buffer, 0, 0, Delay::from_saturating_duration(delay)))
+}
+
+
+/// Encodes a frame of an APNG, the header is written with the first frame.
+/// The number of frames is not known yet, it is set by `set_apng_frames`.
+fn encode_apng_frame(writer: &mut Option<png::Writer<BufWriter<File>>>, file: &File, img: &Image, delay: Duration) -> Result<(), png::EncodingError> {
+    let size = img.size();
+    let writer = match writer {
+        Some(writer) => writer,
+        None => {
+            let mut encoder = png::Encoder::new(BufWriter::new(file.try_clone()?), size.x as u32, size.y as u32);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_animated(u32::MAX, 0)?;
+            encoder.validate_sequence(false);
+            writer.insert(encoder.write_header()?)
+        }
+    };
+
+    let mut data = Vec::with_capacity(3 * (size.x * size.y) as usize);
+    for y in 0..size.y {
+        for x in 0..size.x {
+            let c = img[vec2!(x, y)];
+            data.extend_from_slice(&[c.r, c.g, c.b]);
+        }
+    }
+    writer.set_frame_delay(delay.as_millis().min(u16::MAX as u128) as u16, 1000)?;
+    writer.write_image_data(&data)
+}
+
+
+/// Sets the number of frames in the acTL chunk of the APNG in `file`, and updates its CRC.
+fn set_apng_frames(file: &mut File, frames: u32) -> io::Result<()> {
+    let mut header = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    (&*file).take(256).read_to_end(&mut header)?;
+    let pos = header.windows(4).position(|w| w == b"acTL")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing acTL chunk"))?;
+    if header.len() < pos + 12 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "truncated acTL chunk"));
+    }
+
+    // the CRC covers the type and the data of the chunk (num_frames, num_plays)
+    let mut chunk = header[pos..pos + 12].to_vec();
+    chunk[4..8].copy_from_slice(&frames.to_be_bytes());
+    file.seek(SeekFrom::Start(pos as u64 + 4))?;
+    file.write_all(&chunk[4..8])?;
+    file.seek(SeekFrom::Start(pos as u64 + 12))?;
+    file.write_all(&crc32(&chunk).to_be_bytes())
+}
+
+
+/// CRC-32 (ISO-HDLC) used by the PNG chunks.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in bytes {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
@@ -0,0 +1,286 @@
+/*
+
+    MIT License
+    
+    Copyright (c) 2022 Siandfrance
+    
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+    
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+    
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+
+*/
+
+use crate::math::{Vec2, Rect};
+use crate::img::Image;
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+
+/// Image containing several sprites (or animation frames), with the rectangle of each frame.
+/// Frames are referred to by their index or by their name (see `FrameKey`).
+/// 
+/// # Usage
+/// 
+/// ```
+/// let mut sheet = SpriteSheet::grid(Image::load("player.png")?, (16, 16));
+/// sheet.name_frame("jump", 5);
+/// rdr.draw_sprite(&sheet, "jump", pos);
+/// ```
+#[derive(Clone)]
+pub struct SpriteSheet {
+    image: Arc<Image>,
+    frames: Vec<Rect>,
+    names: HashMap<String, usize>
+}
+
+
+impl SpriteSheet {
+
+    /// Creates a sprite sheet without frames.
+    pub fn new(image: Image) -> Self {
+        Self::from_shared(Arc::new(image))
+    }
+
+
+    /// Creates a sprite sheet without frames from an image that is already shared.
+    pub fn from_shared(image: Arc<Image>) -> Self {
+        Self {
+            image,
+            frames: Vec::new(),
+            names: HashMap::new()
+        }
+    }
+
+
+    /// Creates a sprite sheet where the frames are the cells of a grid of `frame_size`,
+    /// indexed row by row. Incomplete cells on the right and the bottom are ignored.
+    pub fn grid<A>(image: Image, frame_size: A) -> Self 
+        where A: AsRef<Vec2>
+    {
+        let frame_size = *frame_size.as_ref();
+        let size = image.size();
+        let mut sheet = Self::new(image);
+        if frame_size.x > 0 && frame_size.y > 0 {
+            for j in 0..(size.y / frame_size.y) {
+                for i in 0..(size.x / frame_size.x) {
+                    sheet.add_frame(Rect::new(vec2!(i * frame_size.x, j * frame_size.y), frame_size));
+                }
+            }
+        }
+        sheet
+    }
+
+
+    /// Loads the image at `path` and splits it in frames of `frame_size` (see `SpriteSheet::grid`).
+    pub fn load<P, A>(path: P, frame_size: A) -> Result<Self, String> 
+        where P: AsRef<Path>, A: AsRef<Vec2>
+    {
+        Ok(Self::grid(Image::load(path)?, frame_size))
+    }
+
+
+    /// Adds a frame and returns its index.
+    pub fn add_frame(&mut self, rect: Rect) -> usize {
+        self.frames.push(rect);
+        self.frames.len() - 1
+    }
+
+
+    /// Adds a frame named `name` and returns its index.
+    pub fn add_named_frame(&mut self, name: &str, rect: Rect) -> usize {
+        let index = self.add_frame(rect);
+        self.names.insert(name.to_string(), index);
+        index
+    }
+
+
+    /// Gives the name `name` to the frame `index`.
+    pub fn name_frame(&mut self, name: &str, index: usize) {
+        self.names.insert(name.to_string(), index);
+    }
+
+
+    /// Returns the rectangle of `frame` in the image, `None` if there is no such frame.
+    pub fn frame<K>(&self, frame: K) -> Option<Rect> 
+        where K: FrameKey
+    {
+        frame.index(self).and_then(|i| self.frames.get(i).copied())
+    }
+
+
+    /// Returns the number of frames.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+
+    /// Returns true if the sheet has no frames.
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+
+    /// Returns the image of the sheet.
+    pub fn image(&self) -> Arc<Image> {
+        Arc::clone(&self.image)
+    }
+}
+
+
+/// Way to refer to a frame of a `SpriteSheet`: its index (`usize`) or its name (`&str`).
+pub trait FrameKey {
+
+    /// Returns the index of the frame in `sheet`, `None` if it does not exist.
+    fn index(&self, sheet: &SpriteSheet) -> Option<usize>;
+}
+
+
+impl FrameKey for usize {
+    fn index(&self, _: &SpriteSheet) -> Option<usize> {
+        Some(*self)
+    }
+}
+
+
+impl FrameKey for &str {
+    fn index(&self, sheet: &SpriteSheet) -> Option<usize> {
+        sheet.names.get(*self).copied()
+    }
+}
+
+
+/// What an `Animation` does after its last frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LoopMode {
+    /// Stays on the last frame.
+    Once,
+    /// Starts again from the first frame.
+    #[default]
+    Loop,
+    /// Plays backwards to the first frame, then forwards again.
+    PingPong
+}
+
+
+/// Sequence of frames of a `SpriteSheet`, each shown for its own duration.
+/// 
+/// # Usage
+/// 
+/// ```
+/// let walk = Animation::uniform(sheet, &[0, 1, 2, 3], Duration::from_millis(100), LoopMode::Loop);
+/// let start = Instant::now();
+/// loop {
+///     rdr.begin_draw();
+///     rdr.draw_animation(&walk, pos, start.elapsed());
+///     rdr.end_draw();
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Animation {
+    sheet: SpriteSheet,
+    frames: Vec<(usize, Duration)>,
+    mode: LoopMode
+}
+
+
+impl Animation {
+
+    /// Creates an animation without frames.
+    pub fn new(sheet: SpriteSheet, mode: LoopMode) -> Self {
+        Self {
+            sheet,
+            frames: Vec::new(),
+            mode
+        }
+    }
+
+
+    /// Creates an animation showing the frames `frames` of `sheet`, each for `duration`.
+    pub fn uniform(sheet: SpriteSheet, frames: &[usize], duration: Duration, mode: LoopMode) -> Self {
+        let mut anim = Self::new(sheet, mode);
+        for frame in frames {
+            anim.add_frame(*frame, duration);
+        }
+        anim
+    }
+
+
+    /// Adds the frame `frame` of the sheet, shown for `duration`.
+    /// 
+    /// Panics if the frame does not exist.
+    pub fn add_frame<K>(&mut self, frame: K, duration: Duration) 
+        where K: FrameKey
+    {
+        let index = frame.index(&self.sheet)
+            .filter(|i| *i < self.sheet.len())
+            .expect("no such frame in the sprite sheet");
+        self.frames.push((index, duration));
+    }
+
+
+    /// Returns the duration of one play of the animation.
+    pub fn duration(&self) -> Duration {
+        self.frames.iter().map(|(_, d)| *d).sum()
+    }
+
+
+    /// Returns true if an animation that does not loop has reached its last frame after `elapsed`.
+    pub fn is_finished(&self, elapsed: Duration) -> bool {
+        self.mode == LoopMode::Once && elapsed >= self.duration()
+    }
+
+
+    /// Returns the index in the sheet of the frame shown `elapsed` after the start of the animation.
+    /// 
+    /// Panics if the animation has no frames.
+    pub fn frame_at(&self, elapsed: Duration) -> usize {
+        let last = self.frames.last().expect("the animation has no frames").0;
+        let total = self.duration();
+        if total.is_zero() {
+            return last;
+        }
+
+        let t = match self.mode {
+            LoopMode::Once if elapsed >= total => return last,
+            LoopMode::Once => elapsed,
+            LoopMode::Loop => Duration::from_nanos((elapsed.as_nanos() % total.as_nanos()) as u64),
+            LoopMode::PingPong => {
+                let t = elapsed.as_nanos() % (2 * total.as_nanos());
+                // the second half plays the animation backwards
+                Duration::from_nanos(if t < total.as_nanos() { t } else { 2 * total.as_nanos() - 1 - t } as u64)
+            }
+        };
+
+        let mut end = Duration::ZERO;
+        for (index, duration) in self.frames.iter() {
+            end += *duration;
+            if t < end {
+                return *index;
+            }
+        }
+        last
+    }
+
+
+    /// Returns the sprite sheet of the animation.
+    pub fn sheet(&self) -> &SpriteSheet {
+        &self.sheet
+    }
+}
@@ -0,0 +1,105 @@
+/*
+
+    MIT License
+    
+    Copyright (c) 2022 Siandfrance
+    
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+    
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+    
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+
+*/
+
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+
+/// Magic number of the legacy terminfo format (16 bits numbers)
+const MAGIC_16: u16 = 0o432;
+
+/// Magic number of the extended terminfo format (32 bits numbers)
+const MAGIC_32: u16 = 0o1036;
+
+/// Index of the `colors` capability in the numbers section
+const COLORS: usize = 13;
+
+
+/// Returns the directories where the compiled terminfo entries may be, in search order.
+fn search_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Ok(dir) = env::var("TERMINFO") {
+        dirs.push(PathBuf::from(dir));
+    }
+    if let Ok(home) = env::var("HOME") {
+        dirs.push(PathBuf::from(home).join(".terminfo"));
+    }
+    if let Ok(list) = env::var("TERMINFO_DIRS") {
+        dirs.extend(list.split(':').filter(|d| !d.is_empty()).map(PathBuf::from));
+    }
+    dirs.extend(["/etc/terminfo", "/lib/terminfo", "/usr/share/terminfo"].iter().map(PathBuf::from));
+    dirs
+}
+
+
+/// Reads the compiled terminfo entry of `term`.
+fn read_entry(term: &str) -> Option<Vec<u8>> {
+    let first = term.chars().next()?;
+    for dir in search_dirs() {
+        // entries are in a subdirectory named after the first letter (or its hex code on macOS)
+        for sub in [first.to_string(), format!("{:x}", first as u32)] {
+            if let Ok(data) = fs::read(dir.join(sub).join(term)) {
+                return Some(data);
+            }
+        }
+    }
+    None
+}
+
+
+/// Returns the number of colors supported by `term` according to its terminfo entry,
+/// or None if the entry could not be found or read.
+pub(crate) fn max_colors(term: &str) -> Option<i32> {
+    let data = read_entry(term)?;
+    let short = |i: usize| data.get(2 * i..2 * i + 2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+
+    let magic = short(0)?;
+    let names_size = short(1)? as usize;
+    let bools_count = short(2)? as usize;
+    let nums_count = short(3)? as usize;
+    if nums_count <= COLORS {
+        return None;
+    }
+
+    // numbers are aligned on 2 bytes
+    let mut offset = 12 + names_size + bools_count;
+    offset += offset % 2;
+
+    let colors = match magic {
+        MAGIC_16 => {
+            let b = data.get(offset + 2 * COLORS..offset + 2 * COLORS + 2)?;
+            i16::from_le_bytes([b[0], b[1]]) as i32
+        }
+        MAGIC_32 => {
+            let b = data.get(offset + 4 * COLORS..offset + 4 * COLORS + 4)?;
+            i32::from_le_bytes([b[0], b[1], b[2], b[3]])
+        }
+        _ => return None
+    };
+    if colors < 0 { None } else { Some(colors) }
+}
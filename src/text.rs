@@ -0,0 +1,255 @@
+/*
+
+    MIT License
+
+    Copyright (c) 2022 Siandfrance
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+
+*/
+
+
+
+use crate::math::Vec2;
+
+#[cfg(feature = "ttf")]
+use std::path::Path;
+
+
+/// Width of a glyph in pixels.
+pub const GLYPH_WIDTH: i32 = 5;
+
+/// Height of a glyph in pixels.
+pub const GLYPH_HEIGHT: i32 = 7;
+
+/// Horizontal distance between two characters.
+pub const ADVANCE: i32 = GLYPH_WIDTH + 1;
+
+/// Vertical distance between two lines.
+pub const LINE_HEIGHT: i32 = GLYPH_HEIGHT + 1;
+
+
+/// Embedded bitmap font used to draw text in images (see `Image::text` and `Renderer::draw_text`).
+/// Glyphs of the characters from ' ' to '~', column by column (the lowest bit is the top pixel).
+/// Other characters are drawn as '?'.
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5f, 0x00, 0x00], // '!'
+    [0x00, 0x07, 0x00, 0x07, 0x00], // '"'
+    [0x14, 0x7f, 0x14, 0x7f, 0x14], // '#'
+    [0x24, 0x2a, 0x7f, 0x2a, 0x12], // '$'
+    [0x23, 0x13, 0x08, 0x64, 0x62], // '%'
+    [0x36, 0x49, 0x55, 0x22, 0x50], // '&'
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '''
+    [0x00, 0x1c, 0x22, 0x41, 0x00], // '('
+    [0x00, 0x41, 0x22, 0x1c, 0x00], // ')'
+    [0x08, 0x2a, 0x1c, 0x2a, 0x08], // '*'
+    [0x08, 0x08, 0x3e, 0x08, 0x08], // '+'
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ','
+    [0x08, 0x08, 0x08, 0x08, 0x08], // '-'
+    [0x00, 0x60, 0x60, 0x00, 0x00], // '.'
+    [0x20, 0x10, 0x08, 0x04, 0x02], // '/'
+    [0x3e, 0x51, 0x49, 0x45, 0x3e], // '0'
+    [0x00, 0x42, 0x7f, 0x40, 0x00], // '1'
+    [0x42, 0x61, 0x51, 0x49, 0x46], // '2'
+    [0x21, 0x41, 0x45, 0x4b, 0x31], // '3'
+    [0x18, 0x14, 0x12, 0x7f, 0x10], // '4'
+    [0x27, 0x45, 0x45, 0x45, 0x39], // '5'
+    [0x3c, 0x4a, 0x49, 0x49, 0x30], // '6'
+    [0x01, 0x71, 0x09, 0x05, 0x03], // '7'
+    [0x36, 0x49, 0x49, 0x49, 0x36], // '8'
+    [0x06, 0x49, 0x49, 0x29, 0x1e], // '9'
+    [0x00, 0x36, 0x36, 0x00, 0x00], // ':'
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ';'
+    [0x08, 0x14, 0x22, 0x41, 0x00], // '<'
+    [0x14, 0x14, 0x14, 0x14, 0x14], // '='
+    [0x00, 0x41, 0x22, 0x14, 0x08], // '>'
+    [0x02, 0x01, 0x51, 0x09, 0x06], // '?'
+    [0x32, 0x49, 0x79, 0x41, 0x3e], // '@'
+    [0x7e, 0x11, 0x11, 0x11, 0x7e], // 'A'
+    [0x7f, 0x49, 0x49, 0x49, 0x36], // 'B'
+    [0x3e, 0x41, 0x41, 0x41, 0x22], // 'C'
+    [0x7f, 0x41, 0x41, 0x22, 0x1c], // 'D'
+    [0x7f, 0x49, 0x49, 0x49, 0x41], // 'E'
+    [0x7f, 0x09, 0x09, 0x09, 0x01], // 'F'
+    [0x3e, 0x41, 0x49, 0x49, 0x7a], // 'G'
+    [0x7f, 0x08, 0x08, 0x08, 0x7f], // 'H'
+    [0x00, 0x41, 0x7f, 0x41, 0x00], // 'I'
+    [0x20, 0x40, 0x41, 0x3f, 0x01], // 'J'
+    [0x7f, 0x08, 0x14, 0x22, 0x41], // 'K'
+    [0x7f, 0x40, 0x40, 0x40, 0x40], // 'L'
+    [0x7f, 0x02, 0x0c, 0x02, 0x7f], // 'M'
+    [0x7f, 0x04, 0x08, 0x10, 0x7f], // 'N'
+    [0x3e, 0x41, 0x41, 0x41, 0x3e], // 'O'
+    [0x7f, 0x09, 0x09, 0x09, 0x06], // 'P'
+    [0x3e, 0x41, 0x51, 0x21, 0x5e], // 'Q'
+    [0x7f, 0x09, 0x19, 0x29, 0x46], // 'R'
+    [0x46, 0x49, 0x49, 0x49, 0x31], // 'S'
+    [0x01, 0x01, 0x7f, 0x01, 0x01], // 'T'
+    [0x3f, 0x40, 0x40, 0x40, 0x3f], // 'U'
+    [0x1f, 0x20, 0x40, 0x20, 0x1f], // 'V'
+    [0x3f, 0x40, 0x38, 0x40, 0x3f], // 'W'
+    [0x63, 0x14, 0x08, 0x14, 0x63], // 'X'
+    [0x07, 0x08, 0x70, 0x08, 0x07], // 'Y'
+    [0x61, 0x51, 0x49, 0x45, 0x43], // 'Z'
+    [0x00, 0x7f, 0x41, 0x41, 0x00], // '['
+    [0x02, 0x04, 0x08, 0x10, 0x20], // '\'
+    [0x00, 0x41, 0x41, 0x7f, 0x00], // ']'
+    [0x04, 0x02, 0x01, 0x02, 0x04], // '^'
+    [0x40, 0x40, 0x40, 0x40, 0x40], // '_'
+    [0x00, 0x01, 0x02, 0x04, 0x00], // '`'
+    [0x20, 0x54, 0x54, 0x54, 0x78], // 'a'
+    [0x7f, 0x48, 0x44, 0x44, 0x38], // 'b'
+    [0x38, 0x44, 0x44, 0x44, 0x20], // 'c'
+    [0x38, 0x44, 0x44, 0x48, 0x7f], // 'd'
+    [0x38, 0x54, 0x54, 0x54, 0x18], // 'e'
+    [0x08, 0x7e, 0x09, 0x01, 0x02], // 'f'
+    [0x0c, 0x52, 0x52, 0x52, 0x3e], // 'g'
+    [0x7f, 0x08, 0x04, 0x04, 0x78], // 'h'
+    [0x00, 0x44, 0x7d, 0x40, 0x00], // 'i'
+    [0x20, 0x40, 0x44, 0x3d, 0x00], // 'j'
+    [0x7f, 0x10, 0x28, 0x44, 0x00], // 'k'
+    [0x00, 0x41, 0x7f, 0x40, 0x00], // 'l'
+    [0x7c, 0x04, 0x18, 0x04, 0x78], // 'm'
+    [0x7c, 0x08, 0x04, 0x04, 0x78], // 'n'
+    [0x38, 0x44, 0x44, 0x44, 0x38], // 'o'
+    [0x7c, 0x14, 0x14, 0x14, 0x08], // 'p'
+    [0x08, 0x14, 0x14, 0x18, 0x7c], // 'q'
+    [0x7c, 0x08, 0x04, 0x04, 0x08], // 'r'
+    [0x48, 0x54, 0x54, 0x54, 0x20], // 's'
+    [0x04, 0x3f, 0x44, 0x40, 0x20], // 't'
+    [0x3c, 0x40, 0x40, 0x20, 0x7c], // 'u'
+    [0x1c, 0x20, 0x40, 0x20, 0x1c], // 'v'
+    [0x3c, 0x40, 0x30, 0x40, 0x3c], // 'w'
+    [0x44, 0x28, 0x10, 0x28, 0x44], // 'x'
+    [0x0c, 0x50, 0x50, 0x50, 0x3c], // 'y'
+    [0x44, 0x64, 0x54, 0x4c, 0x44], // 'z'
+    [0x00, 0x08, 0x36, 0x41, 0x00], // '{'
+    [0x00, 0x00, 0x7f, 0x00, 0x00], // '|'
+    [0x00, 0x41, 0x36, 0x08, 0x00], // '}'
+    [0x08, 0x04, 0x08, 0x10, 0x08]  // '~'
+];
+
+
+/// Returns the columns of the glyph of `c` (the lowest bit is the top pixel).
+pub(crate) fn glyph(c: char) -> [u8; 5] {
+    match c {
+        ' '..='~' => FONT[c as usize - ' ' as usize],
+        _ => FONT['?' as usize - ' ' as usize]
+    }
+}
+
+
+/// Returns the size in pixels of `text` when it is drawn (lines are separated by `\n`).
+pub fn text_size(text: &str) -> Vec2 {
+    let lines = text.split('\n');
+    let (count, width) = lines.fold((0, 0), |(n, w), line| (n + 1, w.max(line.chars().count() as i32)));
+    vec2!((width * ADVANCE - 1).max(0), (count - 1) * LINE_HEIGHT + GLYPH_HEIGHT)
+}
+
+
+/// TrueType (or OpenType) font, used to draw scalable text (see `Image::ttf_text`).
+/// Only available with the `ttf` feature.
+#[cfg(feature = "ttf")]
+pub struct Font {
+    inner: fontdue::Font
+}
+
+
+#[cfg(feature = "ttf")]
+impl Font {
+
+    /// Loads the font file at `path`.
+    pub fn load<P>(path: P) -> Result<Self, String> 
+        where P: AsRef<Path>
+    {
+        let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+        Self::from_bytes(&bytes)
+    }
+
+
+    /// Parses a font from the content of a font file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let inner = fontdue::Font::from_bytes(bytes, fontdue::FontSettings::default())?;
+        Ok(Self { inner })
+    }
+
+
+    /// Returns the size in pixels of `text` when it is drawn with a height of `px` pixels per line
+    /// (lines are separated by `\n`).
+    pub fn text_size(&self, text: &str, px: f32) -> Vec2 {
+        let mut width = 0;
+        let mut count = 0;
+        self.layout(text, px, |i, _, x, _| {
+            count = count.max(i + 1);
+            width = width.max(x);
+        });
+        vec2!(width, count * self.line_height(px))
+    }
+
+
+    /// Height in pixels of a line of text drawn with a size of `px`.
+    fn line_height(&self, px: f32) -> i32 {
+        self.inner.horizontal_line_metrics(px).map_or(px, |m| m.new_line_size).ceil() as i32
+    }
+
+
+    /// Calls `f(line, c, x, baseline)` for each character of `text` and once at the end of each line with `c = '\n'`,
+    /// `x` is the position of the pen relative to the start of the line and `baseline` the distance from the top of the line.
+    fn layout<F>(&self, text: &str, px: f32, mut f: F) 
+        where F: FnMut(i32, char, i32, i32)
+    {
+        let ascent = self.inner.horizontal_line_metrics(px).map_or(px, |m| m.ascent).round() as i32;
+        for (j, line) in text.split('\n').enumerate() {
+            let mut x = 0.;
+            let mut prev = None;
+            for c in line.chars() {
+                if let Some(p) = prev {
+                    x += self.inner.horizontal_kern(p, c, px).unwrap_or(0.);
+                }
+                f(j as i32, c, x.round() as i32, ascent);
+                x += self.inner.metrics(c, px).advance_width;
+                prev = Some(c);
+            }
+            f(j as i32, '\n', x.ceil() as i32, ascent);
+        }
+    }
+
+
+    /// Calls `f(p, coverage)` for each pixel covered by `text` drawn at `pos` with a size of `px`.
+    pub(crate) fn rasterize<F>(&self, pos: Vec2, text: &str, px: f32, mut f: F) 
+        where F: FnMut(Vec2, u8)
+    {
+        let line_height = self.line_height(px);
+        self.layout(text, px, |j, c, x, baseline| {
+            if c == '\n' {
+                return;
+            }
+            let (metrics, bitmap) = self.inner.rasterize(c, px);
+            let left = pos.x + x + metrics.xmin;
+            let top = pos.y + j * line_height + baseline - metrics.height as i32 - metrics.ymin;
+            for (i, coverage) in bitmap.into_iter().enumerate() {
+                if coverage != 0 {
+                    let (dx, dy) = ((i % metrics.width) as i32, (i / metrics.width) as i32);
+                    f(vec2!(left + dx, top + dy), coverage);
+                }
+            }
+        });
+    }
+}
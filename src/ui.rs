@@ -0,0 +1,372 @@
+/*
+
+    MIT License
+    
+    Copyright (c) 2022 Siandfrance
+    
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+    
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+    
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+
+*/
+
+
+
+use crate::math::{Vec2, Rect};
+use crate::img::Color;
+use crate::rds::Frame;
+use crate::input::{InputEvent, KeyEvent, Key, KeyKind, MouseEvent, MouseButton};
+use crate::text;
+
+use std::ops::Range;
+
+
+/// Colors and spacing of the widgets.
+/// 
+/// # Usage
+/// 
+/// ```
+/// let style = Style { accent: Color::ORANGE, ..Style::default() };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    pub text: Color,
+    pub background: Color,
+    /// Background of the widget under the mouse
+    pub hovered: Color,
+    /// Background of the widget held with the mouse
+    pub pressed: Color,
+    pub border: Color,
+    /// Border of the widget focused with the keyboard
+    pub focus: Color,
+    /// Filled part of sliders, checkboxes and progress bars
+    pub accent: Color,
+    /// Space between the border of a widget and its content, in pixels
+    pub padding: i32
+}
+
+
+impl Style {
+
+    /// Returns the part of `rect` inside its border and padding, where the content of a widget goes.
+    pub fn content(&self, rect: Rect) -> Rect {
+        let inset = 1 + self.padding;
+        Rect::new(rect.pos + vec2!(inset, inset), vec2!((rect.size.x - 2 * inset).max(0), (rect.size.y - 2 * inset).max(0)))
+    }
+
+
+    /// Returns the rectangle of the border of `rect` (`rect_boudary` also draws the end of the rectangle).
+    pub(crate) fn border(rect: Rect) -> Rect {
+        Rect::new(rect.pos, rect.size - vec2!(1, 1))
+    }
+}
+
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            text: Color::WHITE,
+            background: Color::hex(0x303446),
+            hovered: Color::hex(0x414559),
+            pressed: Color::hex(0x626880),
+            border: Color::hex(0x737994),
+            focus: Color::hex(0xe5c890),
+            accent: Color::hex(0x8caaee),
+            padding: 2
+        }
+    }
+}
+
+
+/// State of the UI kept between frames, see `begin`.
+#[derive(Debug, Clone)]
+pub struct UiState {
+    pub style: Style,
+    /// Last known position of the mouse
+    mouse: Vec2,
+    mouse_down: bool,
+    /// Widget pressed with the mouse, until the button is released
+    active: Option<usize>,
+    /// Widget focused with the keyboard
+    focus: Option<usize>,
+    /// Number of widgets of the previous frame, to cycle the focus
+    widgets: usize
+}
+
+
+impl Default for UiState {
+    fn default() -> Self {
+        Self {
+            style: Style::default(),
+            mouse: Vec2::ZERO,
+            mouse_down: false,
+            active: None,
+            focus: None,
+            widgets: 0
+        }
+    }
+}
+
+
+impl UiState {
+
+    /// Creates the state of a UI with the default style.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+
+    /// Moves the keyboard focus by `n` widgets, wrapping around.
+    fn move_focus(&mut self, n: i32) {
+        if self.widgets == 0 {
+            return;
+        }
+        let count = self.widgets as i32;
+        self.focus = Some(match self.focus {
+            Some(f) => (f as i32 + n).rem_euclid(count) as usize,
+            None if n > 0 => 0,
+            None => self.widgets - 1
+        });
+    }
+}
+
+
+/// Immediate mode UI of a frame: widgets are drawn and return their interaction when they are called.
+/// Widgets are identified by their call order, that must be the same from one frame to the next.
+/// 
+/// Left click activates a widget, Tab and Shift+Tab (or Down and Up) move the keyboard focus, Enter or Space
+/// activates the focused widget and Left and Right move the focused slider. Escape removes the focus.
+pub struct Ui<'a> {
+    state: &'a mut UiState,
+    frame: &'a mut Frame,
+    /// Left click pressed and released during the frame
+    pressed: Option<Vec2>,
+    released: Option<Vec2>,
+    /// Enter or Space on the focused widget
+    activate: bool,
+    /// Left or Right on the focused widget
+    step: i32,
+    /// Id of the next widget
+    next: usize
+}
+
+
+/// Starts the UI of a frame, with the input events received since the previous frame.
+/// 
+/// # Usage
+/// 
+/// ```
+/// let mut state = UiState::new();
+/// game::run(LoopConfig { mouse: true, ..LoopConfig::default() }, |ctx| {
+///     let mut ui = ui::begin(&mut state, ctx.frame, ctx.events);
+///     ui.label("Paused", vec2!(10, 4));
+///     if ui.button("Resume", Rect::new(vec2!(10, 14), vec2!(40, 11))) {
+///         paused = false;
+///     }
+///     ui.slider(&mut volume, 0.0..1.0, Rect::new(vec2!(10, 28), vec2!(40, 7)));
+/// });
+/// ```
+pub fn begin<'a>(state: &'a mut UiState, frame: &'a mut Frame, events: &[InputEvent]) -> Ui<'a> {
+    let mut pressed = None;
+    let mut released = None;
+    let mut activate = false;
+    let mut step = 0;
+    for event in events {
+        match *event {
+            InputEvent::Mouse(MouseEvent::ButtonPressed(MouseButton::Left, pos)) => {
+                state.mouse = pos;
+                state.mouse_down = true;
+                pressed = Some(pos);
+            }
+            InputEvent::Mouse(MouseEvent::ButtonReleased(MouseButton::Left, pos)) => {
+                state.mouse = pos;
+                state.mouse_down = false;
+                released = Some(pos);
+            }
+            InputEvent::Mouse(MouseEvent::Hold(_, pos)) => state.mouse = pos,
+            InputEvent::Key(KeyEvent { kind: KeyKind::Release, .. }) => (),
+            InputEvent::Key(KeyEvent { key, mods, .. }) => match key {
+                Key::Tab if mods.shift => state.move_focus(-1),
+                Key::Tab | Key::Down => state.move_focus(1),
+                Key::Up => state.move_focus(-1),
+                Key::Enter | Key::Char(' ') => activate = true,
+                Key::Left => step -= 1,
+                Key::Right => step += 1,
+                Key::Esc => state.focus = None,
+                _ => ()
+            }
+            _ => ()
+        }
+    }
+
+    Ui {
+        state,
+        frame,
+        pressed,
+        released,
+        activate,
+        step,
+        next: 0
+    }
+}
+
+
+/// Interaction with a widget during a frame
+struct Interaction {
+    hovered: bool,
+    held: bool,
+    focused: bool,
+    /// Clicked, or activated with the keyboard
+    clicked: bool
+}
+
+
+impl<'a> Ui<'a> {
+
+    /// Returns the style of the widgets.
+    pub fn style(&self) -> Style {
+        self.state.style
+    }
+
+
+    /// Returns the frame the UI is drawn on, to draw things between the widgets.
+    pub fn frame(&mut self) -> &mut Frame {
+        self.frame
+    }
+
+
+    /// Registers a widget covering `rect` and returns how it is interacted with.
+    fn interact(&mut self, rect: Rect) -> Interaction {
+        let id = self.next;
+        self.next += 1;
+
+        if self.pressed.is_some_and(|p| rect.contains(p)) {
+            self.state.active = Some(id);
+            self.state.focus = Some(id);
+        }
+        let active = self.state.active == Some(id);
+        let focused = self.state.focus == Some(id);
+        let clicked = (active && self.released.is_some_and(|p| rect.contains(p))) || (focused && self.activate);
+        if active && self.released.is_some() {
+            self.state.active = None;
+        }
+
+        Interaction {
+            hovered: rect.contains(self.state.mouse),
+            held: self.state.active == Some(id) && self.state.mouse_down,
+            focused,
+            clicked
+        }
+    }
+
+
+    /// Draws the background and the border of a widget.
+    fn draw_box(&mut self, rect: Rect, i: &Interaction) {
+        let style = self.state.style;
+        let bg = if i.held {
+            style.pressed
+        } else if i.hovered {
+            style.hovered
+        } else {
+            style.background
+        };
+        self.frame.draw_rect(rect, bg);
+        self.frame.draw_rect_boundary(Style::border(rect), if i.focused { style.focus } else { style.border });
+    }
+
+
+    /// Draws `text` at `pos`.
+    pub fn label<A>(&mut self, text: &str, pos: A) 
+        where A: AsRef<Vec2>
+    {
+        let color = self.state.style.text;
+        self.frame.draw_text(*pos.as_ref(), text, color);
+    }
+
+
+    /// Draws a button with `text` centered in `rect`, returns true if it was clicked.
+    pub fn button(&mut self, text: &str, rect: Rect) -> bool {
+        let i = self.interact(rect);
+        self.draw_box(rect, &i);
+        let pos = rect.pos + (rect.size - text::text_size(text)) / 2;
+        self.label(text, pos);
+        i.clicked
+    }
+
+
+    /// Draws a checkbox of the height of `rect` followed by `text`, toggles `value` when clicked.
+    /// Returns true if `value` changed.
+    pub fn checkbox(&mut self, text: &str, value: &mut bool, rect: Rect) -> bool {
+        let i = self.interact(rect);
+        if i.clicked {
+            *value = !*value;
+        }
+
+        let style = self.state.style;
+        let bx = Rect::new(rect.pos, vec2!(rect.size.y, rect.size.y));
+        self.draw_box(bx, &i);
+        if *value {
+            let p = style.padding;
+            self.frame.draw_rect(Rect::new(bx.pos + vec2!(p + 1, p + 1), bx.size - vec2!(2 * p + 2, 2 * p + 2)), style.accent);
+        }
+        let text_pos = vec2!(bx.end().x + style.padding, rect.pos.y + (rect.size.y - text::text_size(text).y) / 2);
+        self.label(text, text_pos);
+        i.clicked
+    }
+
+
+    /// Draws a horizontal slider for `value` in `range`, that follows the mouse while it is held.
+    /// The focused slider moves by a twentieth of `range` with Left and Right.
+    /// Returns true if `value` changed.
+    pub fn slider(&mut self, value: &mut f32, range: Range<f32>, rect: Rect) -> bool {
+        let i = self.interact(rect);
+        let old = *value;
+        let len = range.end - range.start;
+        if i.held && rect.size.x > 1 {
+            let t = (self.state.mouse.x - rect.pos.x) as f32 / (rect.size.x - 1) as f32;
+            *value = range.start + t.clamp(0., 1.) * len;
+        }
+        if i.focused && self.step != 0 {
+            *value += self.step as f32 * len / 20.;
+        }
+        *value = value.clamp(range.start.min(range.end), range.start.max(range.end));
+
+        self.draw_box(rect, &i);
+        let style = self.state.style;
+        let t = if len == 0. { 0. } else { (*value - range.start) / len };
+        let inner = Rect::new(rect.pos + vec2!(1, 1), rect.size - vec2!(2, 2));
+        let filled = (inner.size.x as f32 * t).round() as i32;
+        if filled > 0 && inner.size.y > 0 {
+            self.frame.draw_rect(Rect::new(inner.pos, vec2!(filled, inner.size.y)), style.accent);
+        }
+        *value != old
+    }
+}
+
+
+impl Drop for Ui<'_> {
+    fn drop(&mut self) {
+        self.state.widgets = self.next;
+        if self.state.focus.is_some_and(|f| f >= self.next) {
+            self.state.focus = None;
+        }
+        // released outside of any widget
+        if self.released.is_some() {
+            self.state.active = None;
+        }
+    }
+}
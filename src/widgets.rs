@@ -0,0 +1,298 @@
+/*
+
+    MIT License
+    
+    Copyright (c) 2022 Siandfrance
+    
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+    
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+    
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+
+*/
+
+
+
+use crate::math::{Vec2, Rect};
+use crate::img::{Image, Anchor};
+use crate::text;
+use crate::ui::Style;
+
+use std::ops::Range;
+
+
+/// Retained widget, that keeps its state and is drawn onto an image with a `Style`.
+/// 
+/// # Usage
+/// 
+/// ```
+/// let style = Style::default();
+/// let mut downloads = ListBox::new(files);
+/// let mut progress = ProgressBar::new(0.);
+/// loop {
+///     progress.value = done as f32 / total as f32;
+///     downloads.draw(&mut img, Rect::new(vec2!(0, 0), vec2!(80, 40)), &style);
+///     progress.draw(&mut img, Rect::new(vec2!(0, 42), vec2!(80, 11)), &style);
+/// }
+/// ```
+pub trait Widget {
+
+    /// Draws the widget in `rect` of `img`.
+    fn draw(&self, img: &mut Image, rect: Rect, style: &Style);
+}
+
+
+/// Draws the background and the border of a widget.
+fn draw_box(img: &mut Image, rect: Rect, style: &Style) {
+    img.rect(rect, style.background);
+    img.rect_boudary(Style::border(rect), style.border);
+}
+
+
+/// Text aligned in its rectangle, without background.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Label {
+    pub text: String,
+    pub align: Anchor
+}
+
+
+impl Label {
+
+    /// Creates a label aligned to the top left of its rectangle.
+    pub fn new(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            align: Anchor::TopLeft
+        }
+    }
+
+
+    /// Sets the alignment of the text.
+    pub fn with_align(mut self, align: Anchor) -> Self {
+        self.align = align;
+        self
+    }
+}
+
+
+impl Widget for Label {
+    fn draw(&self, img: &mut Image, rect: Rect, style: &Style) {
+        let pos = rect.pos + self.align.offset(text::text_size(&self.text), rect.size);
+        img.text(pos, &self.text, style.text);
+    }
+}
+
+
+/// Bar filled with the accent color up to `value`, from 0 (empty) to 1 (full).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ProgressBar {
+    pub value: f32,
+    /// Shows the percentage at the center of the bar
+    pub show_percent: bool
+}
+
+
+impl ProgressBar {
+
+    pub fn new(value: f32) -> Self {
+        Self {
+            value,
+            show_percent: false
+        }
+    }
+
+
+    /// Shows the percentage at the center of the bar.
+    pub fn with_percent(mut self) -> Self {
+        self.show_percent = true;
+        self
+    }
+}
+
+
+impl Widget for ProgressBar {
+    fn draw(&self, img: &mut Image, rect: Rect, style: &Style) {
+        draw_box(img, rect, style);
+        let inner = style.content(rect);
+        let filled = (inner.size.x as f32 * self.value.clamp(0., 1.)).round() as i32;
+        img.rect(Rect::new(inner.pos, vec2!(filled, inner.size.y)), style.accent);
+        if self.show_percent {
+            let percent = format!("{}%", (self.value.clamp(0., 1.) * 100.).round());
+            Label::new(&percent).with_align(Anchor::Center).draw(img, rect, style);
+        }
+    }
+}
+
+
+/// Horizontal slider showing `value` in `range` with a handle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Slider {
+    pub value: f32,
+    pub range: Range<f32>
+}
+
+
+impl Slider {
+
+    pub fn new(value: f32, range: Range<f32>) -> Self {
+        Self {
+            value,
+            range
+        }
+    }
+
+
+    /// Returns the fraction of the range where the value is, from 0 to 1.
+    fn fraction(&self) -> f32 {
+        let len = self.range.end - self.range.start;
+        if len == 0. { 0. } else { ((self.value - self.range.start) / len).clamp(0., 1.) }
+    }
+
+
+    /// Sets the value under the x coordinate of `pos` when the slider is drawn in `rect` (eg. for a mouse drag).
+    pub fn set_from_pos<A>(&mut self, rect: Rect, style: &Style, pos: A) 
+        where A: AsRef<Vec2>
+    {
+        let inner = style.content(rect);
+        let t = if inner.size.x > 1 { (pos.as_ref().x - inner.pos.x) as f32 / (inner.size.x - 1) as f32 } else { 0. };
+        self.value = self.range.start + t.clamp(0., 1.) * (self.range.end - self.range.start);
+    }
+}
+
+
+impl Widget for Slider {
+    fn draw(&self, img: &mut Image, rect: Rect, style: &Style) {
+        draw_box(img, rect, style);
+        let inner = style.content(rect);
+        if inner.is_empty() {
+            return;
+        }
+        // track in the middle, handle over it
+        let y = inner.pos.y + inner.size.y / 2;
+        img.line((inner.pos.x, y), (inner.end().x - 1, y), style.border);
+        let x = inner.pos.x + ((inner.size.x - 1) as f32 * self.fraction()).round() as i32;
+        if let Some(handle) = Rect::new(vec2!(x - 1, inner.pos.y), vec2!(3, inner.size.y)).intersection(&inner) {
+            img.rect(handle, style.accent);
+        }
+    }
+}
+
+
+/// List of items with an optional selection, scrolled to keep the selected item visible.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ListBox {
+    pub items: Vec<String>,
+    pub selected: Option<usize>,
+    /// Index of the first visible item
+    pub scroll: usize
+}
+
+
+impl ListBox {
+
+    pub fn new(items: Vec<String>) -> Self {
+        Self {
+            items,
+            selected: None,
+            scroll: 0
+        }
+    }
+
+
+    /// Returns the height of an item in pixels.
+    fn item_height(style: &Style) -> i32 {
+        text::GLYPH_HEIGHT + style.padding
+    }
+
+
+    /// Returns the number of items visible when the list is drawn in `rect`.
+    pub fn visible_items(&self, rect: Rect, style: &Style) -> usize {
+        (style.content(rect).size.y / ListBox::item_height(style)).max(1) as usize
+    }
+
+
+    /// Selects the next item (or the first one), and scrolls to it.
+    pub fn select_next(&mut self, rect: Rect, style: &Style) {
+        if !self.items.is_empty() {
+            self.select(self.selected.map_or(0, |i| (i + 1).min(self.items.len() - 1)), rect, style);
+        }
+    }
+
+
+    /// Selects the previous item (or the first one), and scrolls to it.
+    pub fn select_previous(&mut self, rect: Rect, style: &Style) {
+        if !self.items.is_empty() {
+            self.select(self.selected.map_or(0, |i| i.saturating_sub(1)), rect, style);
+        }
+    }
+
+
+    /// Selects the item `i` and scrolls so that it is visible when the list is drawn in `rect`.
+    pub fn select(&mut self, i: usize, rect: Rect, style: &Style) {
+        let visible = self.visible_items(rect, style);
+        self.selected = Some(i);
+        if i < self.scroll {
+            self.scroll = i;
+        } else if i >= self.scroll + visible {
+            self.scroll = i + 1 - visible;
+        }
+    }
+
+
+    /// Returns the index of the item under `pos` when the list is drawn in `rect` (eg. for a mouse click).
+    pub fn item_at<A>(&self, rect: Rect, style: &Style, pos: A) -> Option<usize> 
+        where A: AsRef<Vec2>
+    {
+        let inner = style.content(rect);
+        let pos = *pos.as_ref();
+        if !inner.contains(pos) {
+            return None;
+        }
+        let i = self.scroll + ((pos.y - inner.pos.y) / ListBox::item_height(style)) as usize;
+        (i < self.items.len()).then_some(i)
+    }
+}
+
+
+impl Widget for ListBox {
+    fn draw(&self, img: &mut Image, rect: Rect, style: &Style) {
+        draw_box(img, rect, style);
+        let inner = style.content(rect);
+        let height = ListBox::item_height(style);
+
+        // the items can not overdraw the border
+        let clip = img.clip();
+        match clip {
+            Some(c) => match c.intersection(&inner) {
+                Some(r) => img.set_clip(r),
+                None => return
+            }
+            None => img.set_clip(inner)
+        }
+        for (n, item) in self.items.iter().enumerate().skip(self.scroll).take(self.visible_items(rect, style)) {
+            let y = inner.pos.y + (n - self.scroll) as i32 * height;
+            if self.selected == Some(n) {
+                img.rect(Rect::new(vec2!(inner.pos.x, y), vec2!(inner.size.x, height)), style.accent);
+            }
+            img.text(vec2!(inner.pos.x, y + style.padding / 2), item, style.text);
+        }
+        match clip {
+            Some(c) => img.set_clip(c),
+            None => img.clear_clip()
+        }
+    }
+}